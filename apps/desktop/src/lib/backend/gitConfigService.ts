@@ -36,4 +36,6 @@ export class GbConfig {
 	signingFormat?: string | undefined;
 	gpgProgram?: string | undefined;
 	gpgSshProgram?: string | undefined;
+	gpgX509Program?: string | undefined;
+	gpgSshDefaultKeyCommand?: string | undefined;
 }
@@ -18,6 +18,25 @@ export type Key = Exclude<KeyType, 'local'> | LocalKey;
 
 export type HostType = { type: ForgeType };
 
+export type SnapshotPolicy = {
+	enabled: boolean;
+	intervalSecs: number;
+	excludePaths: string[];
+	onBranchSwitch: boolean;
+	saveBurstFiles: number | undefined;
+};
+
+export type FileWatcherSettings = {
+	backend: 'auto' | 'native' | 'polling';
+	pollIntervalMs: number;
+};
+
+export type LinkedWorktree = {
+	name: string;
+	gitDir: string;
+	commonDir: string;
+};
+
 export class Project {
 	id!: string;
 	title!: string;
@@ -29,6 +48,9 @@ export class Project {
 	omit_certificate_check: boolean | undefined;
 	use_diff_context: boolean | undefined;
 	snapshot_lines_threshold!: number | undefined;
+	snapshot_policy!: SnapshotPolicy;
+	file_watcher!: FileWatcherSettings;
+	linked_worktree?: LinkedWorktree;
 	use_experimental_locking!: boolean;
 	git_host!: {
 		hostType: HostType | undefined;
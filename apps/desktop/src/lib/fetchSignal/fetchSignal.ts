@@ -1,6 +1,15 @@
 import { listen } from '$lib/backend/ipc';
 import { readable } from 'svelte/store';
 
+// Branches and tags that changed outside of the app, e.g. by `git fetch` or `git push` in a terminal.
+const REF_EVENTS = [
+	'git/remote-refs',
+	'git/local-branches-created',
+	'git/local-branches-deleted',
+	'git/local-branches-updated',
+	'git/tags'
+];
+
 export class FetchSignal {
 	// Stores only emit unique values so we use a counter to ensure
 	// derived stores are updated.
@@ -14,5 +23,21 @@ export class FetchSignal {
 		return async () => await unsubscribe();
 	});
 
+	// Emits a new value when the branch the workspace is based on moved.
+	readonly targetMoved = readable<number>(undefined, (set) => {
+		const unsubscribe = listen<any>(`project://${this.projectId}/git/target-moved`, () =>
+			set(this.counter++)
+		);
+		return async () => await unsubscribe();
+	});
+
+	// Emits a new value when branches or tags were created, moved or deleted.
+	readonly refs = readable<number>(undefined, (set) => {
+		const unsubscribers = REF_EVENTS.map((name) =>
+			listen<any>(`project://${this.projectId}/${name}`, () => set(this.counter++))
+		);
+		return async () => await Promise.all(unsubscribers.map((unsubscribe) => unsubscribe()));
+	});
+
 	constructor(private projectId: string) {}
 }
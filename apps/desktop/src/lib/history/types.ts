@@ -23,14 +23,19 @@ export type Operation =
 	| 'UnapplyBranch'
 	| 'CherryPick'
 	| 'SquashCommit'
+	| 'AutosquashCommits'
+	| 'SplitCommit'
 	| 'UpdateCommitMessage'
 	| 'MoveCommit'
 	| 'RestoreFromSnapshot'
 	| 'ReorderCommit'
 	| 'InsertBlankCommit'
 	| 'MoveCommitFile'
+	| 'MoveCommitHunks'
 	| 'FileChanges'
-	| 'EnterEditMode';
+	| 'SwitchBranch'
+	| 'EnterEditMode'
+	| 'RepairProject';
 
 export class Trailer {
 	key!: string;
@@ -9,8 +9,15 @@ import type { ContentSection, HunkSection } from '$lib/utils/fileSections';
 
 type ParsedFiles = [RemoteFile, (ContentSection | HunkSection)[]][];
 
+type UncommitedFilesDelta = {
+	changed: unknown[];
+	removed: string[];
+};
+
 export class UncommitedFilesWatcher {
 	uncommitedFiles: Readable<ParsedFiles>;
+	// Deltas are applied on top of the last complete listing.
+	private files = new Map<string, RemoteFile>();
 
 	constructor(private project: Project) {
 		this.uncommitedFiles = readable([] as ParsedFiles, (set) => {
@@ -19,8 +26,12 @@ export class UncommitedFilesWatcher {
 			});
 
 			const unsubscribe = this.listen(set);
+			const unsubscribeDelta = this.listenDelta(set);
 
-			return unsubscribe;
+			return () => {
+				unsubscribe();
+				unsubscribeDelta();
+			};
 		});
 	}
 
@@ -29,7 +40,16 @@ export class UncommitedFilesWatcher {
 			id: this.project.id
 		});
 
-		const orderedFiles = plainToInstance(RemoteFile, uncommitedFiles).sort((a, b) =>
+		return this.replaceFiles(uncommitedFiles);
+	}
+
+	private replaceFiles(files: unknown[]) {
+		this.files = new Map(plainToInstance(RemoteFile, files).map((file) => [file.path, file]));
+		return this.parsedFiles();
+	}
+
+	private parsedFiles() {
+		const orderedFiles = Array.from(this.files.values()).sort((a, b) =>
 			a.path?.localeCompare(b.path)
 		);
 
@@ -38,11 +58,23 @@ export class UncommitedFilesWatcher {
 
 	private listen(callback: (files: ParsedFiles) => void) {
 		return listen<unknown[]>(`project://${this.project.id}/uncommited-files`, (event) => {
-			const orderedFiles = plainToInstance(RemoteFile, event.payload).sort((a, b) =>
-				a.path?.localeCompare(b.path)
-			);
-
-			callback(parseRemoteFiles(orderedFiles));
+			callback(this.replaceFiles(event.payload));
 		});
 	}
+
+	private listenDelta(callback: (files: ParsedFiles) => void) {
+		return listen<UncommitedFilesDelta>(
+			`project://${this.project.id}/uncommited-files-delta`,
+			(event) => {
+				for (const path of event.payload.removed) {
+					this.files.delete(path);
+				}
+				for (const file of plainToInstance(RemoteFile, event.payload.changed)) {
+					this.files.set(file.path, file);
+				}
+
+				callback(this.parsedFiles());
+			}
+		);
+	}
 }
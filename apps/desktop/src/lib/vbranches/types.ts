@@ -198,6 +198,20 @@ export class ConflictEntries {
 	}
 }
 
+export type SignatureStatus =
+	| 'good'
+	| 'bad'
+	| 'unknownKey'
+	| 'expired'
+	| 'revoked'
+	| 'unverifiable';
+
+export interface SignatureVerification {
+	status: SignatureStatus;
+	signer?: string;
+	fingerprint?: string;
+}
+
 export class DetailedCommit {
 	id!: string;
 	author!: Author;
@@ -212,6 +226,7 @@ export class DetailedCommit {
 	branchId!: string;
 	changeId!: string;
 	isSigned!: boolean;
+	signature?: SignatureVerification;
 	relatedTo?: Commit;
 	conflicted!: boolean;
 	// Set if a GitButler branch reference pointing to this commit exists. In the format of "refs/remotes/origin/my-branch"
@@ -283,6 +298,7 @@ export class Commit {
 	createdAt!: Date;
 	changeId!: string;
 	isSigned!: boolean;
+	signature?: SignatureVerification;
 	parentIds!: string[];
 	conflicted!: boolean;
 
@@ -1,11 +1,15 @@
 use super::r#virtual as vbranch;
+use crate::autosquash;
 use crate::branch_upstream_integration;
+use crate::doctor::{self, Problem, RepairOutcome};
+use crate::move_commit_hunks;
 use crate::move_commits;
 use crate::reorder::{self, StackOrder};
 use crate::reorder_commits;
+use crate::split_commit::{self, CommitSplit};
 use crate::upstream_integration::{
-    self, BaseBranchResolution, BaseBranchResolutionApproach, BranchStatuses, Resolution,
-    UpstreamIntegrationContext,
+    self, BaseBranchResolution, BaseBranchResolutionApproach, BranchIntegrationPreview,
+    BranchStatuses, Resolution, ResolutionApproach, UpstreamIntegrationContext,
 };
 use crate::{
     base,
@@ -59,6 +63,21 @@ pub fn create_commit(
     result
 }
 
+/// Check the GitButler state of `project` for inconsistencies without changing anything.
+pub fn diagnose_project(project: &Project) -> Result<Vec<Problem>> {
+    let ctx = CommandContext::open(project)?;
+    let guard = project.shared_worktree_access();
+    doctor::diagnose(&ctx, guard.read_permission())
+}
+
+/// Repair the problems [`diagnose_project()`] finds in `project` that can be repaired safely, after
+/// taking a snapshot that allows undoing the repairs.
+pub fn repair_project(project: &Project) -> Result<RepairOutcome> {
+    let ctx = CommandContext::open(project)?;
+    let mut guard = project.exclusive_worktree_access();
+    doctor::repair(&ctx, guard.write_permission())
+}
+
 pub fn can_apply_remote_branch(project: &Project, branch_name: &RemoteRefname) -> Result<bool> {
     let ctx = CommandContext::open(project)?;
     assure_open_workspace_mode(&ctx)
@@ -81,6 +100,7 @@ pub fn list_virtual_branches(
 pub fn list_virtual_branches_cached(
     project: &Project,
     worktree_changes: Option<DiffByPathMap>,
+    changed_paths: Option<&[PathBuf]>,
 ) -> Result<(Vec<vbranch::VirtualBranch>, Vec<gitbutler_diff::FileDiff>)> {
     let ctx = open_with_verify(project)?;
 
@@ -91,6 +111,7 @@ pub fn list_virtual_branches_cached(
         &ctx,
         project.exclusive_worktree_access().write_permission(),
         worktree_changes,
+        changed_paths,
     )
     .map_err(Into::into)
 }
@@ -149,7 +170,8 @@ pub fn list_remote_commit_files(
     commit_oid: git2::Oid,
 ) -> Result<Vec<RemoteBranchFile>> {
     let ctx = CommandContext::open(project)?;
-    crate::file::list_remote_commit_files(ctx.repository(), commit_oid).map_err(Into::into)
+    crate::file::list_remote_commit_files(ctx.repository(), commit_oid, project.diff_settings)
+        .map_err(Into::into)
 }
 
 pub fn set_base_branch(project: &Project, target_branch: &RemoteRefname) -> Result<BaseBranch> {
@@ -321,6 +343,24 @@ pub fn move_commit_file(
         .map_err(Into::into)
 }
 
+pub fn move_commit_hunks(
+    project: &Project,
+    branch_id: StackId,
+    from_commit_oid: git2::Oid,
+    to_commit_oid: git2::Oid,
+    ownership: &BranchOwnershipClaims,
+) -> Result<git2::Oid> {
+    let ctx = open_with_verify(project)?;
+    assure_open_workspace_mode(&ctx).context("Moving hunks requires open workspace mode")?;
+    let mut guard = project.exclusive_worktree_access();
+    let _ = ctx.project().create_snapshot(
+        SnapshotDetails::new(OperationKind::MoveCommitHunks),
+        guard.write_permission(),
+    );
+    move_commit_hunks::move_commit_hunks(&ctx, branch_id, from_commit_oid, to_commit_oid, ownership)
+        .map_err(Into::into)
+}
+
 pub fn undo_commit(project: &Project, branch_id: StackId, commit_oid: git2::Oid) -> Result<()> {
     let ctx = open_with_verify(project)?;
     assure_open_workspace_mode(&ctx).context("Undoing a commit requires open workspace mode")?;
@@ -461,6 +501,33 @@ pub fn squash(project: &Project, branch_id: StackId, commit_oid: git2::Oid) -> R
     vbranch::squash(&ctx, branch_id, commit_oid).map_err(Into::into)
 }
 
+pub fn autosquash(project: &Project, branch_id: StackId) -> Result<()> {
+    let ctx = open_with_verify(project)?;
+    assure_open_workspace_mode(&ctx).context("Autosquashing requires open workspace mode")?;
+    let mut guard = project.exclusive_worktree_access();
+    let _ = ctx.project().create_snapshot(
+        SnapshotDetails::new(OperationKind::AutosquashCommits),
+        guard.write_permission(),
+    );
+    autosquash::autosquash(&ctx, branch_id, guard.write_permission()).map_err(Into::into)
+}
+
+pub fn split_commit(
+    project: &Project,
+    branch_id: StackId,
+    commit_oid: git2::Oid,
+    splits: &[CommitSplit],
+) -> Result<Vec<git2::Oid>> {
+    let ctx = open_with_verify(project)?;
+    assure_open_workspace_mode(&ctx).context("Splitting a commit requires open workspace mode")?;
+    let mut guard = project.exclusive_worktree_access();
+    let _ = ctx.project().create_snapshot(
+        SnapshotDetails::new(OperationKind::SplitCommit),
+        guard.write_permission(),
+    );
+    split_commit::split_commit(&ctx, branch_id, commit_oid, splits).map_err(Into::into)
+}
+
 pub fn update_commit_message(
     project: &Project,
     branch_id: StackId,
@@ -566,6 +633,28 @@ pub fn get_uncommited_files_reusable(project: &Project) -> Result<DiffByPathMap>
     crate::branch::get_uncommited_files_raw(&context, guard.read_permission())
 }
 
+/// Update `files`, as previously returned by [`get_uncommited_files_reusable()`] or this function
+/// while `HEAD` pointed to `base_id`, by diffing only the worktree-relative `paths`.
+/// If `HEAD` moved since, or `base_id` is `None`, all files are diffed instead.
+///
+/// Returns the id of the commit `files` are relative to now, to be passed as `base_id` next time.
+pub fn update_uncommited_files_reusable(
+    project: &Project,
+    base_id: Option<git2::Oid>,
+    paths: &[PathBuf],
+    files: &mut DiffByPathMap,
+) -> Result<git2::Oid> {
+    let context = CommandContext::open(project)?;
+    let guard = project.shared_worktree_access();
+    crate::branch::update_uncommited_files_raw(
+        &context,
+        base_id,
+        paths,
+        files,
+        guard.read_permission(),
+    )
+}
+
 pub fn upstream_integration_statuses(
     project: &Project,
     target_commit_oid: Option<git2::Oid>,
@@ -582,6 +671,22 @@ pub fn upstream_integration_statuses(
     upstream_integration::upstream_integration_statuses(&context)
 }
 
+pub fn upstream_integration_preview(
+    project: &Project,
+    approach: ResolutionApproach,
+    base_branch_resolution: Option<BaseBranchResolution>,
+) -> Result<Vec<BranchIntegrationPreview>> {
+    let command_context = CommandContext::open(project)?;
+    let guard = project.shared_worktree_access();
+
+    upstream_integration::preview_upstream_integration(
+        &command_context,
+        &approach,
+        base_branch_resolution,
+        guard.read_permission(),
+    )
+}
+
 pub fn integrate_upstream(
     project: &Project,
     resolutions: &[Resolution],
@@ -0,0 +1,378 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Context as _, Result};
+use bstr::ByteSlice as _;
+use gitbutler_command_context::CommandContext;
+use gitbutler_commit::commit_ext::CommitExt as _;
+use gitbutler_project::access::WorktreeWritePermission;
+use gitbutler_repo::{
+    rebase::{cherry_rebase_group, cherry_rebase_group_preserving_merges},
+    LogUntil, RepositoryExt as _,
+};
+use gitbutler_stack::{commit_by_oid_or_change_id, StackId};
+
+use crate::{
+    branch_trees::{checkout_branch_trees, compute_updated_branch_head_for_commits},
+    VirtualBranchesExt as _,
+};
+
+/// How a `fixup!`, `squash!` or `amend!` commit is combined with its target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FixupKind {
+    /// Keep the changes, but discard the message.
+    Fixup,
+    /// Keep the changes and append the message to the one of the target.
+    Squash,
+    /// Keep the changes and replace the message of the target.
+    Amend,
+}
+
+/// Squash all `fixup!`, `squash!` and `amend!` commits of a stack into the commits they refer to,
+/// like `git rebase --autosquash` does.
+///
+/// Targets are found by their subject, change-id or a prefix of their commit id, and need to be
+/// older than the commit referring to them. Commits without a target are left in place.
+/// Nothing is changed if squashing would lead to a conflicted commit.
+pub(crate) fn autosquash(
+    ctx: &CommandContext,
+    branch_id: StackId,
+    perm: &mut WorktreeWritePermission,
+) -> Result<()> {
+    ctx.assure_resolved()?;
+
+    let repository = ctx.repository();
+    let vb_state = ctx.project().virtual_branches();
+    let default_target = vb_state.get_default_target()?;
+    let mut branch = vb_state.get_branch_in_workspace(branch_id)?;
+    let merge_base = repository.merge_base(branch.head(), default_target.sha)?;
+    let branch_commits = repository.l(branch.head(), LogUntil::Commit(merge_base), false)?;
+
+    let groups = group_fixups(repository, &branch_commits)?;
+    let squashed_into = groups
+        .iter()
+        .filter(|group| !group.fixups.is_empty())
+        .map(|group| group.target)
+        .collect::<Vec<_>>();
+    if squashed_into.is_empty() {
+        return Ok(());
+    }
+
+    if !branch.allow_rebasing {
+        let pushed_commits = branch.upstream_head.map_or_else(
+            || Ok(vec![]),
+            |upstream_head| {
+                repository.l(upstream_head, LogUntil::Commit(default_target.sha), false)
+            },
+        )?;
+        if squashed_into
+            .iter()
+            .any(|target| pushed_commits.contains(target))
+        {
+            // squashing into a pushed commit will cause a force push that is not allowed
+            bail!("force push not allowed");
+        }
+    }
+
+    let (new_head, rewritten) = squash_groups(repository, merge_base, &groups)?;
+    let updated =
+        compute_updated_branch_head_for_commits(repository, branch.head(), branch.tree, new_head)?;
+
+    // Series now point to the rewritten commit, which for squashed commits is the one they were
+    // squashed into. This has to happen while the old commits are still part of the stack.
+    let mut new_heads = HashMap::new();
+    for head in &branch.heads {
+        let commit_id =
+            commit_by_oid_or_change_id(&head.target, repository, branch.head(), merge_base)?
+                .head
+                .id();
+        let commit_id = rewritten.get(&commit_id).copied().unwrap_or(commit_id);
+        new_heads.insert(head.name.clone(), repository.find_commit(commit_id)?);
+    }
+    branch.set_all_heads(ctx, new_heads)?;
+    branch.set_stack_head(ctx, updated.head, Some(updated.tree))?;
+
+    checkout_branch_trees(ctx, perm)?;
+
+    crate::integration::update_workspace_commit(&vb_state, ctx)
+        .context("failed to update gitbutler workspace")?;
+
+    Ok(())
+}
+
+/// A commit along with the fixups that are to be squashed into it, in the order they were made.
+#[derive(Debug, PartialEq)]
+struct Group {
+    target: git2::Oid,
+    fixups: Vec<(FixupKind, git2::Oid)>,
+}
+
+/// Arrange `branch_commits`, ordered from newest to oldest, into groups in the order they should be
+/// rebased, from oldest to newest.
+fn group_fixups(repository: &git2::Repository, branch_commits: &[git2::Oid]) -> Result<Vec<Group>> {
+    let mut groups: Vec<Group> = Vec::new();
+    let mut targets: Vec<git2::Commit> = Vec::new();
+    for commit_id in branch_commits.iter().rev() {
+        let commit = repository.find_commit(*commit_id)?;
+        let subject = commit.summary_bytes().unwrap_or_default().to_str_lossy();
+        if let Some((kind, reference)) = parse_fixup_subject(&subject) {
+            // Like Git, we prefer the oldest commit that matches.
+            if let Some(idx) = targets
+                .iter()
+                .position(|target| is_fixup_target(target, reference))
+            {
+                groups[idx].fixups.push((kind, *commit_id));
+                continue;
+            }
+        }
+        groups.push(Group {
+            target: *commit_id,
+            fixups: vec![],
+        });
+        targets.push(commit);
+    }
+    Ok(groups)
+}
+
+/// Rebase `groups` onto `base`, squashing each fixup into its target, and return the new head
+/// along with the commit each of the commits in `groups` was rewritten to.
+fn squash_groups(
+    repository: &git2::Repository,
+    base: git2::Oid,
+    groups: &[Group],
+) -> Result<(git2::Oid, HashMap<git2::Oid, git2::Oid>)> {
+    let mut head = base;
+    let mut rewritten = HashMap::new();
+    for group in groups {
+        let rebased_target =
+            cherry_rebase_group_preserving_merges(repository, head, &[group.target])?;
+        let rebased_target = ensure_unconflicted(repository, rebased_target)?;
+        if group.fixups.is_empty() {
+            head = rebased_target.id();
+            rewritten.insert(group.target, head);
+            continue;
+        }
+
+        let target = repository.find_commit(group.target)?;
+        let mut message = target.message_bstr().to_str_lossy().into_owned();
+        let mut tip = rebased_target.id();
+        for (kind, fixup) in &group.fixups {
+            tip = cherry_rebase_group(repository, tip, &[*fixup])?;
+            ensure_unconflicted(repository, tip)?;
+            let fixup = repository.find_commit(*fixup)?;
+            message = squash_message(*kind, &message, &fixup.message_bstr().to_str_lossy());
+        }
+
+        // The target was dropped if it became empty, so the fixups go right on top of the head.
+        let parents = if rebased_target.id() == head {
+            vec![rebased_target]
+        } else {
+            rebased_target.parents().collect()
+        };
+        head = repository
+            .commit_with_signature(
+                None,
+                &target.author(),
+                &target.committer(),
+                &message,
+                &repository.find_commit(tip)?.tree()?,
+                &parents.iter().collect::<Vec<_>>(),
+                // keep the identity of the target
+                target.gitbutler_headers(),
+            )
+            .context("failed to commit")?;
+        rewritten.insert(group.target, head);
+        rewritten.extend(group.fixups.iter().map(|(_, fixup)| (*fixup, head)));
+    }
+    Ok((head, rewritten))
+}
+
+fn ensure_unconflicted(
+    repository: &git2::Repository,
+    commit_id: git2::Oid,
+) -> Result<git2::Commit<'_>> {
+    let commit = repository.find_commit(commit_id)?;
+    if commit.is_conflicted() {
+        bail!("Autosquashing would result in conflicts, please squash the commits manually");
+    }
+    Ok(commit)
+}
+
+/// Parse the kind of fixup and the reference to its target from `subject`, which may be prefixed
+/// multiple times for fixups of fixups.
+fn parse_fixup_subject(subject: &str) -> Option<(FixupKind, &str)> {
+    const PREFIXES: [(&str, FixupKind); 3] = [
+        ("fixup! ", FixupKind::Fixup),
+        ("squash! ", FixupKind::Squash),
+        ("amend! ", FixupKind::Amend),
+    ];
+    fn strip(subject: &str) -> Option<(FixupKind, &str)> {
+        PREFIXES.iter().find_map(|(prefix, kind)| {
+            subject
+                .strip_prefix(prefix)
+                .map(|reference| (*kind, reference))
+        })
+    }
+
+    let (kind, mut reference) = strip(subject)?;
+    while let Some((_, inner)) = strip(reference) {
+        reference = inner;
+    }
+    let reference = reference.trim();
+    (!reference.is_empty()).then_some((kind, reference))
+}
+
+fn is_fixup_target(commit: &git2::Commit, reference: &str) -> bool {
+    if commit.summary_bytes().unwrap_or_default().to_str_lossy() == reference {
+        return true;
+    }
+    if commit
+        .change_id()
+        .is_some_and(|change_id| change_id == reference)
+    {
+        return true;
+    }
+    reference.len() >= 4
+        && reference.chars().all(|c| c.is_ascii_hexdigit())
+        && commit.id().to_string().starts_with(reference)
+}
+
+/// Combine `message` of the target with the message of a fixup of `kind`.
+fn squash_message(kind: FixupKind, message: &str, fixup_message: &str) -> String {
+    // The subject only refers to the target, and isn't part of the resulting message.
+    let body = fixup_message
+        .split_once('\n')
+        .map_or("", |(_subject, body)| body)
+        .trim_start_matches('\n');
+    match kind {
+        FixupKind::Fixup => message.to_owned(),
+        FixupKind::Squash if body.trim().is_empty() => message.to_owned(),
+        FixupKind::Squash => format!("{}\n\n{}", message.trim_end(), body),
+        FixupKind::Amend if body.trim().is_empty() => message.to_owned(),
+        FixupKind::Amend => body.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use gitbutler_repo::LogUntil;
+    use gitbutler_repo::RepositoryExt as _;
+    use gitbutler_testsupport::testing_repository::{
+        assert_commit_tree_matches, TestingRepository,
+    };
+
+    use super::{group_fixups, parse_fixup_subject, squash_groups, squash_message, FixupKind};
+
+    #[test]
+    fn fixup_subjects_are_parsed() {
+        assert_eq!(
+            parse_fixup_subject("fixup! add feature"),
+            Some((FixupKind::Fixup, "add feature"))
+        );
+        assert_eq!(
+            parse_fixup_subject("squash! fixup! add feature"),
+            Some((FixupKind::Squash, "add feature"))
+        );
+        assert_eq!(
+            parse_fixup_subject("amend! add feature"),
+            Some((FixupKind::Amend, "add feature"))
+        );
+        assert_eq!(parse_fixup_subject("add feature"), None);
+        assert_eq!(parse_fixup_subject("fixup! "), None);
+    }
+
+    #[test]
+    fn messages_are_combined_by_kind() {
+        let target = "add feature\n\nbody\n";
+        assert_eq!(
+            squash_message(FixupKind::Fixup, target, "fixup! add feature\n"),
+            target
+        );
+        assert_eq!(
+            squash_message(FixupKind::Squash, target, "squash! add feature\n\nmore\n"),
+            "add feature\n\nbody\n\nmore\n"
+        );
+        assert_eq!(
+            squash_message(
+                FixupKind::Amend,
+                target,
+                "amend! add feature\n\nnew subject\n"
+            ),
+            "new subject\n"
+        );
+    }
+
+    #[test]
+    fn fixups_are_squashed_into_their_targets() {
+        let test_repository = TestingRepository::open();
+        let repository = &test_repository.repository;
+
+        let base = test_repository.commit_tree(None, &[("a.txt", "1"), ("b.txt", "1")]);
+        let a = test_repository.commit_tree_with_message(
+            Some(&base),
+            "change a",
+            &[("a.txt", "2"), ("b.txt", "1")],
+        );
+        let b = test_repository.commit_tree_with_message(
+            Some(&a),
+            "change b",
+            &[("a.txt", "2"), ("b.txt", "2")],
+        );
+        let fixup = test_repository.commit_tree_with_message(
+            Some(&b),
+            "fixup! change a",
+            &[("a.txt", "3"), ("b.txt", "2")],
+        );
+        let squash = test_repository.commit_tree_with_message(
+            Some(&fixup),
+            "squash! change b\n\nexplain b",
+            &[("a.txt", "3"), ("b.txt", "3")],
+        );
+
+        let commits = repository
+            .l(squash.id(), LogUntil::Commit(base.id()), false)
+            .unwrap();
+        let groups = group_fixups(repository, &commits).unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].target, a.id());
+        assert_eq!(groups[0].fixups, vec![(FixupKind::Fixup, fixup.id())]);
+        assert_eq!(groups[1].target, b.id());
+        assert_eq!(groups[1].fixups, vec![(FixupKind::Squash, squash.id())]);
+
+        let (head, rewritten) = squash_groups(repository, base.id(), &groups).unwrap();
+        let new_b = repository.find_commit(head).unwrap();
+        assert_eq!(rewritten[&b.id()], head);
+        assert_eq!(rewritten[&squash.id()], head);
+        assert_eq!(new_b.message(), Some("change b\n\nexplain b"));
+        assert_commit_tree_matches(repository, &new_b, &[("a.txt", b"3"), ("b.txt", b"3")]);
+
+        let new_a = new_b.parent(0).unwrap();
+        assert_eq!(new_a.message(), Some("change a"));
+        assert_commit_tree_matches(repository, &new_a, &[("a.txt", b"3"), ("b.txt", b"1")]);
+        assert_eq!(new_a.parent_id(0).unwrap(), base.id());
+        assert_eq!(rewritten[&a.id()], new_a.id());
+        assert_eq!(rewritten[&fixup.id()], new_a.id());
+    }
+
+    #[test]
+    fn conflicting_fixups_are_refused() {
+        let test_repository = TestingRepository::open();
+        let repository = &test_repository.repository;
+
+        let base = test_repository.commit_tree(None, &[("a.txt", "1")]);
+        let a =
+            test_repository.commit_tree_with_message(Some(&base), "change a", &[("a.txt", "2")]);
+        let b =
+            test_repository.commit_tree_with_message(Some(&a), "change a again", &[("a.txt", "3")]);
+        let fixup = test_repository.commit_tree_with_message(
+            Some(&b),
+            "fixup! change a",
+            &[("a.txt", "4")],
+        );
+
+        let commits = repository
+            .l(fixup.id(), LogUntil::Commit(base.id()), false)
+            .unwrap();
+        let groups = group_fixups(repository, &commits).unwrap();
+        assert!(squash_groups(repository, base.id(), &groups).is_err());
+    }
+}
@@ -184,7 +184,8 @@ pub(crate) fn set_base_branch(
         // if there are any commits on the head branch or uncommitted changes in the working directory, we need to
         // put them into a virtual branch
 
-        let wd_diff = gitbutler_diff::workdir(repo, current_head_commit.id())?;
+        let wd_diff =
+            gitbutler_diff::workdir(repo, current_head_commit.id(), ctx.project().diff_settings)?;
         if !wd_diff.is_empty() || current_head_commit.id() != target.sha {
             // assign ownership to the branch
             let ownership = wd_diff.iter().fold(
@@ -342,7 +343,7 @@ pub(crate) fn target_to_base_branch(ctx: &CommandContext, target: &Target) -> Re
         .log(oid, LogUntil::Commit(target.sha), false)
         .context("failed to get upstream commits")?
         .iter()
-        .map(commit_to_remote_commit)
+        .map(|commit| commit_to_remote_commit(repo, commit))
         .collect::<Vec<_>>();
 
     // get some recent commits
@@ -350,7 +351,7 @@ pub(crate) fn target_to_base_branch(ctx: &CommandContext, target: &Target) -> Re
         .log(target.sha, LogUntil::Take(20), false)
         .context("failed to get recent commits")?
         .iter()
-        .map(commit_to_remote_commit)
+        .map(|commit| commit_to_remote_commit(repo, commit))
         .collect::<Vec<_>>();
 
     // we assume that only local commits can be conflicted
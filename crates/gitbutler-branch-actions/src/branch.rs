@@ -10,7 +10,7 @@ use gitbutler_oxidize::{git2_to_gix_object_id, gix_to_git2_oid};
 use gitbutler_project::access::WorktreeReadPermission;
 use gitbutler_reference::normalize_branch_name;
 use gitbutler_reference::RemoteRefname;
-use gitbutler_repo::{GixRepositoryExt, RepositoryExt as _};
+use gitbutler_repo::{GixRepositoryExt, RepositoryExt as _, SignatureVerification};
 use gitbutler_serde::BStringForFrontend;
 use gitbutler_stack::{Stack as GitButlerBranch, StackId, Target};
 use gix::object::tree::diff::Action;
@@ -23,6 +23,7 @@ use std::{
     cmp::max,
     collections::{HashMap, HashSet},
     fmt::Debug,
+    path::PathBuf,
     vec,
 };
 use tracing::instrument;
@@ -32,8 +33,41 @@ pub(crate) fn get_uncommited_files_raw(
     ctx: &CommandContext,
     _permission: &WorktreeReadPermission,
 ) -> Result<DiffByPathMap> {
-    gitbutler_diff::workdir(ctx.repository(), ctx.repository().head_commit()?.id())
-        .context("Failed to list uncommited files")
+    gitbutler_diff::workdir(
+        ctx.repository(),
+        ctx.repository().head_commit()?.id(),
+        ctx.project().diff_settings,
+    )
+    .context("Failed to list uncommited files")
+}
+
+/// Update `files`, previously computed against the commit `base_id`, with the changes at `paths`,
+/// or recompute all of them if `HEAD` doesn't point to `base_id` anymore.
+///
+/// Returns the id of the commit `files` are computed against now.
+#[instrument(level = tracing::Level::DEBUG, skip(ctx, paths, files, _permission))]
+pub(crate) fn update_uncommited_files_raw(
+    ctx: &CommandContext,
+    base_id: Option<git2::Oid>,
+    paths: &[PathBuf],
+    files: &mut DiffByPathMap,
+    _permission: &WorktreeReadPermission,
+) -> Result<git2::Oid> {
+    let head_id = ctx.repository().head_commit()?.id();
+    if base_id == Some(head_id) {
+        gitbutler_diff::update_workdir(
+            ctx.repository(),
+            head_id,
+            ctx.project().diff_settings,
+            paths,
+            files,
+        )
+        .context("Failed to update uncommited files")?;
+    } else {
+        *files = gitbutler_diff::workdir(ctx.repository(), head_id, ctx.project().diff_settings)
+            .context("Failed to list uncommited files")?;
+    }
+    Ok(head_id)
 }
 
 pub(crate) fn get_uncommited_files(
@@ -727,4 +761,6 @@ pub struct CommitEntry {
     /// The parent commits of the commit
     #[serde(with = "gitbutler_serde::oid_vec")]
     pub parent_ids: Vec<git2::Oid>,
+    /// The verification status of the signature, if the commit is signed
+    pub signature: Option<SignatureVerification>,
 }
@@ -10,7 +10,7 @@ use gitbutler_patch_reference::ForgeIdentifier;
 use gitbutler_project::access::WorktreeWritePermission;
 use gitbutler_reference::{Refname, RemoteRefname};
 use gitbutler_repo::{
-    rebase::{cherry_rebase_group, gitbutler_merge_commits},
+    rebase::{cherry_rebase_group_preserving_merges, gitbutler_merge_commits},
     LogUntil, RepositoryExt,
 };
 use gitbutler_repo_actions::RepoActionsExt;
@@ -199,6 +199,7 @@ impl BranchManager<'_> {
             &merge_base_tree,
             &head_commit_tree,
             true,
+            self.ctx.project().diff_settings,
         )?;
 
         // assign ownership to the branch
@@ -338,7 +339,11 @@ impl BranchManager<'_> {
                 let commits_to_rebase =
                     repo.l(branch.head(), LogUntil::Commit(merge_base), false)?;
 
-                let head_oid = cherry_rebase_group(repo, default_target.sha, &commits_to_rebase)?;
+                let head_oid = cherry_rebase_group_preserving_merges(
+                    repo,
+                    default_target.sha,
+                    &commits_to_rebase,
+                )?;
 
                 repo.find_commit(head_oid)?
             } else {
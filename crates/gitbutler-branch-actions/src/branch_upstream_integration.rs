@@ -2,7 +2,7 @@ use anyhow::{anyhow, bail, Result};
 use gitbutler_command_context::CommandContext;
 use gitbutler_project::access::WorktreeWritePermission;
 use gitbutler_repo::{
-    rebase::{cherry_rebase_group, gitbutler_merge_commits},
+    rebase::{cherry_rebase_group_preserving_merges, gitbutler_merge_commits},
     LogUntil, RepositoryExt as _,
 };
 use gitbutler_stack::commit_by_oid_or_change_id;
@@ -189,15 +189,22 @@ impl IntegrateUpstreamContext<'_, '_> {
                 self.remote_head,
             )?;
             // First rebase the series with it's remote commits
-            let new_series_head =
-                cherry_rebase_group(self.repository, merge_base, &ordered_commits)?;
+            let new_series_head = cherry_rebase_group_preserving_merges(
+                self.repository,
+                merge_base,
+                &ordered_commits,
+            )?;
             // Get the commits that come after the series head, until the stack head
             let remaining_ids_to_rebase =
                 self.repository
                     .l(self.branch_head, LogUntil::Commit(series_head), false)?;
             // Rebase the remaining commits on top of the new series head in order to get the new stack head
             (
-                cherry_rebase_group(self.repository, new_series_head, &remaining_ids_to_rebase)?,
+                cherry_rebase_group_preserving_merges(
+                    self.repository,
+                    new_series_head,
+                    &remaining_ids_to_rebase,
+                )?,
                 new_series_head,
             )
         };
@@ -237,7 +244,7 @@ impl IntegrateUpstreamContext<'_, '_> {
                 self.remote_head,
             )?;
 
-            cherry_rebase_group(self.repository, merge_base, &ordered_commits)?
+            cherry_rebase_group_preserving_merges(self.repository, merge_base, &ordered_commits)?
         };
 
         // Find what the new head and branch tree should be
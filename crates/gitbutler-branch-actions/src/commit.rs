@@ -7,7 +7,7 @@ use bstr::ByteSlice as _;
 use gitbutler_cherry_pick::ConflictedTreeKey;
 use gitbutler_command_context::CommandContext;
 use gitbutler_commit::commit_ext::CommitExt;
-use gitbutler_repo::rebase::ConflictEntries;
+use gitbutler_repo::{rebase::ConflictEntries, RepositoryExt as _, SignatureVerification};
 use gitbutler_serde::BStringForFrontend;
 use gitbutler_stack::{Stack, StackId};
 use serde::Serialize;
@@ -37,6 +37,8 @@ pub struct VirtualBranchCommit {
     pub branch_id: StackId,
     pub change_id: Option<String>,
     pub is_signed: bool,
+    /// The verification status of the signature, if the commit is signed.
+    pub signature: Option<SignatureVerification>,
     pub conflicted: bool,
     /// The id of the remote commit from which this one was copied, as identified by
     /// having equal author, committer, and commit message.
@@ -108,6 +110,7 @@ pub(crate) fn commit_to_vbranch_commit(
         branch_id: branch.id,
         change_id: commit.change_id(),
         is_signed: commit.is_signed(),
+        signature: repository.verify_commit_signature(commit),
         conflicted: commit.is_conflicted(),
         copied_from_remote_id,
         remote_commit_id,
@@ -0,0 +1,404 @@
+//! Consistency checks of the GitButler state of a project, and safe repairs of the problems found.
+//!
+//! The state spans `virtual_branches.toml`, the operations log and the `gitbutler/workspace` branch, which
+//! can drift apart if an operation is interrupted or objects are removed from the repository, e.g. by
+//! rewriting history outside of GitButler.
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt,
+    path::PathBuf,
+};
+
+use anyhow::{Context, Result};
+use gitbutler_command_context::CommandContext;
+use gitbutler_error::error::Code;
+use gitbutler_operating_modes::{in_open_workspace_mode, OPEN_WORKSPACE_REFS};
+use gitbutler_oplog::{
+    entry::{OperationKind, SnapshotDetails},
+    OplogExt,
+};
+use gitbutler_project::access::{WorktreeReadPermission, WorktreeWritePermission};
+use gitbutler_stack::{Stack, StackId};
+use itertools::Itertools;
+use serde::Serialize;
+
+use crate::{
+    conflicts,
+    integration::{update_workspace_commit, GITBUTLER_WORKSPACE_COMMIT_TITLE},
+    VirtualBranchesExt,
+};
+
+/// A problem with the GitButler state of a project.
+#[derive(Serialize, PartialEq, Eq, Debug, Clone)]
+#[serde(tag = "type", content = "subject", rename_all = "camelCase")]
+pub enum Problem {
+    /// No base branch was set, so nothing else could be checked.
+    MissingDefaultTarget,
+    /// The commit of the base branch doesn't exist.
+    #[serde(rename_all = "camelCase")]
+    MissingTargetCommit {
+        #[serde(with = "gitbutler_serde::oid")]
+        sha: git2::Oid,
+    },
+    /// The head commit of a stack doesn't exist.
+    #[serde(rename_all = "camelCase")]
+    MissingStackHead {
+        stack_id: StackId,
+        name: String,
+        #[serde(with = "gitbutler_serde::oid")]
+        head: git2::Oid,
+    },
+    /// The tree with the uncommitted changes of a stack doesn't exist.
+    #[serde(rename_all = "camelCase")]
+    MissingStackTree {
+        stack_id: StackId,
+        name: String,
+        #[serde(with = "gitbutler_serde::oid")]
+        tree: git2::Oid,
+    },
+    /// A branch of a stack doesn't point to a commit in the stack.
+    #[serde(rename_all = "camelCase")]
+    InvalidPatchReference {
+        stack_id: StackId,
+        name: String,
+        reference: String,
+    },
+    /// A stack claims changes in a file that has no uncommitted changes.
+    #[serde(rename_all = "camelCase")]
+    StaleOwnershipClaim {
+        stack_id: StackId,
+        name: String,
+        path: PathBuf,
+    },
+    /// A stack claims changes in a file that the stack `owner_id` claims as well.
+    #[serde(rename_all = "camelCase")]
+    DuplicateOwnershipClaim {
+        stack_id: StackId,
+        name: String,
+        path: PathBuf,
+        owner_id: StackId,
+    },
+    /// The latest snapshot of the operations log doesn't exist.
+    #[serde(rename_all = "camelCase")]
+    MissingOplogHead {
+        #[serde(with = "gitbutler_serde::oid")]
+        sha: git2::Oid,
+    },
+    /// The snapshots of the operations log aren't protected from garbage collection.
+    #[serde(rename_all = "camelCase")]
+    UnreachableOplog {
+        #[serde(with = "gitbutler_serde::oid")]
+        sha: git2::Oid,
+    },
+    /// The parents of the workspace commit aren't the heads of the applied stacks.
+    #[serde(rename_all = "camelCase")]
+    OutdatedWorkspaceCommit {
+        #[serde(with = "gitbutler_serde::oid")]
+        commit: git2::Oid,
+    },
+}
+
+impl Problem {
+    /// Describe how [`repair()`] fixes this problem, or return `None` if it has to be fixed by hand.
+    pub fn repair_description(&self) -> Option<&'static str> {
+        Some(match self {
+            Problem::MissingDefaultTarget | Problem::MissingTargetCommit { .. } => return None,
+            Problem::MissingStackHead { .. } => "remove the stack as its commits are lost",
+            Problem::MissingStackTree { .. } => "reset the uncommitted changes of the stack",
+            Problem::InvalidPatchReference { .. } => {
+                "point the branch at the next branch above it in the stack"
+            }
+            Problem::StaleOwnershipClaim { .. } => "remove the claim",
+            Problem::DuplicateOwnershipClaim { .. } => "leave the file to the other stack",
+            Problem::MissingOplogHead { .. } => "start a new operations log",
+            Problem::UnreachableOplog { .. } => "protect the snapshots again",
+            Problem::OutdatedWorkspaceCommit { .. } => "recreate the workspace commit",
+        })
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::MissingDefaultTarget => write!(f, "no base branch is set"),
+            Problem::MissingTargetCommit { sha } => {
+                write!(f, "the base branch commit {sha} doesn't exist")
+            }
+            Problem::MissingStackHead { name, head, .. } => {
+                write!(f, "the head commit {head} of stack '{name}' doesn't exist")
+            }
+            Problem::MissingStackTree { name, tree, .. } => write!(
+                f,
+                "the tree {tree} with the uncommitted changes of stack '{name}' doesn't exist"
+            ),
+            Problem::InvalidPatchReference {
+                name, reference, ..
+            } => write!(
+                f,
+                "branch '{reference}' doesn't point to a commit in stack '{name}'"
+            ),
+            Problem::StaleOwnershipClaim { name, path, .. } => write!(
+                f,
+                "stack '{name}' claims '{}' which has no uncommitted changes",
+                path.display()
+            ),
+            Problem::DuplicateOwnershipClaim { name, path, .. } => write!(
+                f,
+                "stack '{name}' claims '{}' which is claimed by another stack",
+                path.display()
+            ),
+            Problem::MissingOplogHead { sha } => {
+                write!(f, "the latest snapshot {sha} doesn't exist")
+            }
+            Problem::UnreachableOplog { sha } => write!(
+                f,
+                "the snapshots up to {sha} may be removed by garbage collection"
+            ),
+            Problem::OutdatedWorkspaceCommit { commit } => write!(
+                f,
+                "the workspace commit {commit} doesn't merge the applied stacks"
+            ),
+        }
+    }
+}
+
+/// The result of [`repair()`].
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RepairOutcome {
+    /// The snapshot taken before repairing, which allows undoing the repairs.
+    /// `None` if there was nothing to repair.
+    #[serde(with = "gitbutler_serde::oid_opt")]
+    pub snapshot: Option<git2::Oid>,
+    /// The problems that were repaired.
+    pub repaired: Vec<Problem>,
+    /// The problems that are left, and need to be fixed by hand.
+    pub remaining: Vec<Problem>,
+}
+
+/// Check the GitButler state of the project of `ctx` for inconsistencies, without changing anything.
+pub(crate) fn diagnose(
+    ctx: &CommandContext,
+    _permission: &WorktreeReadPermission,
+) -> Result<Vec<Problem>> {
+    let repo = ctx.repository();
+    let vb_state = ctx.project().virtual_branches();
+    let target = match vb_state.get_default_target() {
+        Ok(target) => target,
+        Err(err)
+            if matches!(
+                err.downcast_ref::<Code>(),
+                Some(Code::DefaultTargetNotFound)
+            ) =>
+        {
+            return Ok(vec![Problem::MissingDefaultTarget]);
+        }
+        Err(err) => return Err(err),
+    };
+    if repo.find_commit(target.sha).is_err() {
+        return Ok(vec![Problem::MissingTargetCommit { sha: target.sha }]);
+    }
+
+    let mut problems = Vec::new();
+    let stacks = vb_state
+        .list_all_branches()?
+        .into_iter()
+        .sorted_by_key(|stack| (!stack.in_workspace, stack.order))
+        .collect_vec();
+    for stack in &stacks {
+        if repo.find_commit(stack.head()).is_err() {
+            problems.push(Problem::MissingStackHead {
+                stack_id: stack.id,
+                name: stack.name.clone(),
+                head: stack.head(),
+            });
+            continue;
+        }
+        if stack.in_workspace && repo.find_tree(stack.tree).is_err() {
+            problems.push(Problem::MissingStackTree {
+                stack_id: stack.id,
+                name: stack.name.clone(),
+                tree: stack.tree,
+            });
+        }
+        if stack.initialized() {
+            for reference in stack.invalid_heads(ctx)? {
+                problems.push(Problem::InvalidPatchReference {
+                    stack_id: stack.id,
+                    name: stack.name.clone(),
+                    reference,
+                });
+            }
+        }
+    }
+
+    let applied_stacks = stacks
+        .iter()
+        .filter(|stack| stack.in_workspace && repo.find_commit(stack.head()).is_ok())
+        .collect_vec();
+    if in_open_workspace_mode(ctx) {
+        problems.extend(diagnose_ownership(ctx, &applied_stacks)?);
+        problems.extend(diagnose_workspace_commit(ctx, target.sha, &applied_stacks)?);
+    }
+
+    if let Some(oplog_head) = ctx.project().oplog_head()? {
+        if repo.find_commit(oplog_head).is_err() {
+            problems.push(Problem::MissingOplogHead { sha: oplog_head });
+        } else if !ctx.project().oplog_head_is_reachable(oplog_head)? {
+            problems.push(Problem::UnreachableOplog { sha: oplog_head });
+        }
+    }
+    Ok(problems)
+}
+
+/// Claims are only valid for files with uncommitted changes, and each file may only be claimed by one stack.
+fn diagnose_ownership(ctx: &CommandContext, applied_stacks: &[&Stack]) -> Result<Vec<Problem>> {
+    let head_commit = ctx.repository().head()?.peel_to_commit()?;
+    let changed_files = gitbutler_diff::workdir(
+        ctx.repository(),
+        head_commit.id(),
+        ctx.project().diff_settings,
+    )
+    .context("failed to diff workdir")?;
+
+    let mut problems = Vec::new();
+    let mut owners = HashMap::new();
+    for stack in applied_stacks {
+        for claim in &stack.ownership.claims {
+            let path = &claim.file_path;
+            if !changed_files.contains_key(path) {
+                problems.push(Problem::StaleOwnershipClaim {
+                    stack_id: stack.id,
+                    name: stack.name.clone(),
+                    path: path.clone(),
+                });
+            } else if let Some(owner_id) = owners.get(path) {
+                problems.push(Problem::DuplicateOwnershipClaim {
+                    stack_id: stack.id,
+                    name: stack.name.clone(),
+                    path: path.clone(),
+                    owner_id: *owner_id,
+                });
+            } else {
+                owners.insert(path.clone(), stack.id);
+            }
+        }
+    }
+    Ok(problems)
+}
+
+/// The workspace commit must merge exactly the heads of the applied stacks, as done by [`update_workspace_commit()`].
+fn diagnose_workspace_commit(
+    ctx: &CommandContext,
+    target_sha: git2::Oid,
+    applied_stacks: &[&Stack],
+) -> Result<Option<Problem>> {
+    let repo = ctx.repository();
+    let head = repo.head()?;
+    if !head
+        .name()
+        .is_some_and(|name| OPEN_WORKSPACE_REFS.contains(&name))
+        || conflicts::is_conflicting(ctx, None)?
+    {
+        return Ok(None);
+    }
+    let workspace_commit = head.peel_to_commit()?;
+    // Commits made on top of the workspace commit are moved to a new stack when the workspace
+    // is opened next, and the commit is recreated then anyway.
+    if !workspace_commit
+        .message()
+        .is_some_and(|message| message.starts_with(GITBUTLER_WORKSPACE_COMMIT_TITLE))
+    {
+        return Ok(None);
+    }
+
+    let mut expected_parents: BTreeSet<_> = applied_stacks
+        .iter()
+        .map(|stack| stack.head())
+        .filter(|head| *head != target_sha)
+        .collect();
+    if expected_parents.is_empty() {
+        expected_parents.insert(target_sha);
+    }
+    let parents: BTreeSet<_> = workspace_commit.parent_ids().collect();
+    Ok(
+        (parents != expected_parents).then(|| Problem::OutdatedWorkspaceCommit {
+            commit: workspace_commit.id(),
+        }),
+    )
+}
+
+/// Repair all problems found by [`diagnose()`] that can be repaired safely, after taking a snapshot so
+/// the repairs can be undone. Nothing is changed if the snapshot can't be taken.
+pub(crate) fn repair(
+    ctx: &CommandContext,
+    perm: &mut WorktreeWritePermission,
+) -> Result<RepairOutcome> {
+    let (repairable, remaining): (Vec<_>, Vec<_>) = diagnose(ctx, perm.read_permission())?
+        .into_iter()
+        .partition(|problem| problem.repair_description().is_some());
+    if repairable.is_empty() {
+        return Ok(RepairOutcome {
+            snapshot: None,
+            repaired: Vec::new(),
+            remaining,
+        });
+    }
+
+    // Taking the snapshot also repairs the operations log, as it becomes the new head and is protected again.
+    let snapshot = ctx
+        .project()
+        .create_snapshot(SnapshotDetails::new(OperationKind::RepairProject), perm)
+        .context("Refusing to repair the project without a snapshot to restore")?;
+
+    let vb_state = ctx.project().virtual_branches();
+    let mut stacks_with_invalid_heads = HashSet::new();
+    let mut needs_workspace_update = false;
+    for problem in &repairable {
+        match problem {
+            Problem::MissingStackHead { stack_id, .. } => {
+                vb_state.delete_branch_entry(stack_id)?;
+                needs_workspace_update = true;
+            }
+            Problem::MissingStackTree { stack_id, .. } => {
+                let mut stack = vb_state.get_branch(*stack_id)?;
+                stack.tree = ctx.repository().find_commit(stack.head())?.tree_id();
+                vb_state.set_branch(stack)?;
+            }
+            Problem::InvalidPatchReference { stack_id, .. } => {
+                stacks_with_invalid_heads.insert(*stack_id);
+            }
+            Problem::StaleOwnershipClaim { stack_id, path, .. }
+            | Problem::DuplicateOwnershipClaim { stack_id, path, .. } => {
+                let mut stack = vb_state.get_branch(*stack_id)?;
+                stack
+                    .ownership
+                    .claims
+                    .retain(|claim| &claim.file_path != path);
+                vb_state.set_branch(stack)?;
+            }
+            Problem::OutdatedWorkspaceCommit { .. } => needs_workspace_update = true,
+            Problem::MissingOplogHead { .. } | Problem::UnreachableOplog { .. } => {}
+            Problem::MissingDefaultTarget | Problem::MissingTargetCommit { .. } => {
+                unreachable!("problems without repair are filtered out")
+            }
+        }
+    }
+    for stack_id in stacks_with_invalid_heads {
+        vb_state.get_branch(stack_id)?.repair_heads(ctx)?;
+    }
+    if needs_workspace_update && in_open_workspace_mode(ctx) {
+        update_workspace_commit(&vb_state, ctx)?;
+    }
+
+    let remaining = diagnose(ctx, perm.read_permission())?;
+    let repaired = repairable
+        .into_iter()
+        .filter(|problem| !remaining.contains(problem))
+        .collect();
+    Ok(RepairOutcome {
+        snapshot: Some(snapshot),
+        repaired,
+        remaining,
+    })
+}
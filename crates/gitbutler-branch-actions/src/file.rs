@@ -6,7 +6,8 @@ use std::{
 use anyhow::{anyhow, Context, Result};
 use gitbutler_cherry_pick::RepositoryExt as _;
 use gitbutler_command_context::CommandContext;
-use gitbutler_diff::FileDiff;
+use gitbutler_diff::{FileDiff, SubmoduleChange};
+use gitbutler_project::DiffSettings;
 use serde::Serialize;
 
 use crate::{
@@ -25,6 +26,7 @@ pub struct RemoteBranchFile {
 pub(crate) fn list_remote_commit_files(
     repository: &git2::Repository,
     commit_id: git2::Oid,
+    diff_settings: DiffSettings,
 ) -> Result<Vec<RemoteBranchFile>> {
     let commit = repository
         .find_commit(commit_id)
@@ -45,7 +47,8 @@ pub(crate) fn list_remote_commit_files(
     let parent_tree = repository
         .find_real_tree(&parent, Default::default())
         .context("failed to get parent tree")?;
-    let diff_files = gitbutler_diff::trees(repository, &parent_tree, &commit_tree, true)?;
+    let diff_files =
+        gitbutler_diff::trees(repository, &parent_tree, &commit_tree, true, diff_settings)?;
 
     Ok(diff_files
         .into_iter()
@@ -79,6 +82,8 @@ pub struct VirtualBranchFile {
     pub conflicted: bool,
     pub binary: bool,
     pub large: bool,
+    /// `true` if this is a submodule whose hunk describes the change of the commit it points to.
+    pub submodule: bool,
 }
 
 pub trait Get<T> {
@@ -106,7 +111,13 @@ pub(crate) fn list_virtual_commit_files(
     let parent_tree = repository
         .find_real_tree(&parent, Default::default())
         .context("failed to get parent tree")?;
-    let diff = gitbutler_diff::trees(ctx.repository(), &parent_tree, &commit_tree, context_lines)?;
+    let diff = gitbutler_diff::trees(
+        ctx.repository(),
+        &parent_tree,
+        &commit_tree,
+        context_lines,
+        ctx.project().diff_settings,
+    )?;
     let hunks_by_filepath = virtual_hunks_by_file_diffs(&ctx.project().path, diff);
     Ok(virtual_hunks_into_virtual_files(ctx, hunks_by_filepath))
 }
@@ -134,6 +145,7 @@ pub(crate) fn virtual_hunks_into_virtual_files(
             let id = path.display().to_string();
             let conflicted = conflicts::is_conflicting(ctx, Some(&path)).unwrap_or(false);
             let binary = hunks.iter().any(|h| h.binary);
+            let submodule = matches!(hunks.as_slice(), [hunk] if !hunk.binary && SubmoduleChange::from_diff(&hunk.diff).is_some());
             let modified_at = hunks.iter().map(|h| h.modified_at).max().unwrap_or(0);
             debug_assert!(hunks.iter().all(|hunk| hunk.file_path == path));
             VirtualBranchFile {
@@ -142,6 +154,7 @@ pub(crate) fn virtual_hunks_into_virtual_files(
                 hunks,
                 binary,
                 large: false,
+                submodule,
                 modified_at,
                 conflicted,
             }
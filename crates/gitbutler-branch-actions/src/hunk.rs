@@ -5,7 +5,7 @@ use std::{
     time::SystemTime,
 };
 
-use gitbutler_diff::{GitHunk, Hunk, HunkHash};
+use gitbutler_diff::{GitHunk, Hunk, HunkHash, MovedBlock};
 use gitbutler_hunk_dependency::locks::HunkLock;
 use gitbutler_serde::BStringForFrontend;
 use itertools::Itertools;
@@ -40,6 +40,8 @@ pub struct VirtualBranchHunk {
     pub change_type: gitbutler_diff::ChangeType,
     /// Indicates that the hunk depends on multiple branches. In this case the hunk cant be moved or comitted.
     pub poisoned: bool,
+    /// Blocks of lines in this hunk that were moved from or to another location.
+    pub moved: Vec<MovedBlock>,
 }
 
 /// Lifecycle
@@ -77,6 +79,7 @@ impl VirtualBranchHunk {
             locked_to: Some(locked_to.clone().into_boxed_slice()),
             change_type: hunk.change_type,
             poisoned: branch_deps_count > 1,
+            moved: hunk.moved,
         }
     }
 }
@@ -91,6 +94,7 @@ impl From<VirtualBranchHunk> for GitHunk {
             diff_lines: val.diff,
             binary: val.binary,
             change_type: val.change_type,
+            moved: val.moved,
         }
     }
 }
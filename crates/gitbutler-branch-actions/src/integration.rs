@@ -1,6 +1,6 @@
 use std::{path::PathBuf, vec};
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use bstr::ByteSlice;
 use gitbutler_branch::BranchCreateRequest;
 use gitbutler_branch::{self, GITBUTLER_WORKSPACE_REFERENCE};
@@ -118,6 +118,40 @@ fn read_workspace_file(path: &PathBuf) -> Result<Option<PreviousHead>> {
     }
 }
 
+/// Fail if another worktree of `repo` has the workspace branch checked out. Worktrees share their branches,
+/// so GitButler can only manage the workspace of one of them at a time.
+fn ensure_workspace_not_checked_out_elsewhere(repo: &git2::Repository) -> Result<()> {
+    let common_dir = repo.commondir();
+    let mut worktrees = vec![(
+        common_dir.to_owned(),
+        common_dir.parent().map(ToOwned::to_owned),
+    )];
+    for name in repo.worktrees()?.iter().flatten() {
+        worktrees.push((
+            common_dir.join("worktrees").join(name),
+            repo.find_worktree(name)
+                .ok()
+                .map(|worktree| worktree.path().to_owned()),
+        ));
+    }
+
+    let workspace_head = format!("ref: {}", *GITBUTLER_WORKSPACE_REFERENCE);
+    for (git_dir, worktree_dir) in worktrees {
+        if git_dir == repo.path() {
+            continue;
+        }
+        let head = std::fs::read_to_string(git_dir.join("HEAD")).unwrap_or_default();
+        if head.trim() == workspace_head {
+            bail!(
+                "The GitButler workspace is already used by the worktree at '{}'. Worktrees share their branches, \
+                 so only one of them can be managed by GitButler at a time.",
+                worktree_dir.unwrap_or(git_dir).display()
+            );
+        }
+    }
+    Ok(())
+}
+
 fn write_workspace_file(head: &git2::Reference, path: PathBuf) -> Result<()> {
     let sha = head.target().unwrap().to_string();
     std::fs::write(path, format!(":{}", sha))?;
@@ -136,6 +170,10 @@ pub fn update_workspace_commit(
 
     // get current repo head for reference
     let head_ref = repo.head()?;
+    let workspace_refname = GITBUTLER_WORKSPACE_REFERENCE.to_string();
+    if head_ref.name() != Some(workspace_refname.as_str()) {
+        ensure_workspace_not_checked_out_elsewhere(repo)?;
+    }
     let workspace_filepath = repo.path().join("workspace");
     let mut prev_branch = read_workspace_file(&workspace_filepath)?;
     if let Some(branch) = &prev_branch {
@@ -224,18 +262,12 @@ pub fn update_workspace_commit(
         parents.iter().collect::<Vec<_>>().as_slice(),
     )?;
 
-    // Create or replace the workspace branch reference, then set as HEAD.
-    repo.reference(
-        &GITBUTLER_WORKSPACE_REFERENCE.clone().to_string(),
-        final_commit,
-        true,
-        "updated workspace commit",
-    )?;
-    repo.set_head(&GITBUTLER_WORKSPACE_REFERENCE.clone().to_string())?;
-
-    let mut index = repo.index()?;
-    index.read_tree(&workspace_tree)?;
-    index.write()?;
+    // Create or replace the workspace branch reference, then set as HEAD. All references are
+    // updated together so they can't disagree about the state of the workspace after a crash.
+    let mut transaction = ctx.project().journal().transaction();
+    transaction
+        .update_reference(&workspace_refname, final_commit, "updated workspace commit")
+        .update_symbolic_reference("HEAD", &workspace_refname);
 
     // finally, update the refs/gitbutler/ heads to the states of the current virtual branches
     for branch in &virtual_branches {
@@ -265,13 +297,17 @@ pub fn update_workspace_commit(
             branch_head = repo.find_commit(branch_head_oid)?;
         }
 
-        repo.reference(
-            &branch.refname()?.to_string(),
+        transaction.update_reference(
+            branch.refname()?.to_string(),
             branch_head.id(),
-            true,
             "update virtual branch",
-        )?;
+        );
     }
+    transaction.commit(repo)?;
+
+    let mut index = repo.index()?;
+    index.read_tree(&workspace_tree)?;
+    index.write()?;
 
     Ok(final_commit)
 }
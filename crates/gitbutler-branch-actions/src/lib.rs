@@ -2,17 +2,18 @@
 mod actions;
 // This is our API
 pub use actions::{
-    amend, can_apply_remote_branch, create_commit, create_virtual_branch,
-    create_virtual_branch_from_branch, delete_local_branch, fetch_from_remotes, find_commit,
-    get_base_branch_data, get_remote_branch_data, get_uncommited_files,
+    amend, autosquash, can_apply_remote_branch, create_commit, create_virtual_branch,
+    create_virtual_branch_from_branch, delete_local_branch, diagnose_project, fetch_from_remotes,
+    find_commit, get_base_branch_data, get_remote_branch_data, get_uncommited_files,
     get_uncommited_files_reusable, insert_blank_commit, integrate_upstream,
     integrate_upstream_commits, list_local_branches, list_remote_commit_files,
     list_virtual_branches, list_virtual_branches_cached, move_commit, move_commit_file,
-    push_base_branch, push_virtual_branch, reorder_commit, reorder_stack, reset_files,
-    reset_virtual_branch, resolve_upstream_integration, save_and_unapply_virutal_branch,
-    set_base_branch, set_target_push_remote, squash, unapply_ownership,
-    unapply_without_saving_virtual_branch, undo_commit, update_branch_order, update_commit_message,
-    update_virtual_branch, upstream_integration_statuses,
+    move_commit_hunks, push_base_branch, push_virtual_branch, reorder_commit, reorder_stack,
+    repair_project, reset_files, reset_virtual_branch, resolve_upstream_integration,
+    save_and_unapply_virutal_branch, set_base_branch, set_target_push_remote, split_commit, squash,
+    unapply_ownership, unapply_without_saving_virtual_branch, undo_commit, update_branch_order,
+    update_commit_message, update_uncommited_files_reusable, update_virtual_branch,
+    upstream_integration_preview, upstream_integration_statuses,
 };
 
 mod r#virtual;
@@ -36,6 +37,9 @@ pub mod upstream_integration;
 mod integration;
 pub use integration::{update_workspace_commit, verify_branch};
 
+mod doctor;
+pub use doctor::{Problem, RepairOutcome};
+
 mod file;
 pub use file::{Get, RemoteBranchFile};
 
@@ -44,12 +48,17 @@ pub use remote::{RemoteBranch, RemoteBranchData, RemoteCommit};
 
 pub mod conflicts;
 
+mod autosquash;
 pub mod branch_trees;
 pub mod branch_upstream_integration;
+mod move_commit_hunks;
 mod move_commits;
+mod push_validation;
 pub mod reorder;
 pub use reorder::{SeriesOrder, StackOrder};
 mod reorder_commits;
+mod split_commit;
+pub use split_commit::CommitSplit;
 mod undo_commit;
 
 mod author;
@@ -0,0 +1,298 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use anyhow::{bail, Context as _, Result};
+use bstr::ByteSlice as _;
+use gitbutler_cherry_pick::RepositoryExt as _;
+use gitbutler_command_context::CommandContext;
+use gitbutler_commit::{commit_ext::CommitExt as _, commit_headers::HasCommitHeaders as _};
+use gitbutler_diff::GitHunk;
+use gitbutler_hunk_dependency::{InputCommit, InputDiff, InputFile, InputStack, WorkspaceRanges};
+use gitbutler_repo::{rebase::cherry_rebase_group, LogUntil, RepositoryExt as _};
+use gitbutler_stack::{BranchOwnershipClaims, StackId};
+
+use crate::VirtualBranchesExt as _;
+
+/// Moves the hunks of `from_commit_id` that are claimed by `ownership` into `to_commit_id`.
+///
+/// Both commits have to be part of the branch, but can be in different series of it. All commits
+/// in between are rebased, and the moved hunks may not depend on any of them (when moving down),
+/// nor may any of them depend on the moved hunks (when moving up).
+///
+/// Returns the id of the commit the hunks were moved into.
+pub(crate) fn move_commit_hunks(
+    ctx: &CommandContext,
+    branch_id: StackId,
+    from_commit_id: git2::Oid,
+    to_commit_id: git2::Oid,
+    ownership: &BranchOwnershipClaims,
+) -> Result<git2::Oid> {
+    ctx.assure_resolved()?;
+
+    if from_commit_id == to_commit_id {
+        bail!("Can not move hunks into the commit they are in");
+    }
+
+    let repository = ctx.repository();
+    let vb_state = ctx.project().virtual_branches();
+    let mut branch = vb_state.get_branch_in_workspace(branch_id)?;
+    let default_target = vb_state.get_default_target()?;
+
+    let branch_commits =
+        repository.l(branch.head(), LogUntil::Commit(default_target.sha), false)?;
+    let (Some(from_position), Some(to_position)) = (
+        branch_commits.iter().position(|id| *id == from_commit_id),
+        branch_commits.iter().position(|id| *id == to_commit_id),
+    ) else {
+        bail!("Both commits must be in the branch");
+    };
+
+    let from_commit = repository.find_commit(from_commit_id)?;
+    let to_commit = repository.find_commit(to_commit_id)?;
+    for commit in [&from_commit, &to_commit] {
+        if commit.is_conflicted() {
+            bail!("Can not move hunks of conflicted commit {}", commit.id());
+        }
+        if commit.parent_count() != 1 {
+            bail!("Can not move hunks of merge commit {}", commit.id());
+        }
+    }
+
+    let from_parent = from_commit.parent(0)?;
+    let from_diffs = gitbutler_diff::trees(
+        repository,
+        &from_parent.tree()?,
+        &from_commit.tree()?,
+        true,
+        ctx.project().diff_settings,
+    )
+    .context("failed to diff trees")?;
+
+    // Split the patch of the "from" commit into the hunks we move and the ones we keep.
+    let mut hunks_to_move = HashMap::<PathBuf, Vec<GitHunk>>::new();
+    let mut hunks_to_keep = HashMap::<PathBuf, Vec<GitHunk>>::new();
+    for (path, file_diff) in &from_diffs {
+        for hunk in &file_diff.hunks {
+            let claimed = ownership.claims.iter().any(|claim| {
+                claim.file_path == *path
+                    && claim.hunks.iter().any(|claimed_hunk| {
+                        claimed_hunk.start == hunk.new_start
+                            && claimed_hunk.end == hunk.new_start + hunk.new_lines
+                    })
+            });
+            let hunks = if claimed {
+                &mut hunks_to_move
+            } else {
+                &mut hunks_to_keep
+            };
+            hunks.entry(path.clone()).or_default().push(hunk.clone());
+        }
+    }
+    if hunks_to_move.is_empty() {
+        bail!("target ownership not found");
+    }
+    if hunks_to_keep.is_empty() {
+        bail!("Can not move all changes out of a commit, squash it instead");
+    }
+
+    // Commits are listed from the head down, so a higher position is lower in the history.
+    let moving_down = to_position > from_position;
+    if moving_down {
+        let between = repository.l(from_parent.id(), LogUntil::Commit(to_commit_id), false)?;
+        ensure_independent_of(ctx, branch_id, &between, &hunks_to_move)?;
+    } else {
+        let between = repository.l(
+            to_commit.parent_id(0)?,
+            LogUntil::Commit(from_commit_id),
+            false,
+        )?;
+        ensure_not_depended_on(ctx, branch_id, &from_commit, &between, &hunks_to_move)?;
+    }
+
+    let from_tree = from_commit.tree()?;
+    let stripped_tree_id = gitbutler_diff::write::hunks_onto_tree_in_memory(
+        repository,
+        &from_parent.tree()?,
+        &from_tree,
+        &hunks_to_keep,
+    )?;
+    let stripped_tree = repository.find_tree(stripped_tree_id)?;
+
+    let new_to_commit_id = if moving_down {
+        // The rebase of the "from" commit drops the hunks, as they are already part of its new base.
+        let new_to_commit_id =
+            commit_with_moved_hunks(repository, &to_commit, &stripped_tree, &from_tree)?;
+        let ids_to_rebase = repository.l(branch.head(), LogUntil::Commit(to_commit_id), false)?;
+        let new_head = cherry_rebase_group(repository, new_to_commit_id, &ids_to_rebase)?;
+        branch.set_stack_head(ctx, new_head, None)?;
+        new_to_commit_id
+    } else {
+        let new_from_commit_id = repository
+            .commit_with_signature(
+                None,
+                &from_commit.author(),
+                &from_commit.committer(),
+                &from_commit.message_bstr().to_str_lossy(),
+                &stripped_tree,
+                &[&from_parent],
+                from_commit.gitbutler_headers(),
+            )
+            .context("failed to commit")?;
+
+        let between = repository.l(
+            to_commit.parent_id(0)?,
+            LogUntil::Commit(from_commit_id),
+            false,
+        )?;
+        let new_parent_id = cherry_rebase_group(repository, new_from_commit_id, &between)?;
+        let rebased_to_commit_id = cherry_rebase_group(repository, new_parent_id, &[to_commit_id])?;
+        if rebased_to_commit_id == new_parent_id {
+            bail!("commit {to_commit_id} became empty while rebasing");
+        }
+
+        let rebased_to_commit = repository.find_commit(rebased_to_commit_id)?;
+        if rebased_to_commit.is_conflicted() {
+            bail!("commit {to_commit_id} conflicts with the remaining changes");
+        }
+        let new_to_commit_id =
+            commit_with_moved_hunks(repository, &rebased_to_commit, &stripped_tree, &from_tree)?;
+        let ids_to_rebase = repository.l(branch.head(), LogUntil::Commit(to_commit_id), false)?;
+        let new_head = cherry_rebase_group(repository, new_to_commit_id, &ids_to_rebase)?;
+        branch.set_stack_head(ctx, new_head, None)?;
+        new_to_commit_id
+    };
+
+    crate::integration::update_workspace_commit(&vb_state, ctx)
+        .context("failed to update gitbutler workspace")?;
+
+    Ok(new_to_commit_id)
+}
+
+/// Rewrites `commit` with the changes between `stripped_tree` and `original_tree`, i.e. the moved hunks.
+fn commit_with_moved_hunks(
+    repository: &git2::Repository,
+    commit: &git2::Commit,
+    stripped_tree: &git2::Tree,
+    original_tree: &git2::Tree,
+) -> Result<git2::Oid> {
+    let mut merge_index =
+        repository.merge_trees_gitbutler(stripped_tree, &commit.tree()?, original_tree, None)?;
+    if merge_index.has_conflicts() {
+        bail!("The hunks conflict with commit {}", commit.id());
+    }
+    let tree_id = merge_index.write_tree_to(repository)?;
+    let parents = commit.parents().collect::<Vec<_>>();
+    repository
+        .commit_with_signature(
+            None,
+            &commit.author(),
+            &commit.committer(),
+            &commit.message_bstr().to_str_lossy(),
+            &repository.find_tree(tree_id)?,
+            &parents.iter().collect::<Vec<_>>(),
+            commit.gitbutler_headers(),
+        )
+        .context("failed to commit")
+}
+
+/// Fails if any of `hunks`, which apply on top of `commit_ids`, touch lines changed by one of them.
+fn ensure_independent_of(
+    ctx: &CommandContext,
+    branch_id: StackId,
+    commit_ids: &[git2::Oid],
+    hunks: &HashMap<PathBuf, Vec<GitHunk>>,
+) -> Result<()> {
+    let commits = commit_ids
+        .iter()
+        .rev()
+        .map(|id| input_commit(ctx, &ctx.repository().find_commit(*id)?))
+        .collect::<Result<Vec<_>>>()?;
+    let ranges = WorkspaceRanges::create(vec![InputStack {
+        stack_id: branch_id,
+        commits,
+    }])?;
+    for (path, hunks) in hunks {
+        for hunk in hunks {
+            if let Some(dependencies) = ranges.intersection(path, hunk.old_start, hunk.old_lines) {
+                bail!(
+                    "Hunk at {}:{} depends on commit {}",
+                    path.display(),
+                    hunk.new_start,
+                    dependencies[0].commit_id
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Fails if any of `commit_ids`, which are on top of `commit`, touch lines changed by `hunks` of it.
+fn ensure_not_depended_on(
+    ctx: &CommandContext,
+    branch_id: StackId,
+    commit: &git2::Commit,
+    commit_ids: &[git2::Oid],
+    hunks: &HashMap<PathBuf, Vec<GitHunk>>,
+) -> Result<()> {
+    let mut commits = vec![InputCommit {
+        commit_id: commit.id(),
+        files: hunks
+            .iter()
+            .map(|(path, hunks)| InputFile {
+                path: path.clone(),
+                diffs: hunks.iter().map(input_diff).collect(),
+            })
+            .collect(),
+    }];
+    for id in commit_ids.iter().rev() {
+        let next = input_commit(ctx, &ctx.repository().find_commit(*id)?)?;
+        let ranges = WorkspaceRanges::create(vec![InputStack {
+            stack_id: branch_id,
+            commits: commits.clone(),
+        }])?;
+        for file in &next.files {
+            for diff in &file.diffs {
+                let depends_on_hunks = ranges
+                    .intersection(&file.path, diff.old_start, diff.old_lines)
+                    .is_some_and(|dependencies| {
+                        dependencies
+                            .iter()
+                            .any(|dependency| dependency.commit_id == commit.id())
+                    });
+                if depends_on_hunks {
+                    bail!("Commit {} depends on the hunks being moved", next.commit_id);
+                }
+            }
+        }
+        commits.push(next);
+    }
+    Ok(())
+}
+
+fn input_commit(ctx: &CommandContext, commit: &git2::Commit) -> Result<InputCommit> {
+    let diffs = gitbutler_diff::trees(
+        ctx.repository(),
+        &commit.parent(0)?.tree()?,
+        &commit.tree()?,
+        false,
+        ctx.project().diff_settings,
+    )?;
+    Ok(InputCommit {
+        commit_id: commit.id(),
+        files: diffs
+            .into_iter()
+            .map(|(path, file_diff)| InputFile {
+                path,
+                diffs: file_diff.hunks.iter().map(input_diff).collect(),
+            })
+            .collect(),
+    })
+}
+
+fn input_diff(hunk: &GitHunk) -> InputDiff {
+    InputDiff {
+        old_start: hunk.old_start,
+        old_lines: hunk.old_lines,
+        new_start: hunk.new_start,
+        new_lines: hunk.new_lines,
+    }
+}
@@ -5,7 +5,7 @@ use crate::{
 use anyhow::{anyhow, bail, Context, Result};
 use gitbutler_command_context::CommandContext;
 use gitbutler_commit::commit_ext::CommitExt;
-use gitbutler_project::access::WorktreeWritePermission;
+use gitbutler_project::{access::WorktreeWritePermission, DiffSettings};
 use gitbutler_repo::{rebase::cherry_rebase_group, LogUntil, RepositoryExt};
 use gitbutler_stack::{OwnershipClaim, StackId};
 use std::collections::HashMap;
@@ -60,6 +60,7 @@ pub(crate) fn move_commit(
         &source_commit_parent_tree,
         &source_commit_tree,
         true,
+        ctx.project().diff_settings,
     )?;
 
     let default_target = vb_state.get_default_target()?;
@@ -93,8 +94,12 @@ pub(crate) fn move_commit(
         )?);
     }
 
-    let is_ancestor_locked =
-        check_source_lock_to_commits(ctx.repository(), &ancestor_commits, &source_commit_diff);
+    let is_ancestor_locked = check_source_lock_to_commits(
+        ctx.repository(),
+        &ancestor_commits,
+        &source_commit_diff,
+        ctx.project().diff_settings,
+    );
 
     if is_source_locked {
         bail!("the source branch contains hunks locked to the target commit")
@@ -109,8 +114,12 @@ pub(crate) fn move_commit(
         // the source commit and its first descendant
         let mut commits_to_check = commits_to_check.clone();
         commits_to_check.push(source_commit.clone());
-        let is_descendant_locked =
-            check_source_lock_to_commits(ctx.repository(), &commits_to_check, &source_commit_diff);
+        let is_descendant_locked = check_source_lock_to_commits(
+            ctx.repository(),
+            &commits_to_check,
+            &source_commit_diff,
+            ctx.project().diff_settings,
+        );
 
         if is_descendant_locked {
             bail!("the target commit contains hunks locked to its descendants")
@@ -205,6 +214,7 @@ fn check_source_lock_to_commits(
     repository: &git2::Repository,
     commits: &Vec<git2::Commit>,
     source_commit_diff: &HashMap<std::path::PathBuf, Vec<gitbutler_diff::GitHunk>>,
+    diff_settings: DiffSettings,
 ) -> bool {
     let mut previous: Option<&git2::Commit> = None;
 
@@ -219,7 +229,7 @@ fn check_source_lock_to_commits(
         let old_tree = commit.tree().unwrap();
         let new_tree = previous_commit.tree().unwrap();
 
-        let diff = gitbutler_diff::trees(repository, &old_tree, &new_tree, true);
+        let diff = gitbutler_diff::trees(repository, &old_tree, &new_tree, true, diff_settings);
 
         if diff.is_err() {
             previous = Some(commit);
@@ -292,11 +302,13 @@ mod tests {
                 locked_to: None,
                 change_type: gitbutler_diff::ChangeType::Modified,
                 poisoned: false,
+                moved: Vec::new(),
             }],
             modified_at: 0,
             conflicted: false,
             binary: false,
             large: false,
+            submodule: false,
         }];
         source_branch_non_comitted_files
     }
@@ -316,6 +328,7 @@ mod tests {
                 diff_lines: "".into(),
                 binary: false,
                 change_type: gitbutler_diff::ChangeType::Modified,
+                moved: Vec::new(),
             }],
         )]
         .into_iter()
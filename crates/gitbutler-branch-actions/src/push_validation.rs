@@ -0,0 +1,108 @@
+use anyhow::{anyhow, Context, Result};
+use bstr::ByteSlice;
+use gitbutler_cherry_pick::ConflictedTreeKey;
+use gitbutler_commit::commit_ext::CommitExt;
+use gitbutler_error::error::{self, Code};
+use gitbutler_repo::{rebase::ConflictEntries, LogUntil, RepositoryExt as _};
+
+/// Fail with [`Code::PushConflictedCommits`] if any commit from `head` down to, but excluding, `base`
+/// is conflicted, listing the offending commits along with their conflicted files.
+///
+/// Conflicted commits store GitButler's representation of the conflict as their tree, which must never
+/// end up on a remote.
+pub(crate) fn ensure_no_conflicted_commits(
+    repo: &git2::Repository,
+    branch_name: &str,
+    head: git2::Oid,
+    base: git2::Oid,
+) -> Result<()> {
+    let conflicted = repo
+        .log(head, LogUntil::Commit(base), false)?
+        .into_iter()
+        .filter(|commit| commit.is_conflicted())
+        .map(|commit| describe_conflicted_commit(repo, &commit))
+        .collect::<Result<Vec<_>>>()?;
+    if conflicted.is_empty() {
+        return Ok(());
+    }
+
+    let message = format!(
+        "Branch '{branch_name}' can't be pushed as it contains conflicted commits. Resolve them first:\n{}",
+        conflicted.join("\n")
+    );
+    Err(anyhow!(
+        "refusing to push {} conflicted commit(s)",
+        conflicted.len()
+    ))
+    .context(error::Context::new(message).with_code(Code::PushConflictedCommits))
+}
+
+fn describe_conflicted_commit(repo: &git2::Repository, commit: &git2::Commit) -> Result<String> {
+    let conflicted_files = commit
+        .tree()?
+        .get_name(&ConflictedTreeKey::ConflictFiles)
+        .map(|entry| repo.find_blob(entry.id()))
+        .transpose()?
+        .and_then(|blob| toml::from_str::<ConflictEntries>(&blob.content().to_str_lossy()).ok())
+        .unwrap_or_default();
+    let paths = conflicted_files
+        .paths()
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>();
+    Ok(format!(
+        "  {} {} ({})",
+        &commit.id().to_string()[..7],
+        commit.summary().unwrap_or_default(),
+        paths.join(", ")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use gitbutler_error::error::{AnyhowContextExt as _, Code};
+    use gitbutler_repo::rebase::cherry_rebase_group;
+    use gitbutler_testsupport::testing_repository::TestingRepository;
+
+    use super::ensure_no_conflicted_commits;
+
+    #[test]
+    fn unconflicted_commits_can_be_pushed() {
+        let test_repository = TestingRepository::open();
+        let a = test_repository.commit_tree(None, &[("foo.txt", "a")]);
+        let b = test_repository.commit_tree(Some(&a), &[("foo.txt", "b")]);
+
+        ensure_no_conflicted_commits(&test_repository.repository, "feature", b.id(), a.id())
+            .unwrap();
+    }
+
+    #[test]
+    fn conflicted_commits_are_refused() {
+        let test_repository = TestingRepository::open();
+        let a = test_repository.commit_tree(None, &[("foo.txt", "a")]);
+        let b = test_repository.commit_tree(Some(&a), &[("foo.txt", "b")]);
+        let c = test_repository.commit_tree(Some(&a), &[("foo.txt", "c")]);
+        let conflicted =
+            cherry_rebase_group(&test_repository.repository, b.id(), &[c.id()]).unwrap();
+
+        let err = ensure_no_conflicted_commits(
+            &test_repository.repository,
+            "feature",
+            conflicted,
+            a.id(),
+        )
+        .unwrap_err();
+        let context = err.custom_context().expect("a code is attached");
+        assert_eq!(context.code, Code::PushConflictedCommits);
+        let message = context.message.unwrap();
+        assert!(
+            message.contains(&format!("{} ", &conflicted.to_string()[..7])),
+            "{message}"
+        );
+        assert!(message.contains("(foo.txt)"), "{message}");
+
+        // The conflicted commit isn't part of what's pushed.
+        ensure_no_conflicted_commits(&test_repository.repository, "feature", b.id(), a.id())
+            .unwrap();
+    }
+}
@@ -6,7 +6,7 @@ use gitbutler_branch::ReferenceExt;
 use gitbutler_command_context::CommandContext;
 use gitbutler_commit::commit_ext::CommitExt;
 use gitbutler_reference::{Refname, RemoteRefname};
-use gitbutler_repo::{LogUntil, RepositoryExt};
+use gitbutler_repo::{LogUntil, RepositoryExt, SignatureVerification};
 use gitbutler_repo_actions::RepoActionsExt;
 use gitbutler_serde::BStringForFrontend;
 use gitbutler_stack::{Target, VirtualBranchesHandle};
@@ -58,6 +58,8 @@ pub struct RemoteCommit {
     #[serde(with = "gitbutler_serde::oid_vec")]
     pub parent_ids: Vec<git2::Oid>,
     pub conflicted: bool,
+    /// The verification status of the signature, if the commit is signed.
+    pub signature: Option<SignatureVerification>,
 }
 
 /// Return information on all local branches, while skipping gitbutler-specific branches in `refs/heads`.
@@ -127,7 +129,7 @@ pub(crate) fn get_commit_data(
             }
         }
     };
-    Ok(Some(commit_to_remote_commit(&commit)))
+    Ok(Some(commit_to_remote_commit(ctx.repository(), &commit)))
 }
 
 pub(crate) fn branch_to_remote_branch(
@@ -192,7 +194,7 @@ pub(crate) fn branch_to_remote_branch_data(
                 behind: count_behind,
                 commits: ahead
                     .into_iter()
-                    .map(|commit| commit_to_remote_commit(&commit))
+                    .map(|commit| commit_to_remote_commit(ctx.repository(), &commit))
                     .collect::<Vec<_>>(),
                 fork_point,
             })
@@ -200,7 +202,10 @@ pub(crate) fn branch_to_remote_branch_data(
         .transpose()
 }
 
-pub(crate) fn commit_to_remote_commit(commit: &git2::Commit) -> RemoteCommit {
+pub(crate) fn commit_to_remote_commit(
+    repository: &git2::Repository,
+    commit: &git2::Commit,
+) -> RemoteCommit {
     let parent_ids = commit.parents().map(|c| c.id()).collect();
     RemoteCommit {
         id: commit.id().to_string(),
@@ -210,6 +215,7 @@ pub(crate) fn commit_to_remote_commit(commit: &git2::Commit) -> RemoteCommit {
         change_id: commit.change_id(),
         parent_ids,
         conflicted: commit.is_conflicted(),
+        signature: repository.verify_commit_signature(commit),
     }
 }
 
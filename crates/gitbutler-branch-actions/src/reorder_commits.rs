@@ -1,7 +1,7 @@
 use anyhow::{bail, Context as _, Result};
 use gitbutler_command_context::CommandContext;
 use gitbutler_project::access::WorktreeWritePermission;
-use gitbutler_repo::{rebase::cherry_rebase_group, LogUntil, RepositoryExt as _};
+use gitbutler_repo::{rebase::cherry_rebase_group_preserving_merges, LogUntil, RepositoryExt as _};
 use gitbutler_stack::StackId;
 
 use crate::{
@@ -140,10 +140,11 @@ fn inner_reorder_commit(
     let reordered_commits = reorder_commit_list(subject_commit, offset, branch_commits)?;
 
     // Rebase branch commits
-    // We are passing all the commits to the cherry_rebase_group funcion, but
-    // this is not a concern as it will verbaitm copy any commits that have
-    // not had their parents changed.
-    let new_head_oid = cherry_rebase_group(repository, base_commit, &reordered_commits)?;
+    // We are passing all the commits to the cherry_rebase_group_preserving_merges
+    // funcion, but this is not a concern as it will verbaitm copy any commits that
+    // have not had their parents changed.
+    let new_head_oid =
+        cherry_rebase_group_preserving_merges(repository, base_commit, &reordered_commits)?;
 
     // Calculate the new head and tree
     let BranchHeadAndTree {
@@ -0,0 +1,171 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use anyhow::{bail, Context as _, Result};
+use gitbutler_command_context::CommandContext;
+use gitbutler_commit::{
+    commit_ext::CommitExt as _,
+    commit_headers::{CommitHeadersV2, HasCommitHeaders as _},
+};
+use gitbutler_diff::GitHunk;
+use gitbutler_repo::{rebase::cherry_rebase_group, LogUntil, RepositoryExt as _};
+use gitbutler_stack::{BranchOwnershipClaims, StackId};
+use serde::Deserialize;
+
+use crate::VirtualBranchesExt as _;
+
+/// One of the commits a commit is split into.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CommitSplit {
+    /// The hunks of the original commit that go into this commit.
+    pub ownership: BranchOwnershipClaims,
+    /// The message of the new commit.
+    pub message: String,
+}
+
+impl CommitSplit {
+    pub fn new(ownership: BranchOwnershipClaims, message: impl Into<String>) -> Self {
+        CommitSplit {
+            ownership,
+            message: message.into(),
+        }
+    }
+}
+
+/// Splits a commit into consecutive commits, one for each of the `splits` in order, each with the
+/// hunks of the original commit that are claimed by its ownership. Hunks that aren't claimed
+/// end up in the last commit, so it always has the same tree as the original commit.
+///
+/// The first commit keeps the change-id of the original commit, and all commits above are rebased
+/// onto the last one.
+///
+/// Returns the ids of the new commits, from the first to the last.
+pub(crate) fn split_commit(
+    ctx: &CommandContext,
+    branch_id: StackId,
+    commit_oid: git2::Oid,
+    splits: &[CommitSplit],
+) -> Result<Vec<git2::Oid>> {
+    ctx.assure_resolved()?;
+
+    if splits.len() < 2 {
+        bail!("A commit must be split into at least two commits");
+    }
+
+    let repository = ctx.repository();
+    let vb_state = ctx.project().virtual_branches();
+    let mut branch = vb_state.get_branch_in_workspace(branch_id)?;
+    let default_target = vb_state.get_default_target()?;
+
+    let branch_commits =
+        repository.l(branch.head(), LogUntil::Commit(default_target.sha), false)?;
+    if !branch_commits.contains(&commit_oid) {
+        bail!("commit {commit_oid} not in the branch")
+    }
+
+    let commit = repository.find_commit(commit_oid)?;
+    if commit.is_conflicted() {
+        bail!("Can not split a conflicted commit");
+    }
+    if commit.parent_count() != 1 {
+        bail!("Can only split commits with a single parent");
+    }
+    let parent = commit.parent(0)?;
+    let parent_tree = parent.tree()?;
+    let commit_tree = commit.tree()?;
+
+    let commit_diffs = gitbutler_diff::trees(
+        repository,
+        &parent_tree,
+        &commit_tree,
+        true,
+        ctx.project().diff_settings,
+    )
+    .context("failed to diff trees")?;
+
+    // Assign each hunk to the first split claiming it, or the last one.
+    let mut hunks_by_split = vec![HashMap::<PathBuf, Vec<GitHunk>>::new(); splits.len()];
+    for (path, file_diff) in &commit_diffs {
+        for hunk in &file_diff.hunks {
+            let mut claimed_by = splits.iter().enumerate().filter(|(_, split)| {
+                split.ownership.claims.iter().any(|claim| {
+                    claim.file_path == *path
+                        && claim.hunks.iter().any(|claimed_hunk| {
+                            claimed_hunk.start == hunk.new_start
+                                && claimed_hunk.end == hunk.new_start + hunk.new_lines
+                        })
+                })
+            });
+            let idx = match (claimed_by.next(), claimed_by.next()) {
+                (Some((idx, _)), None) => idx,
+                (None, _) => splits.len() - 1,
+                (Some(_), Some(_)) => bail!(
+                    "hunk at {}:{} is claimed by more than one commit",
+                    path.display(),
+                    hunk.new_start
+                ),
+            };
+            hunks_by_split[idx]
+                .entry(path.clone())
+                .or_default()
+                .push(hunk.clone());
+        }
+    }
+    if let Some(idx) = hunks_by_split.iter().position(HashMap::is_empty) {
+        bail!("split {} doesn't contain any changes", idx + 1);
+    }
+
+    let mut new_commits = Vec::with_capacity(splits.len());
+    let mut hunks_so_far = HashMap::<PathBuf, Vec<GitHunk>>::new();
+    let mut head = parent;
+    for (idx, (split, hunks)) in splits.iter().zip(hunks_by_split).enumerate() {
+        let is_last = idx == splits.len() - 1;
+        let tree = if is_last {
+            commit_tree.clone()
+        } else {
+            // Hunks are relative to the parent, so each commit applies all hunks up to it.
+            for (path, hunks) in hunks {
+                hunks_so_far.entry(path).or_default().extend(hunks);
+            }
+            let tree_id = gitbutler_diff::write::hunks_onto_tree_in_memory(
+                repository,
+                &parent_tree,
+                &commit_tree,
+                &hunks_so_far,
+            )?;
+            repository.find_tree(tree_id)?
+        };
+
+        let commit_headers = if idx == 0 {
+            commit.gitbutler_headers()
+        } else {
+            Some(CommitHeadersV2::new())
+        };
+        let new_commit_oid = repository
+            .commit_with_signature(
+                None,
+                &commit.author(),
+                &commit.committer(),
+                &split.message,
+                &tree,
+                &[&head],
+                commit_headers,
+            )
+            .context("failed to commit")?;
+        new_commits.push(new_commit_oid);
+        head = repository.find_commit(new_commit_oid)?;
+    }
+
+    let ids_to_rebase = repository.l(branch.head(), LogUntil::Commit(commit_oid), false)?;
+    let new_head = cherry_rebase_group(repository, head.id(), &ids_to_rebase)?;
+    branch.set_stack_head(ctx, new_head, None)?;
+
+    // Series that ended with the original commit end with the last new commit now.
+    let first_commit = repository.find_commit(new_commits[0])?;
+    branch.replace_head(ctx, &first_commit, &head)?;
+
+    crate::integration::update_workspace_commit(&vb_state, ctx)
+        .context("failed to update gitbutler workspace")?;
+
+    Ok(new_commits)
+}
@@ -16,6 +16,7 @@ use serde::{Deserialize, Serialize};
 use crate::{
     actions::open_with_verify,
     commit::{commit_to_vbranch_commit, VirtualBranchCommit},
+    push_validation::ensure_no_conflicted_commits,
     r#virtual::{CommitData, IsCommitIntegrated, PatchSeries},
     VirtualBranchesExt,
 };
@@ -181,7 +182,8 @@ pub fn push_stack(project: &Project, branch_id: StackId, with_force: bool) -> Re
 
     let repo = ctx.repository();
     let default_target = state.get_default_target()?;
-    let merge_base = repo.find_commit(repo.merge_base(stack.head(), default_target.sha)?)?;
+    let merge_base_id = repo.merge_base(stack.head(), default_target.sha)?;
+    let merge_base = repo.find_commit(merge_base_id)?;
     let merge_base = if let Some(change_id) = merge_base.change_id() {
         CommitOrChangeId::ChangeId(change_id)
     } else {
@@ -192,6 +194,7 @@ pub fn push_stack(project: &Project, branch_id: StackId, with_force: bool) -> Re
     ctx.fetch(&default_target.push_remote_name(), None)?;
     let check_commit = IsCommitIntegrated::new(ctx, &default_target)?;
     let stack_series = stack.list_series(ctx)?;
+    let mut series_to_push = Vec::new();
     for series in stack_series {
         if series.head.target == merge_base {
             // Nothing to push for this one
@@ -201,7 +204,12 @@ pub fn push_stack(project: &Project, branch_id: StackId, with_force: bool) -> Re
             // Already integrated, nothing to push
             continue;
         }
-        let push_details = stack.push_details(ctx, series.head.name)?;
+        let push_details = stack.push_details(ctx, series.head.name.clone())?;
+        // Validate all series before pushing any of them.
+        ensure_no_conflicted_commits(repo, &series.head.name, push_details.head, merge_base_id)?;
+        series_to_push.push(push_details);
+    }
+    for push_details in series_to_push {
         ctx.push(
             push_details.head,
             &push_details.remote_refname,
@@ -21,7 +21,7 @@ use gitbutler_hunk_dependency::{
     InputStack,
 };
 use gitbutler_operating_modes::assure_open_workspace_mode;
-use gitbutler_project::access::WorktreeWritePermission;
+use gitbutler_project::{access::WorktreeWritePermission, DiffSettings};
 use gitbutler_repo::{LogUntil, RepositoryExt as _};
 use gitbutler_stack::{BranchOwnershipClaims, OwnershipClaim, Stack, StackId};
 use itertools::Itertools;
@@ -40,7 +40,7 @@ pub fn get_applied_status(
     ctx: &CommandContext,
     perm: Option<&mut WorktreeWritePermission>,
 ) -> Result<VirtualBranchesStatus> {
-    get_applied_status_cached(ctx, perm, None)
+    get_applied_status_cached(ctx, perm, None, None)
 }
 
 /// Returns branches and their associated file changes, in addition to a list
@@ -48,12 +48,15 @@ pub fn get_applied_status(
 /// `worktree_changes` are all changed files against the current `HEAD^{tree}` and index
 /// against the current working tree directory, and it's used to avoid double-computing
 /// this expensive information.
+/// `changed_paths` are the paths whose changes differ from the last time the status was computed,
+/// or `None` if any of them may differ. Only the trees of the branches at these paths are rewritten.
 // TODO(kv): make this side effect free
-#[instrument(level = tracing::Level::DEBUG, skip(ctx, perm, worktree_changes))]
+#[instrument(level = tracing::Level::DEBUG, skip(ctx, perm, worktree_changes, changed_paths))]
 pub fn get_applied_status_cached(
     ctx: &CommandContext,
     perm: Option<&mut WorktreeWritePermission>,
     worktree_changes: Option<gitbutler_diff::DiffByPathMap>,
+    changed_paths: Option<&[PathBuf]>,
 ) -> Result<VirtualBranchesStatus> {
     assure_open_workspace_mode(ctx).context("ng applied status requires open workspace mode")?;
     let workspace_head = get_workspace_head(ctx)?;
@@ -62,8 +65,12 @@ pub fn get_applied_status_cached(
         .virtual_branches()
         .list_branches_in_workspace()?;
     let base_file_diffs = worktree_changes.map(Ok).unwrap_or_else(|| {
-        gitbutler_diff::workdir(ctx.repository(), workspace_head.to_owned())
-            .context("failed to diff workdir")
+        gitbutler_diff::workdir(
+            ctx.repository(),
+            workspace_head.to_owned(),
+            ctx.project().diff_settings,
+        )
+        .context("failed to diff workdir")
     })?;
 
     let mut skipped_files: Vec<gitbutler_diff::FileDiff> = Vec::new();
@@ -108,7 +115,13 @@ pub fn get_applied_status_cached(
         )?
     } else {
         let base_tree = ctx.repository().find_commit(default_target.sha)?.tree()?;
-        compute_old_locks(ctx.repository(), &base_diffs, &virtual_branches, base_tree)?
+        compute_old_locks(
+            ctx.repository(),
+            &base_diffs,
+            &virtual_branches,
+            base_tree,
+            ctx.project().diff_settings,
+        )?
     };
 
     for branch in &mut virtual_branches {
@@ -231,7 +244,26 @@ pub fn get_applied_status_cached(
     // write updated state if not resolving
     if !ctx.is_resolving() {
         for (vbranch, files) in &mut hunks_by_branch {
-            vbranch.tree = gitbutler_diff::write::hunks_onto_oid(ctx, vbranch.head(), files)?;
+            vbranch.tree = match changed_paths {
+                // The tree already has the hunks of all other files, which are unchanged.
+                Some(changed_paths) => {
+                    let changed_tree = gitbutler_diff::write::hunks_onto_oid(
+                        ctx,
+                        vbranch.head(),
+                        files
+                            .iter()
+                            .filter(|(path, _)| changed_paths.contains(*path)),
+                    )?;
+                    let repo = ctx.repository();
+                    gitbutler_diff::write::replace_paths(
+                        repo,
+                        &repo.find_tree(vbranch.tree)?,
+                        &repo.find_tree(changed_tree)?,
+                        changed_paths,
+                    )?
+                }
+                None => gitbutler_diff::write::hunks_onto_oid(ctx, vbranch.head(), files)?,
+            };
             vb_state
                 .set_branch(vbranch.clone())
                 .context(format!("failed to write virtual branch {}", vbranch.name))?;
@@ -338,12 +370,13 @@ fn compute_old_locks(
     unstaged_hunks_by_path: &HashMap<PathBuf, Vec<gitbutler_diff::GitHunk>>,
     virtual_branches: &[Stack],
     base_tree: Tree,
+    diff_settings: DiffSettings,
 ) -> Result<HashMap<HunkHash, Vec<HunkLock>>> {
     let mut diff_opts = git2::DiffOptions::new();
-    let opts = diff_opts
+    let opts = diff_settings
+        .apply_to(&mut diff_opts, None)
         .show_binary(true)
-        .ignore_submodules(true)
-        .context_lines(3);
+        .ignore_submodules(false);
 
     let branch_path_diffs = virtual_branches
         .iter()
@@ -355,8 +388,16 @@ fn compute_old_locks(
             let diff = repository
                 .diff_tree_to_tree(Some(&base_tree), Some(&tree), Some(opts))
                 .ok()?;
-            let hunks_by_filepath =
+            let mut hunks_by_filepath =
                 gitbutler_diff::hunks_by_filepath(Some(repository), &diff).ok()?;
+            gitbutler_diff::apply_histogram(
+                repository,
+                &diff,
+                &mut hunks_by_filepath,
+                diff_settings,
+                None,
+            )
+            .ok()?;
 
             Some((branch, hunks_by_filepath))
         })
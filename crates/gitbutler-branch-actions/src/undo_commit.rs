@@ -4,6 +4,7 @@ use anyhow::{bail, Context as _, Result};
 use gitbutler_command_context::CommandContext;
 use gitbutler_commit::commit_ext::CommitExt as _;
 use gitbutler_diff::Hunk;
+use gitbutler_project::DiffSettings;
 use gitbutler_repo::{rebase::cherry_rebase_group, LogUntil, RepositoryExt as _};
 use gitbutler_stack::{OwnershipClaim, Stack, StackId};
 
@@ -32,7 +33,12 @@ pub(crate) fn undo_commit(
     let UndoResult {
         new_head: new_head_commit,
         ownership_update,
-    } = inner_undo_commit(ctx.repository(), branch.head(), commit_oid)?;
+    } = inner_undo_commit(
+        ctx.repository(),
+        branch.head(),
+        commit_oid,
+        ctx.project().diff_settings,
+    )?;
 
     for ownership in ownership_update {
         branch.ownership.put(ownership);
@@ -58,6 +64,7 @@ fn inner_undo_commit(
     repository: &git2::Repository,
     branch_head_commit: git2::Oid,
     commit_to_remove: git2::Oid,
+    diff_settings: DiffSettings,
 ) -> Result<UndoResult> {
     let commit_to_remove = repository.find_commit(commit_to_remove)?;
 
@@ -72,7 +79,13 @@ fn inner_undo_commit(
         .tree()
         .context("failed to get parent tree")?;
 
-    let diff = gitbutler_diff::trees(repository, &commit_parent_tree, &commit_tree, true)?;
+    let diff = gitbutler_diff::trees(
+        repository,
+        &commit_parent_tree,
+        &commit_tree,
+        true,
+        diff_settings,
+    )?;
     let diff: HashMap<_, _> = gitbutler_diff::diff_files_into_hunks(diff).collect();
     let ownership_update = diff
         .iter()
@@ -148,6 +161,7 @@ mod test {
                 &test_repository.repository,
                 conflicted_commit.id(),
                 conflicted_commit.id(),
+                Default::default(),
             );
 
             assert!(
@@ -167,7 +181,13 @@ mod test {
             let UndoResult {
                 new_head,
                 ownership_update,
-            } = inner_undo_commit(&test_repository.repository, c.id(), c.id()).unwrap();
+            } = inner_undo_commit(
+                &test_repository.repository,
+                c.id(),
+                c.id(),
+                Default::default(),
+            )
+            .unwrap();
 
             assert_eq!(new_head, b.id(), "The new head should be C's parent");
             assert_eq!(
@@ -206,7 +226,13 @@ mod test {
             let UndoResult {
                 new_head,
                 ownership_update,
-            } = inner_undo_commit(&test_repository.repository, c.id(), b.id()).unwrap();
+            } = inner_undo_commit(
+                &test_repository.repository,
+                c.id(),
+                b.id(),
+                Default::default(),
+            )
+            .unwrap();
 
             let new_head_commit: git2::Commit =
                 test_repository.repository.find_commit(new_head).unwrap();
@@ -1,13 +1,20 @@
+use std::{collections::HashMap, path::PathBuf};
+
 use anyhow::{anyhow, bail, Result};
-use gitbutler_cherry_pick::RepositoryExt as _;
+use bstr::ByteSlice;
+use gitbutler_cherry_pick::{ConflictedTreeKey, RepositoryExt as _};
 use gitbutler_command_context::CommandContext;
-use gitbutler_project::access::WorktreeWritePermission;
+use gitbutler_commit::commit_ext::CommitExt as _;
+use gitbutler_project::access::{WorktreeReadPermission, WorktreeWritePermission};
 use gitbutler_repo::{
-    rebase::{cherry_rebase_group, gitbutler_merge_commits},
+    rebase::{
+        cherry_rebase_group_preserving_merges, cherry_rebase_group_preserving_merges_with_mapping,
+        gitbutler_merge_commits, ConflictEntries,
+    },
     LogUntil, RepositoryExt as _,
 };
 use gitbutler_repo_actions::RepoActionsExt as _;
-use gitbutler_stack::{Stack, StackId, Target, VirtualBranchesHandle};
+use gitbutler_stack::{commit_by_oid_or_change_id, Stack, StackId, Target, VirtualBranchesHandle};
 use serde::{Deserialize, Serialize};
 
 use crate::{
@@ -43,7 +50,7 @@ pub enum BaseBranchResolutionApproach {
 
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
 #[serde(tag = "type", content = "subject", rename_all = "camelCase")]
-enum ResolutionApproach {
+pub enum ResolutionApproach {
     Rebase,
     Merge,
     Unapply,
@@ -82,6 +89,49 @@ pub struct Resolution {
     approach: ResolutionApproach,
 }
 
+/// What would happen to a single commit when integrating upstream changes.
+#[derive(Serialize, PartialEq, Debug)]
+#[serde(tag = "type", content = "subject", rename_all = "camelCase")]
+pub enum CommitIntegrationOutcome {
+    /// The commit stays as it is.
+    Unchanged,
+    /// The commit applies cleanly on top of the new target.
+    Applied,
+    /// The commit would become conflicted in the listed files.
+    Conflicted { conflicted_files: Vec<PathBuf> },
+    /// The commit would not introduce any changes anymore, as they are already part of the new target.
+    Empty,
+    /// The commit is already part of the new target and would be dropped from the branch.
+    Integrated,
+}
+
+#[derive(Serialize, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CommitIntegrationPreview {
+    #[serde(with = "gitbutler_serde::oid")]
+    pub id: git2::Oid,
+    pub outcome: CommitIntegrationOutcome,
+}
+
+#[derive(Serialize, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SeriesIntegrationPreview {
+    pub name: String,
+    /// The commits of the series, the newest commit first.
+    pub commits: Vec<CommitIntegrationPreview>,
+}
+
+#[derive(Serialize, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct BranchIntegrationPreview {
+    pub branch_id: StackId,
+    /// The series of the stack in the same order as [`Stack::heads`].
+    pub series: Vec<SeriesIntegrationPreview>,
+    /// The outcome of the merge commit with the new target when merging, or `None` when rebasing.
+    pub merge_commit: Option<CommitIntegrationOutcome>,
+    pub potentially_conflicted_uncommited_changes: bool,
+}
+
 enum IntegrationResult {
     UpdatedObjects { head: git2::Oid, tree: git2::Oid },
     UnapplyBranch,
@@ -102,6 +152,24 @@ impl<'a> UpstreamIntegrationContext<'a> {
         command_context: &'a CommandContext,
         target_commit_oid: Option<git2::Oid>,
         permission: &'a mut WorktreeWritePermission,
+    ) -> Result<Self> {
+        Self::open_inner(command_context, target_commit_oid, Some(permission))
+    }
+
+    /// Like [`Self::open()`], but for computing what an integration would do without changing anything,
+    /// which is why reading the worktree is all that's needed.
+    pub(crate) fn open_read_only(
+        command_context: &'a CommandContext,
+        target_commit_oid: Option<git2::Oid>,
+        _permission: &WorktreeReadPermission,
+    ) -> Result<Self> {
+        Self::open_inner(command_context, target_commit_oid, None)
+    }
+
+    fn open_inner(
+        command_context: &'a CommandContext,
+        target_commit_oid: Option<git2::Oid>,
+        permission: Option<&'a mut WorktreeWritePermission>,
     ) -> Result<Self> {
         let virtual_branches_handle = command_context.project().virtual_branches();
         let target = virtual_branches_handle.get_default_target()?;
@@ -119,7 +187,7 @@ impl<'a> UpstreamIntegrationContext<'a> {
         let virtual_branches_in_workspace = virtual_branches_handle.list_branches_in_workspace()?;
 
         Ok(Self {
-            _permission: Some(permission),
+            _permission: permission,
             repository,
             new_target,
             old_target,
@@ -168,10 +236,18 @@ pub fn upstream_integration_statuses(
                 };
             }
 
-            let head_merge_index =
-                repository.merge_trees(&old_target_tree, &new_target_tree, &head_tree, None)?;
-            let mut tree_merge_index =
-                repository.merge_trees(&old_target_tree, &new_target_tree, &tree, None)?;
+            let head_merge_index = repository.merge_trees_gitbutler(
+                &old_target_tree,
+                &new_target_tree,
+                &head_tree,
+                None,
+            )?;
+            let mut tree_merge_index = repository.merge_trees_gitbutler(
+                &old_target_tree,
+                &new_target_tree,
+                &tree,
+                None,
+            )?;
 
             // Is the branch conflicted?
             // A branch can't be integrated if its conflicted
@@ -223,6 +299,197 @@ pub fn upstream_integration_statuses(
     Ok(BranchStatuses::UpdatesRequired(statuses))
 }
 
+/// Perform the integration of the new target into all applied stacks with `approach` in memory, and
+/// report the outcome for every commit of every series, without touching references or the worktree.
+///
+/// `base_branch_resolution_approach` must be set if the target diverged, just like when integrating.
+pub fn upstream_integration_preview(
+    context: &UpstreamIntegrationContext,
+    approach: &ResolutionApproach,
+    base_branch_resolution_approach: Option<&BaseBranchResolutionApproach>,
+) -> Result<Vec<BranchIntegrationPreview>> {
+    let UpstreamIntegrationContext {
+        repository,
+        new_target,
+        old_target,
+        virtual_branches_in_workspace,
+        target_branch_name,
+        ..
+    } = context;
+    // All objects created while integrating stay in memory.
+    let inmemory_repo = repository.in_memory_repo()?;
+
+    virtual_branches_in_workspace
+        .iter()
+        .map(|virtual_branch| {
+            let mut outcomes = HashMap::new();
+            let (new_head, merge_commit) = match approach {
+                ResolutionApproach::Rebase => {
+                    // Pick the same commits as `compute_resolutions()` does.
+                    let lower_bound = if base_branch_resolution_approach.is_some() {
+                        old_target.id()
+                    } else {
+                        new_target.id()
+                    };
+                    let virtual_branch_commits = repository.l(
+                        virtual_branch.head(),
+                        LogUntil::Commit(lower_bound),
+                        false,
+                    )?;
+                    // Rebase the whole series at once so merges are recreated with their rebased parents.
+                    let (new_head, mapping) = cherry_rebase_group_preserving_merges_with_mapping(
+                        &inmemory_repo,
+                        new_target.id(),
+                        &virtual_branch_commits,
+                    )?;
+                    for (commit_id, rebased) in mapping {
+                        let outcome = match rebased {
+                            // Commits that don't change anything anymore are dropped by the rebase.
+                            None => CommitIntegrationOutcome::Empty,
+                            Some(rebased) => commit_outcome(
+                                &inmemory_repo,
+                                &inmemory_repo.find_commit(commit_id)?,
+                                &inmemory_repo.find_commit(rebased)?,
+                            )?,
+                        };
+                        outcomes.insert(commit_id, outcome);
+                    }
+                    (new_head, None)
+                }
+                ResolutionApproach::Merge => {
+                    let merge_commit = gitbutler_merge_commits(
+                        &inmemory_repo,
+                        inmemory_repo.find_commit(virtual_branch.head())?,
+                        inmemory_repo.find_commit(new_target.id())?,
+                        &virtual_branch.name,
+                        target_branch_name,
+                    )?;
+                    let outcome = if merge_commit.is_conflicted() {
+                        CommitIntegrationOutcome::Conflicted {
+                            conflicted_files: conflicted_files(&inmemory_repo, &merge_commit)?,
+                        }
+                    } else {
+                        CommitIntegrationOutcome::Applied
+                    };
+                    (merge_commit.id(), Some(outcome))
+                }
+                ResolutionApproach::Unapply | ResolutionApproach::Delete => {
+                    bail!("Only rebasing and merging can be previewed")
+                }
+            };
+
+            let updated = compute_updated_branch_head(&inmemory_repo, virtual_branch, new_head)?;
+            let potentially_conflicted_uncommited_changes = updated.head != new_head;
+
+            let mut series = Vec::new();
+            let merge_base = repository.merge_base(virtual_branch.head(), old_target.id())?;
+            let mut previous_head = merge_base;
+            for head in &virtual_branch.heads {
+                let head_commit = match commit_by_oid_or_change_id(
+                    &head.target,
+                    repository,
+                    virtual_branch.head(),
+                    merge_base,
+                ) {
+                    Ok(commits) if !head.archived => commits.head.id(),
+                    _ => {
+                        series.push(SeriesIntegrationPreview {
+                            name: head.name.clone(),
+                            commits: vec![],
+                        });
+                        continue;
+                    }
+                };
+                let commits = repository
+                    .l(head_commit, LogUntil::Commit(previous_head), false)?
+                    .into_iter()
+                    .map(|id| {
+                        let outcome = match outcomes.remove(&id) {
+                            Some(outcome) => outcome,
+                            None if id == new_target.id()
+                                || repository.graph_descendant_of(new_target.id(), id)? =>
+                            {
+                                CommitIntegrationOutcome::Integrated
+                            }
+                            None => CommitIntegrationOutcome::Unchanged,
+                        };
+                        Ok(CommitIntegrationPreview { id, outcome })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                series.push(SeriesIntegrationPreview {
+                    name: head.name.clone(),
+                    commits,
+                });
+                previous_head = head_commit;
+            }
+
+            Ok(BranchIntegrationPreview {
+                branch_id: virtual_branch.id,
+                series,
+                merge_commit,
+                potentially_conflicted_uncommited_changes,
+            })
+        })
+        .collect()
+}
+
+pub(crate) fn preview_upstream_integration(
+    command_context: &CommandContext,
+    approach: &ResolutionApproach,
+    base_branch_resolution: Option<BaseBranchResolution>,
+    permission: &WorktreeReadPermission,
+) -> Result<Vec<BranchIntegrationPreview>> {
+    let (target_commit_oid, base_branch_resolution_approach) = base_branch_resolution
+        .map(|r| (Some(r.target_commit_oid), Some(r.approach)))
+        .unwrap_or((None, None));
+
+    let context =
+        UpstreamIntegrationContext::open_read_only(command_context, target_commit_oid, permission)?;
+    upstream_integration_preview(&context, approach, base_branch_resolution_approach.as_ref())
+}
+
+/// Determine what happened to `original` when it was rebased into `rebased`.
+fn commit_outcome(
+    repository: &git2::Repository,
+    original: &git2::Commit,
+    rebased: &git2::Commit,
+) -> Result<CommitIntegrationOutcome> {
+    if original.id() == rebased.id() {
+        return Ok(CommitIntegrationOutcome::Unchanged);
+    }
+    if rebased.is_conflicted() {
+        return Ok(CommitIntegrationOutcome::Conflicted {
+            conflicted_files: conflicted_files(repository, rebased)?,
+        });
+    }
+    let introduces_changes = |commit: &git2::Commit| -> Result<bool> {
+        let Ok(parent) = commit.parent(0) else {
+            return Ok(true);
+        };
+        let parent_tree = repository.find_real_tree(&parent, Default::default())?;
+        let tree = repository.find_real_tree(commit, Default::default())?;
+        Ok(parent_tree.id() != tree.id())
+    };
+    if introduces_changes(original)? && !introduces_changes(rebased)? {
+        return Ok(CommitIntegrationOutcome::Empty);
+    }
+    Ok(CommitIntegrationOutcome::Applied)
+}
+
+fn conflicted_files(repository: &git2::Repository, commit: &git2::Commit) -> Result<Vec<PathBuf>> {
+    let Some(entry) = commit.tree()?.get_name(&ConflictedTreeKey::ConflictFiles) else {
+        return Ok(vec![]);
+    };
+    let conflict_files = repository.find_blob(entry.id())?;
+    let conflict_files: ConflictEntries =
+        toml::from_str(&conflict_files.content().to_str_lossy()).unwrap_or_default();
+    Ok(conflict_files
+        .paths()
+        .into_iter()
+        .map(ToOwned::to_owned)
+        .collect())
+}
+
 pub(crate) fn integrate_upstream(
     command_context: &CommandContext,
     resolutions: &[Resolution],
@@ -379,7 +646,7 @@ pub(crate) fn resolve_upstream_integration(
         }
         BaseBranchResolutionApproach::Rebase => {
             let commits = repo.l(old_target_id, LogUntil::Commit(fork_point), false)?;
-            let new_head = cherry_rebase_group(repo, new_target_id, &commits)?;
+            let new_head = cherry_rebase_group_preserving_merges(repo, new_target_id, &commits)?;
 
             Ok(new_head)
         }
@@ -464,8 +731,11 @@ fn compute_resolutions(
                         false,
                     )?;
 
-                    let new_head =
-                        cherry_rebase_group(repository, new_target.id(), &virtual_branch_commits)?;
+                    let new_head = cherry_rebase_group_preserving_merges(
+                        repository,
+                        new_target.id(),
+                        &virtual_branch_commits,
+                    )?;
 
                     // Get the updated tree oid
                     let BranchHeadAndTree {
@@ -491,6 +761,7 @@ fn compute_resolutions(
 #[cfg(test)]
 mod test {
     use gitbutler_commit::commit_ext::CommitExt as _;
+    use gitbutler_patch_reference::{CommitOrChangeId, PatchReference};
     use gitbutler_testsupport::testing_repository::TestingRepository;
 
     use super::*;
@@ -767,7 +1038,7 @@ mod test {
                 false,
             )
             .unwrap();
-        let head_after_rebase = cherry_rebase_group(
+        let head_after_rebase = cherry_rebase_group_preserving_merges(
             &test_repository.repository,
             new_target.id(),
             &commits_to_rebase,
@@ -842,7 +1113,7 @@ mod test {
                 false,
             )
             .unwrap();
-        let head_after_rebase = cherry_rebase_group(
+        let head_after_rebase = cherry_rebase_group_preserving_merges(
             &test_repository.repository,
             new_target.id(),
             &commits_to_rebase,
@@ -1175,4 +1446,175 @@ mod test {
             BranchStatuses::UpdatesRequired(vec![(branch.id, BranchStatus::SaflyUpdatable)]),
         )
     }
+
+    #[test]
+    fn test_preview_reports_outcome_per_commit() {
+        let test_repository = TestingRepository::open();
+        let initial_commit = test_repository.commit_tree(None, &[("foo.txt", "bar")]);
+        let old_target = test_repository.commit_tree(Some(&initial_commit), &[("foo.txt", "baz")]);
+        let integrated =
+            test_repository.commit_tree(Some(&old_target), &[("foo.txt", "baz"), ("b.txt", "b")]);
+        let becomes_empty = test_repository.commit_tree(
+            Some(&integrated),
+            &[("foo.txt", "baz"), ("b.txt", "b"), ("a.txt", "a")],
+        );
+        let conflicting = test_repository.commit_tree(
+            Some(&becomes_empty),
+            &[("foo.txt", "fux"), ("b.txt", "b"), ("a.txt", "a")],
+        );
+        let new_target = test_repository.commit_tree(
+            Some(&integrated),
+            &[("foo.txt", "qux"), ("b.txt", "b"), ("a.txt", "a")],
+        );
+
+        let mut branch = make_branch(conflicting.id(), conflicting.tree_id());
+        branch.heads = vec![PatchReference {
+            target: CommitOrChangeId::CommitId(conflicting.id().to_string()),
+            name: "series".into(),
+            description: None,
+            forge_id: None,
+            archived: false,
+        }];
+
+        let context = UpstreamIntegrationContext {
+            _permission: None,
+            old_target,
+            new_target,
+            repository: &test_repository.repository,
+            virtual_branches_in_workspace: vec![branch.clone()],
+            target_branch_name: "main".to_string(),
+        };
+
+        let preview =
+            upstream_integration_preview(&context, &ResolutionApproach::Rebase, None).unwrap();
+        assert_eq!(
+            preview,
+            vec![BranchIntegrationPreview {
+                branch_id: branch.id,
+                series: vec![SeriesIntegrationPreview {
+                    name: "series".into(),
+                    commits: vec![
+                        CommitIntegrationPreview {
+                            id: conflicting.id(),
+                            outcome: CommitIntegrationOutcome::Conflicted {
+                                conflicted_files: vec!["foo.txt".into()]
+                            },
+                        },
+                        CommitIntegrationPreview {
+                            id: becomes_empty.id(),
+                            outcome: CommitIntegrationOutcome::Empty,
+                        },
+                        CommitIntegrationPreview {
+                            id: integrated.id(),
+                            outcome: CommitIntegrationOutcome::Integrated,
+                        },
+                    ],
+                }],
+                merge_commit: None,
+                potentially_conflicted_uncommited_changes: false,
+            }]
+        );
+
+        let preview =
+            upstream_integration_preview(&context, &ResolutionApproach::Merge, None).unwrap();
+        assert_eq!(
+            preview[0].merge_commit,
+            Some(CommitIntegrationOutcome::Conflicted {
+                conflicted_files: vec!["foo.txt".into()]
+            })
+        );
+        let outcomes = preview[0].series[0]
+            .commits
+            .iter()
+            .map(|commit| &commit.outcome)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            outcomes,
+            [
+                &CommitIntegrationOutcome::Unchanged,
+                &CommitIntegrationOutcome::Unchanged,
+                &CommitIntegrationOutcome::Integrated
+            ]
+        );
+    }
+
+    #[test]
+    fn test_preview_of_series_with_merge_commit() {
+        let test_repository = TestingRepository::open();
+        let repository = &test_repository.repository;
+        let old_target = test_repository.commit_tree(None, &[("foo.txt", "bar")]);
+        let new_target = test_repository.commit_tree(Some(&old_target), &[("foo.txt", "baz")]);
+        let a =
+            test_repository.commit_tree(Some(&old_target), &[("foo.txt", "bar"), ("a.txt", "a")]);
+        let side =
+            test_repository.commit_tree(Some(&old_target), &[("foo.txt", "bar"), ("s.txt", "s")]);
+        let merge_tree = test_repository
+            .commit_tree(
+                Some(&a),
+                &[("foo.txt", "bar"), ("a.txt", "a"), ("s.txt", "s")],
+            )
+            .tree()
+            .unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+        let merge = repository
+            .commit(
+                None,
+                &signature,
+                &signature,
+                "merge",
+                &merge_tree,
+                &[&a, &side],
+            )
+            .unwrap();
+        let merge = repository.find_commit(merge).unwrap();
+        let c = test_repository.commit_tree(
+            Some(&merge),
+            &[
+                ("foo.txt", "bar"),
+                ("a.txt", "a"),
+                ("s.txt", "s"),
+                ("c.txt", "c"),
+            ],
+        );
+
+        let mut branch = make_branch(c.id(), c.tree_id());
+        branch.heads = vec![PatchReference {
+            target: CommitOrChangeId::CommitId(c.id().to_string()),
+            name: "series".into(),
+            description: None,
+            forge_id: None,
+            archived: false,
+        }];
+
+        let context = UpstreamIntegrationContext {
+            _permission: None,
+            old_target,
+            new_target,
+            repository,
+            virtual_branches_in_workspace: vec![branch.clone()],
+            target_branch_name: "main".to_string(),
+        };
+
+        let preview =
+            upstream_integration_preview(&context, &ResolutionApproach::Rebase, None).unwrap();
+        assert_eq!(
+            preview[0].series[0].commits,
+            [
+                CommitIntegrationPreview {
+                    id: c.id(),
+                    outcome: CommitIntegrationOutcome::Applied,
+                },
+                CommitIntegrationPreview {
+                    id: merge.id(),
+                    outcome: CommitIntegrationOutcome::Applied,
+                },
+                CommitIntegrationPreview {
+                    id: a.id(),
+                    outcome: CommitIntegrationOutcome::Applied,
+                },
+            ],
+            "the merged side branch isn't part of the series"
+        );
+        assert!(!preview[0].potentially_conflicted_uncommited_changes);
+    }
 }
@@ -4,6 +4,7 @@ use crate::{
     file::VirtualBranchFile,
     hunk::VirtualBranchHunk,
     integration::get_workspace_head,
+    push_validation::ensure_no_conflicted_commits,
     remote::{branch_to_remote_branch, RemoteBranch},
     stack::stack_series,
     status::{get_applied_status, get_applied_status_cached},
@@ -266,19 +267,20 @@ pub fn list_virtual_branches(
     ctx: &CommandContext,
     perm: &mut WorktreeWritePermission,
 ) -> Result<(Vec<VirtualBranch>, Vec<gitbutler_diff::FileDiff>)> {
-    list_virtual_branches_cached(ctx, perm, None)
+    list_virtual_branches_cached(ctx, perm, None, None)
 }
 
 /// `worktree_changes` are all changed files against the current `HEAD^{tree}` and index
 /// against the current working tree directory, and it's used to avoid double-computing
-/// this expensive information.
-#[instrument(level = tracing::Level::DEBUG, skip(ctx, perm, worktree_changes))]
+/// this expensive information, along with the `changed_paths` in it since the last time, if known.
+#[instrument(level = tracing::Level::DEBUG, skip(ctx, perm, worktree_changes, changed_paths))]
 pub fn list_virtual_branches_cached(
     ctx: &CommandContext,
     // TODO(ST): this should really only shared access, but there is some internals
     //           that conditionally write things.
     perm: &mut WorktreeWritePermission,
     worktree_changes: Option<gitbutler_diff::DiffByPathMap>,
+    changed_paths: Option<&[PathBuf]>,
 ) -> Result<(Vec<VirtualBranch>, Vec<gitbutler_diff::FileDiff>)> {
     assure_open_workspace_mode(ctx)
         .context("Listing virtual branches requires open workspace mode")?;
@@ -290,7 +292,7 @@ pub fn list_virtual_branches_cached(
         .get_default_target()
         .context("failed to get default target")?;
 
-    let status = get_applied_status_cached(ctx, Some(perm), worktree_changes)?;
+    let status = get_applied_status_cached(ctx, Some(perm), worktree_changes, changed_paths)?;
     let max_selected_for_changes = status
         .branches
         .iter()
@@ -729,6 +731,7 @@ pub(crate) fn reset_branch(
             .tree()
             .map_err(anyhow::Error::from)?,
         true,
+        ctx.project().diff_settings,
     )?;
 
     // Assign the new hunks to the branch we're working on.
@@ -928,6 +931,10 @@ pub(crate) fn push(
         ))
     };
 
+    let repo = ctx.repository();
+    let merge_base = repo.merge_base(vbranch.head(), default_target.sha)?;
+    ensure_no_conflicted_commits(repo, &vbranch.name, vbranch.head(), merge_base)?;
+
     ctx.push(vbranch.head(), &remote_branch, with_force, None, askpass)?;
 
     vbranch.upstream = Some(remote_branch.clone());
@@ -1099,8 +1106,12 @@ pub(crate) fn move_commit_file(
     )?;
 
     // get a list of all the diffs across all the virtual branches
-    let base_file_diffs = gitbutler_diff::workdir(ctx.repository(), default_target.sha)
-        .context("failed to diff workdir")?;
+    let base_file_diffs = gitbutler_diff::workdir(
+        ctx.repository(),
+        default_target.sha,
+        ctx.project().diff_settings,
+    )
+    .context("failed to diff workdir")?;
 
     // filter base_file_diffs to HashMap<filepath, Vec<GitHunk>> only for hunks in target_ownership
     // this is essentially the group of patches that we're "moving"
@@ -1157,9 +1168,14 @@ pub(crate) fn move_commit_file(
         // we need to remove the parts of this patch that are in target_ownership (the parts we're moving)
         // and then apply the rest to the parent tree of the "from" commit to
         // create the new "from" commit without the changes we're moving
-        let from_commit_diffs =
-            gitbutler_diff::trees(ctx.repository(), &from_parent_tree, &from_tree, true)
-                .context("failed to diff trees")?;
+        let from_commit_diffs = gitbutler_diff::trees(
+            ctx.repository(),
+            &from_parent_tree,
+            &from_tree,
+            true,
+            ctx.project().diff_settings,
+        )
+        .context("failed to diff trees")?;
 
         // filter from_commit_diffs to HashMap<filepath, Vec<GitHunk>> only for hunks NOT in target_ownership
         // this is the patch parts we're keeping
@@ -0,0 +1,83 @@
+use gitbutler_patch_reference::CommitOrChangeId;
+use gitbutler_stack::VirtualBranchesHandle;
+
+use super::*;
+
+#[test]
+fn series_pointing_to_a_fixup_follow_it_into_its_target() -> anyhow::Result<()> {
+    let Test {
+        repository,
+        project,
+        ..
+    } = &Test::default();
+
+    gitbutler_branch_actions::set_base_branch(project, &"refs/remotes/origin/master".parse()?)?;
+    let branch_id =
+        gitbutler_branch_actions::create_virtual_branch(project, &BranchCreateRequest::default())?;
+
+    fs::write(repository.path().join("one.txt"), "one")?;
+    gitbutler_branch_actions::create_commit(project, branch_id, "commit one", None, false)?;
+    fs::write(repository.path().join("two.txt"), "two")?;
+    gitbutler_branch_actions::create_commit(project, branch_id, "commit two", None, false)?;
+    fs::write(repository.path().join("one.txt"), "one fixed")?;
+    gitbutler_branch_actions::create_commit(project, branch_id, "fixup! commit one", None, false)?;
+    fs::write(repository.path().join("three.txt"), "three")?;
+    gitbutler_branch_actions::create_commit(project, branch_id, "commit three", None, false)?;
+
+    let branch = gitbutler_branch_actions::list_virtual_branches(project)?
+        .0
+        .into_iter()
+        .find(|b| b.id == branch_id)
+        .unwrap();
+    let fixup = &branch.commits[1];
+    assert_eq!(fixup.description, "fixup! commit one");
+
+    let vb_state = VirtualBranchesHandle::new(project.gb_dir());
+    let mut stack = vb_state.get_branch(branch_id)?;
+    let mut bottom = stack.heads[0].clone();
+    bottom.name = "bottom".into();
+    bottom.target = fixup.change_id.clone().map_or_else(
+        || CommitOrChangeId::CommitId(fixup.id.to_string()),
+        CommitOrChangeId::ChangeId,
+    );
+    stack.heads.insert(0, bottom);
+    vb_state.set_branch(stack)?;
+
+    gitbutler_branch_actions::autosquash(project, branch_id)?;
+
+    let branch = gitbutler_branch_actions::list_virtual_branches(project)?
+        .0
+        .into_iter()
+        .find(|b| b.id == branch_id)
+        .unwrap();
+    let series = branch
+        .series
+        .iter()
+        .map(|series| {
+            (
+                series.name.as_str(),
+                series
+                    .patches
+                    .iter()
+                    .map(|c| c.description.to_string())
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        series,
+        vec![
+            (
+                branch.series[0].name.as_str(),
+                vec!["commit three".to_owned(), "commit two".to_owned()]
+            ),
+            ("bottom", vec!["commit one".to_owned()]),
+        ],
+        "the series on the fixup now ends with the commit it was squashed into"
+    );
+    assert_eq!(
+        fs::read_to_string(repository.path().join("one.txt"))?,
+        "one fixed"
+    );
+    Ok(())
+}
@@ -0,0 +1,164 @@
+use gitbutler_branch_actions::Problem;
+use gitbutler_oplog::{entry::OperationKind, OplogExt};
+use gitbutler_patch_reference::CommitOrChangeId;
+use gitbutler_stack::VirtualBranchesHandle;
+
+use super::*;
+
+#[test]
+fn healthy_project_has_no_problems() -> anyhow::Result<()> {
+    let Test {
+        repository,
+        project,
+        ..
+    } = &Test::default();
+
+    gitbutler_branch_actions::set_base_branch(project, &"refs/remotes/origin/master".parse()?)?;
+    fs::write(repository.path().join("file.txt"), "content")?;
+    let branch_id =
+        gitbutler_branch_actions::create_virtual_branch(project, &BranchCreateRequest::default())?;
+    gitbutler_branch_actions::create_commit(project, branch_id, "commit", None, false)?;
+    gitbutler_branch_actions::list_virtual_branches(project)?;
+
+    assert_eq!(gitbutler_branch_actions::diagnose_project(project)?, vec![]);
+
+    let outcome = gitbutler_branch_actions::repair_project(project)?;
+    assert_eq!(outcome.snapshot, None, "there is nothing to repair");
+    assert!(outcome.repaired.is_empty());
+    assert!(outcome.remaining.is_empty());
+    Ok(())
+}
+
+#[test]
+fn missing_base_branch_cannot_be_repaired() -> anyhow::Result<()> {
+    let Test { project, .. } = &Test::default();
+
+    let problems = gitbutler_branch_actions::diagnose_project(project)?;
+    assert_eq!(problems, vec![Problem::MissingDefaultTarget]);
+    assert_eq!(problems[0].repair_description(), None);
+
+    let outcome = gitbutler_branch_actions::repair_project(project)?;
+    assert_eq!(outcome.snapshot, None);
+    assert_eq!(outcome.remaining, vec![Problem::MissingDefaultTarget]);
+    Ok(())
+}
+
+#[test]
+fn repair_broken_state() -> anyhow::Result<()> {
+    let Test {
+        repository,
+        project,
+        ..
+    } = &Test::default();
+
+    gitbutler_branch_actions::set_base_branch(project, &"refs/remotes/origin/master".parse()?)?;
+    let branch_id =
+        gitbutler_branch_actions::create_virtual_branch(project, &BranchCreateRequest::default())?;
+
+    let vb_state = VirtualBranchesHandle::new(project.gb_dir());
+    let mut stack = vb_state.get_branch(branch_id)?;
+    stack.ownership.put("missing.txt:1-2".parse()?);
+    let mut dangling = stack.heads[0].clone();
+    dangling.name = "dangling".into();
+    dangling.target = CommitOrChangeId::CommitId("d6a8a84e46b5ba2f2a7d9ff6d9c6b5d7c8e9f0a1".into());
+    stack.heads.insert(0, dangling);
+    vb_state.set_branch(stack.clone())?;
+
+    let oplog_head = project
+        .oplog_head()?
+        .expect("creating the branch took a snapshot");
+    let reflog_path = repository
+        .path()
+        .join(".git/logs/refs/heads/gitbutler/target");
+    fs::remove_file(reflog_path)?;
+
+    let problems = gitbutler_branch_actions::diagnose_project(project)?;
+    assert_eq!(
+        problems,
+        vec![
+            Problem::InvalidPatchReference {
+                stack_id: branch_id,
+                name: stack.name.clone(),
+                reference: "dangling".into(),
+            },
+            Problem::StaleOwnershipClaim {
+                stack_id: branch_id,
+                name: stack.name.clone(),
+                path: "missing.txt".into(),
+            },
+            Problem::UnreachableOplog { sha: oplog_head },
+        ]
+    );
+    assert!(problems
+        .iter()
+        .all(|problem| problem.repair_description().is_some()));
+
+    let outcome = gitbutler_branch_actions::repair_project(project)?;
+    assert_eq!(outcome.repaired, problems);
+    assert_eq!(outcome.remaining, vec![]);
+
+    let snapshot = outcome
+        .snapshot
+        .expect("a snapshot is taken before repairing");
+    let snapshots = project.list_snapshots(1, None)?;
+    assert_eq!(snapshots[0].commit_id, snapshot);
+    assert_eq!(
+        snapshots[0]
+            .details
+            .as_ref()
+            .map(|details| details.operation),
+        Some(OperationKind::RepairProject)
+    );
+
+    let stack = vb_state.get_branch(branch_id)?;
+    assert!(stack.ownership.claims.is_empty());
+    assert_eq!(
+        stack.heads[0].target, stack.heads[1].target,
+        "the dangling branch is empty now"
+    );
+    gitbutler_branch_actions::list_virtual_branches(project)?;
+    Ok(())
+}
+
+#[test]
+fn recreate_outdated_workspace_commit() -> anyhow::Result<()> {
+    let Test {
+        repository,
+        project,
+        ..
+    } = &Test::default();
+
+    gitbutler_branch_actions::set_base_branch(project, &"refs/remotes/origin/master".parse()?)?;
+    let repo = git2::Repository::open(repository.path())?;
+    let outdated_workspace_commit = repo.head()?.peel_to_commit()?.id();
+
+    fs::write(repository.path().join("file.txt"), "content")?;
+    let branch_id =
+        gitbutler_branch_actions::create_virtual_branch(project, &BranchCreateRequest::default())?;
+    let commit_id =
+        gitbutler_branch_actions::create_commit(project, branch_id, "commit", None, false)?;
+    repo.reference(
+        "refs/heads/gitbutler/workspace",
+        outdated_workspace_commit,
+        true,
+        "simulate a workspace commit that wasn't updated",
+    )?;
+
+    let problems = gitbutler_branch_actions::diagnose_project(project)?;
+    assert_eq!(
+        problems,
+        vec![Problem::OutdatedWorkspaceCommit {
+            commit: outdated_workspace_commit
+        }]
+    );
+
+    let outcome = gitbutler_branch_actions::repair_project(project)?;
+    assert_eq!(outcome.repaired, problems);
+    assert_eq!(outcome.remaining, vec![]);
+    let workspace_commit = repo.head()?.peel_to_commit()?;
+    assert_eq!(
+        workspace_commit.parent_ids().collect::<Vec<_>>(),
+        [commit_id]
+    );
+    Ok(())
+}
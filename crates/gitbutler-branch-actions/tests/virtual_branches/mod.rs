@@ -57,15 +57,18 @@ impl Test {
 
 mod amend;
 mod apply_virtual_branch;
+mod autosquash;
 mod branch_trees;
 mod create_commit;
 mod create_virtual_branch_from_branch;
+mod doctor;
 mod init;
 mod insert_blank_commit;
 mod list;
 mod list_details;
 mod locking;
 mod move_commit_file;
+mod move_commit_hunks;
 mod move_commit_to_vbranch;
 mod oplog;
 mod references;
@@ -74,6 +77,7 @@ mod reset_virtual_branch;
 mod save_and_unapply_virtual_branch;
 mod selected_for_changes;
 mod set_base_branch;
+mod split_commit;
 mod squash;
 mod unapply_ownership;
 mod unapply_without_saving_virtual_branch;
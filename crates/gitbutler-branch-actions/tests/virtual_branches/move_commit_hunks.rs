@@ -0,0 +1,236 @@
+use gitbutler_branch::BranchCreateRequest;
+use gitbutler_commit::commit_ext::CommitExt;
+use gitbutler_stack::BranchOwnershipClaims;
+
+use super::*;
+
+#[test]
+fn move_hunks_down() {
+    let Test {
+        repository,
+        project,
+        ..
+    } = &Test::default();
+
+    gitbutler_branch_actions::set_base_branch(
+        project,
+        &"refs/remotes/origin/master".parse().unwrap(),
+    )
+    .unwrap();
+
+    let branch_id =
+        gitbutler_branch_actions::create_virtual_branch(project, &BranchCreateRequest::default())
+            .unwrap();
+
+    fs::write(repository.path().join("file.txt"), "content").unwrap();
+    let commit1_id =
+        gitbutler_branch_actions::create_commit(project, branch_id, "commit one", None, false)
+            .unwrap();
+    let commit1 = repository.find_commit(commit1_id).unwrap();
+
+    fs::write(repository.path().join("file2.txt"), "content2").unwrap();
+    let commit2_id =
+        gitbutler_branch_actions::create_commit(project, branch_id, "commit two", None, false)
+            .unwrap();
+
+    fs::write(repository.path().join("file3.txt"), "content3").unwrap();
+    fs::write(repository.path().join("file4.txt"), "content4").unwrap();
+    let commit3_id =
+        gitbutler_branch_actions::create_commit(project, branch_id, "commit three", None, false)
+            .unwrap();
+    let commit3 = repository.find_commit(commit3_id).unwrap();
+
+    let to_move: BranchOwnershipClaims = "file4.txt:1-2".parse().unwrap();
+    gitbutler_branch_actions::move_commit_hunks(
+        project, branch_id, commit3_id, commit1_id, &to_move,
+    )
+    .unwrap();
+
+    let branch = gitbutler_branch_actions::list_virtual_branches(project)
+        .unwrap()
+        .0
+        .into_iter()
+        .find(|b| b.id == branch_id)
+        .unwrap();
+
+    assert_eq!(branch.commits.len(), 3);
+    assert_eq!(commit1.change_id(), branch.commits[2].change_id);
+    assert_eq!(commit3.change_id(), branch.commits[0].change_id);
+    assert_eq!(branch.commits[2].files.len(), 2);
+    assert_eq!(branch.commits[1].files.len(), 1);
+    assert_eq!(branch.commits[0].files.len(), 1);
+    assert_ne!(branch.commits[1].id, commit2_id);
+}
+
+#[test]
+fn move_hunks_up() {
+    let Test {
+        repository,
+        project,
+        ..
+    } = &Test::default();
+
+    gitbutler_branch_actions::set_base_branch(
+        project,
+        &"refs/remotes/origin/master".parse().unwrap(),
+    )
+    .unwrap();
+
+    let branch_id =
+        gitbutler_branch_actions::create_virtual_branch(project, &BranchCreateRequest::default())
+            .unwrap();
+
+    fs::write(repository.path().join("file.txt"), "content").unwrap();
+    fs::write(repository.path().join("file2.txt"), "content2").unwrap();
+    let commit1_id =
+        gitbutler_branch_actions::create_commit(project, branch_id, "commit one", None, false)
+            .unwrap();
+
+    fs::write(repository.path().join("file3.txt"), "content3").unwrap();
+    let _commit2_id =
+        gitbutler_branch_actions::create_commit(project, branch_id, "commit two", None, false)
+            .unwrap();
+
+    fs::write(repository.path().join("file4.txt"), "content4").unwrap();
+    let commit3_id =
+        gitbutler_branch_actions::create_commit(project, branch_id, "commit three", None, false)
+            .unwrap();
+
+    let to_move: BranchOwnershipClaims = "file2.txt:1-2".parse().unwrap();
+    gitbutler_branch_actions::move_commit_hunks(
+        project, branch_id, commit1_id, commit3_id, &to_move,
+    )
+    .unwrap();
+
+    let branch = gitbutler_branch_actions::list_virtual_branches(project)
+        .unwrap()
+        .0
+        .into_iter()
+        .find(|b| b.id == branch_id)
+        .unwrap();
+
+    assert_eq!(branch.commits.len(), 3);
+    assert_eq!(branch.commits[0].files.len(), 2);
+    assert_eq!(branch.commits[1].files.len(), 1);
+    assert_eq!(branch.commits[2].files.len(), 1);
+}
+
+#[test]
+fn move_dependent_hunk_down() {
+    let Test {
+        repository,
+        project,
+        ..
+    } = &Test::default();
+
+    gitbutler_branch_actions::set_base_branch(
+        project,
+        &"refs/remotes/origin/master".parse().unwrap(),
+    )
+    .unwrap();
+
+    let branch_id =
+        gitbutler_branch_actions::create_virtual_branch(project, &BranchCreateRequest::default())
+            .unwrap();
+
+    fs::write(repository.path().join("file.txt"), "content").unwrap();
+    let commit1_id =
+        gitbutler_branch_actions::create_commit(project, branch_id, "commit one", None, false)
+            .unwrap();
+
+    fs::write(repository.path().join("file2.txt"), "content2").unwrap();
+    let _commit2_id =
+        gitbutler_branch_actions::create_commit(project, branch_id, "commit two", None, false)
+            .unwrap();
+
+    // changes the line added by the second commit
+    fs::write(repository.path().join("file2.txt"), "content2 changed").unwrap();
+    fs::write(repository.path().join("file3.txt"), "content3").unwrap();
+    let commit3_id =
+        gitbutler_branch_actions::create_commit(project, branch_id, "commit three", None, false)
+            .unwrap();
+
+    let to_move: BranchOwnershipClaims = "file2.txt:1-2".parse().unwrap();
+    let result = gitbutler_branch_actions::move_commit_hunks(
+        project, branch_id, commit3_id, commit1_id, &to_move,
+    );
+    assert!(result.is_err());
+}
+
+#[test]
+fn move_hunks_uses_the_commit_and_not_the_worktree() {
+    let Test {
+        repository,
+        project,
+        ..
+    } = &Test::default();
+
+    gitbutler_branch_actions::set_base_branch(
+        project,
+        &"refs/remotes/origin/master".parse().unwrap(),
+    )
+    .unwrap();
+
+    let branch_id =
+        gitbutler_branch_actions::create_virtual_branch(project, &BranchCreateRequest::default())
+            .unwrap();
+
+    fs::write(repository.path().join("file.txt"), "content").unwrap();
+    let commit1_id =
+        gitbutler_branch_actions::create_commit(project, branch_id, "commit one", None, false)
+            .unwrap();
+
+    fs::write(repository.path().join("file3.txt"), "content3").unwrap();
+    fs::write(repository.path().join("file4.txt"), "content4").unwrap();
+    let commit2_id =
+        gitbutler_branch_actions::create_commit(project, branch_id, "commit two", None, false)
+            .unwrap();
+
+    // uncommitted changes to the files of the commit the hunks are moved out of
+    fs::remove_file(repository.path().join("file3.txt")).unwrap();
+    fs::write(repository.path().join("file4.txt"), "uncommitted").unwrap();
+
+    let to_move: BranchOwnershipClaims = "file4.txt:1-2".parse().unwrap();
+    let new_commit1_id = gitbutler_branch_actions::move_commit_hunks(
+        project, branch_id, commit2_id, commit1_id, &to_move,
+    )
+    .unwrap();
+
+    let branch = gitbutler_branch_actions::list_virtual_branches(project)
+        .unwrap()
+        .0
+        .into_iter()
+        .find(|b| b.id == branch_id)
+        .unwrap();
+    assert_eq!(branch.commits[1].id, new_commit1_id);
+
+    let repo = git2::Repository::open(repository.path()).unwrap();
+    let content = |commit_id: git2::Oid, path: &str| {
+        let tree = repo.find_commit(commit_id).unwrap().tree().unwrap();
+        tree.get_path(path::Path::new(path)).ok().map(|entry| {
+            let blob = entry.to_object(&repo).unwrap().peel_to_blob().unwrap();
+            String::from_utf8(blob.content().to_vec()).unwrap()
+        })
+    };
+    assert_eq!(
+        content(new_commit1_id, "file4.txt").as_deref(),
+        Some("content4")
+    );
+    assert_eq!(content(new_commit1_id, "file3.txt"), None);
+    let new_commit2_id = branch.commits[0].id;
+    assert_eq!(
+        content(new_commit2_id, "file3.txt").as_deref(),
+        Some("content3")
+    );
+    assert_eq!(
+        content(new_commit2_id, "file4.txt").as_deref(),
+        Some("content4")
+    );
+
+    assert_eq!(
+        fs::read_to_string(repository.path().join("file4.txt")).unwrap(),
+        "uncommitted",
+        "the worktree is left alone"
+    );
+    assert!(!repository.path().join("file3.txt").exists());
+}
@@ -87,6 +87,40 @@ fn workdir_vbranch_restore() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[test]
+fn auto_snapshot_honors_policy() -> anyhow::Result<()> {
+    let test = Test::default();
+    let Test {
+        repository,
+        project,
+        ..
+    } = &test;
+
+    gitbutler_branch_actions::set_base_branch(
+        project,
+        &"refs/remotes/origin/master".parse().unwrap(),
+    )?;
+    gitbutler_branch_actions::create_virtual_branch(project, &BranchCreateRequest::default())?;
+
+    let worktree_dir = repository.path();
+    fs::create_dir_all(worktree_dir.join("generated"))?;
+    fs::write(worktree_dir.join("generated/file.txt"), make_lines(40))?;
+    gitbutler_branch_actions::list_virtual_branches(project)?;
+    assert!(project.should_auto_snapshot(Duration::ZERO)?);
+
+    let mut project = project.clone();
+    project.snapshot_policy.exclude_paths = vec!["generated".into()];
+    assert!(
+        !project.should_auto_snapshot(Duration::ZERO)?,
+        "changes to excluded paths don't count"
+    );
+
+    project.snapshot_policy.exclude_paths.clear();
+    project.snapshot_policy.enabled = false;
+    assert!(!project.should_auto_snapshot(Duration::ZERO)?);
+    Ok(())
+}
+
 fn wd_file_count(worktree_dir: &&Path) -> anyhow::Result<usize> {
     Ok(glob::glob(&worktree_dir.join("file*").to_string_lossy())?.count())
 }
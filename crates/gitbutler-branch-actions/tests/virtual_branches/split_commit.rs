@@ -0,0 +1,192 @@
+use gitbutler_branch::BranchCreateRequest;
+use gitbutler_branch_actions::CommitSplit;
+use gitbutler_commit::commit_ext::CommitExt;
+
+use super::*;
+
+#[test]
+fn split_by_file() {
+    let Test {
+        repository,
+        project,
+        ..
+    } = &Test::default();
+
+    gitbutler_branch_actions::set_base_branch(
+        project,
+        &"refs/remotes/origin/master".parse().unwrap(),
+    )
+    .unwrap();
+
+    let branch_id =
+        gitbutler_branch_actions::create_virtual_branch(project, &BranchCreateRequest::default())
+            .unwrap();
+
+    fs::write(repository.path().join("file.txt"), "content").unwrap();
+    fs::write(repository.path().join("file2.txt"), "content2").unwrap();
+    fs::write(repository.path().join("file3.txt"), "content3").unwrap();
+    let commit1_id =
+        gitbutler_branch_actions::create_commit(project, branch_id, "commit one", None, false)
+            .unwrap();
+    let commit1 = repository.find_commit(commit1_id).unwrap();
+
+    fs::write(repository.path().join("file4.txt"), "content4").unwrap();
+    let commit2_id =
+        gitbutler_branch_actions::create_commit(project, branch_id, "commit two", None, false)
+            .unwrap();
+
+    let new_commits = gitbutler_branch_actions::split_commit(
+        project,
+        branch_id,
+        commit1_id,
+        &[
+            CommitSplit::new("file2.txt:1-2".parse().unwrap(), "first"),
+            CommitSplit::new("file3.txt:1-2".parse().unwrap(), "second"),
+            CommitSplit::new(Default::default(), "rest"),
+        ],
+    )
+    .unwrap();
+    assert_eq!(new_commits.len(), 3);
+
+    let branch = gitbutler_branch_actions::list_virtual_branches(project)
+        .unwrap()
+        .0
+        .into_iter()
+        .find(|b| b.id == branch_id)
+        .unwrap();
+
+    let descriptions = branch
+        .commits
+        .iter()
+        .map(|c| c.description.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(descriptions, vec!["commit two", "rest", "second", "first"]);
+    assert_ne!(branch.commits[0].id, commit2_id);
+
+    // the first commit keeps the change-id, the others get new ones
+    assert_eq!(commit1.change_id(), branch.commits[3].change_id);
+    assert_ne!(commit1.change_id(), branch.commits[2].change_id);
+    assert_ne!(commit1.change_id(), branch.commits[1].change_id);
+
+    let files = |idx: usize| {
+        branch.commits[idx]
+            .files
+            .iter()
+            .map(|f| f.path.clone())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(files(3), vec![PathBuf::from("file2.txt")]);
+    assert_eq!(files(2), vec![PathBuf::from("file3.txt")]);
+    assert_eq!(files(1), vec![PathBuf::from("file.txt")]);
+    assert_eq!(files(0), vec![PathBuf::from("file4.txt")]);
+
+    // the last commit has the tree of the commit that was split
+    let last = repository.find_commit(new_commits[2]).unwrap();
+    assert_eq!(last.tree_id(), commit1.tree_id());
+}
+
+#[test]
+fn hunk_claimed_twice() {
+    let Test {
+        repository,
+        project,
+        ..
+    } = &Test::default();
+
+    gitbutler_branch_actions::set_base_branch(
+        project,
+        &"refs/remotes/origin/master".parse().unwrap(),
+    )
+    .unwrap();
+
+    let branch_id =
+        gitbutler_branch_actions::create_virtual_branch(project, &BranchCreateRequest::default())
+            .unwrap();
+
+    fs::write(repository.path().join("file.txt"), "content").unwrap();
+    fs::write(repository.path().join("file2.txt"), "content2").unwrap();
+    let commit_id =
+        gitbutler_branch_actions::create_commit(project, branch_id, "commit", None, false).unwrap();
+
+    let result = gitbutler_branch_actions::split_commit(
+        project,
+        branch_id,
+        commit_id,
+        &[
+            CommitSplit::new("file2.txt:1-2".parse().unwrap(), "first"),
+            CommitSplit::new("file2.txt:1-2".parse().unwrap(), "second"),
+        ],
+    );
+    assert!(result.is_err());
+}
+
+#[test]
+fn split_uses_the_commit_and_not_the_worktree() {
+    let Test {
+        repository,
+        project,
+        ..
+    } = &Test::default();
+
+    gitbutler_branch_actions::set_base_branch(
+        project,
+        &"refs/remotes/origin/master".parse().unwrap(),
+    )
+    .unwrap();
+
+    let branch_id =
+        gitbutler_branch_actions::create_virtual_branch(project, &BranchCreateRequest::default())
+            .unwrap();
+
+    fs::write(repository.path().join("file.txt"), "content").unwrap();
+    fs::write(repository.path().join("file2.txt"), "content2").unwrap();
+    fs::write(repository.path().join("file3.txt"), "content3").unwrap();
+    let commit_id =
+        gitbutler_branch_actions::create_commit(project, branch_id, "commit", None, false).unwrap();
+
+    // uncommitted changes to the files being split
+    fs::write(repository.path().join("file2.txt"), "uncommitted").unwrap();
+    fs::remove_file(repository.path().join("file3.txt")).unwrap();
+
+    let new_commits = gitbutler_branch_actions::split_commit(
+        project,
+        branch_id,
+        commit_id,
+        &[
+            CommitSplit::new("file2.txt:1-2".parse().unwrap(), "first"),
+            CommitSplit::new("file3.txt:1-2".parse().unwrap(), "second"),
+            CommitSplit::new(Default::default(), "rest"),
+        ],
+    )
+    .unwrap();
+
+    let repo = git2::Repository::open(repository.path()).unwrap();
+    let content = |commit_id: git2::Oid, path: &str| {
+        let tree = repo.find_commit(commit_id).unwrap().tree().unwrap();
+        tree.get_path(path::Path::new(path)).ok().map(|entry| {
+            let blob = entry.to_object(&repo).unwrap().peel_to_blob().unwrap();
+            String::from_utf8(blob.content().to_vec()).unwrap()
+        })
+    };
+    assert_eq!(
+        content(new_commits[0], "file2.txt").as_deref(),
+        Some("content2")
+    );
+    assert_eq!(content(new_commits[0], "file3.txt"), None);
+    assert_eq!(
+        content(new_commits[1], "file2.txt").as_deref(),
+        Some("content2")
+    );
+    assert_eq!(
+        content(new_commits[1], "file3.txt").as_deref(),
+        Some("content3")
+    );
+    assert_eq!(content(new_commits[1], "file.txt"), None);
+
+    assert_eq!(
+        fs::read_to_string(repository.path().join("file2.txt")).unwrap(),
+        "uncommitted",
+        "the worktree is left alone"
+    );
+    assert!(!repository.path().join("file3.txt").exists());
+}
@@ -0,0 +1,322 @@
+//! Translate between GitButler conflicted commits and files with standard diff3-style conflict markers,
+//! which is what Git itself produces and what external tools understand.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use bstr::ByteSlice;
+use gitbutler_commit::commit_ext::CommitExt;
+
+use crate::{merge_driver::empty_ancestor, ConflictedTreeKey, RepositoryExt};
+
+/// The length of the conflict markers we write and understand.
+const MARKER_SIZE: usize = 7;
+
+/// A conflicted commit with its conflicts written out as files with conflict markers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MaterializedConflicts {
+    /// The tree of the auto-resolution, with each conflicted file replaced by its version with conflict markers.
+    pub tree_id: git2::Oid,
+    /// The worktree-relative paths of all conflicted files.
+    pub conflicted_paths: Vec<PathBuf>,
+    /// The message of the commit, followed by a list of conflicted files, like Git writes into `MERGE_MSG`.
+    pub message: String,
+}
+
+/// Turn the conflicted `commit` into a tree in which all conflicting files contain diff3-style conflict markers.
+///
+/// Fails if `commit` isn't conflicted.
+pub fn materialize_conflicts(
+    repo: &git2::Repository,
+    commit: &git2::Commit,
+) -> Result<MaterializedConflicts> {
+    if !commit.is_conflicted() {
+        bail!("Commit {} isn't conflicted", commit.id());
+    }
+    let base = repo.find_real_tree(commit, ConflictedTreeKey::Base)?;
+    let ours = repo.find_real_tree(commit, ConflictedTreeKey::Ours)?;
+    let theirs = repo.find_real_tree(commit, ConflictedTreeKey::Theirs)?;
+    let auto_resolution = repo.find_real_tree(commit, ConflictedTreeKey::AutoResolution)?;
+
+    let short_id = |id: git2::Oid| id.to_string()[..7].to_owned();
+    let subject = commit.summary().unwrap_or_default();
+    let ours_label = commit
+        .parent_ids()
+        .next()
+        .map_or_else(|| "ours".to_owned(), short_id);
+    let base_label = format!("parent of {} ({subject})", short_id(commit.id()));
+    let theirs_label = format!("{} ({subject})", short_id(commit.id()));
+
+    let mut index = repo.merge_trees_gitbutler(&base, &ours, &theirs, None)?;
+    let conflicts = index.conflicts()?.collect::<Result<Vec<_>, _>>()?;
+
+    let mut builder = git2::build::TreeUpdateBuilder::new();
+    let mut conflicted_paths = Vec::new();
+    for conflict in conflicts {
+        let (id, mode, path) = match (conflict.ancestor, conflict.our, conflict.their) {
+            (ancestor, Some(ours), Some(theirs)) => {
+                let ancestor = match ancestor {
+                    Some(ancestor) => ancestor,
+                    None => empty_ancestor(repo, &ours)?,
+                };
+                let mut opts = git2::MergeFileOptions::new();
+                opts.style_diff3(true)
+                    .marker_size(MARKER_SIZE as u16)
+                    .ancestor_label(&base_label)
+                    .our_label(&ours_label)
+                    .their_label(&theirs_label);
+                let merged =
+                    repo.merge_file_from_index(&ancestor, &ours, &theirs, Some(&mut opts))?;
+                (repo.blob(merged.content())?, ours.mode, ours.path)
+            }
+            // Modified on one side but deleted on the other, so keep the modification.
+            (_, Some(side), None) | (_, None, Some(side)) => (side.id, side.mode, side.path),
+            (Some(_), None, None) => continue,
+            (None, None, None) => unreachable!("conflicts always have at least one side"),
+        };
+        let path = PathBuf::from(path.to_str()?);
+        builder.upsert(&path, id, file_mode(mode));
+        conflicted_paths.push(path);
+    }
+    conflicted_paths.sort();
+
+    let tree_id = builder.create_updated(repo, &auto_resolution)?;
+    Ok(MaterializedConflicts {
+        tree_id,
+        message: conflicts_message(&commit.message_bstr().to_str_lossy(), &conflicted_paths),
+        conflicted_paths,
+    })
+}
+
+/// Create a regular commit from the conflicted `commit`, with the same parents, author and committer, whose
+/// tree contains conflict markers and whose message lists the conflicted files.
+/// Teammates can check it out with any Git client, resolve the conflicts and commit the result.
+pub fn export_conflicted_commit(
+    repo: &git2::Repository,
+    commit: &git2::Commit,
+) -> Result<git2::Oid> {
+    let materialized = materialize_conflicts(repo, commit)?;
+    let parents = commit.parents().collect::<Vec<_>>();
+    let commit_id = repo.commit(
+        None,
+        &commit.author(),
+        &commit.committer(),
+        &materialized.message,
+        &repo.find_tree(materialized.tree_id)?,
+        &parents.iter().collect::<Vec<_>>(),
+    )?;
+    Ok(commit_id)
+}
+
+/// Write all files of the conflicted `commit` into `target_dir`, with conflicting files containing
+/// conflict markers, and place the message listing the conflicts into `MERGE_MSG` within `git_dir`, if given.
+pub fn checkout_conflicted_commit(
+    repo: &git2::Repository,
+    commit: &git2::Commit,
+    target_dir: &Path,
+    git_dir: Option<&Path>,
+) -> Result<MaterializedConflicts> {
+    let materialized = materialize_conflicts(repo, commit)?;
+    let tree = repo.find_tree(materialized.tree_id)?;
+    repo.checkout_tree(
+        tree.as_object(),
+        Some(
+            git2::build::CheckoutBuilder::new()
+                .target_dir(target_dir)
+                .update_index(false)
+                .force(),
+        ),
+    )
+    .with_context(|| format!("failed to write conflicts to '{}'", target_dir.display()))?;
+    if let Some(git_dir) = git_dir {
+        fs::write(git_dir.join("MERGE_MSG"), &materialized.message)?;
+    }
+    Ok(materialized)
+}
+
+/// The sides of a conflict as reconstructed from files with conflict markers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportedConflicts {
+    /// The tree with the "ours" side of each conflict, which is the parent tree.
+    pub ours: git2::Oid,
+    /// The tree with the "theirs" side of each conflict, along with all changes that didn't conflict.
+    pub theirs: git2::Oid,
+    /// The parent tree with the common ancestor of each conflict, or "ours" if the markers didn't contain it.
+    pub base: git2::Oid,
+    /// The tree with the "ours" side of each conflict, along with all changes that didn't conflict.
+    pub auto_resolution: git2::Oid,
+    /// The worktree-relative paths of all files that contained conflict markers.
+    pub conflicted_paths: Vec<PathBuf>,
+}
+
+/// Reconstruct the sides of the conflicts from the files with diff3-style conflict markers in `tree`,
+/// whose commit has `parent_tree` as parent, which is the inverse of [`materialize_conflicts()`].
+/// Return `None` if there are no conflict markers.
+///
+/// As markers only cover the conflicting parts of files, everything else in conflicting files is the same
+/// on all sides. The changes from `parent_tree` to `tree` which didn't conflict are part of "theirs" and of
+/// the auto-resolution, while "ours" and the base are `parent_tree` itself, so the changes survive when the
+/// conflicted commit is picked again.
+pub fn import_conflict_markers(
+    repo: &git2::Repository,
+    tree: &git2::Tree,
+    parent_tree: &git2::Tree,
+) -> Result<Option<ImportedConflicts>> {
+    let mut blobs = Vec::new();
+    tree.walk(git2::TreeWalkMode::PreOrder, |dir, entry| {
+        if entry.kind() == Some(git2::ObjectType::Blob) {
+            if let Some(name) = entry.name() {
+                blobs.push((PathBuf::from(dir).join(name), entry.id(), entry.filemode()));
+            }
+        }
+        git2::TreeWalkResult::Ok
+    })?;
+
+    let mut ours = git2::build::TreeUpdateBuilder::new();
+    let mut theirs = git2::build::TreeUpdateBuilder::new();
+    let mut base = git2::build::TreeUpdateBuilder::new();
+    let mut auto_resolution = git2::build::TreeUpdateBuilder::new();
+    let mut conflicted_paths = Vec::new();
+    for (path, id, mode) in blobs {
+        let blob = repo.find_blob(id)?;
+        if blob.is_binary() {
+            continue;
+        }
+        let Some(sides) = split_conflict_markers(blob.content()) else {
+            continue;
+        };
+        let mode = file_mode(mode as u32);
+        let ours_id = repo.blob(&sides.ours)?;
+        ours.upsert(&path, ours_id, mode);
+        auto_resolution.upsert(&path, ours_id, mode);
+        theirs.upsert(&path, repo.blob(&sides.theirs)?, mode);
+        base.upsert(&path, repo.blob(&sides.base)?, mode);
+        conflicted_paths.push(path);
+    }
+    if conflicted_paths.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(ImportedConflicts {
+        ours: ours.create_updated(repo, parent_tree)?,
+        theirs: theirs.create_updated(repo, tree)?,
+        base: base.create_updated(repo, parent_tree)?,
+        auto_resolution: auto_resolution.create_updated(repo, tree)?,
+        conflicted_paths,
+    }))
+}
+
+/// The content of a file for each side of its conflicts.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Sides {
+    ours: Vec<u8>,
+    theirs: Vec<u8>,
+    base: Vec<u8>,
+}
+
+/// Split `content` with conflict markers into the content of each side, or return `None` if there
+/// are no complete conflict markers.
+fn split_conflict_markers(content: &[u8]) -> Option<Sides> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum State {
+        Outside,
+        Ours,
+        Base,
+        Theirs,
+    }
+    let is_marker = |line: &[u8], marker: u8| {
+        let line = line.trim_end_with(|c| c == '\n' || c == '\r');
+        line.len() >= MARKER_SIZE
+            && line[..MARKER_SIZE].iter().all(|b| *b == marker)
+            && line.get(MARKER_SIZE).map_or(true, |b| *b == b' ')
+    };
+
+    let mut sides = Sides::default();
+    let mut state = State::Outside;
+    let mut has_conflicts = false;
+    let mut has_base = false;
+    for line in content.lines_with_terminator() {
+        state = match state {
+            State::Outside if is_marker(line, b'<') => State::Ours,
+            State::Ours if is_marker(line, b'|') => {
+                has_base = true;
+                State::Base
+            }
+            State::Ours | State::Base if is_marker(line, b'=') => State::Theirs,
+            State::Theirs if is_marker(line, b'>') => {
+                has_conflicts = true;
+                State::Outside
+            }
+            state => {
+                match state {
+                    State::Outside => {
+                        sides.ours.extend_from_slice(line);
+                        sides.theirs.extend_from_slice(line);
+                        sides.base.extend_from_slice(line);
+                    }
+                    State::Ours => {
+                        sides.ours.extend_from_slice(line);
+                        if !has_base {
+                            sides.base.extend_from_slice(line);
+                        }
+                    }
+                    State::Base => sides.base.extend_from_slice(line),
+                    State::Theirs => sides.theirs.extend_from_slice(line),
+                }
+                state
+            }
+        };
+        if state == State::Outside {
+            has_base = false;
+        }
+    }
+    (has_conflicts && state == State::Outside).then_some(sides)
+}
+
+fn conflicts_message(message: &str, conflicted_paths: &[PathBuf]) -> String {
+    let mut message = message.trim_end().to_owned();
+    message.push_str("\n\nConflicts:\n");
+    for path in conflicted_paths {
+        message.push('\t');
+        message.push_str(&path.to_string_lossy());
+        message.push('\n');
+    }
+    message
+}
+
+fn file_mode(mode: u32) -> git2::FileMode {
+    match mode {
+        0o100755 => git2::FileMode::BlobExecutable,
+        0o120000 => git2::FileMode::Link,
+        _ => git2::FileMode::Blob,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split_conflict_markers;
+
+    #[test]
+    fn diff3_markers_are_split_into_sides() {
+        let content = b"a\n<<<<<<< ours\nb\n||||||| base\nx\n=======\nc\n>>>>>>> theirs\nd\n";
+        let sides = split_conflict_markers(content).unwrap();
+        assert_eq!(sides.ours, b"a\nb\nd\n");
+        assert_eq!(sides.base, b"a\nx\nd\n");
+        assert_eq!(sides.theirs, b"a\nc\nd\n");
+    }
+
+    #[test]
+    fn merge_markers_use_ours_as_base() {
+        let content = b"<<<<<<< ours\nb\n=======\nc\n>>>>>>> theirs\n";
+        let sides = split_conflict_markers(content).unwrap();
+        assert_eq!(sides.base, sides.ours);
+        assert_eq!(sides.theirs, b"c\n");
+    }
+
+    #[test]
+    fn incomplete_or_missing_markers_are_ignored() {
+        assert!(split_conflict_markers(b"a\n=======\nb\n").is_none());
+        assert!(split_conflict_markers(b"<<<<<<< ours\nb\n=======\nc\n").is_none());
+    }
+}
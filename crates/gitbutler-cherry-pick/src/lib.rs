@@ -6,6 +6,14 @@
 
 use std::ops::Deref;
 
+mod conflict_markers;
+pub use conflict_markers::{
+    checkout_conflicted_commit, export_conflicted_commit, import_conflict_markers,
+    materialize_conflicts, ImportedConflicts, MaterializedConflicts,
+};
+mod merge_driver;
+pub use merge_driver::{resolve_with_merge_drivers, MergeDriver};
+
 use anyhow::Context;
 use git2::MergeOptions;
 use gitbutler_commit::commit_ext::CommitExt;
@@ -46,6 +54,13 @@ pub trait RepositoryExt {
         to_rebase: &git2::Commit,
         merge_options: Option<&MergeOptions>,
     ) -> Result<git2::Index, anyhow::Error>;
+    fn merge_trees_gitbutler(
+        &self,
+        ancestor_tree: &git2::Tree,
+        our_tree: &git2::Tree,
+        their_tree: &git2::Tree,
+        merge_options: Option<&MergeOptions>,
+    ) -> Result<git2::Index, anyhow::Error>;
     fn find_real_tree(
         &self,
         commit: &git2::Commit,
@@ -79,10 +94,26 @@ impl RepositoryExt for git2::Repository {
         // Get the original theirs
         let thiers = self.find_real_tree(to_rebase, ConflictedTreeKey::Theirs)?;
 
-        self.merge_trees(&base, &ours, &thiers, merge_options)
+        self.merge_trees_gitbutler(&base, &ours, &thiers, merge_options)
             .context("failed to merge trees for cherry pick")
     }
 
+    /// Merge trees like [`git2::Repository::merge_trees()`], but resolve conflicts with the merge drivers
+    /// configured in `.gitattributes`, so the result only has conflicts that remain after that.
+    fn merge_trees_gitbutler(
+        &self,
+        ancestor_tree: &git2::Tree,
+        our_tree: &git2::Tree,
+        their_tree: &git2::Tree,
+        merge_options: Option<&MergeOptions>,
+    ) -> Result<git2::Index, anyhow::Error> {
+        let mut index = self.merge_trees(ancestor_tree, our_tree, their_tree, merge_options)?;
+        if index.has_conflicts() {
+            resolve_with_merge_drivers(self, &mut index)?;
+        }
+        Ok(index)
+    }
+
     /// Find the real tree of a commit, which is the tree of the commit if it's not in a conflicted state
     /// or the parent parent tree if it is in a conflicted state
     ///
@@ -0,0 +1,243 @@
+//! Support for merge drivers configured with the `merge` attribute in `.gitattributes`.
+//!
+//! `libgit2` performs a plain three-way text merge for every file, so we post-process the conflicts
+//! it leaves behind and resolve them with the driver that Git would have used.
+use std::{
+    fs,
+    io::Write as _,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{Context, Result};
+
+/// The merge driver that applies to a path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeDriver {
+    /// The regular three-way text merge, which leaves conflicts for overlapping changes.
+    Text,
+    /// Like [`Text`](Self::Text), but overlapping changes are resolved by keeping the lines of both sides.
+    Union,
+    /// Resolve conflicts by keeping our version of the file.
+    Ours,
+    /// Never merge, but keep conflicts for resolution by the user.
+    Binary,
+    /// A user-configured `merge.<name>.driver` command.
+    External {
+        /// The name of the driver as used in `.gitattributes`.
+        name: String,
+        /// The command line with `%O`, `%A`, `%B`, `%L` and `%P` placeholders.
+        command: String,
+    },
+}
+
+/// The default length of conflict markers.
+const DEFAULT_MARKER_SIZE: u32 = 7;
+
+impl MergeDriver {
+    /// Determine the driver for `rela_path` from the `merge` attribute as seen in the worktree of `repo`,
+    /// and the `merge.<name>.driver` configuration.
+    ///
+    /// Like Git, user-configured drivers take precedence over built-in ones of the same name, and
+    /// unknown drivers fall back to a text merge.
+    pub fn for_path(repo: &git2::Repository, rela_path: &Path) -> Result<Self> {
+        let value = repo.get_attr(rela_path, "merge", git2::AttrCheckFlags::FILE_THEN_INDEX)?;
+        let name = match git2::AttrValue::from_string(value) {
+            git2::AttrValue::True | git2::AttrValue::Unspecified => return Ok(MergeDriver::Text),
+            git2::AttrValue::False => return Ok(MergeDriver::Binary),
+            git2::AttrValue::String(name) => name,
+            git2::AttrValue::Bytes(name) => std::str::from_utf8(name)?,
+        };
+        let config = repo.config()?;
+        if let Ok(command) = config.get_string(&format!("merge.{name}.driver")) {
+            return Ok(MergeDriver::External {
+                name: name.to_owned(),
+                command,
+            });
+        }
+        Ok(match name {
+            "union" => MergeDriver::Union,
+            "ours" => MergeDriver::Ours,
+            "binary" => MergeDriver::Binary,
+            _ => MergeDriver::Text,
+        })
+    }
+}
+
+/// Resolve the conflicts in `index`, as produced by merging trees in `repo`, using the merge driver
+/// configured for each conflicting path. Conflicts that the driver can't resolve are left in place,
+/// so [`git2::Index::has_conflicts()`] tells if a conflicted result is genuinely necessary.
+pub fn resolve_with_merge_drivers(repo: &git2::Repository, index: &mut git2::Index) -> Result<()> {
+    let conflicts = index.conflicts()?.collect::<Result<Vec<_>, _>>()?;
+    for conflict in conflicts {
+        let (Some(mut ours), Some(theirs)) = (conflict.our, conflict.their) else {
+            // Deletions and renames aren't handled by merge drivers.
+            continue;
+        };
+        let is_blob = |entry: &git2::IndexEntry| entry.mode != u32::from(git2::FileMode::Commit);
+        if !is_blob(&ours)
+            || !is_blob(&theirs)
+            || conflict.ancestor.as_ref().is_some_and(|e| !is_blob(e))
+        {
+            continue;
+        }
+        let rela_path = PathBuf::from(std::str::from_utf8(&ours.path)?);
+
+        let resolution = match MergeDriver::for_path(repo, &rela_path)? {
+            MergeDriver::Text | MergeDriver::Binary => None,
+            MergeDriver::Ours => Some(repo.find_blob(ours.id)?.content().to_owned()),
+            MergeDriver::Union => {
+                let ancestor = match conflict.ancestor {
+                    Some(ancestor) => ancestor,
+                    None => empty_ancestor(repo, &ours)?,
+                };
+                let mut opts = git2::MergeFileOptions::new();
+                opts.favor(git2::FileFavor::Union);
+                let merged =
+                    repo.merge_file_from_index(&ancestor, &ours, &theirs, Some(&mut opts))?;
+                merged
+                    .is_automergeable()
+                    .then(|| merged.content().to_owned())
+            }
+            MergeDriver::External { name, command } => {
+                let ancestor = conflict
+                    .ancestor
+                    .map(|ancestor| repo.find_blob(ancestor.id))
+                    .transpose()?;
+                run_external_driver(
+                    repo,
+                    &rela_path,
+                    &command,
+                    ancestor.as_ref().map_or(&[][..], |blob| blob.content()),
+                    repo.find_blob(ours.id)?.content(),
+                    repo.find_blob(theirs.id)?.content(),
+                )
+                .with_context(|| {
+                    format!("merge driver '{name}' failed on '{}'", rela_path.display())
+                })?
+            }
+        };
+
+        if let Some(content) = resolution {
+            index.conflict_remove(&rela_path)?;
+            ours.flags = 0; // the stage is part of the flags, and we want to add it as resolved
+            index.add_frombuffer(&ours, &content)?;
+        }
+    }
+    Ok(())
+}
+
+/// Add/add conflicts have no ancestor, so merge against an empty file instead, just like Git does.
+pub(crate) fn empty_ancestor(
+    repo: &git2::Repository,
+    ours: &git2::IndexEntry,
+) -> Result<git2::IndexEntry> {
+    Ok(git2::IndexEntry {
+        ctime: git2::IndexTime::new(0, 0),
+        mtime: git2::IndexTime::new(0, 0),
+        dev: 0,
+        ino: 0,
+        mode: ours.mode,
+        uid: 0,
+        gid: 0,
+        file_size: 0,
+        id: repo.blob(&[])?,
+        flags: 0,
+        flags_extended: 0,
+        path: ours.path.clone(),
+    })
+}
+
+/// Run the external driver `command` with the three versions of `rela_path`, returning the merged content
+/// if it succeeded, or `None` if it signalled that conflicts remain with a non-zero exit code.
+fn run_external_driver(
+    repo: &git2::Repository,
+    rela_path: &Path,
+    command: &str,
+    ancestor: &[u8],
+    ours: &[u8],
+    theirs: &[u8],
+) -> Result<Option<Vec<u8>>> {
+    let temp_file = |content: &[u8]| -> Result<tempfile::NamedTempFile> {
+        let mut file = tempfile::NamedTempFile::new()?;
+        file.write_all(content)?;
+        file.flush()?;
+        Ok(file)
+    };
+    let (ancestor, ours, theirs) = (temp_file(ancestor)?, temp_file(ours)?, temp_file(theirs)?);
+
+    let marker_size = repo
+        .get_attr(
+            rela_path,
+            "conflict-marker-size",
+            git2::AttrCheckFlags::FILE_THEN_INDEX,
+        )?
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(DEFAULT_MARKER_SIZE);
+    let path = rela_path.to_string_lossy();
+    let command = expand_placeholders(
+        command,
+        &[
+            ('O', quote(&ancestor.path().to_string_lossy())),
+            ('A', quote(&ours.path().to_string_lossy())),
+            ('B', quote(&theirs.path().to_string_lossy())),
+            ('L', marker_size.to_string()),
+            ('P', quote(&path)),
+        ],
+    );
+
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(&command)
+        .current_dir(repo.workdir().unwrap_or(repo.path()))
+        .status()
+        .with_context(|| format!("failed to run '{command}'"))?;
+    if !status.success() {
+        return Ok(None);
+    }
+    // Like Git, the driver leaves the result in the file of our version.
+    Ok(Some(fs::read(ours.path())?))
+}
+
+/// Replace `%<char>` in `command` with its value, and `%%` with `%`.
+fn expand_placeholders(command: &str, values: &[(char, String)]) -> String {
+    let mut out = String::with_capacity(command.len());
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => out.push('%'),
+            Some(placeholder) => match values.iter().find(|(key, _)| *key == placeholder) {
+                Some((_, value)) => out.push_str(value),
+                None => {
+                    out.push('%');
+                    out.push(placeholder);
+                }
+            },
+            None => out.push('%'),
+        }
+    }
+    out
+}
+
+/// Quote `value` for use as single argument in a shell.
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::expand_placeholders;
+
+    #[test]
+    fn placeholders_are_expanded() {
+        let values = [('A', "'ours'".to_string()), ('L', "7".to_string())];
+        assert_eq!(
+            expand_placeholders("driver %A -L %L 100%% %X%", &values),
+            "driver 'ours' -L 7 100% %X%"
+        );
+    }
+}
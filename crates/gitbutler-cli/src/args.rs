@@ -124,6 +124,19 @@ pub mod project {
             /// The long name of the remote reference to track, like `refs/remotes/origin/main`.
             remote_ref_name: RemoteRefname,
         },
+        /// List all worktrees of the project's repository, and the projects they were added as.
+        Worktrees,
+        /// Check the GitButler state of the project for inconsistencies.
+        Doctor {
+            /// Repair the problems that can be repaired safely, after taking a snapshot to undo the repairs with.
+            #[clap(long)]
+            fix: bool,
+        },
+        /// Watch the project for changes and print them as lines of JSON until interrupted.
+        ///
+        /// This only works while the project isn't open in the app, which provides the same events
+        /// on the `events.sock` socket in its data directory.
+        Watch,
     }
 }
 
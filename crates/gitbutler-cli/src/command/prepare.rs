@@ -1,15 +1,17 @@
 use std::path::PathBuf;
 
 use anyhow::{bail, Context};
-use gitbutler_project::Project;
+use gitbutler_project::{LinkedWorktree, Project};
 
 pub fn project_from_path(path: PathBuf) -> anyhow::Result<Project> {
-    let worktree_dir = gix::discover(path)?
+    let repo = gix::discover(path)?;
+    let worktree_dir = repo
         .work_dir()
         .context("Bare repositories aren't supported")?
         .to_owned();
     Ok(Project {
         path: worktree_dir,
+        linked_worktree: LinkedWorktree::from_repository(&repo)?,
         ..Default::default()
     })
 }
@@ -18,6 +20,14 @@ pub fn project_controller(
     app_suffix: Option<String>,
     app_data_dir: Option<PathBuf>,
 ) -> anyhow::Result<gitbutler_project::Controller> {
+    let path = app_data_dir_or_default(app_suffix, app_data_dir)?;
+    Ok(gitbutler_project::Controller::from_path(path))
+}
+
+pub fn app_data_dir_or_default(
+    app_suffix: Option<String>,
+    app_data_dir: Option<PathBuf>,
+) -> anyhow::Result<PathBuf> {
     let path = if let Some(dir) = app_data_dir {
         std::fs::create_dir_all(&dir).context("Failed to assure the designated data-dir exists")?;
         dir
@@ -40,5 +50,5 @@ pub fn project_controller(
         bail!("Path '{}' must be a valid directory", path.display());
     }
     eprintln!("Using projects from '{}'", path.display());
-    Ok(gitbutler_project::Controller::from_path(path))
+    Ok(path)
 }
@@ -1,8 +1,9 @@
 use std::path::PathBuf;
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use gitbutler_project::Project;
 use gitbutler_reference::RemoteRefname;
+use gitbutler_watcher::stream::{EventStream, JsonLines};
 
 use crate::command::debug_print;
 
@@ -35,6 +36,100 @@ pub fn add(
     debug_print(project)
 }
 
+pub fn worktrees(ctrl: gitbutler_project::Controller, path: PathBuf) -> Result<()> {
+    let project = added_project(&ctrl, path)?;
+    for worktree in ctrl.worktrees(project.id)? {
+        println!(
+            "{path} {name} {project_id}",
+            path = worktree.path.display(),
+            name = worktree.name.as_deref().unwrap_or("(main)"),
+            project_id = worktree
+                .project_id
+                .map_or_else(|| "-".to_owned(), |id| id.to_string())
+        );
+    }
+    Ok(())
+}
+
+pub fn watch(app_data_dir: PathBuf, path: PathBuf) -> Result<()> {
+    let ctrl = gitbutler_project::Controller::from_path(&app_data_dir);
+    let project = added_project(&ctrl, path)?;
+    let _guard = project.try_exclusive_access().context(
+        "The project is open in the app - read its events from 'events.sock' in the app data directory instead",
+    )?;
+
+    let stream = EventStream::new(vec![Box::new(JsonLines::new(std::io::stdout()))]);
+    let handler = gitbutler_watcher::Handler::new(
+        ctrl,
+        gitbutler_user::Controller::from_path(&app_data_dir),
+        move |change| {
+            stream.send(&change);
+            Ok(())
+        },
+    );
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(async {
+            let watcher = gitbutler_watcher::watch_in_background(
+                handler,
+                &project.path,
+                project.id,
+                project.file_watcher,
+            )?;
+            // Start with the current state of the stacks.
+            watcher.post(gitbutler_watcher::Action::CalculateVirtualBranches(
+                project.id,
+            ))?;
+            tokio::signal::ctrl_c().await?;
+            Ok::<_, anyhow::Error>(())
+        })
+}
+
+/// Return the project of the worktree at `path`.
+fn added_project(ctrl: &gitbutler_project::Controller, path: PathBuf) -> Result<Project> {
+    let worktree_dir = gix::discover(path)?
+        .work_dir()
+        .context("Bare repositories aren't supported")?
+        .canonicalize()?;
+    ctrl.list()?
+        .into_iter()
+        .find(|project| project.path == worktree_dir)
+        .with_context(|| {
+            format!(
+                "'{}' isn't a GitButler project yet - add it first",
+                worktree_dir.display()
+            )
+        })
+}
+
+pub fn doctor(project: Project, fix: bool) -> Result<()> {
+    let problems = if fix {
+        let outcome = gitbutler_branch_actions::repair_project(&project)?;
+        if let Some(snapshot) = outcome.snapshot {
+            println!("Took snapshot {snapshot} before repairing");
+        }
+        for problem in outcome.repaired {
+            println!("repaired: {problem}");
+        }
+        outcome.remaining
+    } else {
+        gitbutler_branch_actions::diagnose_project(&project)?
+    };
+
+    if problems.is_empty() {
+        println!("No problems found");
+        return Ok(());
+    }
+    for problem in &problems {
+        match problem.repair_description() {
+            Some(repair) => println!("{problem} (--fix will {repair})"),
+            None => println!("{problem}"),
+        }
+    }
+    bail!("Found {} problem(s)", problems.len())
+}
+
 pub fn switch_to_workspace(project: Project, refname: RemoteRefname) -> Result<()> {
     debug_print(gitbutler_branch_actions::set_base_branch(
         &project, &refname,
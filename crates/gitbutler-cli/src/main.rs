@@ -53,6 +53,19 @@ fn main() -> Result<()> {
                 let project = command::prepare::project_from_path(args.current_dir)?;
                 command::project::switch_to_workspace(project, remote_ref_name)
             }
+            Some(project::SubCommands::Worktrees) => {
+                let ctrl = command::prepare::project_controller(app_suffix, app_data_dir)?;
+                command::project::worktrees(ctrl, args.current_dir)
+            }
+            Some(project::SubCommands::Doctor { fix }) => {
+                let project = command::prepare::project_from_path(args.current_dir)?;
+                command::project::doctor(project, fix)
+            }
+            Some(project::SubCommands::Watch) => {
+                let app_data_dir =
+                    command::prepare::app_data_dir_or_default(app_suffix, app_data_dir)?;
+                command::project::watch(app_data_dir, args.current_dir)
+            }
             Some(project::SubCommands::Add {
                 switch_to_workspace,
                 path,
@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use gitbutler_project::Project;
 
 pub struct CommandContext {
@@ -9,9 +9,31 @@ pub struct CommandContext {
 }
 
 impl CommandContext {
-    /// Open the repository identified by `project` and perform some checks.
+    /// Open the repository identified by `project` and perform some checks, and complete or undo
+    /// any transaction of its [journal](gitbutler_project::Journal) that was interrupted.
+    ///
+    /// If the project is a linked worktree, the repository is opened through its private git directory.
     pub fn open(project: &Project) -> Result<Self> {
         let repo = git2::Repository::open(&project.path)?;
+        if let Some(worktree) = &project.linked_worktree {
+            // Another worktree may have been created in the same place after ours was removed, which must not
+            // be confused with ours as the GitButler state lives in the git directory we know.
+            if gix::path::realpath(repo.path()).ok().as_ref() != Some(&worktree.git_dir) {
+                bail!(
+                    "The worktree at '{}' isn't the worktree '{}' of the repository at '{}' anymore",
+                    project.path.display(),
+                    worktree.name,
+                    worktree.common_dir.display()
+                );
+            }
+        }
+
+        // Changes to GitButler's state that were interrupted by a crash are completed or undone
+        // before anything reads it.
+        project
+            .journal()
+            .recover(&repo)
+            .context("Failed to recover interrupted changes to the project")?;
 
         // XXX(qix-): This is a temporary measure to disable GC on the project repository.
         // XXX(qix-): We do this because the internal repository we use to store the "virtual"
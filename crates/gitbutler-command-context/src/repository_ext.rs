@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use anyhow::{Context, Result};
 use gix::bstr::{BString, ByteVec};
 use tracing::instrument;
@@ -8,43 +10,79 @@ use tracing::instrument;
 pub trait RepositoryExtLite {
     /// Exclude files that are larger than `limit_in_bytes` (eg. database.sql which may never be intended to be committed)
     /// so they don't show up in the next diff.
-    fn ignore_large_files_in_diffs(&self, limit_in_bytes: u64) -> Result<()>;
+    ///
+    /// Returns the worktree-relative paths of the excluded files, so callers can account for them without
+    /// reading their content, as is done for files tracked by Git LFS.
+    fn ignore_large_files_in_diffs(&self, limit_in_bytes: u64) -> Result<Vec<PathBuf>>;
+
+    /// Like [`Self::ignore_large_files_in_diffs()`], but only look at the files at or below the
+    /// worktree-relative `paths`.
+    fn ignore_large_files_in_diffs_at(
+        &self,
+        limit_in_bytes: u64,
+        paths: &[PathBuf],
+    ) -> Result<Vec<PathBuf>>;
 }
 
 impl RepositoryExtLite for git2::Repository {
     #[instrument(level = tracing::Level::DEBUG, skip(self), err(Debug))]
-    fn ignore_large_files_in_diffs(&self, limit_in_bytes: u64) -> Result<()> {
-        use gix::bstr::ByteSlice;
-        let repo = gix::open(self.path())?;
-        let worktree_dir = repo
-            .work_dir()
-            .context("All repos are expected to have a worktree")?;
-        let files_to_exclude: Vec<_> = repo
-            .dirwalk_iter(
-                repo.index_or_empty()?,
-                None::<BString>,
-                Default::default(),
-                repo.dirwalk_options()?
-                    .emit_ignored(None)
-                    .emit_pruned(false)
-                    .emit_untracked(gix::dir::walk::EmissionMode::Matching),
-            )?
-            .filter_map(Result::ok)
-            .filter_map(|item| {
-                let path = worktree_dir.join(gix::path::from_bstr(item.entry.rela_path.as_bstr()));
-                let file_is_too_large = path
-                    .metadata()
-                    .map_or(false, |md| md.is_file() && md.len() > limit_in_bytes);
-                file_is_too_large
-                    .then(|| Vec::from(item.entry.rela_path).into_string().ok())
-                    .flatten()
-            })
-            .collect();
-        // TODO(ST): refactor this to be path-safe and ' ' save - the returned list is space separated (!!)
-        //           Just make sure this isn't needed anymore.
-        let ignore_list = files_to_exclude.join(" ");
-        // In-memory, libgit2 internal ignore rule
-        self.add_ignore_rule(&ignore_list)?;
-        Ok(())
+    fn ignore_large_files_in_diffs(&self, limit_in_bytes: u64) -> Result<Vec<PathBuf>> {
+        ignore_large_files(self, limit_in_bytes, None::<BString>)
     }
+
+    #[instrument(level = tracing::Level::DEBUG, skip(self), err(Debug))]
+    fn ignore_large_files_in_diffs_at(
+        &self,
+        limit_in_bytes: u64,
+        paths: &[PathBuf],
+    ) -> Result<Vec<PathBuf>> {
+        // Paths are taken literally, and match everything below them if they are directories.
+        let pathspecs = paths.iter().map(|path| {
+            let mut pathspec = BString::from(":(literal)");
+            pathspec.push_str(gix::path::into_bstr(path.as_path()).as_ref());
+            pathspec
+        });
+        ignore_large_files(self, limit_in_bytes, pathspecs)
+    }
+}
+
+/// Exclude the files larger than `limit_in_bytes` that match `pathspecs` from diffs of `git2_repo`, or all of them
+/// if there is no pathspec.
+fn ignore_large_files(
+    git2_repo: &git2::Repository,
+    limit_in_bytes: u64,
+    pathspecs: impl IntoIterator<Item = impl AsRef<gix::bstr::BStr>>,
+) -> Result<Vec<PathBuf>> {
+    use gix::bstr::ByteSlice;
+    let repo = gix::open(git2_repo.path())?;
+    let worktree_dir = repo
+        .work_dir()
+        .context("All repos are expected to have a worktree")?;
+    let files_to_exclude: Vec<_> = repo
+        .dirwalk_iter(
+            repo.index_or_empty()?,
+            pathspecs,
+            Default::default(),
+            repo.dirwalk_options()?
+                .emit_ignored(None)
+                .emit_pruned(false)
+                .emit_untracked(gix::dir::walk::EmissionMode::Matching),
+        )?
+        .filter_map(Result::ok)
+        .filter_map(|item| {
+            let path = worktree_dir.join(gix::path::from_bstr(item.entry.rela_path.as_bstr()));
+            let file_is_too_large = path
+                .metadata()
+                .map_or(false, |md| md.is_file() && md.len() > limit_in_bytes);
+            file_is_too_large
+                .then(|| Vec::from(item.entry.rela_path).into_string().ok())
+                .flatten()
+        })
+        .collect();
+    // TODO(ST): refactor this to be path-safe and ' ' save - the returned list is space separated (!!)
+    //           Just make sure this isn't needed anymore.
+    let ignore_list = files_to_exclude.join(" ");
+    // In-memory, libgit2 internal ignore rule
+    git2_repo.add_ignore_rule(&ignore_list)?;
+    Ok(files_to_exclude.into_iter().map(PathBuf::from).collect())
 }
@@ -10,12 +10,16 @@ pub struct GbConfig {
     pub signing_format: Option<String>,
     pub gpg_program: Option<String>,
     pub gpg_ssh_program: Option<String>,
+    pub gpg_x509_program: Option<String>,
+    pub gpg_ssh_default_key_command: Option<String>,
 }
 const SIGN_COMMITS: &str = "gitbutler.signCommits";
 const SIGNING_KEY: &str = "user.signingKey";
 const SIGNING_FORMAT: &str = "gpg.format";
 const GPG_PROGRAM: &str = "gpg.program";
 const GPG_SSH_PROGRAM: &str = "gpg.ssh.program";
+const GPG_X509_PROGRAM: &str = "gpg.x509.program";
+const GPG_SSH_DEFAULT_KEY_COMMAND: &str = "gpg.ssh.defaultKeyCommand";
 
 pub trait GitConfig {
     fn gb_config(&self) -> Result<GbConfig>;
@@ -29,12 +33,16 @@ impl GitConfig for git2::Repository {
         let signing_format = get_string(self, SIGNING_FORMAT)?;
         let gpg_program = get_string(self, GPG_PROGRAM)?;
         let gpg_ssh_program = get_string(self, GPG_SSH_PROGRAM)?;
+        let gpg_x509_program = get_string(self, GPG_X509_PROGRAM)?;
+        let gpg_ssh_default_key_command = get_string(self, GPG_SSH_DEFAULT_KEY_COMMAND)?;
         Ok(GbConfig {
             sign_commits,
             signing_key,
             signing_format,
             gpg_program,
             gpg_ssh_program,
+            gpg_x509_program,
+            gpg_ssh_default_key_command,
         })
     }
     fn set_gb_config(&self, config: GbConfig) -> Result<()> {
@@ -53,6 +61,16 @@ impl GitConfig for git2::Repository {
         if let Some(gpg_ssh_program) = config.gpg_ssh_program {
             set_local_string(self, GPG_SSH_PROGRAM, &gpg_ssh_program)?;
         }
+        if let Some(gpg_x509_program) = config.gpg_x509_program {
+            set_local_string(self, GPG_X509_PROGRAM, &gpg_x509_program)?;
+        }
+        if let Some(gpg_ssh_default_key_command) = config.gpg_ssh_default_key_command {
+            set_local_string(
+                self,
+                GPG_SSH_DEFAULT_KEY_COMMAND,
+                &gpg_ssh_default_key_command,
+            )?;
+        }
         Ok(())
     }
 }
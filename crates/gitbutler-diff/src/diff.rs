@@ -4,10 +4,15 @@ use anyhow::{Context, Result};
 use bstr::{BStr, BString, ByteSlice, ByteVec};
 use gitbutler_cherry_pick::RepositoryExt;
 use gitbutler_command_context::RepositoryExtLite;
+use gitbutler_project::DiffSettings;
 use gitbutler_serde::BStringForFrontend;
 use serde::{Deserialize, Serialize};
 use tracing::instrument;
 
+use crate::{
+    apply_histogram, detect_moved_lines, lfs, update_moved_lines, MoveDirection, MovedBlock,
+};
+
 pub type DiffByPathMap = HashMap<PathBuf, FileDiff>;
 
 /// The type of change
@@ -51,6 +56,9 @@ pub struct GitHunk {
     pub diff_lines: BStringForFrontend,
     pub binary: bool,
     pub change_type: ChangeType,
+    /// Blocks of lines in this hunk that were moved from or to another location,
+    /// if [moved-lines detection](crate::detect_moved_lines) was performed.
+    pub moved: Vec<MovedBlock>,
 }
 
 /// Lifecycle
@@ -66,6 +74,7 @@ impl GitHunk {
             diff_lines: hex_id.into(),
             binary: true,
             change_type,
+            moved: Vec::new(),
         }
     }
 
@@ -79,6 +88,7 @@ impl GitHunk {
             diff_lines: Default::default(),
             binary: false,
             change_type: ChangeType::Added,
+            moved: Vec::new(),
         }
     }
 }
@@ -123,21 +133,71 @@ pub struct FileDiff {
     pub new_size_bytes: u64,
 }
 
+/// Diff the worktree and index against the tree of `commit_oid`, configured by `settings`.
 #[instrument(level = tracing::Level::DEBUG, skip(repo))]
-pub fn workdir(repo: &git2::Repository, commit_oid: git2::Oid) -> Result<DiffByPathMap> {
+pub fn workdir(
+    repo: &git2::Repository,
+    commit_oid: git2::Oid,
+    settings: DiffSettings,
+) -> Result<DiffByPathMap> {
+    let mut files = workdir_at_paths(repo, commit_oid, settings, None)?;
+    if settings.detect_moved_lines {
+        detect_moved_lines(&mut files);
+    }
+    Ok(files)
+}
+
+/// Update `files`, as previously produced by [`workdir()`] for the same `commit_oid` and `settings`,
+/// with the current state of the worktree and index at `paths`, without diffing any other path.
+///
+/// `paths` are relative to the worktree and may be directories, in which case all files below them are updated.
+#[instrument(level = tracing::Level::DEBUG, skip(repo, files), fields(paths = paths.len()))]
+pub fn update_workdir(
+    repo: &git2::Repository,
+    commit_oid: git2::Oid,
+    settings: DiffSettings,
+    paths: &[PathBuf],
+    files: &mut DiffByPathMap,
+) -> Result<()> {
+    if paths.is_empty() {
+        return Ok(());
+    }
+    let updated = workdir_at_paths(repo, commit_oid, settings, Some(paths))?;
+    files.retain(|path, _| !paths.iter().any(|changed| path.starts_with(changed)));
+    files.extend(updated);
+    // Moves may cross into files that didn't change, so they are looked for in the whole set.
+    if settings.detect_moved_lines {
+        update_moved_lines(files, paths);
+    }
+    Ok(())
+}
+
+/// Diff the worktree and index against the tree of `commit_oid`, limited to `paths` if given.
+fn workdir_at_paths(
+    repo: &git2::Repository,
+    commit_oid: git2::Oid,
+    settings: DiffSettings,
+    paths: Option<&[PathBuf]>,
+) -> Result<DiffByPathMap> {
     let commit = repo
         .find_commit(commit_oid)
         .context("failed to find commit")?;
     let old_tree = repo.find_real_tree(&commit, Default::default())?;
 
     let mut diff_opts = git2::DiffOptions::new();
-    diff_opts
+    settings
+        .apply_to(&mut diff_opts, None)
         .recurse_untracked_dirs(true)
         .include_untracked(true)
         .show_binary(true)
         .show_untracked_content(true)
-        .ignore_submodules(true)
-        .context_lines(3);
+        .ignore_submodules(false);
+    if let Some(paths) = paths {
+        for path in paths {
+            diff_opts.pathspec(path.as_path());
+        }
+        diff_opts.disable_pathspec_match(true);
+    }
 
     let mut index = repo.index()?;
     // Just a hack to resolve conflicts, which don't get diffed.
@@ -157,32 +217,50 @@ pub fn workdir(repo: &git2::Repository, commit_oid: git2::Oid) -> Result<DiffByP
     for conflict_path_to_resolve in paths_to_add {
         index.add_path(conflict_path_to_resolve.as_ref())?;
     }
-    repo.ignore_large_files_in_diffs(50_000_000)?;
+    let large_files = match paths {
+        Some(paths) => repo.ignore_large_files_in_diffs_at(50_000_000, paths)?,
+        None => repo.ignore_large_files_in_diffs(50_000_000)?,
+    };
     let diff = repo.diff_tree_to_workdir_with_index(Some(&old_tree), Some(&mut diff_opts))?;
-    hunks_by_filepath(Some(repo), &diff)
+    let mut files = hunks_by_filepath(Some(repo), &diff)?;
+    apply_histogram(repo, &diff, &mut files, settings, None)?;
+    lfs::pointers_for_worktree_changes(repo, &old_tree, &large_files, &mut files)?;
+    // Submodules with uncommitted changes only aren't changed in the eyes of the superproject.
+    files.retain(|_, file| {
+        !matches!(file.hunks.as_slice(), [hunk] if hunk.submodule_change().map_or(false, |change| change.is_unchanged()))
+    });
+    Ok(files)
 }
 
+/// Diff `old_tree` against `new_tree`, configured by `settings`.
+/// If `include_context` is `false`, no context lines are produced regardless of `settings`.
 pub fn trees(
     repo: &git2::Repository,
     old_tree: &git2::Tree,
     new_tree: &git2::Tree,
     include_context: bool,
+    settings: DiffSettings,
 ) -> Result<DiffByPathMap> {
     let mut diff_opts = git2::DiffOptions::new();
     let context_lines = match include_context {
-        true => 3,
-        false => 0,
+        true => None,
+        false => Some(0),
     };
-    diff_opts
+    settings
+        .apply_to(&mut diff_opts, context_lines)
         .recurse_untracked_dirs(true)
         .include_untracked(true)
         .show_binary(true)
-        .ignore_submodules(true)
-        .context_lines(context_lines)
+        .ignore_submodules(false)
         .show_untracked_content(true);
 
     let diff = repo.diff_tree_to_tree(Some(old_tree), Some(new_tree), Some(&mut diff_opts))?;
-    hunks_by_filepath(None, &diff)
+    let mut files = hunks_by_filepath(None, &diff)?;
+    apply_histogram(repo, &diff, &mut files, settings, context_lines)?;
+    if settings.detect_moved_lines {
+        detect_moved_lines(&mut files);
+    }
+    Ok(files)
 }
 
 /// Transform `diff` into a mapping of `worktree-relative path -> FileDiff`, where `FileDiff` is
@@ -229,7 +307,9 @@ pub fn hunks_by_filepath(
                     Some(LineOrHexHash::Line(buf.into()))
                 }
                 D::Binary => {
+                    // LFS-tracked files are turned into pointers later, avoid storing their content.
                     if let Some((full_path, repo)) = repo
+                        .filter(|repo| !lfs::is_lfs_tracked(repo, file_path))
                         .and_then(|repo| repo.workdir())
                         .map(|workdir| workdir.join(file_path))
                         .zip(repo)
@@ -297,6 +377,7 @@ pub fn hunks_by_filepath(
                                         diff_lines: line.into_owned().into(),
                                         binary: false,
                                         change_type,
+                                        moved: Vec::new(),
                                     }
                                 }
                                 LineOrHexHash::HexHashOfBinaryBlob(id) => {
@@ -408,6 +489,17 @@ pub fn reverse_hunk(hunk: &GitHunk) -> Option<GitHunk> {
             diff_lines: diff.into(),
             binary: hunk.binary,
             change_type: new_change_type,
+            moved: hunk
+                .moved
+                .iter()
+                .map(|block| MovedBlock {
+                    direction: match block.direction {
+                        MoveDirection::From => MoveDirection::To,
+                        MoveDirection::To => MoveDirection::From,
+                    },
+                    ..block.clone()
+                })
+                .collect(),
         })
     }
 }
@@ -0,0 +1,345 @@
+use std::ops::Range;
+
+use anyhow::Result;
+use bstr::{BString, ByteSlice, ByteVec};
+use gitbutler_project::{DiffAlgorithm, DiffSettings};
+use gix::diff::blob::{
+    diff_with_tokens,
+    intern::{Interner, Token},
+    Algorithm,
+};
+
+use crate::{ChangeType, DiffByPathMap, GitHunk};
+
+/// If `settings` select the [histogram algorithm](DiffAlgorithm::Histogram), recompute the hunks of all
+/// text files in `files` with it, as `libgit2` can't and produced `diff` with the patience algorithm instead.
+/// `context_lines` overrides [`DiffSettings::context_lines`] if set, just like in [`DiffSettings::apply_to()`].
+///
+/// `files` must have been produced from `diff` by [`hunks_by_filepath()`](crate::hunks_by_filepath()).
+/// Contents that aren't in the object database of `repo` are read from its worktree.
+pub fn apply_histogram(
+    repo: &git2::Repository,
+    diff: &git2::Diff<'_>,
+    files: &mut DiffByPathMap,
+    settings: DiffSettings,
+    context_lines: Option<u32>,
+) -> Result<()> {
+    if settings.algorithm != DiffAlgorithm::Histogram {
+        return Ok(());
+    }
+    let context_lines = context_lines.unwrap_or(settings.context_lines);
+    for delta in diff.deltas() {
+        let (old_file, new_file) = (delta.old_file(), delta.new_file());
+        let Some(file) = new_file
+            .path()
+            .or_else(|| old_file.path())
+            .and_then(|path| files.get_mut(path))
+        else {
+            continue;
+        };
+        // Binary files, submodules and files without changed lines are left as they are.
+        let is_text_diff = !file.binary
+            && !file.hunks.is_empty()
+            && file
+                .hunks
+                .iter()
+                .all(|hunk| !hunk.binary && hunk.diff_lines.starts_with(b"@@"))
+            && old_file.mode() != git2::FileMode::Commit
+            && new_file.mode() != git2::FileMode::Commit;
+        if !is_text_diff {
+            continue;
+        }
+        let old = content(repo, &old_file)?;
+        let new = content(repo, &new_file)?;
+        let change_type = file.hunks[0].change_type;
+        file.hunks = histogram_hunks(&old, &new, settings, context_lines, change_type);
+    }
+    Ok(())
+}
+
+fn content(repo: &git2::Repository, file: &git2::DiffFile<'_>) -> Result<Vec<u8>> {
+    if !file.exists() {
+        return Ok(Vec::new());
+    }
+    if let Ok(blob) = repo.find_blob(file.id()) {
+        return Ok(blob.content().to_owned());
+    }
+    match (repo.workdir(), file.path()) {
+        (Some(workdir), Some(path)) => Ok(std::fs::read(workdir.join(path))?),
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Diff `old` and `new` line by line with the histogram algorithm, and return the hunks with `context_lines`
+/// of context in the format `libgit2` produces them.
+fn histogram_hunks(
+    old: &[u8],
+    new: &[u8],
+    settings: DiffSettings,
+    context_lines: u32,
+    change_type: ChangeType,
+) -> Vec<GitHunk> {
+    let old_lines: Vec<&[u8]> = old.lines_with_terminator().collect();
+    let new_lines: Vec<&[u8]> = new.lines_with_terminator().collect();
+    let mut interner = Interner::new(old_lines.len() + new_lines.len());
+    let mut tokens = |lines: &[&[u8]]| -> Vec<Token> {
+        lines
+            .iter()
+            .map(|line| interner.intern(comparison_key(line, settings)))
+            .collect()
+    };
+    let old_tokens = tokens(&old_lines);
+    let new_tokens = tokens(&new_lines);
+
+    let mut changes = Vec::<(Range<u32>, Range<u32>)>::new();
+    diff_with_tokens(
+        Algorithm::Histogram,
+        &old_tokens,
+        &new_tokens,
+        interner.num_tokens(),
+        |before: Range<u32>, after: Range<u32>| changes.push((before, after)),
+    );
+    if settings.ignore_blank_lines {
+        let is_blank = |line: &&[u8]| line.trim().is_empty();
+        changes.retain(|(before, after)| {
+            !(old_lines[range(before)].iter().all(is_blank)
+                && new_lines[range(after)].iter().all(is_blank))
+        });
+    }
+
+    let mut hunks = Vec::new();
+    let mut remaining = changes.as_slice();
+    while !remaining.is_empty() {
+        // Changes whose context would touch or overlap end up in the same hunk.
+        let in_hunk = 1 + remaining
+            .windows(2)
+            .take_while(|pair| pair[1].0.start - pair[0].0.end <= 2 * context_lines)
+            .count();
+        let (group, rest) = remaining.split_at(in_hunk);
+        remaining = rest;
+
+        let (first_before, first_after) = &group[0];
+        let (last_before, last_after) = &group[group.len() - 1];
+        let old_from = first_before.start.saturating_sub(context_lines);
+        let new_from = first_after.start - (first_before.start - old_from);
+        let old_to = (last_before.end + context_lines).min(old_lines.len() as u32);
+        let new_to = last_after.end + (old_to - last_before.end);
+
+        let (old_start, old_count) = hunk_range(old_from, old_to);
+        let (new_start, new_count) = hunk_range(new_from, new_to);
+        let mut diff_lines = BString::from(format!(
+            "@@ -{} +{} @@\n",
+            header_range(old_start, old_count),
+            header_range(new_start, new_count)
+        ));
+        let mut old_line = old_from;
+        for (before, after) in group {
+            push_lines(
+                &mut diff_lines,
+                b' ',
+                &old_lines[old_line as usize..before.start as usize],
+            );
+            push_lines(&mut diff_lines, b'-', &old_lines[range(before)]);
+            push_lines(&mut diff_lines, b'+', &new_lines[range(after)]);
+            old_line = before.end;
+        }
+        push_lines(
+            &mut diff_lines,
+            b' ',
+            &old_lines[old_line as usize..old_to as usize],
+        );
+
+        hunks.push(GitHunk {
+            old_start,
+            old_lines: old_count,
+            new_start,
+            new_lines: new_count,
+            diff_lines: diff_lines.into(),
+            binary: false,
+            change_type,
+            moved: Vec::new(),
+        });
+    }
+    hunks
+}
+
+/// Return the key by which `line` is compared, which disregards whitespace as configured by `settings`.
+fn comparison_key(line: &[u8], settings: DiffSettings) -> BString {
+    if settings.ignore_whitespace {
+        line.iter()
+            .copied()
+            .filter(|byte| !byte.is_ascii_whitespace())
+            .collect::<Vec<_>>()
+            .into()
+    } else if settings.ignore_whitespace_change {
+        let mut key = BString::default();
+        for word in line.fields_with(|c| c.is_ascii_whitespace()) {
+            if !key.is_empty() || line.first().map_or(false, u8::is_ascii_whitespace) {
+                key.push_byte(b' ');
+            }
+            key.push_str(word);
+        }
+        key
+    } else if settings.ignore_whitespace_eol {
+        line.trim_end_with(|c| c.is_ascii_whitespace()).into()
+    } else {
+        line.into()
+    }
+}
+
+/// Return the start and amount of lines of a hunk spanning the zero-based lines `from..to`, where the start
+/// is one-based, or the line before the hunk if it's empty, just like in the header of the hunk.
+fn hunk_range(from: u32, to: u32) -> (u32, u32) {
+    let count = to - from;
+    let start = if count == 0 { from } else { from + 1 };
+    (start, count)
+}
+
+fn header_range(start: u32, count: u32) -> String {
+    if count == 1 {
+        start.to_string()
+    } else {
+        format!("{start},{count}")
+    }
+}
+
+/// Append `lines` to `diff_lines`, each prefixed with `origin`, and mark a missing newline at the end of the file
+/// the way `libgit2` does.
+fn push_lines(diff_lines: &mut BString, origin: u8, lines: &[&[u8]]) {
+    for line in lines {
+        diff_lines.push_byte(origin);
+        diff_lines.push_str(line);
+        if !line.ends_with(b"\n") {
+            diff_lines.push_str("\n\\ No newline at end of file\n");
+        }
+    }
+}
+
+fn range(range: &Range<u32>) -> Range<usize> {
+    range.start as usize..range.end as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diff(old: &str, new: &str, settings: DiffSettings) -> Vec<(u32, u32, u32, u32, String)> {
+        histogram_hunks(
+            old.as_bytes(),
+            new.as_bytes(),
+            settings,
+            settings.context_lines,
+            ChangeType::Modified,
+        )
+        .into_iter()
+        .map(|hunk| {
+            (
+                hunk.old_start,
+                hunk.old_lines,
+                hunk.new_start,
+                hunk.new_lines,
+                hunk.diff_lines.to_string(),
+            )
+        })
+        .collect()
+    }
+
+    fn settings(context_lines: u32) -> DiffSettings {
+        DiffSettings {
+            algorithm: DiffAlgorithm::Histogram,
+            context_lines,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn hunks_have_the_format_of_libgit2() {
+        assert_eq!(
+            diff("a\nb\nc\n", "a\nB\nc\n", settings(3)),
+            [(1, 3, 1, 3, "@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n".into())]
+        );
+        assert_eq!(
+            diff("a\nb\nc\n", "a\nb\nc\nd\n", settings(0)),
+            [(3, 0, 4, 1, "@@ -3,0 +4 @@\n+d\n".into())],
+            "empty ranges start at the line before, and single lines have no count"
+        );
+        assert_eq!(
+            diff("", "a\n", settings(3)),
+            [(0, 0, 1, 1, "@@ -0,0 +1 @@\n+a\n".into())]
+        );
+        assert_eq!(
+            diff("a\n", "a", settings(3)),
+            [(
+                1,
+                1,
+                1,
+                1,
+                "@@ -1 +1 @@\n-a\n+a\n\\ No newline at end of file\n".into()
+            )]
+        );
+    }
+
+    #[test]
+    fn changes_with_overlapping_context_share_a_hunk() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n";
+        assert_eq!(
+            diff(old, "1\nx\n3\n4\n5\n6\n7\n8\ny\n10\n", settings(1)).len(),
+            2
+        );
+        assert_eq!(
+            diff(old, "1\nx\n3\n4\ny\n6\n7\n8\n9\n10\n", settings(1)),
+            [(
+                1,
+                6,
+                1,
+                6,
+                "@@ -1,6 +1,6 @@\n 1\n-2\n+x\n 3\n 4\n-5\n+y\n 6\n".into()
+            )]
+        );
+    }
+
+    #[test]
+    fn whitespace_is_ignored_as_configured() {
+        let old = "a b\nc \n";
+        let new = "a  b\nc\n";
+        assert_eq!(diff(old, new, settings(0)).len(), 1);
+        assert!(diff(
+            old,
+            new,
+            DiffSettings {
+                ignore_whitespace_change: true,
+                ..settings(0)
+            }
+        )
+        .is_empty());
+        assert_eq!(
+            diff(
+                old,
+                new,
+                DiffSettings {
+                    ignore_whitespace_eol: true,
+                    ..settings(0)
+                }
+            ),
+            [(1, 1, 1, 1, "@@ -1 +1 @@\n-a b\n+a  b\n".into())]
+        );
+        assert!(diff(
+            "ab\n",
+            "a b\n",
+            DiffSettings {
+                ignore_whitespace: true,
+                ..settings(0)
+            }
+        )
+        .is_empty());
+        assert!(diff(
+            "a\nb\n",
+            "a\n\n\nb\n",
+            DiffSettings {
+                ignore_blank_lines: true,
+                ..settings(0)
+            }
+        )
+        .is_empty());
+    }
+}
@@ -0,0 +1,371 @@
+//! Support for files tracked by [Git LFS](https://git-lfs.com).
+//!
+//! Git stores such files as small pointer files, while the worktree contains the actual (smudged) content.
+//! As `libgit2` doesn't run the `lfs` filter, we translate between both representations here so diffs
+//! and trees only ever contain pointers.
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use anyhow::{Context, Result};
+use bstr::{BString, ByteSlice};
+use sha2::{Digest, Sha256};
+
+use crate::{ChangeType, DiffByPathMap, FileDiff, GitHunk};
+
+const POINTER_VERSION: &str = "https://git-lfs.github.com/spec/v1";
+/// Pointer files are tiny, anything larger than this can't be a pointer.
+const MAX_POINTER_SIZE: u64 = 1024;
+
+/// The content of a Git LFS pointer file, which identifies the actual content by hash and size.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LfsPointer {
+    /// The hex-encoded SHA-256 of the actual content.
+    pub oid: String,
+    /// The size of the actual content in bytes.
+    pub size: u64,
+}
+
+/// Lifecycle
+impl LfsPointer {
+    /// Parse `data` as pointer file, or return `None` if it isn't one.
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        if data.len() as u64 > MAX_POINTER_SIZE {
+            return None;
+        }
+        let mut lines = data.lines();
+        let version = lines.next()?.strip_prefix(b"version ")?;
+        if version != POINTER_VERSION.as_bytes() {
+            return None;
+        }
+        let (mut oid, mut size) = (None, None);
+        for line in lines {
+            if let Some(hex) = line.strip_prefix(b"oid sha256:") {
+                oid = Some(hex.to_str().ok()?.to_owned());
+            } else if let Some(bytes) = line.strip_prefix(b"size ") {
+                size = Some(bytes.to_str().ok()?.parse().ok()?);
+            }
+        }
+        let oid =
+            oid.filter(|oid| oid.len() == 64 && oid.bytes().all(|b| b.is_ascii_hexdigit()))?;
+        Some(LfsPointer { oid, size: size? })
+    }
+
+    /// Compute the pointer for the file at `path` by hashing its content.
+    ///
+    /// The result is cached by path, size and modification time, so unchanged files are only hashed once.
+    /// Only the 1024 most recently used pointers are kept, across all repositories.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        static CACHE: Mutex<PointerCache> = Mutex::new(PointerCache {
+            entries: BTreeMap::new(),
+            uses: 0,
+        });
+
+        let metadata = path.metadata()?;
+        let (size, mtime) = (metadata.len(), metadata.modified()?);
+        if let Some(pointer) = CACHE.lock().expect("not poisoned").get(path, size, mtime) {
+            return Ok(pointer);
+        }
+
+        let mut hasher = Sha256::new();
+        let mut file = fs::File::open(path)?;
+        let size = io::copy(&mut file, &mut hasher)?;
+        let pointer = LfsPointer {
+            oid: hex::encode(hasher.finalize()),
+            size,
+        };
+        CACHE
+            .lock()
+            .expect("not poisoned")
+            .insert(path.to_owned(), size, mtime, pointer.clone());
+        Ok(pointer)
+    }
+}
+
+/// The maximum amount of pointers kept by [`LfsPointer::from_file()`], which is plenty for the LFS-tracked
+/// files that change in the worktrees of all open projects.
+const MAX_CACHED_POINTERS: usize = 1024;
+
+/// Pointers by path, along with the size and modification time of the file they were computed from.
+struct PointerCache {
+    entries: BTreeMap<PathBuf, CachedPointer>,
+    /// Incremented on each access, to know which entry was used least recently.
+    uses: u64,
+}
+
+struct CachedPointer {
+    size: u64,
+    mtime: SystemTime,
+    pointer: LfsPointer,
+    last_use: u64,
+}
+
+impl PointerCache {
+    fn get(&mut self, path: &Path, size: u64, mtime: SystemTime) -> Option<LfsPointer> {
+        self.uses += 1;
+        let entry = self
+            .entries
+            .get_mut(path)
+            .filter(|entry| entry.size == size && entry.mtime == mtime)?;
+        entry.last_use = self.uses;
+        Some(entry.pointer.clone())
+    }
+
+    fn insert(&mut self, path: PathBuf, size: u64, mtime: SystemTime, pointer: LfsPointer) {
+        self.uses += 1;
+        if self.entries.len() >= MAX_CACHED_POINTERS && !self.entries.contains_key(&path) {
+            let least_recently_used = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_use)
+                .map(|(path, _)| path.clone());
+            if let Some(path) = least_recently_used {
+                self.entries.remove(&path);
+            }
+        }
+        self.entries.insert(
+            path,
+            CachedPointer {
+                size,
+                mtime,
+                pointer,
+                last_use: self.uses,
+            },
+        );
+    }
+}
+
+/// Access
+impl LfsPointer {
+    /// Serialize this pointer in its canonical form, as `git lfs clean` would.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        format!(
+            "version {POINTER_VERSION}\noid sha256:{}\nsize {}\n",
+            self.oid, self.size
+        )
+        .into_bytes()
+    }
+
+    /// The path at which the object is kept in the local LFS store of the repository at `git_dir`.
+    pub fn object_path(&self, git_dir: &Path) -> PathBuf {
+        git_dir
+            .join("lfs")
+            .join("objects")
+            .join(&self.oid[..2])
+            .join(&self.oid[2..4])
+            .join(&self.oid)
+    }
+}
+
+/// Return `true` if `.gitattributes` declare `rela_path` to be handled by the `lfs` filter.
+pub fn is_lfs_tracked(repo: &git2::Repository, rela_path: &Path) -> bool {
+    repo.get_attr(rela_path, "filter", git2::AttrCheckFlags::FILE_THEN_INDEX)
+        .ok()
+        .flatten()
+        == Some("lfs")
+}
+
+/// Return the pointer for the LFS-tracked file at `rela_path` in the worktree of `repo`, or `None` if
+/// the file doesn't exist.
+///
+/// If the worktree contains a pointer file, as is the case if the `lfs` filter isn't installed,
+/// it is used as is, otherwise the pointer is computed from the file content.
+pub fn worktree_pointer(repo: &git2::Repository, rela_path: &Path) -> Result<Option<LfsPointer>> {
+    let path = repo
+        .workdir()
+        .context("LFS pointers are only computed for non-bare repositories")?
+        .join(rela_path);
+    let Ok(metadata) = path.symlink_metadata() else {
+        return Ok(None);
+    };
+    if !metadata.is_file() {
+        return Ok(None);
+    }
+    if metadata.len() <= MAX_POINTER_SIZE {
+        if let Some(pointer) = LfsPointer::from_bytes(&fs::read(&path)?) {
+            return Ok(Some(pointer));
+        }
+    }
+    LfsPointer::from_file(&path).map(Some)
+}
+
+/// Place the content of the worktree file at `rela_path` into the local LFS store if it matches `pointer`,
+/// which is what `git lfs clean` would do before the pointer is committed.
+pub fn store_object(repo: &git2::Repository, rela_path: &Path, pointer: &LfsPointer) -> Result<()> {
+    let object_path = pointer.object_path(repo.path());
+    if object_path.exists() {
+        return Ok(());
+    }
+    let Some(workdir) = repo.workdir() else {
+        return Ok(());
+    };
+    let path = workdir.join(rela_path);
+    if worktree_pointer(repo, rela_path)?.as_ref() != Some(pointer)
+        || LfsPointer::from_bytes(&fs::read(&path)?).is_some()
+    {
+        // The content isn't available, so there is nothing to store.
+        return Ok(());
+    }
+    let object_dir = object_path
+        .parent()
+        .expect("objects are stored in a directory");
+    fs::create_dir_all(object_dir)?;
+    let tmp = tempfile::NamedTempFile::new_in(object_dir)?;
+    fs::copy(&path, tmp.path())?;
+    tmp.persist(&object_path)?;
+    Ok(())
+}
+
+/// Replace the pointer file at `rela_path` in the worktree with its content from the local LFS store,
+/// which is what `git lfs smudge` would do on checkout.
+///
+/// Returns `true` if the file was replaced, or `false` if it isn't a pointer or the content isn't available.
+pub fn smudge_worktree_file(repo: &git2::Repository, rela_path: &Path) -> Result<bool> {
+    let Some(workdir) = repo.workdir() else {
+        return Ok(false);
+    };
+    let path = workdir.join(rela_path);
+    let Ok(metadata) = path.symlink_metadata() else {
+        return Ok(false);
+    };
+    if !metadata.is_file() || metadata.len() > MAX_POINTER_SIZE {
+        return Ok(false);
+    }
+    let Some(pointer) = LfsPointer::from_bytes(&fs::read(&path)?) else {
+        return Ok(false);
+    };
+    let object_path = pointer.object_path(repo.path());
+    if !object_path.is_file() {
+        return Ok(false);
+    }
+    let mut tmp = tempfile::NamedTempFile::new_in(path.parent().unwrap_or(workdir))?;
+    io::copy(&mut fs::File::open(&object_path)?, &mut tmp)?;
+    tmp.flush()?;
+    tmp.as_file().set_permissions(metadata.permissions())?;
+    tmp.persist(&path)?;
+    Ok(true)
+}
+
+/// Replace the changes of LFS-tracked files in `files`, as obtained by diffing `old_tree` against the
+/// worktree of `repo`, with changes between pointer files.
+/// `excluded_paths` are files that were left out of the diff for their size, and those which are LFS-tracked
+/// are added by comparing their pointers, so their content is only hashed when their size or modification
+/// time changes.
+/// Files whose content matches the pointer in `old_tree` are removed as they are unchanged in the eyes of Git LFS.
+pub(crate) fn pointers_for_worktree_changes(
+    repo: &git2::Repository,
+    old_tree: &git2::Tree,
+    excluded_paths: &[PathBuf],
+    files: &mut DiffByPathMap,
+) -> Result<()> {
+    let lfs_paths: Vec<_> = files
+        .keys()
+        .chain(excluded_paths)
+        .filter(|path| is_lfs_tracked(repo, path))
+        .cloned()
+        .collect();
+    for path in lfs_paths {
+        let old = old_tree
+            .get_path(&path)
+            .ok()
+            .and_then(|entry| repo.find_blob(entry.id()).ok())
+            .and_then(|blob| LfsPointer::from_bytes(blob.content()));
+        let new = worktree_pointer(repo, &path)?;
+        let change_type = match (&old, &new) {
+            (None, Some(_)) => ChangeType::Added,
+            (Some(_), None) => ChangeType::Deleted,
+            _ => ChangeType::Modified,
+        };
+        match pointer_hunk(old.as_ref(), new.as_ref(), change_type) {
+            None => {
+                files.remove(&path);
+            }
+            Some(hunk) => {
+                let file = files.entry(path.clone()).or_insert_with(|| FileDiff {
+                    old_path: Some(path.clone()),
+                    new_path: Some(path.clone()),
+                    hunks: Vec::new(),
+                    skipped: false,
+                    binary: false,
+                    old_size_bytes: 0,
+                    new_size_bytes: 0,
+                });
+                file.hunks = vec![hunk];
+                file.binary = false;
+                file.old_size_bytes = old.map_or(0, |pointer| pointer.size);
+                file.new_size_bytes = new.map_or(0, |pointer| pointer.size);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Produce a hunk that describes the change from `old` to `new` pointer as textual diff of the pointer files,
+/// or `None` if both are the same.
+pub(crate) fn pointer_hunk(
+    old: Option<&LfsPointer>,
+    new: Option<&LfsPointer>,
+    change_type: ChangeType,
+) -> Option<GitHunk> {
+    if old == new {
+        return None;
+    }
+    let old_content = old.map(LfsPointer::to_bytes).unwrap_or_default();
+    let new_content = new.map(LfsPointer::to_bytes).unwrap_or_default();
+    let old_lines: Vec<_> = old_content.lines_with_terminator().collect();
+    let new_lines: Vec<_> = new_content.lines_with_terminator().collect();
+
+    let prefix = old_lines
+        .iter()
+        .zip(&new_lines)
+        .take_while(|(old, new)| old == new)
+        .count();
+    let suffix = old_lines[prefix..]
+        .iter()
+        .rev()
+        .zip(new_lines[prefix..].iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+
+    let (old_start, new_start) = (
+        u32::from(!old_lines.is_empty()),
+        u32::from(!new_lines.is_empty()),
+    );
+    let mut diff = BString::from(format!(
+        "@@ -{old_start},{} +{new_start},{} @@\n",
+        old_lines.len(),
+        new_lines.len()
+    ));
+    let context = |diff: &mut BString, lines: &[&[u8]]| {
+        for line in lines {
+            diff.push(b' ');
+            diff.extend_from_slice(line);
+        }
+    };
+    context(&mut diff, &old_lines[..prefix]);
+    for line in &old_lines[prefix..old_lines.len() - suffix] {
+        diff.push(b'-');
+        diff.extend_from_slice(line);
+    }
+    for line in &new_lines[prefix..new_lines.len() - suffix] {
+        diff.push(b'+');
+        diff.extend_from_slice(line);
+    }
+    context(&mut diff, &old_lines[old_lines.len() - suffix..]);
+
+    Some(GitHunk {
+        old_start,
+        old_lines: old_lines.len() as u32,
+        new_start,
+        new_lines: new_lines.len() as u32,
+        diff_lines: diff.into(),
+        binary: false,
+        change_type,
+        moved: Vec::new(),
+    })
+}
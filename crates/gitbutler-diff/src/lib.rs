@@ -1,8 +1,15 @@
 mod diff;
+mod histogram;
 mod hunk;
+pub mod lfs;
+mod moved;
+mod submodule;
 pub mod write;
 pub use diff::{
-    diff_files_into_hunks, hunks_by_filepath, reverse_hunk, trees, workdir, ChangeType,
-    DiffByPathMap, FileDiff, GitHunk,
+    diff_files_into_hunks, hunks_by_filepath, reverse_hunk, trees, update_workdir, workdir,
+    ChangeType, DiffByPathMap, FileDiff, GitHunk,
 };
+pub use histogram::apply_histogram;
 pub use hunk::{Hunk, HunkHash};
+pub use moved::{detect_moved_lines, update_moved_lines, MoveDirection, MovedBlock};
+pub use submodule::SubmoduleChange;
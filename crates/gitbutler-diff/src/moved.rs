@@ -0,0 +1,315 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use bstr::ByteSlice;
+use serde::Serialize;
+
+use crate::{DiffByPathMap, GitHunk};
+
+/// The minimum amount of alphanumeric characters a block of lines must contain to be considered moved.
+/// This is the same heuristic that `git diff --color-moved` uses to avoid marking trivial lines
+/// like closing braces as moved.
+const MIN_ALNUM_PER_BLOCK: usize = 20;
+
+/// Whether a block of lines was moved away from or moved to a hunk.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MoveDirection {
+    /// The lines were removed here and added elsewhere.
+    From,
+    /// The lines were added here after being removed elsewhere.
+    To,
+}
+
+/// A block of consecutive lines within a [`GitHunk`] which was moved, along with the location
+/// of its counterpart.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MovedBlock {
+    pub direction: MoveDirection,
+    /// The first line of the block, which is a line in the old file if the lines were moved [from](MoveDirection::From)
+    /// this hunk, or a line in the new file if they were moved [to](MoveDirection::To) this hunk.
+    pub start: u32,
+    /// The amount of lines in the block.
+    pub lines: u32,
+    /// The worktree-relative path of the file with the counterpart of this block.
+    pub counterpart_path: PathBuf,
+    /// The first line of the counterpart block, in the new file if this block was moved away,
+    /// or in the old file if it was moved here.
+    pub counterpart_start: u32,
+}
+
+/// Find blocks of lines that were deleted in one place and added in another, within the same file
+/// or across files, and annotate the respective hunks in `files` with [`GitHunk::moved`].
+///
+/// Lines are compared by content without their line separator, and matching is greedy, preferring the longest
+/// runs of matching lines. Binary hunks are ignored.
+pub fn detect_moved_lines(files: &mut DiffByPathMap) {
+    detect(files, None)
+}
+
+/// Like [`detect_moved_lines()`], but only detect blocks moved from or to the files at or below `changed_paths`
+/// again, as their hunks were recomputed. Blocks moved between all other files are kept as they are.
+pub fn update_moved_lines(files: &mut DiffByPathMap, changed_paths: &[PathBuf]) {
+    detect(files, Some(changed_paths))
+}
+
+fn detect(files: &mut DiffByPathMap, changed_paths: Option<&[PathBuf]>) {
+    let is_changed = |path: &Path| {
+        changed_paths.map_or(true, |changed_paths| {
+            changed_paths
+                .iter()
+                .any(|changed| path.starts_with(changed))
+        })
+    };
+    let mut paths: Vec<_> = files.keys().cloned().collect();
+    paths.sort();
+    let path_changed: Vec<bool> = paths
+        .iter()
+        .map(|path| is_changed(path.as_path()))
+        .collect();
+
+    // Blocks of unchanged files whose counterpart changed are dropped, which frees their lines to be matched again.
+    let mut dropped_blocks = HashMap::<(usize, usize), Vec<MovedBlock>>::new();
+    for (path_idx, path) in paths.iter().enumerate() {
+        let hunks = &mut files
+            .get_mut(path)
+            .expect("paths were taken from files")
+            .hunks;
+        for (hunk_idx, hunk) in hunks.iter_mut().enumerate() {
+            if path_changed[path_idx] {
+                hunk.moved.clear();
+                continue;
+            }
+            let (dropped, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut hunk.moved)
+                .into_iter()
+                .partition(|block| is_changed(block.counterpart_path.as_path()));
+            hunk.moved = kept;
+            if !dropped.is_empty() {
+                dropped_blocks.insert((path_idx, hunk_idx), dropped);
+            }
+        }
+    }
+
+    let mut deletions = Vec::new();
+    let mut additions = Vec::new();
+    for (path_idx, path) in paths.iter().enumerate() {
+        for (hunk_idx, hunk) in files[path].hunks.iter().enumerate() {
+            if hunk.binary {
+                continue;
+            }
+            collect_runs(hunk, path_idx, hunk_idx, &mut deletions, &mut additions);
+        }
+    }
+
+    let mut additions_by_line = HashMap::<&[u8], Vec<(usize, usize)>>::new();
+    for (run_idx, run) in additions.iter().enumerate() {
+        for (line_idx, line) in run.lines.iter().enumerate() {
+            additions_by_line
+                .entry(*line)
+                .or_default()
+                .push((run_idx, line_idx));
+        }
+    }
+
+    // Lines that are part of blocks that were kept can't be matched again, and only lines of changed files
+    // or of dropped blocks can be part of new blocks, as all others were matched before.
+    let kept_lines = |run: &Run<'_>, direction| {
+        let blocks = &files[&paths[run.path_idx]].hunks[run.hunk_idx].moved;
+        lines_in_blocks(run, blocks, direction)
+    };
+    let involved_lines = |run: &Run<'_>, direction| {
+        if path_changed[run.path_idx] {
+            return vec![true; run.lines.len()];
+        }
+        let blocks = dropped_blocks
+            .get(&(run.path_idx, run.hunk_idx))
+            .map_or(&[][..], Vec::as_slice);
+        lines_in_blocks(run, blocks, direction)
+    };
+    let deleted_line_used: Vec<Vec<bool>> = deletions
+        .iter()
+        .map(|run| kept_lines(run, MoveDirection::From))
+        .collect();
+    let deleted_line_involved: Vec<Vec<bool>> = deletions
+        .iter()
+        .map(|run| involved_lines(run, MoveDirection::From))
+        .collect();
+    let mut added_line_used: Vec<Vec<bool>> = additions
+        .iter()
+        .map(|run| kept_lines(run, MoveDirection::To))
+        .collect();
+    let added_line_involved: Vec<Vec<bool>> = additions
+        .iter()
+        .map(|run| involved_lines(run, MoveDirection::To))
+        .collect();
+    let mut moved = Vec::<(usize, usize, MovedBlock)>::new();
+    for ((deleted, deleted_used), deleted_involved) in deletions
+        .iter()
+        .zip(&deleted_line_used)
+        .zip(&deleted_line_involved)
+    {
+        let mut line_idx = 0;
+        while line_idx < deleted.lines.len() {
+            let line = deleted.lines[line_idx];
+            let best_match = (line.iter().any(u8::is_ascii_alphanumeric)
+                && !deleted_used[line_idx])
+                .then(|| additions_by_line.get(line))
+                .flatten()
+                .into_iter()
+                .flatten()
+                .filter(|&&(run_idx, added_idx)| {
+                    deleted_involved[line_idx] || added_line_involved[run_idx][added_idx]
+                })
+                .filter_map(|&(run_idx, added_idx)| {
+                    let added = &additions[run_idx];
+                    let used = &added_line_used[run_idx];
+                    let len = deleted.lines[line_idx..]
+                        .iter()
+                        .zip(&deleted_used[line_idx..])
+                        .zip(added.lines[added_idx..].iter().zip(&used[added_idx..]))
+                        .take_while(|((deleted, deleted_used), (added, used))| {
+                            deleted == added && !**deleted_used && !**used
+                        })
+                        .count();
+                    (len > 0).then_some((run_idx, added_idx, len))
+                })
+                .fold(
+                    None,
+                    |best: Option<(usize, usize, usize)>, candidate| match best {
+                        Some(best) if best.2 >= candidate.2 => Some(best),
+                        _ => Some(candidate),
+                    },
+                );
+
+            let Some((run_idx, added_idx, len)) = best_match.filter(|(_, _, len)| {
+                alphanumeric_count(&deleted.lines[line_idx..line_idx + len]) >= MIN_ALNUM_PER_BLOCK
+            }) else {
+                line_idx += 1;
+                continue;
+            };
+
+            let added = &additions[run_idx];
+            added_line_used[run_idx][added_idx..added_idx + len]
+                .iter_mut()
+                .for_each(|used| *used = true);
+            let deleted_start = deleted.first_line + line_idx as u32;
+            let added_start = added.first_line + added_idx as u32;
+            moved.push((
+                deleted.path_idx,
+                deleted.hunk_idx,
+                MovedBlock {
+                    direction: MoveDirection::From,
+                    start: deleted_start,
+                    lines: len as u32,
+                    counterpart_path: paths[added.path_idx].clone(),
+                    counterpart_start: added_start,
+                },
+            ));
+            moved.push((
+                added.path_idx,
+                added.hunk_idx,
+                MovedBlock {
+                    direction: MoveDirection::To,
+                    start: added_start,
+                    lines: len as u32,
+                    counterpart_path: paths[deleted.path_idx].clone(),
+                    counterpart_start: deleted_start,
+                },
+            ));
+            line_idx += len;
+        }
+    }
+
+    for (path_idx, hunk_idx, block) in moved {
+        let hunk = &mut files
+            .get_mut(&paths[path_idx])
+            .expect("paths were taken from files")
+            .hunks[hunk_idx];
+        hunk.moved.push(block);
+    }
+    for file in files.values_mut() {
+        for hunk in &mut file.hunks {
+            hunk.moved
+                .sort_by_key(|block| (block.direction == MoveDirection::To, block.start));
+        }
+    }
+}
+
+/// A run of consecutive deleted or added lines within a hunk.
+struct Run<'a> {
+    path_idx: usize,
+    hunk_idx: usize,
+    /// The line number of the first line in the run, in the old file for deletions and in the new file for additions.
+    first_line: u32,
+    /// The lines without their `+` or `-` prefix and without line separator.
+    lines: Vec<&'a [u8]>,
+}
+
+fn collect_runs<'a>(
+    hunk: &'a GitHunk,
+    path_idx: usize,
+    hunk_idx: usize,
+    deletions: &mut Vec<Run<'a>>,
+    additions: &mut Vec<Run<'a>>,
+) {
+    let (mut old_line, mut new_line) = (hunk.old_start, hunk.new_start);
+    let mut deleted: Option<Run<'a>> = None;
+    let mut added: Option<Run<'a>> = None;
+    let new_run = |first_line| Run {
+        path_idx,
+        hunk_idx,
+        first_line,
+        lines: Vec::new(),
+    };
+    for line in hunk.diff_lines.lines() {
+        match line.first() {
+            Some(b'-') => {
+                deleted
+                    .get_or_insert_with(|| new_run(old_line))
+                    .lines
+                    .push(&line[1..]);
+                old_line += 1;
+            }
+            Some(b'+') => {
+                added
+                    .get_or_insert_with(|| new_run(new_line))
+                    .lines
+                    .push(&line[1..]);
+                new_line += 1;
+            }
+            Some(b' ') => {
+                deletions.extend(deleted.take());
+                additions.extend(added.take());
+                old_line += 1;
+                new_line += 1;
+            }
+            // Hunk headers and `\ No newline at end of file` markers.
+            _ => {}
+        }
+    }
+    deletions.extend(deleted);
+    additions.extend(added);
+}
+
+/// Return which lines of `run` are part of the `blocks` moved in `direction`.
+fn lines_in_blocks(run: &Run<'_>, blocks: &[MovedBlock], direction: MoveDirection) -> Vec<bool> {
+    (0..run.lines.len() as u32)
+        .map(|idx| {
+            let line = run.first_line + idx;
+            blocks.iter().any(|block| {
+                block.direction == direction
+                    && (block.start..block.start + block.lines).contains(&line)
+            })
+        })
+        .collect()
+}
+
+fn alphanumeric_count(lines: &[&[u8]]) -> usize {
+    lines
+        .iter()
+        .map(|line| line.iter().filter(|b| b.is_ascii_alphanumeric()).count())
+        .sum()
+}
@@ -0,0 +1,64 @@
+use bstr::ByteSlice;
+
+use crate::GitHunk;
+
+/// A change to the commit a submodule, i.e. a gitlink in the tree, points to.
+///
+/// `libgit2` represents these as a diff of the single line `Subproject commit <hex>` for either side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubmoduleChange {
+    /// The commit the submodule pointed to before, or `None` if it was added.
+    pub old: Option<git2::Oid>,
+    /// The commit the submodule points to now, or `None` if it was removed.
+    pub new: Option<git2::Oid>,
+    /// `true` if the submodule worktree has uncommitted changes, which aren't part of this change.
+    pub dirty: bool,
+}
+
+impl SubmoduleChange {
+    /// Parse the `+`, `-` and ` ` prefixed `diff` lines, possibly with hunk header, as change to a submodule,
+    /// or return `None` if it isn't one.
+    pub fn from_diff(diff: &[u8]) -> Option<Self> {
+        let mut change = SubmoduleChange {
+            old: None,
+            new: None,
+            dirty: false,
+        };
+        for line in diff.lines() {
+            if line.starts_with(b"@@") {
+                continue;
+            }
+            let (side, commit) = line.split_first()?;
+            let commit = commit.strip_prefix(b"Subproject commit ")?.to_str().ok()?;
+            let (commit, dirty) = match commit.strip_suffix("-dirty") {
+                Some(commit) => (commit, true),
+                None => (commit, false),
+            };
+            let id: git2::Oid = commit.parse().ok()?;
+            match *side {
+                b'-' if change.old.is_none() => change.old = Some(id),
+                b'+' if change.new.is_none() => {
+                    change.new = Some(id);
+                    change.dirty = dirty;
+                }
+                _ => return None,
+            }
+        }
+        (change.old.is_some() || change.new.is_some()).then_some(change)
+    }
+
+    /// Return `true` if the commit the submodule points to didn't change.
+    pub fn is_unchanged(&self) -> bool {
+        self.old == self.new
+    }
+}
+
+impl GitHunk {
+    /// Return the change of a submodule if this hunk represents one.
+    pub fn submodule_change(&self) -> Option<SubmoduleChange> {
+        if self.binary {
+            return None;
+        }
+        SubmoduleChange::from_diff(&self.diff_lines)
+    }
+}
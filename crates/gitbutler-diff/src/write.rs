@@ -9,7 +9,10 @@ use gitbutler_cherry_pick::RepositoryExt as _;
 use gitbutler_command_context::CommandContext;
 use hex::ToHex;
 
-use crate::GitHunk;
+use crate::{
+    lfs::{self, LfsPointer},
+    GitHunk,
+};
 
 // this function takes a list of file ownership,
 // constructs a tree from those changes on top of the target
@@ -59,9 +62,17 @@ where
         let hunks: Vec<GitHunk> = hunks.borrow().iter().map(|h| h.clone().into()).collect();
         let full_path = ctx.project().worktree_path().join(rel_path);
 
-        let is_submodule = full_path.is_dir()
-            && hunks.len() == 1
-            && hunks[0].diff_lines.contains_str(b"Subproject commit");
+        // submodules are recorded as the commit they point to
+        if let Some(change) = (hunks.len() == 1)
+            .then(|| hunks[0].submodule_change())
+            .flatten()
+        {
+            match change.new {
+                Some(commit_id) => builder.upsert(rel_path, commit_id, git2::FileMode::Commit),
+                None => builder.remove(rel_path),
+            };
+            continue;
+        }
 
         // if file exists
         let full_path_exists = full_path.exists();
@@ -166,6 +177,12 @@ where
 
                     match blob_contents {
                         Ok(blob_contents) => {
+                            // the content of LFS pointers must be available once they are committed
+                            if let Some(pointer) = LfsPointer::from_bytes(&blob_contents)
+                                .filter(|_| lfs::is_lfs_tracked(git_repository, rel_path))
+                            {
+                                lfs::store_object(git_repository, rel_path, &pointer)?;
+                            }
                             // create a blob
                             let new_blob_oid = git_repository.blob(blob_contents.as_bytes())?;
                             // upsert into the builder
@@ -177,23 +194,6 @@ where
                         }
                     }
                 }
-            } else if is_submodule {
-                let mut blob_contents = BString::default();
-
-                let mut hunks = hunks.iter().collect::<Vec<_>>();
-                hunks.sort_by_key(|hunk| hunk.new_start);
-                let mut all_diffs = BString::default();
-                for hunk in hunks {
-                    all_diffs.push_str(&hunk.diff_lines);
-                }
-                let patch = Patch::from_bytes(&all_diffs)?;
-                blob_contents = apply(&blob_contents, &patch)
-                    .context(format!("failed to apply {}", all_diffs))?;
-
-                // create a blob
-                let new_blob_oid = git_repository.blob(blob_contents.as_bytes())?;
-                // upsert into the builder
-                builder.upsert(rel_path, new_blob_oid, filemode);
             } else if !full_path_exists
                 && discard_hunk.map_or(false, |hunk| hunk.change_type == crate::ChangeType::Added)
             {
@@ -208,6 +208,15 @@ where
 
                 let new_blob_oid = git_repository.blob(&blob_contents)?;
                 builder.upsert(rel_path, new_blob_oid, filemode);
+            } else if let Some(pointer) = lfs::is_lfs_tracked(git_repository, rel_path)
+                .then(|| lfs::worktree_pointer(git_repository, rel_path))
+                .transpose()?
+                .flatten()
+            {
+                // store the pointer instead of the content, just like `git lfs clean` would
+                lfs::store_object(git_repository, rel_path, &pointer)?;
+                let blob_oid = git_repository.blob(&pointer.to_bytes())?;
+                builder.upsert(rel_path, blob_oid, filemode);
             } else {
                 // create a git blob from a file on disk
                 let blob_oid = git_repository
@@ -229,6 +238,115 @@ where
     Ok(tree_oid)
 }
 
+/// Write a tree that is `base_tree` with `files` applied, whose hunks are changes from `base_tree`
+/// to `new_tree`, using only objects of `repo`.
+///
+/// Unlike [`hunks_onto_tree()`], the worktree is neither read nor changed, the mode of each file
+/// is the one it has in `new_tree`, and it's an error if hunks don't apply.
+pub fn hunks_onto_tree_in_memory<T>(
+    repo: &git2::Repository,
+    base_tree: &git2::Tree,
+    new_tree: &git2::Tree,
+    files: impl IntoIterator<Item = (impl Borrow<PathBuf>, impl Borrow<Vec<T>>)>,
+) -> Result<git2::Oid>
+where
+    T: Into<GitHunk> + Clone,
+{
+    let mut builder = git2::build::TreeUpdateBuilder::new();
+    for (rel_path, hunks) in files {
+        let rel_path = rel_path.borrow();
+        let hunks: Vec<GitHunk> = hunks.borrow().iter().map(|h| h.clone().into()).collect();
+
+        if let Some(change) = (hunks.len() == 1)
+            .then(|| hunks[0].submodule_change())
+            .flatten()
+        {
+            match change.new {
+                Some(commit_id) => builder.upsert(rel_path, commit_id, git2::FileMode::Commit),
+                None => builder.remove(rel_path),
+            };
+            continue;
+        }
+        if hunks
+            .iter()
+            .any(|hunk| hunk.change_type == crate::ChangeType::Deleted)
+        {
+            builder.remove(rel_path);
+            continue;
+        }
+
+        let base_entry = base_tree.get_path(rel_path).ok();
+        let filemode = new_tree
+            .get_path(rel_path)
+            .ok()
+            .or_else(|| base_entry.clone())
+            .map_or(git2::FileMode::Blob, |entry| file_mode(entry.filemode()));
+        if let Some(binary_hunk) = hunks.iter().find(|hunk| hunk.binary) {
+            let blob_oid = binary_hunk
+                .diff_lines
+                .to_str()
+                .context("hex-string")?
+                .parse::<git2::Oid>()
+                .context("failed to diff as oid")?;
+            builder.upsert(rel_path, blob_oid, filemode);
+            continue;
+        }
+
+        let base_blob = base_entry
+            .map(|entry| entry.to_object(repo)?.peel_to_blob())
+            .transpose()
+            .context("failed to get blob")?;
+        let base_contents = base_blob.as_ref().map_or(&[][..], |blob| blob.content());
+
+        let mut hunks = hunks.iter().collect::<Vec<_>>();
+        hunks.sort_by_key(|hunk| hunk.new_start);
+        let mut all_diffs = BString::default();
+        for hunk in hunks {
+            all_diffs.push_str(&hunk.diff_lines);
+        }
+        let patch = Patch::from_bytes(&all_diffs)?;
+        let blob_contents = apply(base_contents, &patch)
+            .with_context(|| format!("failed to apply hunks to {}", rel_path.display()))?;
+        let blob_oid = repo.blob(&blob_contents)?;
+        builder.upsert(rel_path, blob_oid, filemode);
+    }
+
+    let tree_oid = builder
+        .create_updated(repo, base_tree)
+        .context("failed to write updated tree")?;
+    Ok(tree_oid)
+}
+
+/// Write a tree that is `tree` with the entries at `paths` taken from `source_tree`, or removed
+/// if `source_tree` doesn't have them.
+pub fn replace_paths(
+    repo: &git2::Repository,
+    tree: &git2::Tree,
+    source_tree: &git2::Tree,
+    paths: impl IntoIterator<Item = impl Borrow<PathBuf>>,
+) -> Result<git2::Oid> {
+    let mut builder = git2::build::TreeUpdateBuilder::new();
+    for path in paths {
+        let path = path.borrow();
+        match source_tree.get_path(path) {
+            Ok(entry) => builder.upsert(path, entry.id(), file_mode(entry.filemode())),
+            Err(_) => builder.remove(path),
+        };
+    }
+    builder
+        .create_updated(repo, tree)
+        .context("failed to write updated tree")
+}
+
+fn file_mode(mode: i32) -> git2::FileMode {
+    match mode {
+        0o100755 => git2::FileMode::BlobExecutable,
+        0o120000 => git2::FileMode::Link,
+        0o160000 => git2::FileMode::Commit,
+        _ => git2::FileMode::Blob,
+    }
+}
+
 /// Just like [`diffy::apply()`], but on error it will attach hashes of the input `base_image` and `patch`.
 pub fn apply<S: AsRef<[u8]>>(base_image: S, patch: &Patch<'_, [u8]>) -> Result<BString> {
     fn md5_hash_hex(b: impl AsRef<[u8]>) -> String {
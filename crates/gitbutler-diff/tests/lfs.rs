@@ -0,0 +1,75 @@
+use gitbutler_diff::lfs::LfsPointer;
+
+const OID: &str = "4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e2393";
+
+#[test]
+fn pointer_roundtrip() {
+    let pointer = LfsPointer {
+        oid: OID.into(),
+        size: 12345,
+    };
+    let bytes = pointer.to_bytes();
+    assert_eq!(
+        bytes,
+        format!("version https://git-lfs.github.com/spec/v1\noid sha256:{OID}\nsize 12345\n")
+            .as_bytes()
+    );
+    assert_eq!(LfsPointer::from_bytes(&bytes), Some(pointer));
+}
+
+#[test]
+fn pointer_with_extensions_is_parsed() {
+    let input = format!(
+        "version https://git-lfs.github.com/spec/v1\next-0-foo sha256:{OID}\noid sha256:{OID}\nsize 4\n"
+    );
+    assert_eq!(
+        LfsPointer::from_bytes(input.as_bytes()),
+        Some(LfsPointer {
+            oid: OID.into(),
+            size: 4
+        })
+    );
+}
+
+#[test]
+fn non_pointers_are_rejected() {
+    assert_eq!(LfsPointer::from_bytes(b"hello world\n"), None);
+    assert_eq!(
+        LfsPointer::from_bytes(
+            b"version https://git-lfs.github.com/spec/v1\noid sha256:abc\nsize 4\n"
+        ),
+        None,
+        "the oid must be a full SHA-256"
+    );
+    assert_eq!(
+        LfsPointer::from_bytes(
+            format!("version https://git-lfs.github.com/spec/v1\noid sha256:{OID}\n").as_bytes()
+        ),
+        None,
+        "the size is required"
+    );
+}
+
+#[test]
+fn pointer_from_file() -> anyhow::Result<()> {
+    let tmp = tempfile::tempdir()?;
+    let path = tmp.path().join("file");
+    std::fs::write(&path, "foo\n")?;
+    let pointer = LfsPointer::from_file(&path)?;
+    assert_eq!(
+        pointer,
+        LfsPointer {
+            oid: "b5bb9d8014a0f9b1d61e21e796d78dccdf1352f23cd32812f4850b878ae4944c".into(),
+            size: 4
+        },
+        "this is the SHA-256 of the content"
+    );
+    assert_eq!(
+        pointer
+            .object_path(tmp.path())
+            .strip_prefix(tmp.path())?
+            .to_str(),
+        Some("lfs/objects/b5/bb/b5bb9d8014a0f9b1d61e21e796d78dccdf1352f23cd32812f4850b878ae4944c")
+    );
+    Ok(())
+}
@@ -1 +1,5 @@
 pub mod hunk;
+pub mod lfs;
+pub mod moved;
+pub mod submodule;
+pub mod workdir;
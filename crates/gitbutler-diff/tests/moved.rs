@@ -0,0 +1,194 @@
+use std::path::PathBuf;
+
+use gitbutler_diff::{
+    detect_moved_lines, update_moved_lines, ChangeType, DiffByPathMap, FileDiff, GitHunk,
+    MoveDirection, MovedBlock,
+};
+
+#[test]
+fn block_moved_between_files() {
+    let mut files = files([
+        (
+            "a.rs",
+            hunk(
+                (10, 3),
+                (10, 0),
+                "@@ -10,3 +10,0 @@\n-fn moved_function() {\n-    call_something_else();\n-}\n",
+            ),
+        ),
+        (
+            "b.rs",
+            hunk(
+                (4, 0),
+                (5, 3),
+                "@@ -4,0 +5,3 @@\n+fn moved_function() {\n+    call_something_else();\n+}\n",
+            ),
+        ),
+    ]);
+    detect_moved_lines(&mut files);
+
+    assert_eq!(
+        files[&PathBuf::from("a.rs")].hunks[0].moved,
+        [MovedBlock {
+            direction: MoveDirection::From,
+            start: 10,
+            lines: 3,
+            counterpart_path: "b.rs".into(),
+            counterpart_start: 5,
+        }]
+    );
+    assert_eq!(
+        files[&PathBuf::from("b.rs")].hunks[0].moved,
+        [MovedBlock {
+            direction: MoveDirection::To,
+            start: 5,
+            lines: 3,
+            counterpart_path: "a.rs".into(),
+            counterpart_start: 10,
+        }]
+    );
+}
+
+#[test]
+fn block_moved_within_hunk_skips_context() {
+    let mut files = files([(
+        "a.rs",
+        hunk(
+            (1, 5),
+            (1, 5),
+            "@@ -1,5 +1,5 @@\n-let first_moved_line = 1;\n-let second_moved_line = 2;\n unchanged\n unchanged\n+let first_moved_line = 1;\n+let second_moved_line = 2;\n unchanged\n",
+        ),
+    )]);
+    detect_moved_lines(&mut files);
+
+    let moved = &files[&PathBuf::from("a.rs")].hunks[0].moved;
+    assert_eq!(moved.len(), 2);
+    assert_eq!(
+        (
+            moved[0].direction,
+            moved[0].start,
+            moved[0].counterpart_start
+        ),
+        (MoveDirection::From, 1, 3)
+    );
+    assert_eq!(
+        (
+            moved[1].direction,
+            moved[1].start,
+            moved[1].counterpart_start
+        ),
+        (MoveDirection::To, 3, 1)
+    );
+}
+
+#[test]
+fn trivial_lines_are_not_considered_moved() {
+    let mut files = files([
+        ("a.rs", hunk((3, 1), (3, 0), "@@ -3,1 +3,0 @@\n-}\n")),
+        ("b.rs", hunk((7, 0), (8, 2), "@@ -7,0 +8,2 @@\n+}\n+x\n")),
+    ]);
+    detect_moved_lines(&mut files);
+
+    assert!(files.values().all(|file| file.hunks[0].moved.is_empty()));
+}
+
+#[test]
+fn modified_lines_are_not_considered_moved() {
+    let mut files = files([(
+        "a.rs",
+        hunk(
+            (1, 1),
+            (1, 1),
+            "@@ -1,1 +1,1 @@\n-let value = compute_something();\n+let value = compute_something_else();\n",
+        ),
+    )]);
+    detect_moved_lines(&mut files);
+
+    assert!(files[&PathBuf::from("a.rs")].hunks[0].moved.is_empty());
+}
+
+#[test]
+fn update_only_redetects_moves_involving_changed_files() {
+    let moved_away = hunk(
+        (10, 3),
+        (10, 0),
+        "@@ -10,3 +10,0 @@\n-fn moved_function() {\n-    call_something_else();\n-}\n",
+    );
+    let moved_here = |line| {
+        hunk(
+            (line - 1, 0),
+            (line, 3),
+            &format!("@@ -{},0 +{line},3 @@\n+fn moved_function() {{\n+    call_something_else();\n+}}\n", line - 1),
+        )
+    };
+    let unrelated = hunk(
+        (1, 0),
+        (2, 1),
+        "@@ -1,0 +2 @@\n+let unrelated_value = 42;\n",
+    );
+    let mut files = files([
+        ("a.rs", moved_away),
+        ("b.rs", moved_here(5)),
+        ("c.rs", unrelated.clone()),
+    ]);
+    detect_moved_lines(&mut files);
+    let counterparts = |files: &DiffByPathMap| {
+        files[&PathBuf::from("a.rs")].hunks[0]
+            .moved
+            .iter()
+            .map(|block| block.counterpart_path.clone())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(counterparts(&files), [PathBuf::from("b.rs")]);
+
+    files.get_mut(&PathBuf::from("c.rs")).unwrap().hunks = vec![moved_here(20)];
+    update_moved_lines(&mut files, &["c.rs".into()]);
+    assert_eq!(
+        counterparts(&files),
+        [PathBuf::from("b.rs")],
+        "the block moved between unchanged files is kept, and its lines can't be matched again"
+    );
+    assert!(files[&PathBuf::from("c.rs")].hunks[0].moved.is_empty());
+
+    files.get_mut(&PathBuf::from("b.rs")).unwrap().hunks = vec![unrelated];
+    update_moved_lines(&mut files, &["b.rs".into()]);
+    assert_eq!(
+        counterparts(&files),
+        [PathBuf::from("c.rs")],
+        "blocks moved to changed files are detected again"
+    );
+    assert_eq!(
+        files[&PathBuf::from("c.rs")].hunks[0].moved[0].counterpart_start,
+        10
+    );
+}
+
+fn hunk(old: (u32, u32), new: (u32, u32), diff: &str) -> GitHunk {
+    GitHunk {
+        old_start: old.0,
+        old_lines: old.1,
+        new_start: new.0,
+        new_lines: new.1,
+        diff_lines: diff.into(),
+        binary: false,
+        change_type: ChangeType::Modified,
+        moved: Vec::new(),
+    }
+}
+
+fn files<const N: usize>(hunks: [(&str, GitHunk); N]) -> DiffByPathMap {
+    hunks
+        .into_iter()
+        .map(|(path, hunk)| {
+            (
+                PathBuf::from(path),
+                FileDiff {
+                    old_path: Some(path.into()),
+                    new_path: Some(path.into()),
+                    hunks: vec![hunk],
+                    ..Default::default()
+                },
+            )
+        })
+        .collect()
+}
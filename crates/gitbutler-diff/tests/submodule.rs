@@ -0,0 +1,59 @@
+use gitbutler_diff::{ChangeType, GitHunk, SubmoduleChange};
+
+const OLD: &str = "5bd6bed0b35d0b2e6e0c5a5a1c6e2b2d3ae3f8c1";
+const NEW: &str = "9f4a1c1e0a6b2d3c4e5f60718293a4b5c6d7e8f9";
+
+fn oid(hex: &str) -> git2::Oid {
+    hex.parse().unwrap()
+}
+
+#[test]
+fn modified_submodule() {
+    let diff = format!("@@ -1 +1 @@\n-Subproject commit {OLD}\n+Subproject commit {NEW}\n");
+    assert_eq!(
+        SubmoduleChange::from_diff(diff.as_bytes()),
+        Some(SubmoduleChange {
+            old: Some(oid(OLD)),
+            new: Some(oid(NEW)),
+            dirty: false,
+        })
+    );
+}
+
+#[test]
+fn added_and_removed_submodule() {
+    let added = format!("@@ -0,0 +1 @@\n+Subproject commit {NEW}\n");
+    let change = SubmoduleChange::from_diff(added.as_bytes()).unwrap();
+    assert_eq!((change.old, change.new), (None, Some(oid(NEW))));
+
+    let removed = format!("@@ -1 +0,0 @@\n-Subproject commit {OLD}\n");
+    let change = SubmoduleChange::from_diff(removed.as_bytes()).unwrap();
+    assert_eq!((change.old, change.new), (Some(oid(OLD)), None));
+}
+
+#[test]
+fn dirty_submodule_without_new_commit_is_unchanged() {
+    let diff = format!("@@ -1 +1 @@\n-Subproject commit {OLD}\n+Subproject commit {OLD}-dirty\n");
+    let change = SubmoduleChange::from_diff(diff.as_bytes()).unwrap();
+    assert!(change.dirty);
+    assert!(change.is_unchanged());
+}
+
+#[test]
+fn regular_file_is_no_submodule() {
+    let hunk = GitHunk {
+        old_start: 1,
+        old_lines: 1,
+        new_start: 1,
+        new_lines: 2,
+        diff_lines: format!(
+            "@@ -1 +1,2 @@\n-Subproject commit {OLD}\n+Subproject commit {NEW}\n+more\n"
+        )
+        .into(),
+        binary: false,
+        change_type: ChangeType::Modified,
+        moved: Vec::new(),
+    };
+    assert_eq!(hunk.submodule_change(), None);
+    assert_eq!(SubmoduleChange::from_diff(b"@@ -1 +1 @@\n-a\n+b\n"), None);
+}
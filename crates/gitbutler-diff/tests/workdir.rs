@@ -0,0 +1,99 @@
+use std::path::{Path, PathBuf};
+
+use gitbutler_diff::{lfs::LfsPointer, update_workdir, workdir};
+use gitbutler_project::DiffSettings;
+
+#[test]
+fn update_only_rediffs_given_paths() -> anyhow::Result<()> {
+    let tmp = tempfile::tempdir()?;
+    let repo = git2::Repository::init(tmp.path())?;
+    write(tmp.path(), "unchanged.txt", "a\n")?;
+    write(tmp.path(), "modified.txt", "b\n")?;
+    write(tmp.path(), "dir/removed.txt", "c\n")?;
+    let head = commit_all(&repo)?;
+
+    write(tmp.path(), "modified.txt", "b\nb\n")?;
+    write(tmp.path(), "dir/removed.txt", "c\nc\n")?;
+    let settings = DiffSettings::default();
+    let mut files = workdir(&repo, head, settings)?;
+    assert_eq!(files.len(), 2);
+
+    write(tmp.path(), "unchanged.txt", "not picked up\n")?;
+    write(tmp.path(), "modified.txt", "b\nb\nb\n")?;
+    write(tmp.path(), "dir/removed.txt", "c\n")?;
+    write(tmp.path(), "dir/added.txt", "d\n")?;
+    update_workdir(
+        &repo,
+        head,
+        settings,
+        &["modified.txt".into(), "dir".into()],
+        &mut files,
+    )?;
+
+    let mut paths: Vec<_> = files.keys().cloned().collect();
+    paths.sort();
+    assert_eq!(
+        paths,
+        [
+            PathBuf::from("dir/added.txt"),
+            PathBuf::from("modified.txt")
+        ],
+        "paths that weren't passed aren't looked at"
+    );
+    assert_eq!(files[Path::new("modified.txt")].hunks[0].new_lines, 3);
+
+    update_workdir(&repo, head, settings, &["unchanged.txt".into()], &mut files)?;
+    assert_eq!(files, workdir(&repo, head, settings)?);
+    Ok(())
+}
+
+#[test]
+fn text_files_tracked_by_lfs_are_diffed_by_pointer() -> anyhow::Result<()> {
+    let tmp = tempfile::tempdir()?;
+    let repo = git2::Repository::init(tmp.path())?;
+    write(
+        tmp.path(),
+        ".gitattributes",
+        "*.csv filter=lfs diff=lfs merge=lfs -text\n",
+    )?;
+    write(tmp.path(), "data.csv", "a,b\n")?;
+    let pointer = LfsPointer::from_file(&tmp.path().join("data.csv"))?;
+    std::fs::write(tmp.path().join("data.csv"), pointer.to_bytes())?;
+    let head = commit_all(&repo)?;
+
+    write(tmp.path(), "data.csv", "a,b\n")?;
+    let settings = DiffSettings::default();
+    assert_eq!(
+        workdir(&repo, head, settings)?,
+        Default::default(),
+        "the content matches the committed pointer"
+    );
+
+    write(tmp.path(), "data.csv", "a,b\nc,d\n")?;
+    let files = workdir(&repo, head, settings)?;
+    let file = &files[Path::new("data.csv")];
+    assert!(!file.binary);
+    assert_eq!(file.new_size_bytes, 8);
+    assert_eq!(file.hunks.len(), 1);
+    let diff = file.hunks[0].diff_lines.to_string();
+    assert!(
+        diff.contains("-size 4\n") && diff.contains("+size 8\n") && !diff.contains("c,d"),
+        "only the pointer is diffed, not the content: {diff}"
+    );
+    Ok(())
+}
+
+fn write(root: &Path, path: &str, content: &str) -> std::io::Result<()> {
+    let path = root.join(path);
+    std::fs::create_dir_all(path.parent().expect("in worktree"))?;
+    std::fs::write(path, content)
+}
+
+fn commit_all(repo: &git2::Repository) -> anyhow::Result<git2::Oid> {
+    let mut index = repo.index()?;
+    index.add_all(["*"], git2::IndexAddOption::DEFAULT, None)?;
+    index.write()?;
+    let tree = repo.find_tree(index.write_tree()?)?;
+    let signature = git2::Signature::now("test", "test@example.com")?;
+    Ok(repo.commit(Some("HEAD"), &signature, &signature, "init", &tree, &[])?)
+}
@@ -16,10 +16,10 @@ use gitbutler_commit::{
     commit_ext::CommitExt,
     commit_headers::{CommitHeadersV2, HasCommitHeaders},
 };
-use gitbutler_diff::hunks_by_filepath;
+use gitbutler_diff::{apply_histogram, hunks_by_filepath};
 use gitbutler_operating_modes::{
-    operating_mode, read_edit_mode_metadata, write_edit_mode_metadata, EditModeMetadata,
-    OperatingMode, EDIT_BRANCH_REF, WORKSPACE_BRANCH_REF,
+    operating_mode, read_edit_mode_metadata, remove_edit_mode_metadata, write_edit_mode_metadata,
+    EditModeMetadata, OperatingMode, EDIT_BRANCH_REF, WORKSPACE_BRANCH_REF,
 };
 use gitbutler_project::access::{WorktreeReadPermission, WorktreeWritePermission};
 use gitbutler_reference::{ReferenceName, Refname};
@@ -70,7 +70,11 @@ fn get_commit_index(repository: &git2::Repository, commit: &git2::Commit) -> Res
     }
 }
 
-fn checkout_edit_branch(ctx: &CommandContext, commit: &git2::Commit) -> Result<()> {
+fn checkout_edit_branch(
+    ctx: &CommandContext,
+    commit: &git2::Commit,
+    edit_mode_metadata: &EditModeMetadata,
+) -> Result<()> {
     let repository = ctx.repository();
 
     let author_signature = signature(SignaturePurpose::Author)?;
@@ -92,8 +96,18 @@ fn checkout_edit_branch(ctx: &CommandContext, commit: &git2::Commit) -> Result<(
     } else {
         commit.parent(0)?
     };
-    repository.reference(EDIT_BRANCH_REF, commit_parent.id(), true, "")?;
-    repository.set_head(EDIT_BRANCH_REF)?;
+
+    // Switch to the edit branch and persist the metadata together, so edit mode is either fully
+    // entered or not at all, even if GitButler crashes in between.
+    let mut transaction = ctx.project().journal().transaction();
+    transaction
+        .update_reference(EDIT_BRANCH_REF, commit_parent.id(), "")
+        .update_symbolic_reference("HEAD", EDIT_BRANCH_REF);
+    write_edit_mode_metadata(ctx, &mut transaction, edit_mode_metadata)?;
+    transaction
+        .commit(repository)
+        .context("Failed to persist metadata")?;
+
     repository.checkout_head(Some(CheckoutBuilder::new().force().remove_untracked(true)))?;
 
     // Checkout the commit as unstaged changes
@@ -153,8 +167,8 @@ pub(crate) fn enter_edit_mode(
         bail!("Can not enter edit mode for a reference which does not have a cooresponding virtual branch")
     }
 
-    checkout_edit_branch(ctx, commit).context("Failed to checkout edit branch")?;
-    write_edit_mode_metadata(ctx, &edit_mode_metadata).context("Failed to persist metadata")?;
+    checkout_edit_branch(ctx, commit, &edit_mode_metadata)
+        .context("Failed to checkout edit branch")?;
 
     Ok(edit_mode_metadata)
 }
@@ -163,11 +177,12 @@ pub(crate) fn abort_and_return_to_workspace(
     ctx: &CommandContext,
     perm: &mut WorktreeWritePermission,
 ) -> Result<()> {
-    let repository = ctx.repository();
-
-    // Checkout gitbutler workspace branch
-    repository
-        .set_head(WORKSPACE_BRANCH_REF)
+    // Checkout gitbutler workspace branch and leave edit mode together.
+    let mut transaction = ctx.project().journal().transaction();
+    transaction.update_symbolic_reference("HEAD", WORKSPACE_BRANCH_REF);
+    remove_edit_mode_metadata(ctx, &mut transaction);
+    transaction
+        .commit(ctx.repository())
         .context("Failed to set head reference")?;
 
     checkout_branch_trees(ctx, perm)?;
@@ -199,6 +214,17 @@ pub(crate) fn save_and_return_to_workspace(
     // Recommit commit
     let tree = repository.create_wd_tree()?;
 
+    // Remember how conflicts were resolved, to resolve them the same way when they come up again.
+    if commit.is_conflicted() {
+        gitbutler_repo::rerere::record_resolutions(
+            repository,
+            &ctx.project().gb_dir(),
+            &commit,
+            &tree,
+        )
+        .context("Failed to record conflict resolutions")?;
+    }
+
     let (_, committer) = repository.signatures()?;
     let commit_headers = commit
         .gitbutler_headers()
@@ -230,12 +256,20 @@ pub(crate) fn save_and_return_to_workspace(
         tree: new_branch_tree,
     } = compute_updated_branch_head(repository, &virtual_branch, new_branch_head)?;
 
-    virtual_branch.set_stack_head(ctx, new_branch_head, Some(new_branch_tree))?;
-
-    // Switch branch to gitbutler/workspace
-    repository
-        .set_head(WORKSPACE_BRANCH_REF)
-        .context("Failed to set head reference")?;
+    // Update the branch, switch to gitbutler/workspace and leave edit mode together, so edit mode is
+    // either fully left or not at all, even if GitButler crashes in between.
+    let mut transaction = ctx.project().journal().transaction();
+    virtual_branch.set_stack_head_in(
+        ctx,
+        &mut transaction,
+        new_branch_head,
+        Some(new_branch_tree),
+    )?;
+    transaction.update_symbolic_reference("HEAD", WORKSPACE_BRANCH_REF);
+    remove_edit_mode_metadata(ctx, &mut transaction);
+    transaction
+        .commit(repository)
+        .context("Failed to return to the workspace")?;
 
     // Checkout the applied branches
     checkout_branch_trees(ctx, perm)?;
@@ -299,9 +333,23 @@ pub(crate) fn starting_index_state(
 
     dbg!(&conflicts);
 
-    let diff = repository.diff_tree_to_index(Some(&commit_parent_tree), Some(&index), None)?;
+    let mut diff_opts = git2::DiffOptions::new();
+    ctx.project().diff_settings.apply_to(&mut diff_opts, None);
+    let diff = repository.diff_tree_to_index(
+        Some(&commit_parent_tree),
+        Some(&index),
+        Some(&mut diff_opts),
+    )?;
 
-    let diff_files = hunks_by_filepath(Some(repository), &diff)?
+    let mut diff_files = hunks_by_filepath(Some(repository), &diff)?;
+    apply_histogram(
+        repository,
+        &diff,
+        &mut diff_files,
+        ctx.project().diff_settings,
+        None,
+    )?;
+    let diff_files = diff_files
         .into_iter()
         .map(|(path, file)| {
             let binary = file.hunks.iter().any(|h| h.binary);
@@ -133,8 +133,12 @@ pub enum Code {
     CommitSigningFailed,
     CommitHookFailed,
     CommitMergeConflictFailure,
+    /// Pushing was refused as the pushed commits include conflicted commits.
+    PushConflictedCommits,
     ProjectMissing,
     AuthorMissing,
+    /// GitButler state was written by a newer version of the app and can't be used safely.
+    NewerStateVersion,
 }
 
 impl std::fmt::Display for Code {
@@ -147,8 +151,10 @@ impl std::fmt::Display for Code {
             Code::CommitSigningFailed => "errors.commit.signing_failed",
             Code::CommitHookFailed => "errors.commit.hook_failed",
             Code::CommitMergeConflictFailure => "errors.commit.merge_conflict_failure",
+            Code::PushConflictedCommits => "errors.push.conflicted_commits",
             Code::AuthorMissing => "errors.git.author_missing",
             Code::ProjectMissing => "errors.projects.missing",
+            Code::NewerStateVersion => "errors.state.newer_version",
         };
         f.write_str(code)
     }
@@ -2,6 +2,7 @@ use std::{fs, path::PathBuf};
 
 use anyhow::{bail, Context, Result};
 use gitbutler_command_context::CommandContext;
+use gitbutler_project::Transaction;
 use gitbutler_reference::ReferenceName;
 use serde::{Deserialize, Serialize};
 
@@ -35,22 +36,26 @@ pub fn read_edit_mode_metadata(ctx: &CommandContext) -> Result<EditModeMetadata>
     toml::from_str(&edit_mode_metadata).context("Failed to parse edit mode metadata")
 }
 
-#[doc(hidden)]
+/// Persists `edit_mode_metadata` once `transaction` is committed, so it changes together with the
+/// references that put the worktree into edit mode.
 pub fn write_edit_mode_metadata(
     ctx: &CommandContext,
+    transaction: &mut Transaction,
     edit_mode_metadata: &EditModeMetadata,
 ) -> Result<()> {
     let serialized_edit_mode_metadata =
         toml::to_string(edit_mode_metadata).context("Failed to serialize edit mode metadata")?;
-    gitbutler_fs::write(
-        edit_mode_metadata_path(ctx).as_path(),
-        serialized_edit_mode_metadata,
-    )
-    .context("Failed to write edit mode metadata")?;
+    transaction.write_file(edit_mode_metadata_path(ctx), serialized_edit_mode_metadata);
 
     Ok(())
 }
 
+/// Removes the edit mode metadata once `transaction` is committed, along with the references that
+/// put the worktree back into the workspace.
+pub fn remove_edit_mode_metadata(ctx: &CommandContext, transaction: &mut Transaction) {
+    transaction.remove_file(edit_mode_metadata_path(ctx));
+}
+
 /// Holds relevant state required to switch to and from edit mode
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 #[serde(rename_all = "camelCase")]
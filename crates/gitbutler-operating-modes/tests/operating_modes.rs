@@ -18,14 +18,17 @@ fn create_and_checkout_branch(ctx: &CommandContext, branch_name: &str) {
 }
 
 fn create_edit_mode_metadata(ctx: &CommandContext) {
+    let mut transaction = ctx.project().journal().transaction();
     write_edit_mode_metadata(
         ctx,
+        &mut transaction,
         &EditModeMetadata {
             branch_reference: "asdf".into(),
             commit_oid: git2::Oid::zero(),
         },
     )
     .unwrap();
+    transaction.commit(ctx.repository()).unwrap();
 }
 
 mod operating_modes {
@@ -148,13 +148,17 @@ pub enum OperationKind {
     UnapplyBranch,
     CherryPick,
     SquashCommit,
+    AutosquashCommits,
+    SplitCommit,
     UpdateCommitMessage,
     MoveCommit,
     RestoreFromSnapshot,
     ReorderCommit,
     InsertBlankCommit,
     MoveCommitFile,
+    MoveCommitHunks,
     FileChanges,
+    SwitchBranch,
     EnterEditMode,
     SyncWorkspace,
     CreateDependentBranch,
@@ -162,6 +166,7 @@ pub enum OperationKind {
     UpdateDependentBranchName,
     UpdateDependentBranchDescription,
     UpdateDependentBranchForgeId,
+    RepairProject,
     #[default]
     Unknown,
 }
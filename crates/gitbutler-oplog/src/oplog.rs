@@ -9,10 +9,10 @@ use std::{
 use anyhow::{anyhow, bail, Context, Result};
 use git2::{DiffOptions, FileMode};
 use gitbutler_command_context::RepositoryExtLite;
-use gitbutler_diff::{hunks_by_filepath, FileDiff};
+use gitbutler_diff::{apply_histogram, hunks_by_filepath, lfs, FileDiff};
 use gitbutler_project::{
     access::{WorktreeReadPermission, WorktreeWritePermission},
-    Project,
+    Project, SnapshotPolicy,
 };
 use gitbutler_repo::RepositoryExt;
 use gitbutler_repo::SignaturePurpose;
@@ -21,7 +21,7 @@ use tracing::instrument;
 
 use super::{
     entry::{OperationKind, Snapshot, SnapshotDetails, Trailer},
-    reflog::set_reference_to_oplog,
+    reflog::{reflog_references_oplog, set_reference_to_oplog},
     state::OplogHandle,
 };
 
@@ -49,6 +49,7 @@ pub trait OplogExt {
     /// Prepares a snapshot of the current state of the working directory as well as GitButler data.
     /// Returns a tree hash of the snapshot. The snapshot is not discoverable until it is committed with [`commit_snapshot`](Self::commit_snapshot())
     /// If there are files that are untracked and larger than `SNAPSHOT_FILE_LIMIT_BYTES`, they are excluded from snapshot creation and restoring.
+    /// Files tracked by Git LFS are stored as pointers instead, independently of their size.
     fn prepare_snapshot(&self, perm: &WorktreeReadPermission) -> Result<git2::Oid>;
 
     /// Commits the snapshot tree that is created with the [`prepare_snapshot`](Self::prepare_snapshot) method,
@@ -133,6 +134,11 @@ pub trait OplogExt {
 
     /// Gets the sha of the last snapshot commit if present.
     fn oplog_head(&self) -> Result<Option<git2::Oid>>;
+
+    /// Returns `true` if the snapshot commit `oplog_head` is protected from garbage collection by the reflog
+    /// of `refs/heads/gitbutler/target`, or of `refs/worktree/gitbutler/target` in linked worktrees.
+    /// Creating a new snapshot restores this protection.
+    fn oplog_head_is_reachable(&self, oplog_head: git2::Oid) -> Result<bool>;
 }
 
 impl OplogExt for Project {
@@ -220,7 +226,7 @@ impl OplogExt for Project {
 
                 let mut opts = DiffOptions::new();
                 opts.include_untracked(true);
-                opts.ignore_submodules(true);
+                opts.ignore_submodules(false);
                 let diff =
                     repo.diff_tree_to_tree(Some(&parent_tree), Some(&wd_tree), Some(&mut opts))?;
 
@@ -265,6 +271,9 @@ impl OplogExt for Project {
 
     #[instrument(level = tracing::Level::DEBUG, skip(self), err(Debug))]
     fn should_auto_snapshot(&self, check_if_last_snapshot_older_than: Duration) -> Result<bool> {
+        if !self.snapshot_policy.enabled {
+            return Ok(false);
+        }
         let last_snapshot_time = OplogHandle::new(&self.gb_dir()).modified_at()?;
         if last_snapshot_time.elapsed()? <= check_if_last_snapshot_older_than {
             return Ok(false);
@@ -279,7 +288,7 @@ impl OplogExt for Project {
 
     fn snapshot_diff(&self, sha: git2::Oid) -> Result<HashMap<PathBuf, FileDiff>> {
         let worktree_dir = self.path.as_path();
-        let repo = git2::Repository::init(worktree_dir)?;
+        let repo = git2::Repository::open(worktree_dir)?;
 
         let commit = repo.find_commit(sha)?;
 
@@ -291,17 +300,19 @@ impl OplogExt for Project {
         repo.ignore_large_files_in_diffs(SNAPSHOT_FILE_LIMIT_BYTES)?;
 
         let mut diff_opts = git2::DiffOptions::new();
-        diff_opts
+        self.diff_settings
+            .apply_to(&mut diff_opts, None)
             .recurse_untracked_dirs(true)
             .include_untracked(true)
             .show_binary(true)
-            .ignore_submodules(true)
+            .ignore_submodules(false)
             .show_untracked_content(true);
 
         let diff =
             repo.diff_tree_to_tree(Some(&old_wd_tree), Some(&wd_tree), Some(&mut diff_opts))?;
 
-        let hunks = hunks_by_filepath(None, &diff)?;
+        let mut hunks = hunks_by_filepath(None, &diff)?;
+        apply_histogram(&repo, &diff, &mut hunks, self.diff_settings, None)?;
         Ok(hunks)
     }
 
@@ -310,6 +321,10 @@ impl OplogExt for Project {
         let oplog_state = OplogHandle::new(&self.gb_dir());
         oplog_state.oplog_head()
     }
+
+    fn oplog_head_is_reachable(&self, oplog_head: git2::Oid) -> Result<bool> {
+        reflog_references_oplog(&self.path, oplog_head)
+    }
 }
 
 /// Get a tree of the working dir (applied branches merged)
@@ -346,7 +361,7 @@ fn prepare_snapshot(ctx: &Project, _shared_access: &WorktreeReadPermission) -> R
     let vb_blob_id = repo.blob(&vb_content)?;
 
     // Create a tree out of the conflicts state if present
-    let conflicts_tree_id = write_conflicts_tree(worktree_dir, &repo)?;
+    let conflicts_tree_id = write_conflicts_tree(&repo)?;
 
     // write out the index as a tree to store
     let mut index = repo.index()?;
@@ -364,6 +379,15 @@ fn prepare_snapshot(ctx: &Project, _shared_access: &WorktreeReadPermission) -> R
     let mut head_tree_ids = Vec::new();
 
     for branch in vb_state.list_branches_in_workspace()? {
+        // Broken branches are still captured by `virtual_branches.toml`, and must not prevent
+        // the snapshot that is taken before repairing them.
+        if repo.find_commit(branch.head()).is_err() || repo.find_tree(branch.tree).is_err() {
+            tracing::warn!(
+                "skipping branch {} in snapshot as its head or tree is missing",
+                branch.name
+            );
+            continue;
+        }
         head_tree_ids.push(branch.tree);
 
         // commits in virtual branches (tree and commit data)
@@ -475,11 +499,19 @@ fn commit_snapshot(
         parents.as_slice(),
     )?;
 
-    oplog_state.set_oplog_head(snapshot_commit_id)?;
+    // The oplog head and the reflog keeping it reachable change together, or not at all.
+    let mut transaction = ctx.journal().transaction();
+    oplog_state.set_oplog_head(&mut transaction, snapshot_commit_id)?;
 
     let vb_state = VirtualBranchesHandle::new(ctx.gb_dir());
     let target_commit_id = vb_state.get_default_target()?.sha;
-    set_reference_to_oplog(&ctx.path, target_commit_id, snapshot_commit_id)?;
+    set_reference_to_oplog(
+        &ctx.path,
+        target_commit_id,
+        snapshot_commit_id,
+        &mut transaction,
+    )?;
+    transaction.commit(&repo)?;
 
     Ok(snapshot_commit_id)
 }
@@ -503,6 +535,9 @@ fn restore_snapshot(
     let vb_toml_blob = repo
         .find_blob(vb_toml_entry.id())
         .context("failed to convert virtual_branches tree entry to blob")?;
+    // Fail before touching anything if the snapshot was taken by a newer version of the app.
+    VirtualBranchesState::from_toml(from_utf8(vb_toml_blob.content())?)
+        .context("failed to read the virtual branches state of the snapshot")?;
 
     if let Err(err) = restore_conflicts_tree(&snapshot_tree, &repo) {
         tracing::warn!("failed to restore conflicts tree - ignoring: {err}")
@@ -585,6 +620,10 @@ fn restore_snapshot(
     checkout_builder.force();
     // Checkout the tree
     repo.checkout_tree(workdir_tree.as_object(), Some(&mut checkout_builder))?;
+    // Snapshots only contain pointers of LFS-tracked files, replace them with their content.
+    smudge_lfs_files(&repo, &workdir_tree)?;
+    // Checkouts don't touch submodule worktrees, so put them back to the commits they pointed to.
+    checkout_submodules(&repo, &workdir_tree)?;
 
     // Update virtual_branches.toml with the state from the snapshot
     fs::write(
@@ -639,6 +678,65 @@ fn restore_snapshot(
     )
 }
 
+/// Replace all LFS pointer files in the worktree that were checked out from `tree` with their content
+/// from the local LFS store, if available.
+fn smudge_lfs_files(repo: &git2::Repository, tree: &git2::Tree) -> Result<()> {
+    let mut lfs_paths = Vec::new();
+    tree.walk(git2::TreeWalkMode::PreOrder, |dir, entry| {
+        if entry.kind() == Some(git2::ObjectType::Blob) {
+            if let Some(name) = entry.name() {
+                let path = PathBuf::from(dir).join(name);
+                if lfs::is_lfs_tracked(repo, &path) {
+                    lfs_paths.push(path);
+                }
+            }
+        }
+        git2::TreeWalkResult::Ok
+    })?;
+    for path in lfs_paths {
+        lfs::smudge_worktree_file(repo, &path)?;
+    }
+    Ok(())
+}
+
+/// Check out the commit that each submodule in `tree` points to in its worktree, if the submodule is
+/// initialized and the commit is available.
+fn checkout_submodules(repo: &git2::Repository, tree: &git2::Tree) -> Result<()> {
+    let Some(workdir) = repo.workdir() else {
+        return Ok(());
+    };
+    let mut submodules = Vec::new();
+    tree.walk(git2::TreeWalkMode::PreOrder, |dir, entry| {
+        if entry.filemode() == i32::from(git2::FileMode::Commit) {
+            if let Some(name) = entry.name() {
+                submodules.push((PathBuf::from(dir).join(name), entry.id()));
+            }
+        }
+        git2::TreeWalkResult::Ok
+    })?;
+    for (path, commit_id) in submodules {
+        let Ok(submodule) = git2::Repository::open(workdir.join(&path)) else {
+            continue;
+        };
+        if submodule.head().ok().and_then(|head| head.target()) == Some(commit_id) {
+            continue;
+        }
+        let Ok(commit) = submodule.find_commit(commit_id) else {
+            tracing::warn!(
+                "Commit {commit_id} of submodule at '{}' isn't available, leaving it as is",
+                path.display()
+            );
+            continue;
+        };
+        submodule.checkout_tree(
+            commit.as_object(),
+            Some(git2::build::CheckoutBuilder::new().force()),
+        )?;
+        submodule.set_head_detached(commit_id)?;
+    }
+    Ok(())
+}
+
 /// Restore the state of .git/base_merge_parent and .git/conflicts from the snapshot
 /// Will remove those files if they are not present in the snapshot
 fn restore_conflicts_tree(snapshot_tree: &git2::Tree, repo: &git2::Repository) -> Result<()> {
@@ -671,11 +769,8 @@ fn restore_conflicts_tree(snapshot_tree: &git2::Tree, repo: &git2::Repository) -
     Ok(())
 }
 
-fn write_conflicts_tree(
-    worktree_dir: &std::path::Path,
-    repo: &git2::Repository,
-) -> Result<git2::Oid> {
-    let git_dir = worktree_dir.join(".git");
+fn write_conflicts_tree(repo: &git2::Repository) -> Result<git2::Oid> {
+    let git_dir = repo.path();
     let merge_parent_path = git_dir.join("base_merge_parent");
     let merge_parent_blob = if merge_parent_path.exists() {
         let merge_parent_content = fs::read(merge_parent_path)?;
@@ -725,16 +820,18 @@ fn lines_since_snapshot(project: &Project, repo: &git2::Repository) -> Result<us
     let mut lines_changed = 0;
     let dirty_branches = vbranches.iter().filter(|b| !b.ownership.claims.is_empty());
     for branch in dirty_branches {
-        lines_changed += branch_lines_since_snapshot(branch, repo, oplog_commit_id)?;
+        lines_changed +=
+            branch_lines_since_snapshot(branch, repo, oplog_commit_id, &project.snapshot_policy)?;
     }
     Ok(lines_changed)
 }
 
-#[instrument(level = tracing::Level::DEBUG, skip(branch, repo), err(Debug))]
+#[instrument(level = tracing::Level::DEBUG, skip(branch, repo, policy), err(Debug))]
 fn branch_lines_since_snapshot(
     branch: &Stack,
     repo: &git2::Repository,
     head_sha: git2::Oid,
+    policy: &SnapshotPolicy,
 ) -> Result<usize> {
     let active_branch_tree = repo.find_tree(branch.tree)?;
 
@@ -755,15 +852,34 @@ fn branch_lines_since_snapshot(
 
     let mut opts = git2::DiffOptions::new();
     opts.include_untracked(true);
-    opts.ignore_submodules(true);
+    opts.ignore_submodules(false);
 
     let diff = repo.diff_tree_to_tree(
         Some(&active_branch_tree),
         Some(&old_active_branch_tree),
         Some(&mut opts),
-    );
-    let stats = diff?.stats()?;
-    Ok(stats.deletions() + stats.insertions())
+    )?;
+    if policy.exclude_paths.is_empty() {
+        let stats = diff.stats()?;
+        return Ok(stats.deletions() + stats.insertions());
+    }
+
+    let mut lines_changed = 0;
+    for (idx, delta) in diff.deltas().enumerate() {
+        let excluded = delta
+            .new_file()
+            .path()
+            .or_else(|| delta.old_file().path())
+            .map_or(false, |path| policy.is_excluded(path));
+        if excluded {
+            continue;
+        }
+        if let Some(patch) = git2::Patch::from_diff(&diff, idx)? {
+            let (_context, insertions, deletions) = patch.line_stats()?;
+            lines_changed += insertions + deletions;
+        }
+    }
+    Ok(lines_changed)
 }
 
 fn serialize_commit(commit: &git2::Commit<'_>) -> Vec<u8> {
@@ -816,7 +932,7 @@ fn tree_from_applied_vbranches(
         .find_blob(vb_toml_entry.id())
         .context("failed to convert virtual_branches tree entry to blob")?;
 
-    let vbs_from_toml: VirtualBranchesState = toml::from_str(from_utf8(vb_toml_blob.content())?)?;
+    let vbs_from_toml = VirtualBranchesState::from_toml(from_utf8(vb_toml_blob.content())?)?;
     let applied_branch_trees: Vec<git2::Oid> = vbs_from_toml
         .list_branches_in_workspace()?
         .iter()
@@ -1,7 +1,7 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
-use gitbutler_fs::write;
+use gitbutler_project::Transaction;
 use gitbutler_repo::{GITBUTLER_COMMIT_AUTHOR_EMAIL, GITBUTLER_COMMIT_AUTHOR_NAME};
 use gix::config::tree::Key;
 
@@ -10,7 +10,8 @@ use gix::config::tree::Key;
 ///  - The oplog must not be visible in `git log --all` as branch
 ///  - The oplog tree must not be garbage collected (i.e. it must be reachable)
 ///
-/// This needs to be invoked whenever the target head or the oplog head change.
+/// This needs to be invoked whenever the target head or the oplog head change. The reflog is written when
+/// `transaction` is committed, so it can change along with the oplog head.
 ///
 /// How it works:
 /// First a reference gitbutler/target is created, pointing to the head of the target (trunk) branch.
@@ -22,19 +23,16 @@ use gix::config::tree::Key;
 /// <target branch head>                     <oplog head>
 ///
 /// The reflog entry is continuously updated to refer to the current target and oplog head commits.
+///
+/// Branches and their logs are shared by all worktrees of a repository, so in a linked worktree the
+/// per-worktree reference `refs/worktree/gitbutler/target` is used instead, which keeps the oplog of each
+/// worktree reachable on its own.
 pub(super) fn set_reference_to_oplog(
     worktree_dir: &Path,
     target_commit_id: git2::Oid,
     oplog_commit_id: git2::Oid,
+    transaction: &mut Transaction,
 ) -> Result<()> {
-    let reflog_file_path = worktree_dir
-        .join(".git")
-        .join("logs")
-        .join("refs")
-        .join("heads")
-        .join("gitbutler")
-        .join("target");
-
     let mut repo = gix::open_opts(
         worktree_dir,
         // We may override the username as we only write a specific commit log, unrelated to the user.
@@ -46,12 +44,13 @@ pub(super) fn set_reference_to_oplog(
             ]
         }),
     )?;
+    let reflog_file_path = target_reflog_path(&repo);
     // The check is here only to avoid unnecessary writes
-    if repo.try_find_reference("gitbutler/target")?.is_none() {
+    if repo.try_find_reference(target_refname(&repo))?.is_none() {
         repo.refs.write_reflog = gix::refs::store::WriteReflog::Always;
         let target_commit_hex = target_commit_id.to_string();
         repo.reference(
-            "refs/heads/gitbutler/target",
+            target_refname(&repo),
             target_commit_hex.parse::<gix::ObjectId>()?,
             gix::refs::transaction::PreviousValue::Any,
             branch_creation_message(&target_commit_hex),
@@ -70,11 +69,57 @@ pub(super) fn set_reference_to_oplog(
         )
     })?;
     content = set_oplog_ref(&content, &oplog_commit_id.to_string())?;
-    write(reflog_file_path, content)?;
+    transaction.write_file(reflog_file_path, content);
 
     Ok(())
 }
 
+/// Returns `true` if the [target reference](target_refname()) exists and its reflog keeps `oplog_commit_id`
+/// reachable, as arranged by [`set_reference_to_oplog()`].
+pub(super) fn reflog_references_oplog(
+    worktree_dir: &Path,
+    oplog_commit_id: git2::Oid,
+) -> Result<bool> {
+    let repo = gix::open(worktree_dir)?;
+    if repo.try_find_reference(target_refname(&repo))?.is_none() {
+        return Ok(false);
+    }
+    let content = match std::fs::read(target_reflog_path(&repo)) {
+        Ok(c) => c,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(err.into()),
+    };
+    let oplog_commit_id = oplog_commit_id.to_string();
+    Ok(gix::refs::file::log::iter::forward(&content)
+        .filter_map(Result::ok)
+        .any(|line| line.new_oid.as_ref() == oplog_commit_id.as_bytes()))
+}
+
+/// Return the full name of the reference whose reflog protects the oplog of the worktree of `repo`.
+fn target_refname(repo: &gix::Repository) -> &'static str {
+    if is_linked_worktree(repo) {
+        "refs/worktree/gitbutler/target"
+    } else {
+        "refs/heads/gitbutler/target"
+    }
+}
+
+fn target_reflog_path(repo: &gix::Repository) -> PathBuf {
+    // The logs of per-worktree references are private to the worktree, all others are shared.
+    let dir = if is_linked_worktree(repo) {
+        repo.git_dir()
+    } else {
+        repo.common_dir()
+    };
+    target_refname(repo)
+        .split('/')
+        .fold(dir.join("logs"), |path, component| path.join(component))
+}
+
+fn is_linked_worktree(repo: &gix::Repository) -> bool {
+    repo.git_dir() != repo.common_dir()
+}
+
 fn branch_creation_message(commit_id_hex: &str) -> String {
     format!("branch: Created from {commit_id_hex}")
 }
@@ -146,15 +191,82 @@ fn serialize_line(line: gix::refs::file::log::LineRef<'_>) -> String {
 
 #[cfg(test)]
 mod set_target_ref {
-    use std::path::PathBuf;
+    use std::path::{Path, PathBuf};
 
+    use gitbutler_project::Journal;
     use gix::refs::file::log::LineRef;
     use pretty_assertions::assert_eq;
     use tempfile::tempdir;
 
-    use super::{
-        set_reference_to_oplog, GITBUTLER_COMMIT_AUTHOR_EMAIL, GITBUTLER_COMMIT_AUTHOR_NAME,
-    };
+    use super::{GITBUTLER_COMMIT_AUTHOR_EMAIL, GITBUTLER_COMMIT_AUTHOR_NAME};
+
+    fn set_reference_to_oplog(
+        worktree_dir: &Path,
+        target_commit_id: git2::Oid,
+        oplog_commit_id: git2::Oid,
+    ) -> anyhow::Result<()> {
+        let repo = git2::Repository::open(worktree_dir)?;
+        let journal = Journal::new(repo.path().join("gitbutler"));
+        let mut transaction = journal.transaction();
+        super::set_reference_to_oplog(
+            worktree_dir,
+            target_commit_id,
+            oplog_commit_id,
+            &mut transaction,
+        )?;
+        transaction.commit(&repo)
+    }
+
+    #[test]
+    fn linked_worktrees_protect_their_oplog_independently() -> anyhow::Result<()> {
+        let (dir, commit_id) = setup_repo()?;
+        let main_worktree_dir = dir.path();
+        let worktrees = tempdir()?;
+        let linked_worktree_dir = worktrees.path().join("linked");
+        git2::Repository::open(main_worktree_dir)?.worktree(
+            "linked",
+            &linked_worktree_dir,
+            None,
+        )?;
+
+        let main_oplog = git2::Oid::from_str("0123456789abcdef0123456789abcdef01234567")?;
+        let linked_oplog = git2::Oid::from_str("bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb")?;
+        set_reference_to_oplog(main_worktree_dir, commit_id, main_oplog).expect("success");
+        set_reference_to_oplog(&linked_worktree_dir, commit_id, linked_oplog).expect("success");
+
+        assert!(super::reflog_references_oplog(
+            main_worktree_dir,
+            main_oplog
+        )?);
+        assert!(
+            !super::reflog_references_oplog(main_worktree_dir, linked_oplog)?,
+            "the shared reflog isn't touched by the linked worktree"
+        );
+        assert!(super::reflog_references_oplog(
+            &linked_worktree_dir,
+            linked_oplog
+        )?);
+        assert!(!super::reflog_references_oplog(
+            &linked_worktree_dir,
+            main_oplog
+        )?);
+
+        let main_log = main_worktree_dir.join(".git/logs/refs/heads/gitbutler/target");
+        let contents = std::fs::read_to_string(&main_log)?;
+        assert_eq!(
+            reflog_lines(&contents)[1].new_oid,
+            main_oplog.to_string().as_bytes()
+        );
+
+        let linked_log =
+            main_worktree_dir.join(".git/worktrees/linked/logs/refs/worktree/gitbutler/target");
+        let contents = std::fs::read_to_string(&linked_log)?;
+        let lines = reflog_lines(&contents);
+        assert_eq!(lines.len(), 2);
+        assert_signature(lines[0].signature);
+        assert_eq!(lines[1].new_oid, linked_oplog.to_string().as_bytes());
+        Ok(())
+    }
 
     #[test]
     fn reflog_present_but_empty() -> anyhow::Result<()> {
@@ -5,6 +5,7 @@ use std::{
 
 use anyhow::Result;
 use gitbutler_fs::read_toml_file_or_default;
+use gitbutler_project::Transaction;
 use serde::{Deserialize, Deserializer, Serialize};
 
 use super::OPLOG_FILE_NAME;
@@ -57,13 +58,14 @@ impl OplogHandle {
         Self { file_path }
     }
 
-    /// Persists the oplog head for the given repository.
+    /// Persists the oplog head for the given repository once `transaction` is committed.
     ///
-    /// Errors if the file cannot be read or written.
-    pub fn set_oplog_head(&self, sha: git2::Oid) -> Result<()> {
+    /// Errors if the file cannot be read.
+    pub fn set_oplog_head(&self, transaction: &mut Transaction, sha: git2::Oid) -> Result<()> {
         let mut oplog = self.read_file()?;
         oplog.head_sha = Some(sha);
-        self.write_file(oplog)?;
+        oplog.modified_at = SystemTime::now();
+        transaction.write_file(self.file_path.clone(), toml::to_string(&oplog)?);
         Ok(())
     }
 
@@ -89,9 +91,4 @@ impl OplogHandle {
     fn read_file(&self) -> Result<Oplog> {
         read_toml_file_or_default(&self.file_path)
     }
-
-    fn write_file(&self, mut oplog: Oplog) -> Result<()> {
-        oplog.modified_at = SystemTime::now();
-        gitbutler_fs::write(&self.file_path, toml::to_string(&oplog)?)
-    }
 }
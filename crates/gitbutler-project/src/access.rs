@@ -140,6 +140,22 @@ impl LockFile {
         })
     }
 
+    /// Lock the resource, blocking until it is released by its current owner, or pretend it was locked
+    /// if the underlying filesystem didn't support it.
+    pub fn lock(&mut self) -> Result<(), fslock::Error> {
+        self.inner.lock().or_else(|err| {
+            if err.kind() == std::io::ErrorKind::Unsupported {
+                tracing::warn!(
+                    "Filesystem hosting '{}' doesn't support file locking - pretending to own lock to avoid failure",
+                    self.path.display()
+                );
+                Ok(())
+            } else {
+                Err(err)
+            }
+        })
+    }
+
     /// Drop the lock on this file, or do nothing if we don't own the lock.
     pub fn unlock(&mut self) -> Result<(), fslock::Error> {
         if !self.inner.owns_lock() {
@@ -3,7 +3,7 @@ use std::path::{Path, PathBuf};
 use anyhow::{anyhow, bail, Context, Result};
 use gitbutler_error::error;
 
-use super::{storage, storage::UpdateRequest, Project, ProjectId};
+use super::{storage, storage::UpdateRequest, LinkedWorktree, Project, ProjectId, Worktree};
 use crate::AuthKey;
 
 #[derive(Clone)]
@@ -36,14 +36,13 @@ impl Controller {
         if !path.is_dir() {
             bail!("not a directory");
         }
+        let mut linked_worktree = None;
         match gix::open_opts(path, gix::open::Options::isolated()) {
             Ok(repo) if repo.is_bare() => {
                 bail!("bare repositories are unsupported");
             }
             Ok(repo) if repo.worktree().map_or(false, |wt| !wt.is_main()) => {
-                if path.join(".git").is_file() {
-                    bail!("can only work in main worktrees");
-                };
+                linked_worktree = LinkedWorktree::from_repository(&repo)?;
             }
             Ok(repo) => {
                 match repo.work_dir() {
@@ -73,6 +72,7 @@ impl Controller {
             id: ProjectId::generate(),
             title,
             path: gix::path::realpath(path)?,
+            linked_worktree,
             api: None,
             ..Default::default()
         };
@@ -171,7 +171,7 @@ impl Controller {
             }
         }
         // Clean up old virtual_branches.toml that was never used
-        let old_virtual_branches_path = project.path.join(".git").join("virtual_branches.toml");
+        let old_virtual_branches_path = project.git_dir().join("virtual_branches.toml");
         if old_virtual_branches_path.exists() {
             if let Err(error) = std::fs::remove_file(old_virtual_branches_path) {
                 tracing::error!(project_id = %project.id, ?error, "failed to remove old virtual_branches.toml");
@@ -204,7 +204,7 @@ impl Controller {
             tracing::error!(project_id = %id, ?error, "failed to remove project data",);
         }
 
-        if let Err(error) = std::fs::remove_file(project.path.join(".git/gitbutler.json")) {
+        if let Err(error) = std::fs::remove_file(project.git_dir().join("gitbutler.json")) {
             tracing::error!(project_id = %project.id, ?error, "failed to remove .git/gitbutler.json data",);
         }
 
@@ -217,6 +217,41 @@ impl Controller {
         Ok(())
     }
 
+    /// Return all worktrees of the repository of the project with `id`, the main worktree first,
+    /// along with the projects they were added as.
+    pub fn worktrees(&self, id: ProjectId) -> Result<Vec<Worktree>> {
+        let project = self.projects_storage.get(id)?;
+        let repo = gix::open_opts(&project.path, gix::open::Options::isolated())
+            .context("failed to open project repository")?;
+        let main_repo = repo
+            .main_repo()
+            .context("failed to open the main worktree of the repository")?;
+
+        let mut worktrees = Vec::new();
+        if let Some(work_dir) = main_repo.work_dir() {
+            worktrees.push((gix::path::realpath(work_dir)?, None));
+        }
+        for proxy in repo.worktrees()? {
+            worktrees.push((
+                gix::path::realpath(proxy.base()?)?,
+                Some(proxy.id().to_string()),
+            ));
+        }
+
+        let projects = self.list()?;
+        Ok(worktrees
+            .into_iter()
+            .map(|(path, name)| Worktree {
+                project_id: projects
+                    .iter()
+                    .find(|project| project.path == path)
+                    .map(|project| project.id),
+                path,
+                name,
+            })
+            .collect())
+    }
+
     pub fn project_metadata_dir(&self, id: ProjectId) -> PathBuf {
         self.local_data_dir.join("projects").join(id.to_string())
     }
@@ -0,0 +1,461 @@
+//! A write-ahead journal to change several files and references of a project as one transaction.
+//!
+//! Each file is written atomically, but operations like creating a snapshot or entering edit mode
+//! change multiple files and references at once, and a crash in between would leave them inconsistent.
+//! A [`Transaction`] collects these changes and first stages them in the journal directory, along with a
+//! manifest that lists them. Writing the manifest is the commit point: once it exists, the changes are
+//! applied, and if that is interrupted, [`Journal::recover()`] applies them again the next time the
+//! project is opened. Staged contents without a manifest belong to a transaction that never committed,
+//! and are discarded instead, which leaves all files and references as they were before.
+//!
+//! Paths in the manifest are relative to the git directory of the project, the parent of its
+//! [GitButler directory](Project::gb_dir()), so a journal stays valid if the project is moved.
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{access::LockFile, Project};
+
+/// The name of the file listing the changes of a committed transaction.
+const MANIFEST_FILE_NAME: &str = "transaction.json";
+/// The name of the file that is locked while a transaction is committed or recovered.
+const LOCK_FILE_NAME: &str = "journal.lock";
+
+/// A single change to apply as part of a transaction.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum Change {
+    /// Write the file at `path` with the contents staged in the journal directory as `staged`.
+    /// Like all paths of changes, `path` is [relative](Journal::relative_path()) if possible.
+    #[serde(rename_all = "camelCase")]
+    WriteFile { path: PathBuf, staged: String },
+    /// Remove the file at `path` if it exists.
+    RemoveFile { path: PathBuf },
+    /// Point the reference `name` at the object `target`.
+    Reference {
+        name: String,
+        #[serde(with = "gitbutler_serde::oid")]
+        target: git2::Oid,
+        message: String,
+    },
+    /// Point the reference `name` at the reference `target`, which makes `HEAD` point to a branch.
+    SymbolicReference { name: String, target: String },
+}
+
+/// The list of changes of a transaction, which exists only once the transaction is committed.
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Manifest {
+    changes: Vec<Change>,
+}
+
+/// The write-ahead journal of a project, stored in the `journal` directory of its
+/// [GitButler directory](Project::gb_dir()).
+#[derive(Debug, Clone)]
+pub struct Journal {
+    /// The directory that paths of changes are relative to.
+    base: PathBuf,
+    dir: PathBuf,
+}
+
+impl Project {
+    /// Return the journal to change files and references of this project in [transactions](Transaction).
+    pub fn journal(&self) -> Journal {
+        Journal::new(self.gb_dir())
+    }
+}
+
+impl Journal {
+    /// Create a journal that lives in `gb_dir`, the directory holding GitButler's state of a project.
+    pub fn new(gb_dir: impl AsRef<Path>) -> Self {
+        let gb_dir = gb_dir.as_ref();
+        Journal {
+            base: gb_dir.parent().unwrap_or(gb_dir).to_owned(),
+            dir: gb_dir.join("journal"),
+        }
+    }
+
+    /// Start a new transaction, which does nothing until it is [committed](Transaction::commit()).
+    pub fn transaction(&self) -> Transaction {
+        Transaction {
+            journal: self.clone(),
+            changes: Vec::new(),
+            staged: Vec::new(),
+        }
+    }
+
+    /// Finish or undo a transaction that was interrupted, applying the changes of a committed transaction
+    /// to `repo` and discarding the changes of one that never committed.
+    ///
+    /// Returns `true` if an interrupted transaction was found. This is cheap if there isn't one, and is
+    /// expected to be called whenever the project is opened.
+    pub fn recover(&self, repo: &git2::Repository) -> Result<bool> {
+        if !self.has_leftovers()? {
+            return Ok(false);
+        }
+        let _lock = self.lock()?;
+        self.recover_locked(repo)
+    }
+
+    /// Return `true` if there is anything in the journal directory besides the lock file.
+    fn has_leftovers(&self) -> Result<bool> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(err) => return Err(err.into()),
+        };
+        for entry in entries {
+            if entry?.file_name() != LOCK_FILE_NAME {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Block until no other transaction, possibly of another process, is committed or recovered.
+    fn lock(&self) -> Result<LockFile> {
+        std::fs::create_dir_all(&self.dir)?;
+        let mut lock = LockFile::open(self.dir.join(LOCK_FILE_NAME))?;
+        lock.lock()
+            .with_context(|| format!("Failed to lock journal at '{}'", self.dir.display()))?;
+        Ok(lock)
+    }
+
+    fn recover_locked(&self, repo: &git2::Repository) -> Result<bool> {
+        if !self.has_leftovers()? {
+            return Ok(false);
+        }
+        if self.manifest_path().exists() {
+            tracing::warn!(
+                "Completing interrupted transaction in journal at '{}'",
+                self.dir.display()
+            );
+            self.roll_forward(repo)?;
+        } else {
+            tracing::warn!(
+                "Discarding uncommitted transaction in journal at '{}'",
+                self.dir.display()
+            );
+            self.clear()?;
+        }
+        Ok(true)
+    }
+
+    /// Apply all changes of the committed transaction, and clear the journal once they are applied.
+    ///
+    /// Applying a change twice has the same result as applying it once, so this is safe to do again
+    /// after a crash.
+    fn roll_forward(&self, repo: &git2::Repository) -> Result<()> {
+        let manifest: Manifest = serde_json::from_slice(&std::fs::read(self.manifest_path())?)
+            .context("Failed to read the manifest of the journal")?;
+        for change in &manifest.changes {
+            self.apply(repo, change)
+                .with_context(|| format!("Failed to apply {change:?} from the journal"))?;
+        }
+        self.clear()
+    }
+
+    fn apply(&self, repo: &git2::Repository, change: &Change) -> Result<()> {
+        match change {
+            Change::WriteFile { path, staged } => {
+                let contents = std::fs::read(self.dir.join(staged))?;
+                gitbutler_fs::create_dirs_then_write(self.base.join(path), contents)?;
+            }
+            Change::RemoveFile { path } => match std::fs::remove_file(self.base.join(path)) {
+                Ok(()) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err.into()),
+            },
+            Change::Reference {
+                name,
+                target,
+                message,
+            } => {
+                repo.reference(name, *target, true, message)?;
+            }
+            Change::SymbolicReference { name, target } if name == "HEAD" => {
+                repo.set_head(target)?;
+            }
+            Change::SymbolicReference { name, target } => {
+                repo.reference_symbolic(name, target, true, "")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove the manifest first, so an interruption can't make a transaction appear committed
+    /// when its staged contents are gone, and then everything else but the lock file.
+    fn clear(&self) -> Result<()> {
+        match std::fs::remove_file(self.manifest_path()) {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+        for entry in std::fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            if entry.file_name() != LOCK_FILE_NAME {
+                std::fs::remove_file(entry.path())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn manifest_path(&self) -> PathBuf {
+        self.dir.join(MANIFEST_FILE_NAME)
+    }
+
+    /// Return `path` relative to the git directory of the project, or as is if it's outside of it.
+    /// Joining the base with the result yields `path` either way.
+    fn relative_path(&self, path: PathBuf) -> PathBuf {
+        match path.strip_prefix(&self.base) {
+            Ok(relative) => relative.to_owned(),
+            Err(_) => path,
+        }
+    }
+}
+
+/// A set of changes to files and references that are applied all together, or not at all.
+///
+/// Changes are applied in the order they were added once the transaction is [committed](Self::commit()).
+#[must_use = "transactions do nothing unless committed"]
+pub struct Transaction {
+    journal: Journal,
+    changes: Vec<Change>,
+    /// The contents of files to write, by the name they are staged under.
+    staged: Vec<(String, Vec<u8>)>,
+}
+
+impl Transaction {
+    /// Write `contents` to the file at `path`, creating its leading directories if needed.
+    pub fn write_file(
+        &mut self,
+        path: impl Into<PathBuf>,
+        contents: impl Into<Vec<u8>>,
+    ) -> &mut Self {
+        let staged = format!("{}.staged", self.staged.len());
+        self.staged.push((staged.clone(), contents.into()));
+        self.changes.push(Change::WriteFile {
+            path: self.journal.relative_path(path.into()),
+            staged,
+        });
+        self
+    }
+
+    /// Remove the file at `path`, if it exists.
+    pub fn remove_file(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.changes.push(Change::RemoveFile {
+            path: self.journal.relative_path(path.into()),
+        });
+        self
+    }
+
+    /// Create or update the reference `name` to point to `target`, with `message` in its reflog.
+    pub fn update_reference(
+        &mut self,
+        name: impl Into<String>,
+        target: git2::Oid,
+        message: impl Into<String>,
+    ) -> &mut Self {
+        self.changes.push(Change::Reference {
+            name: name.into(),
+            target,
+            message: message.into(),
+        });
+        self
+    }
+
+    /// Create or update the reference `name` to point to the reference `target`, like `HEAD` does
+    /// when a branch is checked out.
+    pub fn update_symbolic_reference(
+        &mut self,
+        name: impl Into<String>,
+        target: impl Into<String>,
+    ) -> &mut Self {
+        self.changes.push(Change::SymbolicReference {
+            name: name.into(),
+            target: target.into(),
+        });
+        self
+    }
+
+    /// Return `true` if nothing was added to this transaction.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Apply all changes to the files of the project and the references of `repo`.
+    ///
+    /// If this fails or the process is interrupted after the transaction was recorded in the journal,
+    /// the remaining changes are applied when the journal is [recovered](Journal::recover()).
+    pub fn commit(self, repo: &git2::Repository) -> Result<()> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        let _lock = self.journal.lock()?;
+        self.journal.recover_locked(repo)?;
+        self.write_ahead()?;
+        self.journal.roll_forward(repo)
+    }
+
+    /// Stage all file contents and then write the manifest, which commits the transaction.
+    fn write_ahead(&self) -> Result<()> {
+        for (staged, contents) in &self.staged {
+            write_durably(&self.journal.dir.join(staged), contents)?;
+        }
+        let manifest = serde_json::to_vec_pretty(&Manifest {
+            changes: self.changes.clone(),
+        })?;
+        let manifest_path = self.journal.manifest_path();
+        let temp_path = manifest_path.with_extension("json.tmp");
+        write_durably(&temp_path, &manifest)?;
+        std::fs::rename(&temp_path, &manifest_path)
+            .context("Failed to commit transaction to the journal")?;
+        Ok(())
+    }
+}
+
+/// Write `contents` to `path` and make sure it's on disk before returning, as the journal is
+/// only useful if it survives a crash of the system, too.
+fn write_durably(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut file = File::create(path)
+        .with_context(|| format!("Failed to create '{}' in journal", path.display()))?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repo_with_commit() -> (tempfile::TempDir, git2::Repository, git2::Oid) {
+        let tmp = tempfile::tempdir().unwrap();
+        let repo = git2::Repository::init(tmp.path()).unwrap();
+        let signature = git2::Signature::now("test", "test@email.com").unwrap();
+        let tree_id = repo.index().unwrap().write_tree().unwrap();
+        let commit_id = {
+            let tree = repo.find_tree(tree_id).unwrap();
+            repo.commit(None, &signature, &signature, "initial", &tree, &[])
+                .unwrap()
+        };
+        (tmp, repo, commit_id)
+    }
+
+    fn transaction(journal: &Journal, dir: &Path, commit_id: git2::Oid) -> Transaction {
+        let mut transaction = journal.transaction();
+        transaction
+            .write_file(dir.join("state/a.toml"), "a")
+            .write_file(dir.join("b.toml"), "b")
+            .remove_file(dir.join("c.toml"))
+            .update_reference("refs/heads/gitbutler/edit", commit_id, "journal test")
+            .update_symbolic_reference("HEAD", "refs/heads/gitbutler/edit");
+        transaction
+    }
+
+    fn assert_applied(repo: &git2::Repository, dir: &Path, commit_id: git2::Oid) {
+        assert_eq!(
+            std::fs::read_to_string(dir.join("state/a.toml")).unwrap(),
+            "a"
+        );
+        assert_eq!(std::fs::read_to_string(dir.join("b.toml")).unwrap(), "b");
+        assert!(!dir.join("c.toml").exists());
+        let head = repo.head().unwrap();
+        assert_eq!(head.name(), Some("refs/heads/gitbutler/edit"));
+        assert_eq!(head.target(), Some(commit_id));
+    }
+
+    #[test]
+    fn commit_applies_all_changes() {
+        let (tmp, repo, commit_id) = repo_with_commit();
+        let journal = Journal::new(tmp.path().join("gb"));
+        std::fs::write(tmp.path().join("c.toml"), "c").unwrap();
+
+        transaction(&journal, tmp.path(), commit_id)
+            .commit(&repo)
+            .unwrap();
+        assert_applied(&repo, tmp.path(), commit_id);
+        assert!(!journal.has_leftovers().unwrap());
+        assert!(!journal.recover(&repo).unwrap());
+    }
+
+    #[test]
+    fn recover_rolls_committed_transaction_forward() {
+        let (tmp, repo, commit_id) = repo_with_commit();
+        let journal = Journal::new(tmp.path().join("gb"));
+        std::fs::write(tmp.path().join("c.toml"), "c").unwrap();
+
+        // Simulate a crash right after the transaction was committed.
+        std::fs::create_dir_all(&journal.dir).unwrap();
+        transaction(&journal, tmp.path(), commit_id)
+            .write_ahead()
+            .unwrap();
+        assert!(!tmp.path().join("b.toml").exists());
+
+        assert!(journal.recover(&repo).unwrap());
+        assert_applied(&repo, tmp.path(), commit_id);
+        assert!(!journal.has_leftovers().unwrap());
+    }
+
+    #[test]
+    fn manifest_paths_are_relative_to_the_git_directory() {
+        let (tmp, repo, commit_id) = repo_with_commit();
+        let journal = Journal::new(tmp.path().join("gb"));
+        let outside = tempfile::tempdir().unwrap();
+        let mut transaction = transaction(&journal, tmp.path(), commit_id);
+        transaction.write_file(outside.path().join("d.toml"), "d");
+
+        std::fs::create_dir_all(&journal.dir).unwrap();
+        transaction.write_ahead().unwrap();
+        let manifest: Manifest =
+            serde_json::from_slice(&std::fs::read(journal.manifest_path()).unwrap()).unwrap();
+        let paths: Vec<_> = manifest
+            .changes
+            .iter()
+            .filter_map(|change| match change {
+                Change::WriteFile { path, .. } | Change::RemoveFile { path } => Some(path.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                PathBuf::from("state/a.toml"),
+                "b.toml".into(),
+                "c.toml".into(),
+                outside.path().join("d.toml"),
+            ],
+            "paths outside of the git directory stay absolute"
+        );
+
+        assert!(journal.recover(&repo).unwrap());
+        assert_applied(&repo, tmp.path(), commit_id);
+        assert_eq!(
+            std::fs::read_to_string(outside.path().join("d.toml")).unwrap(),
+            "d"
+        );
+    }
+
+    #[test]
+    fn recover_discards_uncommitted_transaction() {
+        let (tmp, repo, commit_id) = repo_with_commit();
+        let journal = Journal::new(tmp.path().join("gb"));
+        std::fs::write(tmp.path().join("c.toml"), "c").unwrap();
+
+        // Simulate a crash while contents were staged, before the manifest was written.
+        std::fs::create_dir_all(&journal.dir).unwrap();
+        let transaction = transaction(&journal, tmp.path(), commit_id);
+        for (staged, contents) in &transaction.staged {
+            write_durably(&journal.dir.join(staged), contents).unwrap();
+        }
+
+        assert!(journal.recover(&repo).unwrap());
+        assert!(!journal.has_leftovers().unwrap());
+        assert!(!tmp.path().join("b.toml").exists());
+        assert!(tmp.path().join("c.toml").exists());
+        assert!(repo.find_reference("refs/heads/gitbutler/edit").is_err());
+    }
+}
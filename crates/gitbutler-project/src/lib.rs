@@ -1,12 +1,16 @@
 pub mod access;
 mod controller;
 mod default_true;
+mod journal;
 mod project;
 mod storage;
 
 pub use controller::Controller;
+pub use journal::{Journal, Transaction};
 pub use project::{
-    ApiProject, AuthKey, CodePushState, FetchResult, ForgeSettings, Project, ProjectId,
+    ApiProject, AuthKey, CodePushState, DiffAlgorithm, DiffSettings, FetchResult,
+    FileWatcherBackend, FileWatcherSettings, ForgeSettings, LinkedWorktree, Project, ProjectId,
+    SnapshotPolicy, Worktree,
 };
 pub use storage::UpdateRequest;
 
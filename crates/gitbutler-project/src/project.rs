@@ -75,9 +75,11 @@ pub struct Project {
     pub title: String,
     pub description: Option<String>,
     /// The worktree directory of the project's repository.
-    // TODO(ST): rename this to `worktree_dir` and while at it, add a `git_dir` if it's retrieved from a repo.
-    //           Then find `.join(".git")` and use the `git_dir` instead.
+    // TODO(ST): rename this to `worktree_dir`.
     pub path: path::PathBuf,
+    /// Set if `path` is a worktree created with `git worktree add`, which has a git directory of its own.
+    #[serde(default)]
+    pub linked_worktree: Option<LinkedWorktree>,
     #[serde(default)]
     pub preferred_key: AuthKey,
     /// if ok_with_force_push is true, we'll not try to avoid force pushing
@@ -100,6 +102,54 @@ pub struct Project {
     // Experimental flag for new hunk dependency algorithm
     #[serde(default = "default_true")]
     pub use_experimental_locking: bool,
+    /// How worktree and commit diffs are computed for this project.
+    #[serde(default)]
+    pub diff_settings: DiffSettings,
+    /// When snapshots are taken automatically as files change.
+    #[serde(default)]
+    pub snapshot_policy: SnapshotPolicy,
+    /// How changes to the files of the project are detected.
+    #[serde(default)]
+    pub file_watcher: FileWatcherSettings,
+}
+
+/// A worktree of a repository other than its main worktree.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkedWorktree {
+    /// The name of the worktree, as shown by `git worktree list`.
+    pub name: String,
+    /// The git directory private to the worktree, typically `.git/worktrees/<name>` in the main worktree.
+    /// It contains `HEAD`, the index and the GitButler state of the worktree.
+    pub git_dir: PathBuf,
+    /// The git directory shared by all worktrees of the repository, with its objects, branches and tags.
+    pub common_dir: PathBuf,
+}
+
+/// A worktree of the repository of a project, as listed by [`Controller::worktrees()`](crate::Controller::worktrees()).
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Worktree {
+    /// The directory of the worktree.
+    pub path: PathBuf,
+    /// The name of a linked worktree, or `None` for the main worktree.
+    pub name: Option<String>,
+    /// The project the worktree was added as, if any.
+    pub project_id: Option<ProjectId>,
+}
+
+impl LinkedWorktree {
+    /// Return the linked worktree `repo` was opened from, or `None` if it's the main worktree.
+    pub fn from_repository(repo: &gix::Repository) -> anyhow::Result<Option<Self>> {
+        let Some(worktree) = repo.worktree().filter(|worktree| !worktree.is_main()) else {
+            return Ok(None);
+        };
+        Ok(Some(LinkedWorktree {
+            name: worktree.id().map(ToString::to_string).unwrap_or_default(),
+            git_dir: gix::path::realpath(repo.git_dir())?,
+            common_dir: gix::path::realpath(repo.common_dir())?,
+        }))
+    }
 }
 
 // TODO: Remove after `use_experimental` has been removed.
@@ -116,6 +166,173 @@ pub struct ForgeSettings {
     pub review_template_path: Option<String>,
 }
 
+/// The algorithm used to compute the line-based diff of a file.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum DiffAlgorithm {
+    /// The default algorithm, as used by `git diff`.
+    #[default]
+    Myers,
+    /// Like [`Self::Myers`], but spends extra time to produce the smallest possible diff.
+    Minimal,
+    /// Anchors the diff on unique lines, which typically produces more readable diffs
+    /// for code that was moved around or reformatted.
+    Patience,
+    /// A faster variant of [`Self::Patience`] that also anchors the diff on lines that occur rarely,
+    /// as used by `git diff --histogram`.
+    ///
+    /// As `libgit2` doesn't implement it, the hunks of text files are recomputed with `imara-diff`.
+    Histogram,
+}
+
+/// Settings that control how diffs are produced, and thus which hunks are seen and how they are hashed.
+///
+/// Note that changing any of these changes the hunks that are computed, and with that the hashes stored in
+/// ownership claims. Hashes remain stable as long as the settings don't change.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DiffSettings {
+    pub algorithm: DiffAlgorithm,
+    /// Ignore all whitespace when comparing lines, like `git diff -w`.
+    pub ignore_whitespace: bool,
+    /// Ignore changes in the amount of whitespace, like `git diff -b`.
+    pub ignore_whitespace_change: bool,
+    /// Ignore whitespace at the end of lines, like `git diff --ignore-space-at-eol`.
+    pub ignore_whitespace_eol: bool,
+    /// Ignore changes whose lines are all blank, like `git diff --ignore-blank-lines`.
+    pub ignore_blank_lines: bool,
+    /// The amount of unchanged lines to show around each change.
+    pub context_lines: u32,
+    /// Annotate hunks with blocks of lines that were moved within or between files,
+    /// similar to `git diff --color-moved`.
+    pub detect_moved_lines: bool,
+}
+
+impl Default for DiffSettings {
+    fn default() -> Self {
+        DiffSettings {
+            algorithm: DiffAlgorithm::default(),
+            ignore_whitespace: false,
+            ignore_whitespace_change: false,
+            ignore_whitespace_eol: false,
+            ignore_blank_lines: false,
+            context_lines: 3,
+            detect_moved_lines: false,
+        }
+    }
+}
+
+impl DiffSettings {
+    /// Configure `opts` according to these settings, with `context_lines` overriding
+    /// [`Self::context_lines`] if set.
+    pub fn apply_to<'a>(
+        &self,
+        opts: &'a mut git2::DiffOptions,
+        context_lines: Option<u32>,
+    ) -> &'a mut git2::DiffOptions {
+        let (patience, minimal) = match self.algorithm {
+            DiffAlgorithm::Myers => (false, false),
+            DiffAlgorithm::Minimal => (false, true),
+            // `libgit2` can't produce histogram diffs, so they start out as patience diffs to be recomputed
+            // by `gitbutler_diff::apply_histogram()`.
+            DiffAlgorithm::Patience | DiffAlgorithm::Histogram => (true, false),
+        };
+        opts.patience(patience)
+            .minimal(minimal)
+            .ignore_whitespace(self.ignore_whitespace)
+            .ignore_whitespace_change(self.ignore_whitespace_change)
+            .ignore_whitespace_eol(self.ignore_whitespace_eol)
+            .ignore_blank_lines(self.ignore_blank_lines)
+            .context_lines(context_lines.unwrap_or(self.context_lines))
+    }
+}
+
+/// Controls when snapshots of the worktree are taken automatically as files change.
+///
+/// The amount of changed lines it takes to trigger a snapshot is [`Project::snapshot_lines_threshold()`].
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SnapshotPolicy {
+    /// If `false`, snapshots are never taken automatically.
+    pub enabled: bool,
+    /// The minimum amount of seconds between two automatic snapshots.
+    pub interval_secs: u64,
+    /// Worktree-relative paths of files or directories whose changes never trigger a snapshot
+    /// and don't count towards the changed lines, e.g. generated code.
+    pub exclude_paths: Vec<PathBuf>,
+    /// Take a snapshot whenever `HEAD` is switched to another branch.
+    ///
+    /// The snapshot is taken after the switch, so it captures the state of the branch that was switched to.
+    pub on_branch_switch: bool,
+    /// Disregard the interval if a single batch of file changes touches at least this many files,
+    /// as when running a formatter or a search-and-replace.
+    pub save_burst_files: Option<usize>,
+}
+
+impl Default for SnapshotPolicy {
+    fn default() -> Self {
+        SnapshotPolicy {
+            enabled: true,
+            interval_secs: 300,
+            exclude_paths: Vec::new(),
+            on_branch_switch: false,
+            save_burst_files: None,
+        }
+    }
+}
+
+impl SnapshotPolicy {
+    pub fn interval(&self) -> time::Duration {
+        time::Duration::from_secs(self.interval_secs)
+    }
+
+    /// Return `true` if changes to the worktree-relative `path` should be disregarded.
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        self.exclude_paths
+            .iter()
+            .any(|excluded| path.starts_with(excluded))
+    }
+}
+
+/// The way changes to files in the worktree and the repository are detected.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum FileWatcherBackend {
+    /// Use [`Self::Native`], but fall back to [`Self::Polling`] if it can't be set up,
+    /// e.g. because the limit of inotify watches was reached.
+    #[default]
+    Auto,
+    /// Use the change notifications of the operating system, like inotify on Linux.
+    Native,
+    /// Scan for changes periodically, which is needed on network filesystems or container volumes
+    /// that don't deliver change notifications.
+    Polling,
+}
+
+/// Settings for watching the files of a project, which take effect the next time the project is opened.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FileWatcherSettings {
+    pub backend: FileWatcherBackend,
+    /// The amount of milliseconds between two scans for changes when polling.
+    pub poll_interval_ms: u64,
+}
+
+impl Default for FileWatcherSettings {
+    fn default() -> Self {
+        FileWatcherSettings {
+            backend: FileWatcherBackend::default(),
+            poll_interval_ms: 2000,
+        }
+    }
+}
+
+impl FileWatcherSettings {
+    pub fn poll_interval(&self) -> time::Duration {
+        time::Duration::from_millis(self.poll_interval_ms)
+    }
+}
+
 impl ForgeSettings {
     pub fn init(&mut self, project_path: &Path) {
         if let Some(forge_type) = &self.host_type {
@@ -157,9 +374,35 @@ impl Project {
 
     /// Returns the path to the directory containing the `GitButler` state for this project.
     ///
-    /// Normally this is `.git/gitbutler` in the project's repository.
+    /// Normally this is `.git/gitbutler` in the project's repository, and it's in the
+    /// [private git directory](LinkedWorktree::git_dir) of linked worktrees.
     pub fn gb_dir(&self) -> PathBuf {
-        self.path.join(".git").join("gitbutler")
+        Self::gb_dir_of(&self.git_dir())
+    }
+
+    /// Returns the path to the directory containing the `GitButler` state of the worktree whose
+    /// [git directory](Self::git_dir()) is `git_dir`, which is what [`Self::gb_dir()`] returns for its project.
+    ///
+    /// Use this only where the project isn't at hand, like with a repository opened from its worktree.
+    pub fn gb_dir_of(git_dir: &Path) -> PathBuf {
+        git_dir.join("gitbutler")
+    }
+
+    /// Return the git directory of the project's worktree.
+    pub fn git_dir(&self) -> PathBuf {
+        match &self.linked_worktree {
+            Some(worktree) => worktree.git_dir.clone(),
+            None => self.path.join(".git"),
+        }
+    }
+
+    /// Return the git directory shared by all worktrees of the project's repository, which is the
+    /// same as [`Self::git_dir()`] unless the project is a linked worktree.
+    pub fn common_dir(&self) -> PathBuf {
+        match &self.linked_worktree {
+            Some(worktree) => worktree.common_dir.clone(),
+            None => self.path.join(".git"),
+        }
     }
 
     pub fn snapshot_lines_threshold(&self) -> usize {
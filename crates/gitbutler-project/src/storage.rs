@@ -3,7 +3,10 @@ use std::path::PathBuf;
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 
-use crate::{ApiProject, AuthKey, CodePushState, FetchResult, ForgeSettings, Project, ProjectId};
+use crate::{
+    ApiProject, AuthKey, CodePushState, DiffSettings, FetchResult, FileWatcherSettings,
+    ForgeSettings, Project, ProjectId, SnapshotPolicy,
+};
 
 const PROJECTS_FILE: &str = "projects.json";
 
@@ -29,6 +32,9 @@ pub struct UpdateRequest {
     pub snapshot_lines_threshold: Option<usize>,
     pub git_host: Option<ForgeSettings>,
     pub use_experimental_locking: Option<bool>,
+    pub diff_settings: Option<DiffSettings>,
+    pub snapshot_policy: Option<SnapshotPolicy>,
+    pub file_watcher: Option<FileWatcherSettings>,
 }
 
 impl Storage {
@@ -133,6 +139,18 @@ impl Storage {
             project.use_experimental_locking = *use_experimental_locking;
         }
 
+        if let Some(diff_settings) = update_request.diff_settings {
+            project.diff_settings = diff_settings;
+        }
+
+        if let Some(snapshot_policy) = &update_request.snapshot_policy {
+            project.snapshot_policy = snapshot_policy.clone();
+        }
+
+        if let Some(file_watcher) = update_request.file_watcher {
+            project.file_watcher = file_watcher;
+        }
+
         self.inner
             .write(PROJECTS_FILE, &serde_json::to_string_pretty(&projects)?)?;
 
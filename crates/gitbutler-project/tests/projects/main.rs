@@ -8,6 +8,23 @@ pub fn new() -> (Controller, TempDir) {
     (controller, data_dir)
 }
 
+fn create_initial_commit(repo: &git2::Repository) -> git2::Oid {
+    let signature = git2::Signature::now("test", "test@email.com").unwrap();
+
+    let mut index = repo.index().unwrap();
+    let oid = index.write_tree().unwrap();
+
+    repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        "initial commit",
+        &repo.find_tree(oid).unwrap(),
+        &[],
+    )
+    .unwrap()
+}
+
 mod add {
     use super::*;
 
@@ -21,6 +38,46 @@ mod add {
         assert_eq!(project.title, path.iter().last().unwrap().to_str().unwrap());
     }
 
+    #[test]
+    fn linked_worktree() {
+        let (controller, _tmp) = new();
+        let tmp = tempfile::tempdir().unwrap();
+        let main_worktree_dir = tmp.path().join("main");
+        let worktree_dir = tmp.path().join("worktree");
+
+        let repo = git2::Repository::init(&main_worktree_dir).unwrap();
+        create_initial_commit(&repo);
+
+        let worktree = repo.worktree("feature", &worktree_dir, None).unwrap();
+        let project = controller.add(worktree.path()).unwrap();
+        let linked_worktree = project
+            .linked_worktree
+            .clone()
+            .expect("the worktree is recognized as linked");
+        assert_eq!(linked_worktree.name, "feature");
+        assert_eq!(
+            linked_worktree.common_dir,
+            gix::path::realpath(repo.path()).unwrap()
+        );
+        assert!(
+            project.gb_dir().starts_with(&linked_worktree.git_dir),
+            "each worktree has its own GitButler state"
+        );
+        assert!(project.gb_dir().is_dir());
+
+        let worktrees = controller.worktrees(project.id).unwrap();
+        assert_eq!(worktrees.len(), 2);
+        assert_eq!(
+            worktrees[0].path,
+            gix::path::realpath(&main_worktree_dir).unwrap()
+        );
+        assert_eq!(worktrees[0].name, None);
+        assert_eq!(worktrees[0].project_id, None);
+        assert_eq!(worktrees[1].path, project.path);
+        assert_eq!(worktrees[1].name.as_deref(), Some("feature"));
+        assert_eq!(worktrees[1].project_id, Some(project.id));
+    }
+
     mod error {
         use super::*;
         use std::path::PathBuf;
@@ -105,38 +162,6 @@ mod add {
             assert_eq!(err.to_string(), "bare repositories are unsupported");
         }
 
-        #[test]
-        fn worktree() {
-            let (controller, _tmp) = new();
-            let tmp = tempfile::tempdir().unwrap();
-            let main_worktree_dir = tmp.path().join("main");
-            let worktree_dir = tmp.path().join("worktree");
-
-            let repo = git2::Repository::init(main_worktree_dir).unwrap();
-            create_initial_commit(&repo);
-
-            let worktree = repo.worktree("feature", &worktree_dir, None).unwrap();
-            let err = controller.add(worktree.path()).unwrap_err();
-            assert_eq!(err.to_string(), "can only work in main worktrees");
-        }
-
-        fn create_initial_commit(repo: &git2::Repository) -> git2::Oid {
-            let signature = git2::Signature::now("test", "test@email.com").unwrap();
-
-            let mut index = repo.index().unwrap();
-            let oid = index.write_tree().unwrap();
-
-            repo.commit(
-                Some("HEAD"),
-                &signature,
-                &signature,
-                "initial commit",
-                &repo.find_tree(oid).unwrap(),
-                &[],
-            )
-            .unwrap()
-        }
-
         fn repo_path_at(name: &str) -> PathBuf {
             gitbutler_testsupport::gix_testtools::scripted_fixture_read_only(
                 "various-repositories.sh",
@@ -162,3 +187,106 @@ mod delete {
         assert!(!project.path.join(".gitbutler.json").exists());
     }
 }
+
+mod update {
+    use gitbutler_project::{
+        DiffAlgorithm, DiffSettings, FileWatcherBackend, FileWatcherSettings, SnapshotPolicy,
+        UpdateRequest,
+    };
+
+    use super::*;
+
+    #[test]
+    fn diff_settings() {
+        let (controller, _tmp) = new();
+        let repository = gitbutler_testsupport::TestProject::default();
+        let project = controller.add(repository.path()).unwrap();
+        assert_eq!(project.diff_settings, DiffSettings::default());
+
+        let diff_settings = DiffSettings {
+            algorithm: DiffAlgorithm::Patience,
+            ignore_whitespace: true,
+            ignore_blank_lines: true,
+            context_lines: 1,
+            ..Default::default()
+        };
+        controller
+            .update(&UpdateRequest {
+                id: project.id,
+                diff_settings: Some(diff_settings),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(
+            controller.get(project.id).unwrap().diff_settings,
+            diff_settings
+        );
+    }
+
+    #[test]
+    fn diff_algorithms_are_parsed() {
+        assert_eq!(
+            serde_json::from_str::<DiffAlgorithm>(r#""patience""#).unwrap(),
+            DiffAlgorithm::Patience
+        );
+        assert_eq!(
+            serde_json::from_str::<DiffAlgorithm>(r#""histogram""#).unwrap(),
+            DiffAlgorithm::Histogram
+        );
+        assert!(serde_json::from_str::<DiffAlgorithm>(r#""fastest""#).is_err());
+    }
+
+    #[test]
+    fn snapshot_policy() {
+        let (controller, _tmp) = new();
+        let repository = gitbutler_testsupport::TestProject::default();
+        let project = controller.add(repository.path()).unwrap();
+        assert_eq!(project.snapshot_policy, SnapshotPolicy::default());
+
+        let snapshot_policy = SnapshotPolicy {
+            interval_secs: 60,
+            exclude_paths: vec!["generated".into()],
+            on_branch_switch: true,
+            ..Default::default()
+        };
+        controller
+            .update(&UpdateRequest {
+                id: project.id,
+                snapshot_policy: Some(snapshot_policy.clone()),
+                ..Default::default()
+            })
+            .unwrap();
+        let project = controller.get(project.id).unwrap();
+        assert_eq!(project.snapshot_policy, snapshot_policy);
+        assert!(project
+            .snapshot_policy
+            .is_excluded("generated/schema.rs".as_ref()));
+        assert!(!project.snapshot_policy.is_excluded("generated.rs".as_ref()));
+    }
+
+    #[test]
+    fn file_watcher() {
+        let (controller, _tmp) = new();
+        let repository = gitbutler_testsupport::TestProject::default();
+        let project = controller.add(repository.path()).unwrap();
+        assert_eq!(project.file_watcher.backend, FileWatcherBackend::Auto);
+
+        let file_watcher = FileWatcherSettings {
+            backend: FileWatcherBackend::Polling,
+            poll_interval_ms: 500,
+        };
+        controller
+            .update(&UpdateRequest {
+                id: project.id,
+                file_watcher: Some(file_watcher),
+                ..Default::default()
+            })
+            .unwrap();
+        let project = controller.get(project.id).unwrap();
+        assert_eq!(project.file_watcher, file_watcher);
+        assert_eq!(
+            project.file_watcher.poll_interval(),
+            std::time::Duration::from_millis(500)
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{Config, RepositoryExt};
+use crate::{Config, RepositoryExt, SigningSettings};
 use anyhow::{bail, Result};
 use base64::engine::Engine as _;
 use git2::Oid;
@@ -100,7 +100,8 @@ pub trait RepoCommands {
     fn remotes(&self) -> Result<Vec<String>>;
     fn get_local_config(&self, key: &str) -> Result<Option<String>>;
     fn set_local_config(&self, key: &str, value: &str) -> Result<()>;
-    fn check_signing_settings(&self) -> Result<bool>;
+    /// Sign a test buffer to check that signing works, and return the settings used for it.
+    fn check_signing_settings(&self) -> Result<SigningSettings>;
     /// Read `probably_relative_path` in the following order:
     ///
     /// * worktree
@@ -135,13 +136,11 @@ impl RepoCommands for Project {
         config.set_local(key, value)
     }
 
-    fn check_signing_settings(&self) -> Result<bool> {
+    fn check_signing_settings(&self) -> Result<SigningSettings> {
         let ctx = CommandContext::open(self)?;
-        let signed = ctx.repository().sign_buffer(b"test");
-        match signed {
-            Ok(_) => Ok(true),
-            Err(e) => Err(e),
-        }
+        let settings = ctx.repository().signing_settings()?;
+        ctx.repository().sign_buffer(b"test")?;
+        Ok(settings)
     }
 
     fn remotes(&self) -> Result<Vec<String>> {
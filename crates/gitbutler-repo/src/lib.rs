@@ -1,15 +1,21 @@
 pub mod rebase;
+pub mod rerere;
 
 mod commands;
 pub use commands::{FileInfo, RepoCommands};
 
 mod repository_ext;
-pub use repository_ext::{GixRepositoryExt, LogUntil, RepositoryExt};
+pub use repository_ext::{
+    GixRepositoryExt, LogUntil, RepositoryExt, SigningFormat, SigningSettings,
+};
 
 pub mod credentials;
 
 mod config;
 
+mod signature_verification;
+pub use signature_verification::{SignatureStatus, SignatureVerification};
+
 pub use config::Config;
 
 pub mod temporary_workdir;
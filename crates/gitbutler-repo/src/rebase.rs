@@ -1,5 +1,5 @@
 use std::{
-    collections::HashSet,
+    collections::{BTreeSet, HashMap, HashSet},
     path::{Path, PathBuf},
 };
 
@@ -13,7 +13,7 @@ use gitbutler_commit::{
 };
 use serde::{Deserialize, Serialize};
 
-use crate::{LogUntil, RepositoryExt as _};
+use crate::{rerere, LogUntil, RepositoryExt as _};
 
 /// cherry-pick based rebase, which handles empty commits
 /// this function takes a commit range and generates a Vector of commit oids
@@ -51,6 +51,41 @@ pub fn cherry_rebase_group(
     target_commit_oid: git2::Oid,
     ids_to_rebase: &[git2::Oid],
 ) -> Result<git2::Oid> {
+    rebase_commits(repository, target_commit_oid, ids_to_rebase, false).map(|(head, _)| head)
+}
+
+/// Like [`cherry_rebase_group()`], but merge commits are recreated with all of their parents instead
+/// of being flattened onto their first parent, similar to `git rebase --rebase-merges`.
+///
+/// Non-first parents that are part of `ids_to_rebase` are replaced by their rebased version, all others
+/// are kept. Merges whose other parents all became part of the new history are flattened.
+///
+/// the commit id's to rebase should be ordered such that the child most commit is first
+pub fn cherry_rebase_group_preserving_merges(
+    repository: &git2::Repository,
+    target_commit_oid: git2::Oid,
+    ids_to_rebase: &[git2::Oid],
+) -> Result<git2::Oid> {
+    rebase_commits(repository, target_commit_oid, ids_to_rebase, true).map(|(head, _)| head)
+}
+
+/// Like [`cherry_rebase_group_preserving_merges()`], but also return what became of each commit of
+/// `ids_to_rebase`, in the same order: the id of its rebased version, or `None` if it was dropped
+/// as it doesn't change anything anymore.
+pub fn cherry_rebase_group_preserving_merges_with_mapping(
+    repository: &git2::Repository,
+    target_commit_oid: git2::Oid,
+    ids_to_rebase: &[git2::Oid],
+) -> Result<(git2::Oid, Vec<(git2::Oid, Option<git2::Oid>)>)> {
+    rebase_commits(repository, target_commit_oid, ids_to_rebase, true)
+}
+
+fn rebase_commits(
+    repository: &git2::Repository,
+    target_commit_oid: git2::Oid,
+    ids_to_rebase: &[git2::Oid],
+    preserve_merges: bool,
+) -> Result<(git2::Oid, Vec<(git2::Oid, Option<git2::Oid>)>)> {
     // now, rebase unchanged commits onto the new commit
     let commits_to_rebase = ids_to_rebase
         .iter()
@@ -59,45 +94,94 @@ pub fn cherry_rebase_group(
         .collect::<Result<Vec<_>, _>>()
         .context("failed to read commits to rebase")?;
 
+    // Maps the commits we rebased to their new version, to rewrite the parents of merges.
+    let mut rebased_ids = HashMap::new();
+    let mut mapping = Vec::with_capacity(commits_to_rebase.len());
     let new_head_id = commits_to_rebase
         .into_iter()
-        .fold(
+        .try_fold(
             repository
                 .find_commit(target_commit_oid)
-                .context("failed to find new commit"),
+                .context("failed to find new commit")?,
             |head, to_rebase| {
-                let head = head?;
-
-                if to_rebase.parent_ids().len() == 1 && head.id() == to_rebase.parent_id(0)? {
-                    return Ok(to_rebase);
+                let (head_id, original_id) = (head.id(), to_rebase.id());
+                let other_parents = if preserve_merges {
+                    merge_parents(repository, &head, &to_rebase, &rebased_ids)?
+                } else {
+                    vec![]
                 };
 
-                let mut cherrypick_index = repository
-                    .cherry_pick_gitbutler(&head, &to_rebase, None)
-                    .context("failed to cherry pick")?;
-
-                if cherrypick_index.has_conflicts() {
-                    commit_conflicted_cherry_result(
-                        repository,
-                        head,
-                        to_rebase,
-                        &mut cherrypick_index,
-                    )
+                let unchanged_parents = to_rebase.parent_ids().len() == other_parents.len() + 1
+                    && to_rebase.parent_id(0)? == head.id()
+                    && to_rebase
+                        .parent_ids()
+                        .skip(1)
+                        .eq(other_parents.iter().map(|parent| parent.id()));
+                let new_commit = if unchanged_parents {
+                    to_rebase
                 } else {
-                    commit_unconflicted_cherry_result(repository, head, to_rebase, cherrypick_index)
-                }
+                    let mut cherrypick_index = repository
+                        .cherry_pick_gitbutler(&head, &to_rebase, None)
+                        .context("failed to cherry pick")?;
+
+                    let new_commit = if cherrypick_index.has_conflicts() {
+                        commit_conflicted_cherry_result(
+                            repository,
+                            head,
+                            to_rebase,
+                            &other_parents,
+                            &mut cherrypick_index,
+                        )
+                    } else {
+                        commit_unconflicted_cherry_result(
+                            repository,
+                            head,
+                            to_rebase,
+                            &other_parents,
+                            &mut cherrypick_index,
+                        )
+                    }?;
+                    rebased_ids.insert(original_id, new_commit.id());
+                    new_commit
+                };
+                mapping.push((
+                    original_id,
+                    (new_commit.id() != head_id).then_some(new_commit.id()),
+                ));
+                anyhow::Ok(new_commit)
             },
         )?
         .id();
 
-    Ok(new_head_id)
+    mapping.reverse();
+    Ok((new_head_id, mapping))
+}
+
+/// Return the parents `to_rebase` should have next to `head` when recreating it as merge, which
+/// are its non-first parents in their rebased version, as long as they aren't reachable from `head` already.
+fn merge_parents<'repository>(
+    repository: &'repository git2::Repository,
+    head: &git2::Commit<'repository>,
+    to_rebase: &git2::Commit,
+    rebased_ids: &HashMap<git2::Oid, git2::Oid>,
+) -> Result<Vec<git2::Commit<'repository>>> {
+    let mut parents = Vec::new();
+    for parent_id in to_rebase.parent_ids().skip(1) {
+        let parent_id = rebased_ids.get(&parent_id).copied().unwrap_or(parent_id);
+        if parent_id == head.id() || repository.graph_descendant_of(head.id(), parent_id)? {
+            continue;
+        }
+        parents.push(repository.find_commit(parent_id)?);
+    }
+    Ok(parents)
 }
 
 fn commit_unconflicted_cherry_result<'repository>(
     repository: &'repository git2::Repository,
     head: git2::Commit<'repository>,
     to_rebase: git2::Commit,
-    mut cherrypick_index: git2::Index,
+    other_parents: &[git2::Commit<'repository>],
+    cherrypick_index: &mut git2::Index,
 ) -> Result<git2::Commit<'repository>> {
     let is_merge_commit = to_rebase.parent_count() > 0;
 
@@ -105,8 +189,8 @@ fn commit_unconflicted_cherry_result<'repository>(
         .write_tree_to(repository)
         .context("failed to write merge tree")?;
 
-    // Remove empty merge commits
-    if is_merge_commit && merge_tree_oid == head.tree_id() {
+    // Remove empty merge commits, unless they are recreated as merges which are meaningful on their own
+    if is_merge_commit && other_parents.is_empty() && merge_tree_oid == head.tree_id() {
         return Ok(head);
     }
 
@@ -129,7 +213,7 @@ fn commit_unconflicted_cherry_result<'repository>(
         &to_rebase.committer(),
         &to_rebase.message_bstr().to_str_lossy(),
         &merge_tree,
-        &[&head],
+        &parents(&head, other_parents),
         commit_headers,
     )
     .context("failed to create commit")?;
@@ -141,10 +225,23 @@ fn commit_unconflicted_cherry_result<'repository>(
 
 fn commit_conflicted_cherry_result<'repository>(
     repository: &'repository git2::Repository,
-    head: git2::Commit,
+    head: git2::Commit<'repository>,
     to_rebase: git2::Commit,
+    other_parents: &[git2::Commit<'repository>],
     cherrypick_index: &mut git2::Index,
 ) -> Result<git2::Commit<'repository>> {
+    // Conflicts that were resolved before are resolved the same way again.
+    rerere::replay_resolutions(repository, cherrypick_index)?;
+    if !cherrypick_index.has_conflicts() {
+        return commit_unconflicted_cherry_result(
+            repository,
+            head,
+            to_rebase,
+            other_parents,
+            cherrypick_index,
+        );
+    }
+
     let commit_headers = to_rebase.gitbutler_headers();
 
     // If the commit we're rebasing is conflicted, use the commits original base.
@@ -209,7 +306,7 @@ fn commit_conflicted_cherry_result<'repository>(
         &repository
             .find_tree(tree_oid)
             .context("failed to find tree")?,
-        &[&head],
+        &parents(&head, other_parents),
         commit_headers,
     )
     .context("failed to create commit")?;
@@ -219,6 +316,13 @@ fn commit_conflicted_cherry_result<'repository>(
         .context("failed to find commit")
 }
 
+fn parents<'a, 'repository>(
+    head: &'a git2::Commit<'repository>,
+    other_parents: &'a [git2::Commit<'repository>],
+) -> Vec<&'a git2::Commit<'repository>> {
+    std::iter::once(head).chain(other_parents).collect()
+}
+
 /// Merge two commits together
 ///
 /// The `target_commit` and `incoming_commit` must have a common ancestor.
@@ -242,12 +346,20 @@ pub fn gitbutler_merge_commits<'repository>(
 
     let target_merge_tree = repository.find_real_tree(&target_commit, Default::default())?;
     let incoming_merge_tree = repository.find_real_tree(&incoming_commit, Default::default())?;
-    let mut merged_index =
-        repository.merge_trees(&base_tree, &incoming_merge_tree, &target_merge_tree, None)?;
+    let mut merged_index = repository.merge_trees_gitbutler(
+        &base_tree,
+        &incoming_merge_tree,
+        &target_merge_tree,
+        None,
+    )?;
 
     let tree_oid;
     let conflicted_files;
 
+    if merged_index.has_conflicts() {
+        rerere::replay_resolutions(repository, &mut merged_index)?;
+    }
+
     if merged_index.has_conflicts() {
         conflicted_files = resolve_index(repository, &mut merged_index)?;
 
@@ -324,6 +436,94 @@ pub fn gitbutler_merge_commits<'repository>(
     Ok(repository.find_commit(commit_oid)?)
 }
 
+/// Turn `commit`, whose files contain conflict markers as written by
+/// [`export_conflicted_commit()`](gitbutler_cherry_pick::export_conflicted_commit), back into a
+/// GitButler conflicted commit with the same parents.
+///
+/// Returns `commit` unchanged if none of its files contain conflict markers.
+pub fn import_conflicted_commit<'repository>(
+    repository: &'repository git2::Repository,
+    commit: git2::Commit<'repository>,
+) -> Result<git2::Commit<'repository>> {
+    let tree = commit.tree()?;
+    let parent_tree = match commit.parent(0) {
+        Ok(parent) => parent.tree()?,
+        Err(_) => repository.find_tree(repository.treebuilder(None)?.write()?)?,
+    };
+    let Some(imported) =
+        gitbutler_cherry_pick::import_conflict_markers(repository, &tree, &parent_tree)?
+    else {
+        return Ok(commit);
+    };
+
+    let base_tree = repository.find_tree(imported.base)?;
+    let conflicted_files = ConflictEntries {
+        ancestor_entries: imported
+            .conflicted_paths
+            .iter()
+            .filter(|path| base_tree.get_path(path).is_ok())
+            .cloned()
+            .collect(),
+        our_entries: imported.conflicted_paths.clone(),
+        their_entries: imported.conflicted_paths,
+    };
+    let conflicted_files_string = toml::to_string(&conflicted_files)?;
+    let conflicted_files_blob = repository.blob(conflicted_files_string.as_bytes())?;
+
+    // Like in rebases, the auto-resolution prefers our side.
+    let mut tree_writer = repository.treebuilder(None)?;
+    tree_writer.insert(&*ConflictedTreeKey::Ours, imported.ours, 0o040000)?;
+    tree_writer.insert(&*ConflictedTreeKey::Theirs, imported.theirs, 0o040000)?;
+    tree_writer.insert(&*ConflictedTreeKey::Base, imported.base, 0o040000)?;
+    tree_writer.insert(
+        &*ConflictedTreeKey::AutoResolution,
+        imported.auto_resolution,
+        0o040000,
+    )?;
+    tree_writer.insert(
+        &*ConflictedTreeKey::ConflictFiles,
+        conflicted_files_blob,
+        0o100644,
+    )?;
+
+    // in case someone checks this out with vanilla Git, we should warn why it looks like this
+    let readme_content =
+        b"You have checked out a GitButler Conflicted commit. You probably didn't mean to do this.";
+    let readme_blob = repository.blob(readme_content)?;
+    tree_writer.insert("README.txt", readme_blob, 0o100644)?;
+
+    let tree_oid = tree_writer.write().context("failed to write tree")?;
+
+    let commit_headers = commit.gitbutler_headers().unwrap_or_default();
+    let commit_headers = CommitHeadersV2 {
+        conflicted: Some(conflicted_files.total_entries() as u64),
+        ..commit_headers
+    };
+
+    // The list of conflicted files was added on export, and is now part of the commit again.
+    let message = commit.message_bstr().to_str_lossy();
+    let message = message
+        .split_once("\n\nConflicts:\n")
+        .map_or(message.as_ref(), |(message, _)| message);
+
+    let parents = commit.parents().collect::<Vec<_>>();
+    let commit_oid = crate::RepositoryExt::commit_with_signature(
+        repository,
+        None,
+        &commit.author(),
+        &commit.committer(),
+        message,
+        &repository
+            .find_tree(tree_oid)
+            .context("failed to find tree")?,
+        &parents.iter().collect::<Vec<_>>(),
+        Some(commit_headers),
+    )
+    .context("failed to create commit")?;
+
+    Ok(repository.find_commit(commit_oid)?)
+}
+
 #[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct ConflictEntries {
@@ -339,6 +539,18 @@ impl ConflictEntries {
             || !self.their_entries.is_empty()
     }
 
+    /// Return all conflicted paths, sorted and without duplicates.
+    pub fn paths(&self) -> Vec<&Path> {
+        self.ancestor_entries
+            .iter()
+            .chain(self.our_entries.iter())
+            .chain(self.their_entries.iter())
+            .map(PathBuf::as_path)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
     pub fn total_entries(&self) -> usize {
         let set = self
             .ancestor_entries
@@ -397,9 +609,7 @@ fn resolve_index(
             their_entries.push(their_path);
         } else if let (None, Some(our)) = (&conflict.their, &mut conflict.our) {
             // Our (the commit we're rebasing onto)'s gets kept
-            let blob = repository.find_blob(our.id)?;
-            our.flags = 0; // For some unknown reason we need to set flags to 0
-            index.add_frombuffer(our, blob.content())?;
+            add_resolved_entry(repository, index, our)?;
 
             let our_path = bytes_to_path(&our.path)?;
 
@@ -408,11 +618,9 @@ fn resolve_index(
             // We keep our (the commit we're rebasing onto)'s side of the
             // conflict
             let their_path = bytes_to_path(&their.path)?;
-            let blob = repository.find_blob(our.id)?;
 
             index.remove_path(&their_path)?;
-            our.flags = 0; // For some unknown reason we need to set flags to 0
-            index.add_frombuffer(our, blob.content())?;
+            add_resolved_entry(repository, index, our)?;
 
             let our_path = bytes_to_path(&our.path)?;
 
@@ -428,6 +636,23 @@ fn resolve_index(
     })
 }
 
+/// Add `entry` to `index` as resolution of its conflict.
+fn add_resolved_entry(
+    repository: &git2::Repository,
+    index: &mut git2::Index,
+    entry: &mut git2::IndexEntry,
+) -> Result<()> {
+    entry.flags = 0; // For some unknown reason we need to set flags to 0
+    if entry.mode == u32::from(git2::FileMode::Commit) {
+        // Submodules point to a commit in another repository, there is no blob to read.
+        index.add(entry)?;
+    } else {
+        let blob = repository.find_blob(entry.id)?;
+        index.add_frombuffer(entry, blob.content())?;
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
     #[cfg(test)]
@@ -633,6 +858,421 @@ mod test {
                 ],
             );
         }
+
+        #[test]
+        fn union_merge_driver_avoids_conflict() {
+            let test_repository = TestingRepository::open();
+
+            let attributes = (".gitattributes", "CHANGELOG merge=union\n");
+            let a = test_repository.commit_tree(None, &[attributes, ("CHANGELOG", "a\n")]);
+            let b = test_repository.commit_tree(Some(&a), &[attributes, ("CHANGELOG", "a\nb\n")]);
+            let c = test_repository.commit_tree(Some(&a), &[attributes, ("CHANGELOG", "a\nc\n")]);
+
+            // Rebase C on top of B
+            let result =
+                cherry_rebase_group(&test_repository.repository, b.id(), &[c.id()]).unwrap();
+
+            let commit: git2::Commit = test_repository.repository.find_commit(result).unwrap();
+
+            assert!(!commit.is_conflicted());
+            assert_commit_tree_matches(
+                &test_repository.repository,
+                &commit,
+                &[("CHANGELOG", b"a\nb\nc\n")],
+            );
+        }
+
+        #[test]
+        fn ours_merge_driver_keeps_the_commit_rebased_onto() {
+            let test_repository = TestingRepository::open();
+
+            let attributes = (".gitattributes", "foo.txt merge=ours\n");
+            let a = test_repository.commit_tree(None, &[attributes, ("foo.txt", "a")]);
+            let b = test_repository.commit_tree(Some(&a), &[attributes, ("foo.txt", "b")]);
+            let c = test_repository.commit_tree(Some(&a), &[attributes, ("foo.txt", "c")]);
+
+            let result =
+                cherry_rebase_group(&test_repository.repository, b.id(), &[c.id()]).unwrap();
+
+            let commit: git2::Commit = test_repository.repository.find_commit(result).unwrap();
+
+            assert!(!commit.is_conflicted());
+            assert_commit_tree_matches(&test_repository.repository, &commit, &[("foo.txt", b"b")]);
+        }
+
+        #[test]
+        fn external_merge_driver_resolves_conflict() {
+            let test_repository = TestingRepository::open();
+            test_repository
+                .repository
+                .config()
+                .unwrap()
+                .set_str("merge.theirs.driver", "cp %B %A")
+                .unwrap();
+
+            let attributes = (".gitattributes", "foo.txt merge=theirs\n");
+            let a = test_repository.commit_tree(None, &[attributes, ("foo.txt", "a")]);
+            let b = test_repository.commit_tree(Some(&a), &[attributes, ("foo.txt", "b")]);
+            let c = test_repository.commit_tree(Some(&a), &[attributes, ("foo.txt", "c")]);
+
+            let result =
+                cherry_rebase_group(&test_repository.repository, b.id(), &[c.id()]).unwrap();
+
+            let commit: git2::Commit = test_repository.repository.find_commit(result).unwrap();
+
+            assert!(!commit.is_conflicted());
+            assert_commit_tree_matches(&test_repository.repository, &commit, &[("foo.txt", b"c")]);
+        }
+
+        #[test]
+        fn failing_external_merge_driver_leaves_conflict() {
+            let test_repository = TestingRepository::open();
+            test_repository
+                .repository
+                .config()
+                .unwrap()
+                .set_str("merge.fail.driver", "false")
+                .unwrap();
+
+            let attributes = (".gitattributes", "foo.txt merge=fail\n");
+            let a = test_repository.commit_tree(None, &[attributes, ("foo.txt", "a")]);
+            let b = test_repository.commit_tree(Some(&a), &[attributes, ("foo.txt", "b")]);
+            let c = test_repository.commit_tree(Some(&a), &[attributes, ("foo.txt", "c")]);
+
+            let result =
+                cherry_rebase_group(&test_repository.repository, b.id(), &[c.id()]).unwrap();
+
+            let commit: git2::Commit = test_repository.repository.find_commit(result).unwrap();
+
+            assert!(commit.is_conflicted());
+        }
+    }
+
+    #[cfg(test)]
+    mod cherry_rebase_group_preserving_merges {
+        use gitbutler_testsupport::testing_repository::{
+            assert_commit_tree_matches, TestingRepository,
+        };
+
+        use crate::rebase::{
+            cherry_rebase_group_preserving_merges,
+            cherry_rebase_group_preserving_merges_with_mapping,
+        };
+
+        fn merge<'a>(
+            test_repository: &'a TestingRepository,
+            ours: &git2::Commit<'a>,
+            theirs: &git2::Commit<'a>,
+            files: &[(&str, &str)],
+        ) -> git2::Commit<'a> {
+            let repository = &test_repository.repository;
+            let tree = test_repository
+                .commit_tree(Some(ours), files)
+                .tree()
+                .unwrap();
+            let signature = git2::Signature::now("test", "test@example.com").unwrap();
+            let id = repository
+                .commit(
+                    None,
+                    &signature,
+                    &signature,
+                    "merge",
+                    &tree,
+                    &[ours, theirs],
+                )
+                .unwrap();
+            repository.find_commit(id).unwrap()
+        }
+
+        #[test]
+        fn merge_commits_keep_their_other_parents() {
+            let test_repository = TestingRepository::open();
+            let repository = &test_repository.repository;
+
+            let a = test_repository.commit_tree(None, &[("a.txt", "a")]);
+            let b = test_repository.commit_tree(Some(&a), &[("a.txt", "a"), ("b.txt", "b")]);
+            let side = test_repository.commit_tree(Some(&a), &[("a.txt", "a"), ("s.txt", "s")]);
+            let m = merge(
+                &test_repository,
+                &b,
+                &side,
+                &[("a.txt", "a"), ("b.txt", "b"), ("s.txt", "s")],
+            );
+            let c = test_repository.commit_tree(
+                Some(&m),
+                &[
+                    ("a.txt", "a"),
+                    ("b.txt", "b"),
+                    ("s.txt", "s"),
+                    ("c.txt", "c"),
+                ],
+            );
+            let target = test_repository.commit_tree(Some(&a), &[("a.txt", "a"), ("t.txt", "t")]);
+
+            let result = cherry_rebase_group_preserving_merges(
+                repository,
+                target.id(),
+                &[c.id(), m.id(), b.id()],
+            )
+            .unwrap();
+
+            let new_c = repository.find_commit(result).unwrap();
+            assert_commit_tree_matches(
+                repository,
+                &new_c,
+                &[
+                    ("a.txt", b"a"),
+                    ("b.txt", b"b"),
+                    ("c.txt", b"c"),
+                    ("s.txt", b"s"),
+                    ("t.txt", b"t"),
+                ],
+            );
+            let new_m = new_c.parent(0).unwrap();
+            assert_eq!(new_m.parent_count(), 2);
+            assert_eq!(new_m.parent_id(1).unwrap(), side.id());
+            let new_b = new_m.parent(0).unwrap();
+            assert_eq!(new_b.parent_id(0).unwrap(), target.id());
+        }
+
+        #[test]
+        fn merges_of_integrated_branches_are_flattened() {
+            let test_repository = TestingRepository::open();
+            let repository = &test_repository.repository;
+
+            let a = test_repository.commit_tree(None, &[("a.txt", "a")]);
+            let b = test_repository.commit_tree(Some(&a), &[("a.txt", "a"), ("b.txt", "b")]);
+            let side = test_repository.commit_tree(Some(&a), &[("a.txt", "a"), ("s.txt", "s")]);
+            let m = merge(
+                &test_repository,
+                &b,
+                &side,
+                &[("a.txt", "a"), ("b.txt", "b"), ("s.txt", "s")],
+            );
+            // The merged branch became part of the target.
+            let target =
+                test_repository.commit_tree(Some(&side), &[("a.txt", "a"), ("s.txt", "s")]);
+
+            let result =
+                cherry_rebase_group_preserving_merges(repository, target.id(), &[m.id(), b.id()])
+                    .unwrap();
+
+            // The merge doesn't change anything anymore and is dropped.
+            let new_b = repository.find_commit(result).unwrap();
+            assert_eq!(new_b.parent_ids().collect::<Vec<_>>(), vec![target.id()]);
+            assert_commit_tree_matches(
+                repository,
+                &new_b,
+                &[("a.txt", b"a"), ("b.txt", b"b"), ("s.txt", b"s")],
+            );
+        }
+
+        #[test]
+        fn unchanged_merges_are_kept_verbatim() {
+            let test_repository = TestingRepository::open();
+            let repository = &test_repository.repository;
+
+            let a = test_repository.commit_tree(None, &[("a.txt", "a")]);
+            let b = test_repository.commit_tree(Some(&a), &[("a.txt", "a"), ("b.txt", "b")]);
+            let side = test_repository.commit_tree(Some(&a), &[("a.txt", "a"), ("s.txt", "s")]);
+            let m = merge(
+                &test_repository,
+                &b,
+                &side,
+                &[("a.txt", "a"), ("b.txt", "b"), ("s.txt", "s")],
+            );
+
+            let result =
+                cherry_rebase_group_preserving_merges(repository, a.id(), &[m.id(), b.id()])
+                    .unwrap();
+            assert_eq!(result, m.id());
+        }
+
+        #[test]
+        fn mapping_tells_what_became_of_each_commit() {
+            let test_repository = TestingRepository::open();
+            let repository = &test_repository.repository;
+
+            let a = test_repository.commit_tree(None, &[("a.txt", "a")]);
+            let b = test_repository.commit_tree(Some(&a), &[("a.txt", "a"), ("b.txt", "b")]);
+            let side = test_repository.commit_tree(Some(&a), &[("a.txt", "a"), ("s.txt", "s")]);
+            let m = merge(
+                &test_repository,
+                &b,
+                &side,
+                &[("a.txt", "a"), ("b.txt", "b"), ("s.txt", "s")],
+            );
+            let c = test_repository.commit_tree(
+                Some(&m),
+                &[
+                    ("a.txt", "a"),
+                    ("b.txt", "b"),
+                    ("s.txt", "s"),
+                    ("c.txt", "c"),
+                ],
+            );
+            // The merged branch became part of the target.
+            let target =
+                test_repository.commit_tree(Some(&side), &[("a.txt", "a"), ("s.txt", "s")]);
+
+            let (head, mapping) = cherry_rebase_group_preserving_merges_with_mapping(
+                repository,
+                target.id(),
+                &[c.id(), m.id(), b.id()],
+            )
+            .unwrap();
+
+            let new_c = repository.find_commit(head).unwrap();
+            let new_b = new_c.parent(0).unwrap();
+            assert_eq!(new_b.parent_id(0).unwrap(), target.id());
+            assert_eq!(
+                mapping,
+                [
+                    (c.id(), Some(new_c.id())),
+                    (m.id(), None),
+                    (b.id(), Some(new_b.id()))
+                ],
+                "the merge is dropped as it doesn't change anything anymore"
+            );
+        }
+    }
+
+    #[cfg(test)]
+    mod conflict_markers {
+        use gitbutler_cherry_pick::export_conflicted_commit;
+        use gitbutler_commit::commit_ext::CommitExt as _;
+        use gitbutler_testsupport::testing_repository::{
+            assert_commit_tree_matches, TestingRepository,
+        };
+
+        use crate::rebase::{cherry_rebase_group, import_conflicted_commit};
+
+        #[test]
+        fn export_and_import_roundtrip() {
+            let test_repository = TestingRepository::open();
+            let repository = &test_repository.repository;
+
+            let a = test_repository.commit_tree(None, &[("foo.txt", "a\n"), ("bar.txt", "a\n")]);
+            let b =
+                test_repository.commit_tree(Some(&a), &[("foo.txt", "b\n"), ("bar.txt", "a\n")]);
+            let c =
+                test_repository.commit_tree(Some(&a), &[("foo.txt", "c\n"), ("bar.txt", "a\n")]);
+
+            let result = cherry_rebase_group(repository, b.id(), &[c.id()]).unwrap();
+            let conflicted = repository.find_commit(result).unwrap();
+            assert!(conflicted.is_conflicted());
+
+            let exported = export_conflicted_commit(repository, &conflicted).unwrap();
+            let exported = repository.find_commit(exported).unwrap();
+            assert!(!exported.is_conflicted());
+            assert_eq!(exported.parent_ids().collect::<Vec<_>>(), vec![b.id()]);
+            assert!(exported
+                .message()
+                .unwrap()
+                .ends_with("\n\nConflicts:\n\tfoo.txt\n"));
+            let foo = exported
+                .tree()
+                .unwrap()
+                .get_path("foo.txt".as_ref())
+                .unwrap()
+                .to_object(repository)
+                .unwrap()
+                .peel_to_blob()
+                .unwrap();
+            let foo = std::str::from_utf8(foo.content()).unwrap().to_owned();
+            assert!(foo.starts_with("<<<<<<< "), "{foo}");
+            assert!(foo.contains("\nb\n||||||| parent of "), "{foo}");
+            assert!(foo.contains("\na\n=======\nc\n>>>>>>> "), "{foo}");
+
+            let imported = import_conflicted_commit(repository, exported).unwrap();
+            assert!(imported.is_conflicted());
+            assert_eq!(imported.message(), conflicted.message());
+            assert_commit_tree_matches(
+                repository,
+                &imported,
+                &[
+                    (".auto-resolution/foo.txt", b"b\n"),
+                    (".auto-resolution/bar.txt", b"a\n"),
+                    (".conflict-base-0/foo.txt", b"a\n"),
+                    (".conflict-side-0/foo.txt", b"b\n"),
+                    (".conflict-side-1/foo.txt", b"c\n"),
+                ],
+            );
+        }
+
+        #[test]
+        fn roundtrip_keeps_changes_that_did_not_conflict() {
+            let test_repository = TestingRepository::open();
+            let repository = &test_repository.repository;
+
+            let a = test_repository.commit_tree(None, &[("foo.txt", "a\n"), ("bar.txt", "a\n")]);
+            let b =
+                test_repository.commit_tree(Some(&a), &[("foo.txt", "b\n"), ("bar.txt", "a\n")]);
+            let c = test_repository.commit_tree(
+                Some(&a),
+                &[("foo.txt", "c\n"), ("bar.txt", "c\n"), ("baz.txt", "c\n")],
+            );
+
+            let result = cherry_rebase_group(repository, b.id(), &[c.id()]).unwrap();
+            let conflicted = repository.find_commit(result).unwrap();
+            let exported = export_conflicted_commit(repository, &conflicted).unwrap();
+            let exported = repository.find_commit(exported).unwrap();
+
+            let imported = import_conflicted_commit(repository, exported).unwrap();
+            assert!(imported.is_conflicted());
+            assert_commit_tree_matches(
+                repository,
+                &imported,
+                &[
+                    (".auto-resolution/foo.txt", b"b\n"),
+                    (".auto-resolution/bar.txt", b"c\n"),
+                    (".auto-resolution/baz.txt", b"c\n"),
+                    (".conflict-base-0/foo.txt", b"a\n"),
+                    (".conflict-base-0/bar.txt", b"a\n"),
+                    (".conflict-side-0/foo.txt", b"b\n"),
+                    (".conflict-side-0/bar.txt", b"a\n"),
+                    (".conflict-side-1/foo.txt", b"c\n"),
+                    (".conflict-side-1/bar.txt", b"c\n"),
+                    (".conflict-side-1/baz.txt", b"c\n"),
+                ],
+            );
+            let imported_tree = imported.tree().unwrap();
+            assert!(imported_tree
+                .get_path(".conflict-side-0/baz.txt".as_ref())
+                .is_err());
+            assert!(imported_tree
+                .get_path(".conflict-base-0/baz.txt".as_ref())
+                .is_err());
+
+            // Picking the imported commit again keeps the changes that didn't conflict.
+            let d = test_repository.commit_tree(
+                Some(&b),
+                &[("foo.txt", "b\n"), ("bar.txt", "a\n"), ("qux.txt", "d\n")],
+            );
+            let result = cherry_rebase_group(repository, d.id(), &[imported.id()]).unwrap();
+            let rebased = repository.find_commit(result).unwrap();
+            assert!(rebased.is_conflicted());
+            assert_commit_tree_matches(
+                repository,
+                &rebased,
+                &[
+                    (".auto-resolution/foo.txt", b"b\n"),
+                    (".auto-resolution/bar.txt", b"c\n"),
+                    (".auto-resolution/baz.txt", b"c\n"),
+                    (".auto-resolution/qux.txt", b"d\n"),
+                ],
+            );
+        }
+
+        #[test]
+        fn importing_commit_without_markers_is_noop() {
+            let test_repository = TestingRepository::open();
+            let a = test_repository.commit_tree(None, &[("foo.txt", "a\n")]);
+
+            let imported =
+                import_conflicted_commit(&test_repository.repository, a.clone()).unwrap();
+            assert_eq!(imported.id(), a.id());
+        }
     }
 
     #[cfg(test)]
@@ -2,22 +2,30 @@
 use std::os::unix::fs::PermissionsExt;
 #[cfg(windows)]
 use std::os::windows::process::CommandExt;
-use std::{io::Write, path::Path, process::Stdio, str};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::Stdio,
+    str,
+};
 
 use crate::Config;
 use crate::SignaturePurpose;
+use crate::SignatureVerification;
 use anyhow::{anyhow, bail, Context, Result};
 use bstr::BString;
 use git2::{BlameOptions, StatusOptions, Tree};
 use gitbutler_commit::commit_headers::CommitHeadersV2;
 use gitbutler_config::git::{GbConfig, GitConfig};
+use gitbutler_diff::lfs;
 use gitbutler_error::error::Code;
 use gitbutler_oxidize::{
-    git2_signature_to_gix_signature, git2_to_gix_object_id, gix_to_git2_oid, gix_to_git2_signature,
+    git2_signature_to_gix_signature, git2_to_gix_object_id, gix_to_git2_signature,
 };
 use gitbutler_reference::{Refname, RemoteRefname};
 use gix::fs::is_executable;
 use gix::objs::WriteTo;
+use serde::Serialize;
 use tracing::instrument;
 
 /// Extension trait for `git2::Repository`.
@@ -63,9 +71,16 @@ pub trait RepositoryExt {
     fn in_memory_repo(&self) -> Result<git2::Repository>;
     /// Fetches the workspace commit from the gitbutler/workspace branch
     fn workspace_commit(&self) -> Result<git2::Commit<'_>>;
+    /// Determine how commits are signed, based on `gpg.format`, the program configured for it,
+    /// and `user.signingKey` or the key returned by `gpg.ssh.defaultKeyCommand`.
+    fn signing_settings(&self) -> Result<SigningSettings>;
     /// `buffer` is the commit object to sign, but in theory could be anything to compute the signature for.
     /// Returns the computed signature.
     fn sign_buffer(&self, buffer: &[u8]) -> Result<BString>;
+    /// Verify the signature of `commit` using the programs configured for signing,
+    /// or return `None` if the commit isn't signed.
+    /// Signatures that can't be verified due to errors are reported as such.
+    fn verify_commit_signature(&self, commit: &git2::Commit) -> Option<SignatureVerification>;
 
     fn checkout_index_builder<'a>(&'a self, index: &'a mut git2::Index)
         -> CheckoutIndexBuilder<'a>;
@@ -188,7 +203,7 @@ impl RepositoryExt for git2::Repository {
     /// Note that this will add all untracked and modified files in the worktree to
     /// the object database, and create a tree from it.
     ///
-    /// Note that right now, it doesn't skip big files.
+    /// Note that right now, it doesn't skip big files, but files tracked by Git LFS are stored as pointers.
     ///
     /// It should also be noted that this will fail if run on an empty branch
     /// or if the HEAD branch has no commits
@@ -239,9 +254,30 @@ impl RepositoryExt for git2::Repository {
 
                     let blob = self.blob(path_str.as_bytes())?;
                     tree_update_builder.upsert(path, blob, git2::FileMode::Link);
+                } else if file_path.is_dir() {
+                    // Submodules are recorded as the commit their `HEAD` points to.
+                    let Some(head_id) = git2::Repository::open(&file_path)
+                        .ok()
+                        .and_then(|submodule| submodule.head().ok()?.target())
+                    else {
+                        continue;
+                    };
+                    tree_update_builder.upsert(path, head_id, git2::FileMode::Commit);
                 } else {
-                    let file = std::fs::read(&file_path)?;
-                    let blob = self.blob(&file)?;
+                    let blob = match lfs::is_lfs_tracked(self, path)
+                        .then(|| lfs::worktree_pointer(self, path))
+                        .transpose()?
+                        .flatten()
+                    {
+                        Some(pointer) => {
+                            lfs::store_object(self, path, &pointer)?;
+                            self.blob(&pointer.to_bytes())?
+                        }
+                        None => {
+                            let file = std::fs::read(&file_path)?;
+                            self.blob(&file)?
+                        }
+                    };
 
                     let file_type = if is_executable(&file_path.metadata()?) {
                         git2::FileMode::BlobExecutable
@@ -287,7 +323,6 @@ impl RepositoryExt for git2::Repository {
         parents: &[&git2::Commit<'_>],
         commit_headers: Option<CommitHeadersV2>,
     ) -> Result<git2::Oid> {
-        let repo = gix::open(self.path())?;
         let mut commit = gix::objs::Commit {
             message: message.into(),
             tree: git2_to_gix_object_id(tree.id()),
@@ -322,7 +357,11 @@ impl RepositoryExt for git2::Repository {
             }
         }
         // TODO: extra-headers should be supported in `gix` directly.
-        let oid = gix_to_git2_oid(repo.write_object(&commit)?);
+        let mut buf = Vec::new();
+        commit.write_to(&mut buf)?;
+        // Write through our own object database so in-memory backends, like the one added by
+        // `in_memory_repo()`, receive the commit instead of the object directory.
+        let oid = self.odb()?.write(git2::ObjectType::Commit, &buf)?;
 
         // update reference
         if let Some(refname) = update_ref {
@@ -348,132 +387,20 @@ impl RepositoryExt for git2::Repository {
         self.blame_file(path, Some(&mut opts))
     }
 
-    fn sign_buffer(&self, buffer: &[u8]) -> Result<BString> {
-        // check git config for gpg.signingkey
-        // TODO: support gpg.ssh.defaultKeyCommand to get the signing key if this value doesn't exist
-        let signing_key = self.config()?.get_string("user.signingkey");
-        if let Ok(signing_key) = signing_key {
-            let sign_format = self.config()?.get_string("gpg.format");
-            let is_ssh = if let Ok(sign_format) = sign_format {
-                sign_format == "ssh"
-            } else {
-                false
-            };
-
-            if is_ssh {
-                // write commit data to a temp file so we can sign it
-                let mut signature_storage = tempfile::NamedTempFile::new()?;
-                signature_storage.write_all(buffer)?;
-                let buffer_file_to_sign_path = signature_storage.into_temp_path();
-
-                let gpg_program = self.config()?.get_string("gpg.ssh.program");
-                let mut gpg_program = gpg_program.unwrap_or("ssh-keygen".to_string());
-                // if cmd is "", use gpg
-                if gpg_program.is_empty() {
-                    gpg_program = "ssh-keygen".to_string();
-                }
-
-                let mut cmd = std::process::Command::new(gpg_program);
-                cmd.args(["-Y", "sign", "-n", "git", "-f"]);
-
-                #[cfg(windows)]
-                cmd.creation_flags(0x08000000); // CREATE_NO_WINDOW
-
-                let output;
-                // support literal ssh key
-                if let (true, signing_key) = is_literal_ssh_key(&signing_key) {
-                    // write the key to a temp file
-                    let mut key_storage = tempfile::NamedTempFile::new()?;
-                    key_storage.write_all(signing_key.as_bytes())?;
-
-                    // if on unix
-                    #[cfg(unix)]
-                    {
-                        // make sure the tempfile permissions are acceptable for a private ssh key
-                        let mut permissions = key_storage.as_file().metadata()?.permissions();
-                        permissions.set_mode(0o600);
-                        key_storage.as_file().set_permissions(permissions)?;
-                    }
-
-                    let key_file_path = key_storage.into_temp_path();
-
-                    cmd.arg(&key_file_path);
-                    cmd.arg("-U");
-                    cmd.arg(&buffer_file_to_sign_path);
-                    cmd.stderr(Stdio::piped());
-                    cmd.stdout(Stdio::piped());
-                    cmd.stdin(Stdio::null());
-
-                    let child = cmd.spawn()?;
-                    output = child.wait_with_output()?;
-                } else {
-                    cmd.arg(signing_key);
-                    cmd.arg(&buffer_file_to_sign_path);
-                    cmd.stderr(Stdio::piped());
-                    cmd.stdout(Stdio::piped());
-                    cmd.stdin(Stdio::null());
-
-                    let child = cmd.spawn()?;
-                    output = child.wait_with_output()?;
-                }
-
-                if output.status.success() {
-                    // read signed_storage path plus .sig
-                    let signature_path = buffer_file_to_sign_path.with_extension("sig");
-                    let sig_data = std::fs::read(signature_path)?;
-                    let signature = BString::new(sig_data);
-                    return Ok(signature);
-                } else {
-                    let stderr = BString::new(output.stderr);
-                    let stdout = BString::new(output.stdout);
-                    let std_both = format!("{} {}", stdout, stderr);
-                    bail!("Failed to sign SSH: {}", std_both);
-                }
-            } else {
-                let gpg_program = self
-                    .config()?
-                    .get_path("gpg.program")
-                    .ok()
-                    .filter(|gpg| !gpg.as_os_str().is_empty())
-                    .unwrap_or_else(|| "gpg".into());
-
-                let mut cmd = std::process::Command::new(&gpg_program);
-
-                cmd.args(["--status-fd=2", "-bsau", &signing_key])
-                    .arg("-")
-                    .stdout(Stdio::piped())
-                    .stderr(Stdio::piped())
-                    .stdin(Stdio::piped());
-
-                #[cfg(windows)]
-                cmd.creation_flags(0x08000000); // CREATE_NO_WINDOW
+    fn signing_settings(&self) -> Result<SigningSettings> {
+        signing_settings(&self.config()?)
+    }
 
-                let mut child = match cmd.spawn() {
-                    Ok(child) => child,
-                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
-                        bail!("Could not find '{}'. Please make sure it is in your `PATH` or configure the full path using `gpg.program` in the Git configuration", gpg_program.display())
-                    }
-                    Err(err) => {
-                        return Err(err)
-                            .context(format!("Could not execute GPG program using {:?}", cmd))
-                    }
-                };
-                child.stdin.take().expect("configured").write_all(buffer)?;
-
-                let output = child.wait_with_output()?;
-                if output.status.success() {
-                    // read stdout
-                    let signature = BString::new(output.stdout);
-                    return Ok(signature);
-                } else {
-                    let stderr = BString::new(output.stderr);
-                    let stdout = BString::new(output.stdout);
-                    let std_both = format!("{} {}", stdout, stderr);
-                    bail!("Failed to sign GPG: {}", std_both);
-                }
-            }
+    fn sign_buffer(&self, buffer: &[u8]) -> Result<BString> {
+        let settings = self.signing_settings()?;
+        match settings.format {
+            SigningFormat::Ssh => sign_buffer_ssh(&settings, buffer),
+            SigningFormat::OpenPgp | SigningFormat::X509 => sign_buffer_gpg(&settings, buffer),
         }
-        Err(anyhow::anyhow!("No signing key found"))
+    }
+
+    fn verify_commit_signature(&self, commit: &git2::Commit) -> Option<SignatureVerification> {
+        crate::signature_verification::verify_commit_signature(self, commit)
     }
 
     fn remotes_as_string(&self) -> Result<Vec<String>> {
@@ -631,7 +558,225 @@ impl RepositoryExt for git2::Repository {
     }
 }
 
-/// Signs the buffer with the configured gpg key, returning the signature.
+/// The kind of signatures to create, as configured by `gpg.format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SigningFormat {
+    OpenPgp,
+    Ssh,
+    X509,
+}
+
+impl SigningFormat {
+    /// The configuration keys for the signing program, in order of precedence.
+    fn program_keys(&self) -> &'static [&'static str] {
+        match self {
+            SigningFormat::OpenPgp => &["gpg.openpgp.program", "gpg.program"],
+            SigningFormat::Ssh => &["gpg.ssh.program"],
+            SigningFormat::X509 => &["gpg.x509.program"],
+        }
+    }
+
+    /// Read the program to sign and verify with from `config`, or use the default for this format.
+    pub(crate) fn program(&self, config: &git2::Config) -> PathBuf {
+        self.program_keys()
+            .iter()
+            .find_map(|key| {
+                config
+                    .get_path(key)
+                    .ok()
+                    .filter(|program| !program.as_os_str().is_empty())
+            })
+            .unwrap_or_else(|| match self {
+                SigningFormat::OpenPgp => "gpg".into(),
+                SigningFormat::Ssh => "ssh-keygen".into(),
+                SigningFormat::X509 => "gpgsm".into(),
+            })
+    }
+}
+
+/// How commits are signed, see [`RepositoryExt::signing_settings()`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SigningSettings {
+    pub format: SigningFormat,
+    /// The program that creates the signatures.
+    pub program: PathBuf,
+    /// The key to sign with, as understood by `program`.
+    pub signing_key: String,
+    /// `true` if `signing_key` was provided by `gpg.ssh.defaultKeyCommand`.
+    pub key_from_command: bool,
+}
+
+/// Read how commits are signed from `config`, see [`RepositoryExt::signing_settings()`].
+fn signing_settings(config: &git2::Config) -> Result<SigningSettings> {
+    let format = match config.get_string("gpg.format").ok().as_deref() {
+        Some("ssh") => SigningFormat::Ssh,
+        Some("x509") => SigningFormat::X509,
+        _ => SigningFormat::OpenPgp,
+    };
+    let program = format.program(config);
+
+    // check git config for user.signingkey, and ask gpg.ssh.defaultKeyCommand for SSH keys if it doesn't exist
+    let (signing_key, key_from_command) = match config.get_string("user.signingkey") {
+        Ok(signing_key) => (signing_key, false),
+        Err(_) if format == SigningFormat::Ssh => {
+            let Ok(key_command) = config.get_string("gpg.ssh.defaultKeyCommand") else {
+                bail!("No signing key found");
+            };
+            (ssh_default_key(&key_command)?, true)
+        }
+        Err(_) => bail!("No signing key found"),
+    };
+
+    Ok(SigningSettings {
+        format,
+        program,
+        signing_key,
+        key_from_command,
+    })
+}
+
+/// Run `key_command` like Git does for `gpg.ssh.defaultKeyCommand`, and return the first literal key it prints.
+fn ssh_default_key(key_command: &str) -> Result<String> {
+    #[cfg(unix)]
+    let mut cmd = {
+        let mut cmd = std::process::Command::new("sh");
+        cmd.arg("-c").arg(key_command);
+        cmd
+    };
+    #[cfg(windows)]
+    let mut cmd = {
+        let mut cmd = std::process::Command::new("cmd");
+        cmd.arg("/C").arg(key_command);
+        cmd.creation_flags(0x08000000); // CREATE_NO_WINDOW
+        cmd
+    };
+    let output = cmd
+        .stdin(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .with_context(|| format!("Could not execute gpg.ssh.defaultKeyCommand '{key_command}'"))?;
+    if !output.status.success() {
+        bail!(
+            "gpg.ssh.defaultKeyCommand '{key_command}' failed: {}",
+            BString::new(output.stderr)
+        );
+    }
+    str::from_utf8(&output.stdout)?
+        .lines()
+        .map(str::trim)
+        .find(|line| is_literal_ssh_key(line).0)
+        .map(ToOwned::to_owned)
+        .with_context(|| format!("gpg.ssh.defaultKeyCommand '{key_command}' didn't return a key"))
+}
+
+fn sign_buffer_ssh(settings: &SigningSettings, buffer: &[u8]) -> Result<BString> {
+    // write commit data to a temp file so we can sign it
+    let mut signature_storage = tempfile::NamedTempFile::new()?;
+    signature_storage.write_all(buffer)?;
+    let buffer_file_to_sign_path = signature_storage.into_temp_path();
+
+    let mut cmd = std::process::Command::new(&settings.program);
+    cmd.args(["-Y", "sign", "-n", "git", "-f"]);
+
+    #[cfg(windows)]
+    cmd.creation_flags(0x08000000); // CREATE_NO_WINDOW
+
+    let output;
+    // support literal ssh key
+    if let (true, signing_key) = is_literal_ssh_key(&settings.signing_key) {
+        // write the key to a temp file
+        let mut key_storage = tempfile::NamedTempFile::new()?;
+        key_storage.write_all(signing_key.as_bytes())?;
+
+        // if on unix
+        #[cfg(unix)]
+        {
+            // make sure the tempfile permissions are acceptable for a private ssh key
+            let mut permissions = key_storage.as_file().metadata()?.permissions();
+            permissions.set_mode(0o600);
+            key_storage.as_file().set_permissions(permissions)?;
+        }
+
+        let key_file_path = key_storage.into_temp_path();
+
+        cmd.arg(&key_file_path);
+        cmd.arg("-U");
+        cmd.arg(&buffer_file_to_sign_path);
+        cmd.stderr(Stdio::piped());
+        cmd.stdout(Stdio::piped());
+        cmd.stdin(Stdio::null());
+
+        let child = cmd.spawn()?;
+        output = child.wait_with_output()?;
+    } else {
+        cmd.arg(&settings.signing_key);
+        cmd.arg(&buffer_file_to_sign_path);
+        cmd.stderr(Stdio::piped());
+        cmd.stdout(Stdio::piped());
+        cmd.stdin(Stdio::null());
+
+        let child = cmd.spawn()?;
+        output = child.wait_with_output()?;
+    }
+
+    if output.status.success() {
+        // read signed_storage path plus .sig
+        let signature_path = buffer_file_to_sign_path.with_extension("sig");
+        let sig_data = std::fs::read(signature_path)?;
+        let signature = BString::new(sig_data);
+        Ok(signature)
+    } else {
+        let stderr = BString::new(output.stderr);
+        let stdout = BString::new(output.stdout);
+        let std_both = format!("{} {}", stdout, stderr);
+        bail!("Failed to sign SSH: {}", std_both);
+    }
+}
+
+/// Sign with `gpg` for OpenPGP or `gpgsm` for X.509, which take the same arguments.
+fn sign_buffer_gpg(settings: &SigningSettings, buffer: &[u8]) -> Result<BString> {
+    let gpg_program = &settings.program;
+    let mut cmd = std::process::Command::new(gpg_program);
+
+    cmd.args(["--status-fd=2", "-bsau", &settings.signing_key])
+        .arg("-")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .stdin(Stdio::piped());
+
+    #[cfg(windows)]
+    cmd.creation_flags(0x08000000); // CREATE_NO_WINDOW
+
+    let mut child = match cmd.spawn() {
+        Ok(child) => child,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            bail!(
+                "Could not find '{}'. Please make sure it is in your `PATH` or configure the full path using `{}` in the Git configuration",
+                gpg_program.display(),
+                settings.format.program_keys()[0]
+            )
+        }
+        Err(err) => {
+            return Err(err).context(format!("Could not execute GPG program using {:?}", cmd))
+        }
+    };
+    child.stdin.take().expect("configured").write_all(buffer)?;
+
+    let output = child.wait_with_output()?;
+    if output.status.success() {
+        // read stdout
+        let signature = BString::new(output.stdout);
+        Ok(signature)
+    } else {
+        let stderr = BString::new(output.stderr);
+        let stdout = BString::new(output.stdout);
+        let std_both = format!("{} {}", stdout, stderr);
+        bail!("Failed to sign GPG: {}", std_both);
+    }
+}
+
 pub fn is_literal_ssh_key(string: &str) -> (bool, &str) {
     if let Some(key) = string.strip_prefix("key::") {
         return (true, key);
@@ -724,3 +869,156 @@ pub enum LogUntil {
     /// Traverse the whole graph until it is exhausted.
     End,
 }
+
+#[cfg(test)]
+mod tests {
+    use gitbutler_testsupport::testing_repository::TestingRepository;
+
+    use std::path::Path;
+
+    use super::{signing_settings, ssh_default_key, SigningFormat};
+
+    /// Open the configuration of the repository alone, without the global and system configuration.
+    fn local_config(test_repository: &TestingRepository) -> git2::Config {
+        git2::Config::open(&test_repository.repository.path().join("config")).unwrap()
+    }
+
+    #[test]
+    fn program_defaults_per_format() {
+        let test_repository = TestingRepository::open();
+        let config = local_config(&test_repository);
+
+        assert_eq!(SigningFormat::OpenPgp.program(&config), Path::new("gpg"));
+        assert_eq!(SigningFormat::Ssh.program(&config), Path::new("ssh-keygen"));
+        assert_eq!(SigningFormat::X509.program(&config), Path::new("gpgsm"));
+    }
+
+    #[test]
+    fn program_precedence() {
+        let test_repository = TestingRepository::open();
+        let mut config = local_config(&test_repository);
+
+        config.set_str("gpg.program", "").unwrap();
+        assert_eq!(
+            SigningFormat::OpenPgp.program(&config),
+            Path::new("gpg"),
+            "empty values are ignored"
+        );
+
+        config.set_str("gpg.program", "legacy-gpg").unwrap();
+        assert_eq!(
+            SigningFormat::OpenPgp.program(&config),
+            Path::new("legacy-gpg")
+        );
+
+        config
+            .set_str("gpg.openpgp.program", "openpgp-gpg")
+            .unwrap();
+        assert_eq!(
+            SigningFormat::OpenPgp.program(&config),
+            Path::new("openpgp-gpg"),
+            "the format specific key comes first"
+        );
+        assert_eq!(
+            SigningFormat::X509.program(&config),
+            Path::new("gpgsm"),
+            "other formats don't use the programs for OpenPGP"
+        );
+
+        config.set_str("gpg.ssh.program", "my-ssh-keygen").unwrap();
+        config.set_str("gpg.x509.program", "my-gpgsm").unwrap();
+        assert_eq!(
+            SigningFormat::Ssh.program(&config),
+            Path::new("my-ssh-keygen")
+        );
+        assert_eq!(SigningFormat::X509.program(&config), Path::new("my-gpgsm"));
+    }
+
+    #[test]
+    fn signing_settings_by_format() {
+        let test_repository = TestingRepository::open();
+        let mut config = local_config(&test_repository);
+
+        assert!(
+            signing_settings(&config).is_err(),
+            "there is no key to sign with"
+        );
+
+        config.set_str("user.signingKey", "ABCDEF").unwrap();
+        let settings = signing_settings(&config).unwrap();
+        assert_eq!(settings.format, SigningFormat::OpenPgp);
+        assert_eq!(settings.program, Path::new("gpg"));
+        assert_eq!(settings.signing_key, "ABCDEF");
+        assert!(!settings.key_from_command);
+
+        config.set_str("gpg.format", "x509").unwrap();
+        let settings = signing_settings(&config).unwrap();
+        assert_eq!(settings.format, SigningFormat::X509);
+        assert_eq!(settings.program, Path::new("gpgsm"));
+
+        config.set_str("gpg.format", "ssh").unwrap();
+        let settings = signing_settings(&config).unwrap();
+        assert_eq!(settings.format, SigningFormat::Ssh);
+        assert_eq!(settings.program, Path::new("ssh-keygen"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn signing_key_takes_precedence_over_default_key_command() {
+        let test_repository = TestingRepository::open();
+        let mut config = local_config(&test_repository);
+        config.set_str("gpg.format", "ssh").unwrap();
+        config
+            .set_str(
+                "gpg.ssh.defaultKeyCommand",
+                "echo 'ssh-ed25519 AAAAfromcommand'",
+            )
+            .unwrap();
+
+        let settings = signing_settings(&config).unwrap();
+        assert_eq!(settings.signing_key, "ssh-ed25519 AAAAfromcommand");
+        assert!(settings.key_from_command);
+
+        config
+            .set_str("user.signingKey", "ssh-ed25519 AAAAconfigured")
+            .unwrap();
+        let settings = signing_settings(&config).unwrap();
+        assert_eq!(settings.signing_key, "ssh-ed25519 AAAAconfigured");
+        assert!(!settings.key_from_command);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn default_key_command_only_applies_to_ssh() {
+        let test_repository = TestingRepository::open();
+        let mut config = local_config(&test_repository);
+        config
+            .set_str(
+                "gpg.ssh.defaultKeyCommand",
+                "echo 'ssh-ed25519 AAAAfromcommand'",
+            )
+            .unwrap();
+
+        assert!(signing_settings(&config).is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn ssh_default_key_returns_the_first_literal_key() {
+        assert_eq!(
+            ssh_default_key(
+                "echo 'not a key'; echo 'key::ssh-ed25519 AAAAfirst'; echo 'ssh-rsa AAAAsecond'"
+            )
+            .unwrap(),
+            "key::ssh-ed25519 AAAAfirst"
+        );
+        assert!(
+            ssh_default_key("echo 'not a key'").is_err(),
+            "there must be a key"
+        );
+        assert!(
+            ssh_default_key("exit 1").is_err(),
+            "the command must succeed"
+        );
+    }
+}
@@ -0,0 +1,347 @@
+//! Reuse recorded resolutions of conflicts, similar to `git rerere`.
+//!
+//! Resolutions are recorded per conflicting hunk, keyed by the content of its base, our and their side,
+//! so the same conflict is resolved automatically even if the rest of the file changed in the meantime.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use bstr::ByteSlice;
+use gitbutler_cherry_pick::{ConflictedTreeKey, RepositoryExt as _};
+use gitbutler_project::Project;
+
+use crate::rebase::ConflictEntries;
+
+/// The length of the conflict markers we produce and parse.
+const MARKER_SIZE: usize = 7;
+
+/// The directory in which resolutions are stored, within the GitButler directory `gb_dir` of a project.
+fn resolutions_dir(gb_dir: &Path) -> PathBuf {
+    gb_dir.join("rerere")
+}
+
+/// Record how the conflicts of `conflicted_commit` were resolved in `resolved_tree`, in the
+/// GitButler directory `gb_dir` of the project, as returned by [`Project::gb_dir()`].
+///
+/// Conflicting hunks whose resolution can't be told apart, for instance because the user also changed
+/// lines around them, aren't recorded.
+pub fn record_resolutions(
+    repo: &git2::Repository,
+    gb_dir: &Path,
+    conflicted_commit: &git2::Commit,
+    resolved_tree: &git2::Tree,
+) -> Result<()> {
+    let Some(conflict_files) = conflicted_commit
+        .tree()?
+        .get_name(&ConflictedTreeKey::ConflictFiles)
+    else {
+        return Ok(());
+    };
+    let conflict_files = repo.find_blob(conflict_files.id())?;
+    let conflict_files: ConflictEntries = toml::from_str(&conflict_files.content().to_str_lossy())?;
+
+    let base = repo.find_real_tree(conflicted_commit, ConflictedTreeKey::Base)?;
+    let ours = repo.find_real_tree(conflicted_commit, ConflictedTreeKey::Ours)?;
+    let theirs = repo.find_real_tree(conflicted_commit, ConflictedTreeKey::Theirs)?;
+    for path in conflict_files.paths() {
+        let entry = |tree: &git2::Tree| {
+            tree.get_path(path)
+                .ok()
+                .filter(|entry| entry.kind() == Some(git2::ObjectType::Blob))
+                .map(|entry| index_entry(entry.id(), entry.filemode() as u32, path))
+        };
+        let (Some(our_entry), Some(their_entry), Some(resolved)) =
+            (entry(&ours), entry(&theirs), entry(resolved_tree))
+        else {
+            continue;
+        };
+        let ancestor_entry = match entry(&base) {
+            Some(entry) => entry,
+            None => index_entry(repo.blob(&[])?, our_entry.mode, path),
+        };
+        let preimage = merge_with_markers(repo, &ancestor_entry, &our_entry, &their_entry)?;
+        let resolved = repo.find_blob(resolved.id)?;
+        record_file_resolution(gb_dir, &preimage, resolved.content())
+            .with_context(|| format!("failed to record resolution of '{}'", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Resolve the conflicts in `index` for which resolutions of all conflicting hunks were recorded.
+/// Conflicts with any unknown hunk are left as they are.
+///
+/// Rebases only have the repository at hand, which is opened from the worktree of the project,
+/// so its git directory is the one the project's GitButler directory is in.
+pub(crate) fn replay_resolutions(repo: &git2::Repository, index: &mut git2::Index) -> Result<()> {
+    let dir = resolutions_dir(&Project::gb_dir_of(repo.path()));
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    let conflicts = index.conflicts()?.collect::<Result<Vec<_>, _>>()?;
+    for conflict in conflicts {
+        let (Some(mut ours), Some(theirs)) = (conflict.our, conflict.their) else {
+            continue;
+        };
+        let is_gitlink = |entry: &git2::IndexEntry| entry.mode == u32::from(git2::FileMode::Commit);
+        if is_gitlink(&ours) || is_gitlink(&theirs) {
+            continue;
+        }
+        let path = PathBuf::from(ours.path.to_str()?);
+        let ancestor = match conflict.ancestor {
+            Some(ancestor) if !is_gitlink(&ancestor) => ancestor,
+            Some(_) => continue,
+            None => index_entry(repo.blob(&[])?, ours.mode, &path),
+        };
+        let preimage = merge_with_markers(repo, &ancestor, &ours, &theirs)?;
+
+        let mut resolved = Vec::with_capacity(preimage.len());
+        let mut fully_resolved = true;
+        for segment in parse_segments(&preimage) {
+            match segment {
+                Segment::Text(text) => resolved.extend_from_slice(text),
+                Segment::Conflict { .. } => match fs::read(dir.join(segment.key())) {
+                    Ok(resolution) => resolved.extend_from_slice(&resolution),
+                    Err(_) => {
+                        fully_resolved = false;
+                        break;
+                    }
+                },
+            }
+        }
+        if !fully_resolved {
+            continue;
+        }
+        index.conflict_remove(&path)?;
+        ours.flags = 0; // the stage is part of the flags, and we want to add it as resolved
+        index.add_frombuffer(&ours, &resolved)?;
+    }
+    Ok(())
+}
+
+/// Record the resolution of each conflicting hunk in `preimage` by finding the text around them in `resolved`.
+fn record_file_resolution(gb_dir: &Path, preimage: &[u8], resolved: &[u8]) -> Result<()> {
+    let segments = parse_segments(preimage);
+    let (texts, conflicts): (Vec<_>, Vec<_>) = segments
+        .iter()
+        .partition(|segment| matches!(segment, Segment::Text(_)));
+    if conflicts.is_empty() {
+        return Ok(());
+    }
+    let text = |idx: usize| match texts[idx] {
+        Segment::Text(text) => *text,
+        Segment::Conflict { .. } => unreachable!("partitioned by kind"),
+    };
+
+    // Conflicts are surrounded by text, which may only be empty at the very beginning and end.
+    if !resolved.starts_with(text(0)) {
+        return Ok(());
+    }
+    let mut pos = text(0).len();
+    let mut resolutions = Vec::new();
+    for (idx, conflict) in conflicts.iter().enumerate() {
+        let next = text(idx + 1);
+        let end = if idx + 1 == conflicts.len() {
+            match resolved.len().checked_sub(next.len()) {
+                Some(end) if end >= pos && resolved.ends_with(next) => end,
+                _ => return Ok(()),
+            }
+        } else {
+            match resolved[pos..].find(next).filter(|_| !next.is_empty()) {
+                Some(offset) => pos + offset,
+                None => return Ok(()),
+            }
+        };
+        resolutions.push((conflict.key(), &resolved[pos..end]));
+        pos = end + next.len();
+    }
+
+    let dir = resolutions_dir(gb_dir);
+    fs::create_dir_all(&dir)?;
+    for (key, resolution) in resolutions {
+        fs::write(dir.join(key), resolution)?;
+    }
+    Ok(())
+}
+
+/// Merge the given sides of a file, producing diff3-style conflict markers for conflicting hunks.
+fn merge_with_markers(
+    repo: &git2::Repository,
+    ancestor: &git2::IndexEntry,
+    ours: &git2::IndexEntry,
+    theirs: &git2::IndexEntry,
+) -> Result<Vec<u8>> {
+    let mut opts = git2::MergeFileOptions::new();
+    opts.style_diff3(true).marker_size(MARKER_SIZE as u16);
+    let merged = repo.merge_file_from_index(ancestor, ours, theirs, Some(&mut opts))?;
+    Ok(merged.content().to_owned())
+}
+
+fn index_entry(id: git2::Oid, mode: u32, path: &Path) -> git2::IndexEntry {
+    git2::IndexEntry {
+        ctime: git2::IndexTime::new(0, 0),
+        mtime: git2::IndexTime::new(0, 0),
+        dev: 0,
+        ino: 0,
+        mode,
+        uid: 0,
+        gid: 0,
+        file_size: 0,
+        id,
+        flags: 0,
+        flags_extended: 0,
+        path: path.to_string_lossy().into_owned().into_bytes(),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Segment<'a> {
+    /// Text outside of conflict markers.
+    Text(&'a [u8]),
+    /// The content of each side of a conflicting hunk, without markers.
+    Conflict {
+        ours: &'a [u8],
+        base: &'a [u8],
+        theirs: &'a [u8],
+    },
+}
+
+impl Segment<'_> {
+    /// The key under which the resolution of this conflict is stored.
+    fn key(&self) -> String {
+        let Segment::Conflict { ours, base, theirs } = self else {
+            unreachable!("only conflicts have resolutions")
+        };
+        let preimage = [*ours, &b"\0"[..], *base, &b"\0"[..], *theirs].concat();
+        git2::Oid::hash_object(git2::ObjectType::Blob, &preimage)
+            .expect("hashing in memory doesn't fail")
+            .to_string()
+    }
+}
+
+/// Split `content` with diff3-style conflict markers into alternating text and conflicts,
+/// starting and ending with a possibly empty text.
+fn parse_segments(content: &[u8]) -> Vec<Segment<'_>> {
+    #[derive(Clone, Copy)]
+    enum State {
+        Text,
+        Ours,
+        Base,
+        Theirs,
+    }
+    let is_marker = |line: &[u8], marker: u8| {
+        line.len() >= MARKER_SIZE
+            && line[..MARKER_SIZE].iter().all(|b| *b == marker)
+            && line
+                .get(MARKER_SIZE)
+                .map_or(true, |b| matches!(b, b' ' | b'\n' | b'\r'))
+    };
+
+    let mut segments = Vec::new();
+    let mut state = State::Text;
+    // The start of the current text or side of a conflict.
+    let mut start = 0;
+    let (mut ours, mut base): (&[u8], &[u8]) = (&[], &[]);
+    let mut pos = 0;
+    for line in content.lines_with_terminator() {
+        let line_end = pos + line.len();
+        state = match state {
+            State::Text if is_marker(line, b'<') => {
+                segments.push(Segment::Text(&content[start..pos]));
+                start = line_end;
+                State::Ours
+            }
+            State::Ours if is_marker(line, b'|') => {
+                ours = &content[start..pos];
+                start = line_end;
+                State::Base
+            }
+            State::Base if is_marker(line, b'=') => {
+                base = &content[start..pos];
+                start = line_end;
+                State::Theirs
+            }
+            State::Theirs if is_marker(line, b'>') => {
+                segments.push(Segment::Conflict {
+                    ours,
+                    base,
+                    theirs: &content[start..pos],
+                });
+                start = line_end;
+                State::Text
+            }
+            state => state,
+        };
+        pos = line_end;
+    }
+    if !matches!(state, State::Text) {
+        // An unterminated conflict is just text.
+        return vec![Segment::Text(content)];
+    }
+    segments.push(Segment::Text(&content[start..]));
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use gitbutler_commit::commit_ext::CommitExt as _;
+    use gitbutler_testsupport::testing_repository::{
+        assert_commit_tree_matches, TestingRepository,
+    };
+
+    use super::{parse_segments, record_resolutions, Project, Segment};
+    use crate::rebase::cherry_rebase_group;
+
+    #[test]
+    fn segments_alternate_between_text_and_conflicts() {
+        let content = b"a\n<<<<<<< ours\nb\n||||||| base\nx\n=======\nc\n>>>>>>> theirs\n";
+        assert_eq!(
+            parse_segments(content),
+            vec![
+                Segment::Text(b"a\n"),
+                Segment::Conflict {
+                    ours: b"b\n",
+                    base: b"x\n",
+                    theirs: b"c\n"
+                },
+                Segment::Text(b""),
+            ]
+        );
+        assert_eq!(
+            parse_segments(b"<<<<<<<\na\n"),
+            vec![Segment::Text(b"<<<<<<<\na\n")]
+        );
+    }
+
+    #[test]
+    fn recorded_resolution_is_replayed_on_rebase() {
+        let test_repository = TestingRepository::open();
+        let repository = &test_repository.repository;
+
+        let a = test_repository.commit_tree(None, &[("foo.txt", "1\n2\n3\n")]);
+        let b = test_repository.commit_tree(Some(&a), &[("foo.txt", "1\nb\n3\n")]);
+        let c = test_repository.commit_tree(Some(&a), &[("foo.txt", "1\nc\n3\n")]);
+
+        let conflicted = cherry_rebase_group(repository, b.id(), &[c.id()]).unwrap();
+        let conflicted = repository.find_commit(conflicted).unwrap();
+        assert!(conflicted.is_conflicted());
+
+        let resolved = test_repository.commit_tree(Some(&b), &[("foo.txt", "1\nbc\n3\n")]);
+        record_resolutions(
+            repository,
+            &Project::gb_dir_of(repository.path()),
+            &conflicted,
+            &resolved.tree().unwrap(),
+        )
+        .unwrap();
+
+        // The same conflict, but within a file that changed elsewhere.
+        let d = test_repository.commit_tree(Some(&a), &[("foo.txt", "0\n1\nb\n3\n")]);
+        let result = cherry_rebase_group(repository, d.id(), &[c.id()]).unwrap();
+        let result = repository.find_commit(result).unwrap();
+
+        assert!(!result.is_conflicted());
+        assert_commit_tree_matches(repository, &result, &[("foo.txt", b"0\n1\nbc\n3\n")]);
+    }
+}
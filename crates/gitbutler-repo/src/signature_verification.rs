@@ -0,0 +1,342 @@
+#[cfg(windows)]
+use std::os::windows::process::CommandExt;
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::Mutex,
+};
+
+use anyhow::{Context, Result};
+use bstr::ByteSlice;
+use gitbutler_commit::commit_ext::CommitExt as _;
+use serde::Serialize;
+
+use crate::SigningFormat;
+
+/// The outcome of verifying the signature of a commit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SignatureStatus {
+    /// The signature is valid and made by a trusted key.
+    Good,
+    /// The signature doesn't match the signed commit.
+    Bad,
+    /// The signature is valid, but the key that made it isn't known or trusted.
+    UnknownKey,
+    /// The signature or the key that made it has expired.
+    Expired,
+    /// The key that made the signature was revoked.
+    Revoked,
+    /// The signature couldn't be checked, e.g. because the program to verify it is missing.
+    Unverifiable,
+}
+
+/// Information about the signature of a commit, as reported by the program that verified it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignatureVerification {
+    pub status: SignatureStatus,
+    /// The identity of the signer, the user-id for GPG keys or the principal for SSH keys.
+    pub signer: Option<String>,
+    /// The fingerprint of the key that made the signature.
+    pub fingerprint: Option<String>,
+}
+
+impl SignatureVerification {
+    fn unverifiable() -> Self {
+        SignatureVerification {
+            status: SignatureStatus::Unverifiable,
+            signer: None,
+            fingerprint: None,
+        }
+    }
+}
+
+/// Verify the signature of `commit`, or return `None` if it isn't signed.
+///
+/// Verification spawns `gpg`, `gpgsm` or `ssh-keygen` depending on the kind of signature, so it's only
+/// attempted for commits with a signature header. Good and bad signatures are cached by repository and
+/// commit id, while all other outcomes depend on keys and configuration that may change, and are checked
+/// again next time. Errors are logged and reported as [`SignatureStatus::Unverifiable`].
+pub(crate) fn verify_commit_signature(
+    repo: &git2::Repository,
+    commit: &git2::Commit,
+) -> Option<SignatureVerification> {
+    static CACHE: Mutex<Option<HashMap<(PathBuf, git2::Oid), SignatureVerification>>> =
+        Mutex::new(None);
+
+    if !commit.is_signed() {
+        return None;
+    }
+    let key = (repo.path().to_owned(), commit.id());
+    if let Some(verification) = CACHE
+        .lock()
+        .expect("not poisoned")
+        .as_ref()
+        .and_then(|cache| cache.get(&key))
+    {
+        return Some(verification.clone());
+    }
+
+    let verification = verify(repo, commit.id()).unwrap_or_else(|err| {
+        tracing::warn!(
+            "failed to verify signature of commit {}: {err:#}",
+            commit.id()
+        );
+        Some(SignatureVerification::unverifiable())
+    })?;
+    if matches!(
+        verification.status,
+        SignatureStatus::Good | SignatureStatus::Bad
+    ) {
+        CACHE
+            .lock()
+            .expect("not poisoned")
+            .get_or_insert_with(HashMap::new)
+            .insert(key, verification.clone());
+    }
+    Some(verification)
+}
+
+fn verify(repo: &git2::Repository, commit_id: git2::Oid) -> Result<Option<SignatureVerification>> {
+    let (signature, signed_data) = match repo.extract_signature(&commit_id, None) {
+        Ok(parts) => parts,
+        Err(err) if err.code() == git2::ErrorCode::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    let config = repo.config()?;
+    let verification = if signature.starts_with(b"-----BEGIN SSH SIGNATURE-----") {
+        let program = SigningFormat::Ssh.program(&config);
+        let allowed_signers = config.get_path("gpg.ssh.allowedSignersFile").ok();
+        verify_ssh(
+            &program,
+            allowed_signers.as_deref(),
+            &signature,
+            &signed_data,
+        )
+    } else if signature.starts_with(b"-----BEGIN SIGNED MESSAGE-----") {
+        verify_gpg(
+            &SigningFormat::X509.program(&config),
+            &signature,
+            &signed_data,
+        )
+    } else {
+        verify_gpg(
+            &SigningFormat::OpenPgp.program(&config),
+            &signature,
+            &signed_data,
+        )
+    }?;
+    Ok(Some(verification))
+}
+
+fn verify_gpg(
+    program: &Path,
+    signature: &[u8],
+    signed_data: &[u8],
+) -> Result<SignatureVerification> {
+    let signature_file = write_temp_file(signature)?;
+
+    let mut cmd = Command::new(program);
+    cmd.args(["--status-fd=1", "--verify"])
+        .arg(&signature_file)
+        .arg("-")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .stdin(Stdio::piped());
+
+    #[cfg(windows)]
+    cmd.creation_flags(0x08000000); // CREATE_NO_WINDOW
+
+    let mut child = cmd
+        .spawn()
+        .with_context(|| format!("Could not execute {}", program.display()))?;
+    child
+        .stdin
+        .take()
+        .expect("configured")
+        .write_all(signed_data)?;
+    // The exit code is non-zero for anything but good signatures, the status output tells us why.
+    let output = child.wait_with_output()?;
+    Ok(parse_gpg_status(&output.stdout.to_str_lossy()))
+}
+
+/// Parse the machine-readable output of `gpg --status-fd`, as documented in `doc/DETAILS` of GnuPG.
+fn parse_gpg_status(output: &str) -> SignatureVerification {
+    let mut verification = SignatureVerification::unverifiable();
+    for line in output.lines() {
+        let Some(line) = line.strip_prefix("[GNUPG:] ") else {
+            continue;
+        };
+        let (keyword, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let status = match keyword {
+            "GOODSIG" => SignatureStatus::Good,
+            "BADSIG" => SignatureStatus::Bad,
+            "EXPSIG" | "EXPKEYSIG" => SignatureStatus::Expired,
+            "REVKEYSIG" => SignatureStatus::Revoked,
+            "ERRSIG" | "NO_PUBKEY" => {
+                verification.status = SignatureStatus::UnknownKey;
+                continue;
+            }
+            "VALIDSIG" => {
+                verification.fingerprint = rest.split(' ').next().map(ToOwned::to_owned);
+                continue;
+            }
+            _ => continue,
+        };
+        // These are followed by the key id and the user id.
+        verification.status = status;
+        verification.signer = rest
+            .split_once(' ')
+            .map(|(_key_id, user_id)| user_id.to_owned());
+    }
+    verification
+}
+
+fn verify_ssh(
+    program: &Path,
+    allowed_signers: Option<&Path>,
+    signature: &[u8],
+    signed_data: &[u8],
+) -> Result<SignatureVerification> {
+    let signature_file = write_temp_file(signature)?;
+
+    let principal = match allowed_signers {
+        Some(allowed_signers) => {
+            let output = ssh_keygen(program)
+                .args(["-Y", "find-principals", "-f"])
+                .arg(allowed_signers)
+                .arg("-s")
+                .arg(&signature_file)
+                .stdin(Stdio::null())
+                .output()
+                .with_context(|| format!("Could not execute {}", program.display()))?;
+            if output.status.success() {
+                output
+                    .stdout
+                    .to_str_lossy()
+                    .lines()
+                    .next()
+                    .map(ToOwned::to_owned)
+            } else {
+                None
+            }
+        }
+        None => None,
+    };
+
+    let mut cmd = ssh_keygen(program);
+    match (&principal, allowed_signers) {
+        (Some(principal), Some(allowed_signers)) => {
+            cmd.args(["-Y", "verify", "-f"])
+                .arg(allowed_signers)
+                .args(["-I", principal]);
+        }
+        _ => {
+            cmd.args(["-Y", "check-novalidate"]);
+        }
+    }
+    let mut child = cmd
+        .args(["-n", "git", "-s"])
+        .arg(&signature_file)
+        .stdin(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Could not execute {}", program.display()))?;
+    child
+        .stdin
+        .take()
+        .expect("configured")
+        .write_all(signed_data)?;
+    let output = child.wait_with_output()?;
+
+    let status = match (output.status.success(), &principal) {
+        (false, _) => SignatureStatus::Bad,
+        (true, Some(_)) => SignatureStatus::Good,
+        (true, None) => SignatureStatus::UnknownKey,
+    };
+    Ok(SignatureVerification {
+        status,
+        signer: principal,
+        fingerprint: parse_ssh_fingerprint(&output.stdout.to_str_lossy()),
+    })
+}
+
+fn ssh_keygen(program: &Path) -> Command {
+    let mut cmd = Command::new(program);
+    cmd.stdout(Stdio::piped()).stderr(Stdio::null());
+
+    #[cfg(windows)]
+    cmd.creation_flags(0x08000000); // CREATE_NO_WINDOW
+
+    cmd
+}
+
+/// Extract the fingerprint from output like `Good "git" signature for <principal> with ED25519 key SHA256:<hash>`.
+fn parse_ssh_fingerprint(output: &str) -> Option<String> {
+    output
+        .lines()
+        .find_map(|line| line.split_once(" key "))
+        .and_then(|(_, fingerprint)| fingerprint.split_whitespace().next())
+        .map(ToOwned::to_owned)
+}
+
+fn write_temp_file(content: &[u8]) -> Result<tempfile::TempPath> {
+    let mut file = tempfile::NamedTempFile::new()?;
+    file.write_all(content)?;
+    Ok(file.into_temp_path())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gpg_good_signature() {
+        let output = "[GNUPG:] NEWSIG
+[GNUPG:] KEY_CONSIDERED 4F2A4A1B6D0C8E7A9F3B2C1D0E9F8A7B6C5D4E3F 0
+[GNUPG:] SIG_ID abc 2024-10-01 1727776000
+[GNUPG:] GOODSIG 0E9F8A7B6C5D4E3F Jane Doe <jane@example.com>
+[GNUPG:] VALIDSIG 4F2A4A1B6D0C8E7A9F3B2C1D0E9F8A7B6C5D4E3F 2024-10-01 1727776000 0 4 0 22 10 00 4F2A4A1B6D0C8E7A9F3B2C1D0E9F8A7B6C5D4E3F
+[GNUPG:] TRUST_ULTIMATE 0 pgp";
+        assert_eq!(
+            parse_gpg_status(output),
+            SignatureVerification {
+                status: SignatureStatus::Good,
+                signer: Some("Jane Doe <jane@example.com>".into()),
+                fingerprint: Some("4F2A4A1B6D0C8E7A9F3B2C1D0E9F8A7B6C5D4E3F".into()),
+            }
+        );
+    }
+
+    #[test]
+    fn gpg_missing_key() {
+        let output = "[GNUPG:] NEWSIG
+[GNUPG:] ERRSIG 0E9F8A7B6C5D4E3F 22 10 00 1727776000 9 -
+[GNUPG:] NO_PUBKEY 0E9F8A7B6C5D4E3F";
+        let verification = parse_gpg_status(output);
+        assert_eq!(verification.status, SignatureStatus::UnknownKey);
+        assert_eq!(verification.fingerprint, None);
+    }
+
+    #[test]
+    fn gpg_no_status() {
+        assert_eq!(
+            parse_gpg_status("gpg: can't open 'sig'"),
+            SignatureVerification::unverifiable()
+        );
+    }
+
+    #[test]
+    fn ssh_fingerprint() {
+        assert_eq!(
+            parse_ssh_fingerprint(
+                "Good \"git\" signature for jane@example.com with ED25519 key SHA256:cGVyaGFwcw\n"
+            ),
+            Some("SHA256:cGVyaGFwcw".into())
+        );
+        assert_eq!(parse_ssh_fingerprint(""), None);
+    }
+}
@@ -0,0 +1,54 @@
+use gitbutler_repo::RepositoryExt as _;
+use gitbutler_testsupport::testing_repository::TestingRepository;
+
+#[test]
+fn writes_to_the_object_database_of_the_repository() {
+    let test_repository = TestingRepository::open();
+    let base = test_repository.commit_tree(None, &[("foo.txt", "foo")]);
+
+    let in_memory = test_repository.repository.in_memory_repo().unwrap();
+    let parent = in_memory.find_commit(base.id()).unwrap();
+    let (author, committer) = in_memory.signatures().unwrap();
+    let commit_oid = in_memory
+        .commit_with_signature(
+            None,
+            &author,
+            &committer,
+            "in memory",
+            &parent.tree().unwrap(),
+            &[&parent],
+            None,
+        )
+        .unwrap();
+
+    let commit = in_memory.find_commit(commit_oid).unwrap();
+    assert_eq!(commit.message(), Some("in memory"));
+    assert_eq!(commit.parent_id(0).unwrap(), base.id());
+    assert!(
+        test_repository.repository.find_commit(commit_oid).is_err(),
+        "the commit stays in memory and never reaches the object directory"
+    );
+}
+
+#[test]
+fn updates_the_given_reference() {
+    let test_repository = TestingRepository::open();
+    let base = test_repository.commit_tree(None, &[("foo.txt", "foo")]);
+
+    let repository = &test_repository.repository;
+    let (author, committer) = repository.signatures().unwrap();
+    let commit_oid = repository
+        .commit_with_signature(
+            Some(&"refs/heads/signed".parse().unwrap()),
+            &author,
+            &committer,
+            "on disk",
+            &base.tree().unwrap(),
+            &[&base],
+            None,
+        )
+        .unwrap();
+
+    let reference = repository.find_reference("refs/heads/signed").unwrap();
+    assert_eq!(reference.target(), Some(commit_oid));
+}
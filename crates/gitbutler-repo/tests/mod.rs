@@ -1,3 +1,4 @@
+mod commit_with_signature;
 mod create_wd_tree;
 mod credentials;
 mod merge_base_octopussy;
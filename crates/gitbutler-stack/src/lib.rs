@@ -8,7 +8,9 @@ mod target;
 pub use file_ownership::OwnershipClaim;
 pub use ownership::{reconcile_claims, BranchOwnershipClaims, ClaimOutcome};
 pub use stack::{Stack, StackId};
-pub use state::{VirtualBranches as VirtualBranchesState, VirtualBranchesHandle};
+pub use state::{
+    VirtualBranches as VirtualBranchesState, VirtualBranchesHandle, VIRTUAL_BRANCHES_VERSION,
+};
 pub use target::Target;
 
 mod heads;
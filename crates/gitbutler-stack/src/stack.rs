@@ -13,6 +13,7 @@ use gitbutler_commit::commit_ext::CommitVecExt;
 use gitbutler_id::id::Id;
 use gitbutler_patch_reference::ForgeIdentifier;
 use gitbutler_patch_reference::{CommitOrChangeId, PatchReference};
+use gitbutler_project::Transaction;
 use gitbutler_reference::{normalize_branch_name, Refname, RemoteRefname, VirtualRefname};
 use gitbutler_repo::{LogUntil, RepositoryExt};
 use gix::validate::reference::name_partial;
@@ -417,6 +418,28 @@ impl Stack {
         ctx: &CommandContext,
         commit_id: git2::Oid,
         tree: Option<git2::Oid>,
+    ) -> Result<()> {
+        self.update_stack_head(ctx, commit_id, tree)?;
+        branch_state(ctx).set_branch(self.clone())
+    }
+
+    /// Like [`Self::set_stack_head()`], but persists the stack only once `transaction` is committed.
+    pub fn set_stack_head_in(
+        &mut self,
+        ctx: &CommandContext,
+        transaction: &mut Transaction,
+        commit_id: git2::Oid,
+        tree: Option<git2::Oid>,
+    ) -> Result<()> {
+        self.update_stack_head(ctx, commit_id, tree)?;
+        branch_state(ctx).set_branch_in(transaction, self.clone())
+    }
+
+    fn update_stack_head(
+        &mut self,
+        ctx: &CommandContext,
+        commit_id: git2::Oid,
+        tree: Option<git2::Oid>,
     ) -> Result<()> {
         if !self.initialized() {
             return Err(anyhow!("Stack has not been initialized"));
@@ -437,8 +460,7 @@ impl Stack {
             .last_mut()
             .ok_or_else(|| anyhow!("Invalid state: no heads found"))?;
         head.target = commit.into();
-        validate_target(head, ctx.repository(), stack_head, &state)?;
-        state.set_branch(self.clone())
+        validate_target(head, ctx.repository(), stack_head, &state)
     }
 
     /// Removes any heads that are refering to commits that are no longer between the stack head and the merge base
@@ -457,6 +479,64 @@ impl Stack {
         state.set_branch(self.clone())
     }
 
+    /// Returns the names of the heads that don't point to a commit between the stack head and the merge base,
+    /// along with the top-most head if it doesn't point to the stack head. Archived heads are not checked.
+    pub fn invalid_heads(&self, ctx: &CommandContext) -> Result<Vec<String>> {
+        if !self.initialized() {
+            return Err(anyhow!("Stack has not been initialized"));
+        }
+        let repo = ctx.repository();
+        let stack_head = self.head();
+        let default_target = branch_state(ctx).get_default_target()?;
+        let merge_base = repo.merge_base(stack_head, default_target.sha)?;
+        let mut stack_commits = repo
+            .log(stack_head, LogUntil::Commit(merge_base), false)?
+            .iter()
+            .map(|c| c.id())
+            .collect_vec();
+        stack_commits.push(merge_base);
+
+        let top = self.heads.len() - 1;
+        let mut invalid = vec![];
+        for (idx, head) in self.heads.iter().enumerate() {
+            if head.archived && idx != top {
+                continue;
+            }
+            let commit_id = commit_by_oid_or_change_id(&head.target, repo, stack_head, merge_base)
+                .ok()
+                .map(|commits| commits.head.id());
+            let is_valid = match commit_id {
+                Some(commit_id) if idx == top => commit_id == stack_head,
+                Some(commit_id) => stack_commits.contains(&commit_id),
+                None => false,
+            };
+            if !is_valid {
+                invalid.push(head.name.clone());
+            }
+        }
+        Ok(invalid)
+    }
+
+    /// Retargets the [invalid heads](Self::invalid_heads()) so the stack is consistent again.
+    /// The top-most head is pointed at the stack head, and every other head at the target of the
+    /// next valid head above it, which leaves its series empty but keeps all commits in the stack.
+    pub fn repair_heads(&mut self, ctx: &CommandContext) -> Result<()> {
+        let invalid = self.invalid_heads(ctx)?;
+        if invalid.is_empty() {
+            return Ok(());
+        }
+        let mut target_above: CommitOrChangeId = ctx.repository().find_commit(self.head())?.into();
+        for head in self.heads.iter_mut().rev() {
+            if invalid.contains(&head.name) {
+                head.target = target_above.clone();
+            } else if !head.archived {
+                target_above = head.target.clone();
+            }
+        }
+        self.updated_timestamp_ms = gitbutler_time::time::now_ms();
+        branch_state(ctx).set_branch(self.clone())
+    }
+
     /// Prepares push details according to the series to be pushed (picking out the correct sha and remote refname)
     /// This operation will error out if the target has no push remote configured.
     pub fn push_details(&self, ctx: &CommandContext, branch_name: String) -> Result<PushDetails> {
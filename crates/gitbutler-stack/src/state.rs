@@ -3,10 +3,10 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use git2::Repository;
-use gitbutler_error::error::Code;
-use gitbutler_fs::read_toml_file_or_default;
+use gitbutler_error::error::{self, Code};
+use gitbutler_project::{Journal, Transaction};
 // use gitbutler_project::Project;
 use gitbutler_reference::Refname;
 use itertools::Itertools;
@@ -17,9 +17,50 @@ use crate::{
     target::Target,
 };
 
+/// The version of the format of `virtual_branches.toml` written by this version of the app.
+///
+/// Whenever the format changes in a way that older versions can't read, or in which they would lose
+/// information, add a migration to [`MIGRATIONS`], which increments this version.
+pub const VIRTUAL_BRANCHES_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// A migration of the raw contents of `virtual_branches.toml` to the next version of its format.
+type Migration = fn(&mut toml::Table) -> Result<()>;
+
+/// The migration at index `n` upgrades a file of version `n` to version `n + 1`.
+const MIGRATIONS: &[Migration] = &[explicit_workspace_membership];
+
+/// Files written before the format was versioned rely on defaults for whether branches are in the workspace
+/// and may be rebased, and may still carry the `applied` flag that preceded `in_workspace`.
+fn explicit_workspace_membership(virtual_branches: &mut toml::Table) -> Result<()> {
+    let Some(branches) = virtual_branches.get_mut("branches") else {
+        return Ok(());
+    };
+    let branches = branches
+        .as_table_mut()
+        .ok_or_else(|| anyhow!("'branches' is not a table"))?;
+    for (id, branch) in branches.iter_mut() {
+        let branch = branch
+            .as_table_mut()
+            .ok_or_else(|| anyhow!("branch {id} is not a table"))?;
+        let applied = branch.remove("applied");
+        if !branch.contains_key("in_workspace") {
+            let in_workspace = applied.unwrap_or(toml::Value::Boolean(true));
+            branch.insert("in_workspace".into(), in_workspace);
+        }
+        if !branch.contains_key("allow_rebasing") {
+            branch.insert("allow_rebasing".into(), toml::Value::Boolean(true));
+        }
+    }
+    Ok(())
+}
+
 /// The state of virtual branches data, as persisted in a TOML file.
-#[derive(Serialize, Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct VirtualBranches {
+    /// The version of the format of the file, which is [`VIRTUAL_BRANCHES_VERSION`] once it was read.
+    /// Files without it predate versioning, and are version 0.
+    #[serde(default)]
+    version: u32,
     /// This is the target/base that is set when a repo is added to gb
     default_target: Option<Target>,
     /// The targets for each virtual branch
@@ -28,7 +69,26 @@ pub struct VirtualBranches {
     branches: HashMap<StackId, Stack>,
 }
 
+impl Default for VirtualBranches {
+    fn default() -> Self {
+        VirtualBranches {
+            version: VIRTUAL_BRANCHES_VERSION,
+            default_target: None,
+            branch_targets: HashMap::new(),
+            branches: HashMap::new(),
+        }
+    }
+}
+
 impl VirtualBranches {
+    /// Parses the `contents` of a `virtual_branches.toml` file, migrating it to the current version of the format.
+    ///
+    /// Errors if the file was written by a newer version of the app, as it may contain state that this version
+    /// would drop when writing it back.
+    pub fn from_toml(contents: &str) -> Result<Self> {
+        parse_and_migrate(contents).map(|(virtual_branches, _version)| virtual_branches)
+    }
+
     /// Lists all virtual branches that are in the user's workspace.
     ///
     /// Errors if the file cannot be read or written.
@@ -105,6 +165,17 @@ impl VirtualBranchesHandle {
         Ok(())
     }
 
+    /// Sets the state of the given virtual branch once `transaction` is committed, so it changes along with
+    /// the other files and references of the transaction.
+    ///
+    /// Errors if the file cannot be read.
+    pub fn set_branch_in(&self, transaction: &mut Transaction, branch: Stack) -> Result<()> {
+        let mut virtual_branches = self.read_file()?;
+        virtual_branches.branches.insert(branch.id, branch);
+        transaction.write_file(self.file_path.clone(), toml::to_string(&virtual_branches)?);
+        Ok(())
+    }
+
     /// Marks a particular branch as not in the workspace
     ///
     /// Errors if the file cannot be read or written.
@@ -183,11 +254,72 @@ impl VirtualBranchesHandle {
         })
     }
 
-    /// Reads and parses the state file.
+    /// Reads and parses the state file, migrating it to the current version of the format if needed.
+    /// The file as it was before the migration is kept next to it, e.g. as `virtual_branches.v0.toml`,
+    /// and both are written through the journal of the project.
     ///
     /// If the file does not exist, it will be created.
     fn read_file(&self) -> Result<VirtualBranches> {
-        read_toml_file_or_default(&self.file_path)
+        let contents = match std::fs::read_to_string(&self.file_path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(VirtualBranches::default())
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let (virtual_branches, version) = parse_and_migrate(&contents)
+            .with_context(|| format!("Failed to read {}", self.file_path.display()))?;
+        if version < VIRTUAL_BRANCHES_VERSION {
+            let backup_path = self.unused_backup_path(version);
+            self.migrate(&backup_path, contents, &virtual_branches)
+                .with_context(|| format!("Failed to migrate {}", self.file_path.display()))?;
+            tracing::info!(
+                from = version,
+                to = VIRTUAL_BRANCHES_VERSION,
+                backup = %backup_path.display(),
+                "migrated virtual branches state"
+            );
+        }
+        Ok(virtual_branches)
+    }
+
+    /// Back up the state file with its original `contents` to `backup_path` and replace it with the migrated
+    /// `virtual_branches` in one transaction of the journal of the project, so the backup and the migration
+    /// happen together or not at all, and don't interleave with other transactions.
+    fn migrate(
+        &self,
+        backup_path: &Path,
+        contents: String,
+        virtual_branches: &VirtualBranches,
+    ) -> Result<()> {
+        let gb_dir = self
+            .file_path
+            .parent()
+            .context("the state file is always in the GitButler directory")?;
+        let git_dir = gb_dir
+            .parent()
+            .context("the GitButler directory is always in the git directory")?;
+        let mut transaction = Journal::new(gb_dir).transaction();
+        transaction
+            .write_file(backup_path, contents)
+            .write_file(self.file_path.clone(), toml::to_string(virtual_branches)?);
+        transaction.commit(&Repository::open(git_dir)?)
+    }
+
+    /// Returns the path to back up the state file of `version` to, like `virtual_branches.v0.toml`, or
+    /// `virtual_branches.v0.1.toml` if a backup of this version was made before, e.g. prior to a downgrade.
+    fn unused_backup_path(&self, version: u32) -> PathBuf {
+        let mut backup_path = self
+            .file_path
+            .with_file_name(format!("virtual_branches.v{version}.toml"));
+        let mut attempt = 0;
+        while backup_path.exists() {
+            attempt += 1;
+            backup_path = self
+                .file_path
+                .with_file_name(format!("virtual_branches.v{version}.{attempt}.toml"));
+        }
+        backup_path
     }
 
     fn write_file(&self, virtual_branches: &VirtualBranches) -> Result<()> {
@@ -277,3 +409,44 @@ impl VirtualBranchesHandle {
 fn write<P: AsRef<Path>>(file_path: P, virtual_branches: &VirtualBranches) -> Result<()> {
     gitbutler_fs::write(file_path, toml::to_string(&virtual_branches)?)
 }
+
+/// Returns the version of the format of the parsed `virtual_branches.toml` file in `table`, failing if
+/// it's newer than this version of the app understands.
+fn file_version(table: &toml::Table) -> Result<u32> {
+    let version = match table.get("version") {
+        None => 0,
+        Some(version) => version
+            .as_integer()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| anyhow!("invalid version of virtual branches state: {version}"))?,
+    };
+    if version > VIRTUAL_BRANCHES_VERSION {
+        let message = "The virtual branches of this project were last changed by a newer version of GitButler. \
+                       Please update GitButler to continue working with them.";
+        return Err(anyhow!(
+            "virtual branches state has version {version}, but only versions up to {VIRTUAL_BRANCHES_VERSION} are supported"
+        ))
+        .context(error::Context::new(message).with_code(Code::NewerStateVersion));
+    }
+    Ok(version)
+}
+
+/// Parses the `contents` of a `virtual_branches.toml` file and migrates them to the current version of the format.
+/// Returns the result along with the version the `contents` were written with.
+fn parse_and_migrate(contents: &str) -> Result<(VirtualBranches, u32)> {
+    let mut table: toml::Table = toml::from_str(contents)?;
+    let version = file_version(&table)?;
+    if version < VIRTUAL_BRANCHES_VERSION {
+        migrate(&mut table, version)?;
+    }
+    Ok((toml::Value::Table(table).try_into()?, version))
+}
+
+/// Runs all migrations of the parsed `virtual_branches.toml` file in `table` from `version` to the current one, in order.
+fn migrate(table: &mut toml::Table, version: u32) -> Result<()> {
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        migration(table).with_context(|| format!("migration from version {from} failed"))?;
+        table.insert("version".into(), toml::Value::Integer(from as i64 + 1));
+    }
+    Ok(())
+}
@@ -1,5 +1,6 @@
 pub mod file_ownership;
 pub mod ownership;
+pub mod state;
 
 use anyhow::Result;
 use gitbutler_command_context::CommandContext;
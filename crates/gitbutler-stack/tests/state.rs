@@ -0,0 +1,164 @@
+use std::fs;
+
+use gitbutler_error::error::{AnyhowContextExt, Code};
+use gitbutler_project::Journal;
+use gitbutler_stack::{StackId, VirtualBranchesHandle, VIRTUAL_BRANCHES_VERSION};
+
+const UNAPPLIED_ID: &str = "2c8a0e0e-6a0f-4d7e-9c3b-5e0f6a1b2c3d";
+const APPLIED_ID: &str = "7f1e4b2a-3c5d-4e6f-8a9b-0c1d2e3f4a5b";
+
+/// Return a temporary repository and its GitButler directory, as the state is migrated through its journal.
+fn gb_dir() -> anyhow::Result<(tempfile::TempDir, std::path::PathBuf)> {
+    let tmp = tempfile::tempdir()?;
+    let gb_dir = git2::Repository::init(tmp.path())?.path().join("gitbutler");
+    fs::create_dir_all(&gb_dir)?;
+    Ok((tmp, gb_dir))
+}
+
+fn legacy_branch(id: &str, extra: &str) -> String {
+    format!(
+        r#"
+[branches.{id}]
+id = "{id}"
+name = "branch {id}"
+notes = ""
+created_timestamp_ms = "0"
+updated_timestamp_ms = "0"
+tree = "4b825dc642cb6eb9a060e54bf8d69288fbee4904"
+head = "4b825dc642cb6eb9a060e54bf8d69288fbee4904"
+ownership = ""
+order = 0
+{extra}
+"#
+    )
+}
+
+#[test]
+fn unversioned_file_is_migrated_and_backed_up() -> anyhow::Result<()> {
+    let (_tmp, gb_dir) = gb_dir()?;
+    let legacy = format!(
+        "{}{}",
+        legacy_branch(UNAPPLIED_ID, "applied = false"),
+        legacy_branch(APPLIED_ID, "")
+    );
+    let file_path = gb_dir.join("virtual_branches.toml");
+    fs::write(&file_path, &legacy)?;
+
+    let handle = VirtualBranchesHandle::new(&gb_dir);
+    let unapplied = handle.get_branch(UNAPPLIED_ID.parse::<StackId>()?)?;
+    assert!(
+        !unapplied.in_workspace,
+        "the legacy `applied` flag is carried over"
+    );
+    let applied = handle.get_branch(APPLIED_ID.parse::<StackId>()?)?;
+    assert!(
+        applied.in_workspace,
+        "branches used to be applied by default"
+    );
+    assert!(applied.allow_rebasing);
+
+    assert_eq!(
+        fs::read_to_string(gb_dir.join("virtual_branches.v0.toml"))?,
+        legacy,
+        "the file is backed up as it was before the migration"
+    );
+    let migrated = fs::read_to_string(&file_path)?;
+    assert!(migrated.starts_with(&format!("version = {VIRTUAL_BRANCHES_VERSION}\n")));
+    assert!(!migrated.contains("applied = false"));
+
+    handle.list_all_branches()?;
+    assert_eq!(
+        fs::read_dir(&gb_dir)?
+            .filter_map(Result::ok)
+            .filter(|entry| entry.path().extension() == Some("toml".as_ref()))
+            .count(),
+        2,
+        "migrated files are read as they are"
+    );
+    Ok(())
+}
+
+#[test]
+fn earlier_backups_are_kept() -> anyhow::Result<()> {
+    let (_tmp, gb_dir) = gb_dir()?;
+    let file_path = gb_dir.join("virtual_branches.toml");
+    let first = legacy_branch(APPLIED_ID, "");
+    fs::write(&file_path, &first)?;
+    let handle = VirtualBranchesHandle::new(&gb_dir);
+    handle.list_all_branches()?;
+
+    // An older version of the app may write the old format again.
+    let second = legacy_branch(UNAPPLIED_ID, "applied = false");
+    fs::write(&file_path, &second)?;
+    handle.list_all_branches()?;
+
+    assert_eq!(
+        fs::read_to_string(gb_dir.join("virtual_branches.v0.toml"))?,
+        first,
+        "the first backup isn't overwritten"
+    );
+    assert_eq!(
+        fs::read_to_string(gb_dir.join("virtual_branches.v0.1.toml"))?,
+        second
+    );
+    Ok(())
+}
+
+#[test]
+fn file_of_newer_version_is_refused() -> anyhow::Result<()> {
+    let tmp = tempfile::tempdir()?;
+    let newer = format!(
+        "version = {}\n{}",
+        VIRTUAL_BRANCHES_VERSION + 1,
+        legacy_branch(APPLIED_ID, "in_workspace = true")
+    );
+    let file_path = tmp.path().join("virtual_branches.toml");
+    fs::write(&file_path, &newer)?;
+
+    let err = VirtualBranchesHandle::new(tmp.path())
+        .list_all_branches()
+        .unwrap_err();
+    assert_eq!(
+        err.custom_context().map(|ctx| ctx.code),
+        Some(Code::NewerStateVersion)
+    );
+    assert_eq!(
+        fs::read_to_string(&file_path)?,
+        newer,
+        "the file is left untouched"
+    );
+    Ok(())
+}
+
+#[test]
+fn branches_set_in_a_transaction_are_written_when_it_is_committed() -> anyhow::Result<()> {
+    let tmp = tempfile::tempdir()?;
+    let repo = git2::Repository::init(tmp.path())?;
+    let gb_dir = repo.path().join("gitbutler");
+    fs::create_dir_all(&gb_dir)?;
+    let file_path = gb_dir.join("virtual_branches.toml");
+    fs::write(
+        &file_path,
+        format!(
+            "version = {VIRTUAL_BRANCHES_VERSION}\n{}",
+            legacy_branch(APPLIED_ID, "in_workspace = true\nallow_rebasing = true")
+        ),
+    )?;
+    let handle = VirtualBranchesHandle::new(&gb_dir);
+    let id = APPLIED_ID.parse::<StackId>()?;
+    let mut branch = handle.get_branch(id)?;
+    branch.name = "renamed".into();
+
+    let before = fs::read_to_string(&file_path)?;
+    let mut transaction = Journal::new(&gb_dir).transaction();
+    handle.set_branch_in(&mut transaction, branch)?;
+    assert_eq!(
+        fs::read_to_string(&file_path)?,
+        before,
+        "nothing is written before the transaction is committed"
+    );
+
+    transaction.commit(&repo)?;
+    assert_eq!(handle.get_branch(id)?.name, "renamed");
+    Ok(())
+}
@@ -104,6 +104,11 @@ fn main() {
                     };
                     app_handle.manage(app.users());
                     app_handle.manage(app.projects());
+                    app_handle.manage(std::sync::Arc::new(
+                        gitbutler_watcher::stream::EventStream::new(event_stream_sinks(
+                            &app_data_dir,
+                        )),
+                    ));
 
                     app_handle.manage(gitbutler_feedback::Archival {
                         cache_dir: app_cache_dir,
@@ -150,6 +155,7 @@ fn main() {
                     projects::commands::delete_project,
                     projects::commands::list_projects,
                     projects::commands::set_project_active,
+                    projects::commands::get_watcher_metrics,
                     projects::commands::open_project_in_window,
                     projects::commands::update_project_git_host,
                     repo::commands::git_get_local_config,
@@ -179,6 +185,7 @@ fn main() {
                     virtual_branches::commands::reset_virtual_branch,
                     virtual_branches::commands::amend_virtual_branch,
                     virtual_branches::commands::move_commit_file,
+                    virtual_branches::commands::move_commit_hunks,
                     virtual_branches::commands::undo_commit,
                     virtual_branches::commands::insert_blank_commit,
                     virtual_branches::commands::reorder_commit,
@@ -189,10 +196,13 @@ fn main() {
                     virtual_branches::commands::get_branch_listing_details,
                     virtual_branches::commands::get_remote_branch_data,
                     virtual_branches::commands::squash_branch_commit,
+                    virtual_branches::commands::autosquash_branch_commits,
+                    virtual_branches::commands::split_commit,
                     virtual_branches::commands::fetch_from_remotes,
                     virtual_branches::commands::move_commit,
                     virtual_branches::commands::normalize_branch_name,
                     virtual_branches::commands::upstream_integration_statuses,
+                    virtual_branches::commands::upstream_integration_preview,
                     virtual_branches::commands::integrate_upstream,
                     virtual_branches::commands::resolve_upstream_integration,
                     virtual_branches::commands::find_commit,
@@ -274,3 +284,18 @@ fn main() {
                 });
         });
 }
+
+/// Stream watcher events to a socket in the app data directory for status-line integrations and other
+/// local tools to consume.
+fn event_stream_sinks(
+    app_data_dir: &std::path::Path,
+) -> Vec<Box<dyn gitbutler_watcher::stream::StreamSink>> {
+    #[cfg(unix)]
+    match gitbutler_watcher::stream::SocketSink::bind(app_data_dir.join("events.sock")) {
+        Ok(sink) => return vec![Box::new(sink)],
+        Err(err) => tracing::warn!(?err, "failed to set up the event stream socket"),
+    }
+    #[cfg(not(unix))]
+    let _ = app_data_dir;
+    Vec::new()
+}
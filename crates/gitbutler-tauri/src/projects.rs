@@ -97,6 +97,17 @@ pub mod commands {
         Ok(window_state.set_project_to_window(window.label(), &project)?)
     }
 
+    /// Return how many filesystem events the watcher of the project with `id` handled and how long that took,
+    /// or `None` if the project isn't open.
+    #[tauri::command(async)]
+    #[instrument(skip(window_state), err(Debug))]
+    pub fn get_watcher_metrics(
+        window_state: State<'_, WindowState>,
+        id: ProjectId,
+    ) -> Result<Option<gitbutler_watcher::WatcherMetrics>, Error> {
+        Ok(window_state.watcher_metrics(id))
+    }
+
     /// Open the project with the given ID in a new Window, or focus an existing one.
     ///
     /// Note that this command is blocking the main thread just to prevent the chance for races
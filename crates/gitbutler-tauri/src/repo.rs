@@ -5,7 +5,7 @@ pub mod commands {
     use gitbutler_branch_actions::RemoteBranchFile;
     use gitbutler_project as projects;
     use gitbutler_project::ProjectId;
-    use gitbutler_repo::{FileInfo, RepoCommands};
+    use gitbutler_repo::{FileInfo, RepoCommands, SigningSettings};
     use std::path::Path;
     use std::sync::atomic::AtomicBool;
     use tauri::State;
@@ -39,7 +39,7 @@ pub mod commands {
     pub fn check_signing_settings(
         projects: State<'_, projects::Controller>,
         id: ProjectId,
-    ) -> Result<bool, Error> {
+    ) -> Result<SigningSettings, Error> {
         let project = projects.get(id)?;
         project.check_signing_settings().map_err(Into::into)
     }
@@ -3,11 +3,13 @@ pub mod commands {
     use gitbutler_branch::{BranchCreateRequest, BranchUpdateRequest};
     use gitbutler_branch_actions::internal::PushResult;
     use gitbutler_branch_actions::upstream_integration::{
-        BaseBranchResolution, BaseBranchResolutionApproach, BranchStatuses, Resolution,
+        BaseBranchResolution, BaseBranchResolutionApproach, BranchIntegrationPreview,
+        BranchStatuses, Resolution, ResolutionApproach,
     };
     use gitbutler_branch_actions::{
-        BaseBranch, BranchListing, BranchListingDetails, BranchListingFilter, RemoteBranch,
-        RemoteBranchData, RemoteBranchFile, RemoteCommit, StackOrder, VirtualBranches,
+        BaseBranch, BranchListing, BranchListingDetails, BranchListingFilter, CommitSplit,
+        RemoteBranch, RemoteBranchData, RemoteBranchFile, RemoteCommit, StackOrder,
+        VirtualBranches,
     };
     use gitbutler_command_context::CommandContext;
     use gitbutler_patch_reference::ForgeIdentifier;
@@ -367,6 +369,31 @@ pub mod commands {
         Ok(oid.to_string())
     }
 
+    #[tauri::command(async)]
+    #[instrument(skip(projects, windows), err(Debug))]
+    pub fn move_commit_hunks(
+        windows: State<'_, WindowState>,
+        projects: State<'_, projects::Controller>,
+        project_id: ProjectId,
+        branch_id: StackId,
+        from_commit_oid: String,
+        to_commit_oid: String,
+        ownership: BranchOwnershipClaims,
+    ) -> Result<String, Error> {
+        let project = projects.get(project_id)?;
+        let from_commit_oid = git2::Oid::from_str(&from_commit_oid).map_err(|e| anyhow!(e))?;
+        let to_commit_oid = git2::Oid::from_str(&to_commit_oid).map_err(|e| anyhow!(e))?;
+        let oid = gitbutler_branch_actions::move_commit_hunks(
+            &project,
+            branch_id,
+            from_commit_oid,
+            to_commit_oid,
+            &ownership,
+        )?;
+        emit_vbranches(&windows, project_id);
+        Ok(oid.to_string())
+    }
+
     #[tauri::command(async)]
     #[instrument(skip(projects, windows), err(Debug))]
     pub fn undo_commit(
@@ -494,6 +521,38 @@ pub mod commands {
         Ok(())
     }
 
+    #[tauri::command(async)]
+    #[instrument(skip(projects, windows), err(Debug))]
+    pub fn autosquash_branch_commits(
+        windows: State<'_, WindowState>,
+        projects: State<'_, projects::Controller>,
+        project_id: ProjectId,
+        branch_id: StackId,
+    ) -> Result<(), Error> {
+        let project = projects.get(project_id)?;
+        gitbutler_branch_actions::autosquash(&project, branch_id)?;
+        emit_vbranches(&windows, project_id);
+        Ok(())
+    }
+
+    #[tauri::command(async)]
+    #[instrument(skip(projects, windows), err(Debug))]
+    pub fn split_commit(
+        windows: State<'_, WindowState>,
+        projects: State<'_, projects::Controller>,
+        project_id: ProjectId,
+        branch_id: StackId,
+        commit_oid: String,
+        splits: Vec<CommitSplit>,
+    ) -> Result<Vec<String>, Error> {
+        let project = projects.get(project_id)?;
+        let commit_oid = git2::Oid::from_str(&commit_oid).map_err(|e| anyhow!(e))?;
+        let oids =
+            gitbutler_branch_actions::split_commit(&project, branch_id, commit_oid, &splits)?;
+        emit_vbranches(&windows, project_id);
+        Ok(oids.iter().map(ToString::to_string).collect())
+    }
+
     #[tauri::command(async)]
     #[instrument(skip(projects, windows), err(Debug))]
     pub fn fetch_from_remotes(
@@ -596,6 +655,22 @@ pub mod commands {
         )?)
     }
 
+    #[tauri::command(async)]
+    #[instrument(skip(projects), err(Debug))]
+    pub fn upstream_integration_preview(
+        projects: State<'_, projects::Controller>,
+        project_id: ProjectId,
+        approach: ResolutionApproach,
+        base_branch_resolution: Option<BaseBranchResolution>,
+    ) -> Result<Vec<BranchIntegrationPreview>, Error> {
+        let project = projects.get(project_id)?;
+        Ok(gitbutler_branch_actions::upstream_integration_preview(
+            &project,
+            approach,
+            base_branch_resolution,
+        )?)
+    }
+
     #[tauri::command(async)]
     #[instrument(skip(projects, windows), err(Debug))]
     pub fn integrate_upstream(
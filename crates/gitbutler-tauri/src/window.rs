@@ -44,6 +44,55 @@ pub(super) mod state {
                         payload: serde_json::json!({}),
                         project_id,
                     },
+                    Change::GitRemoteRefsUpdated {
+                        project_id,
+                        refnames,
+                    } => ChangeForFrontend {
+                        name: format!("project://{}/git/remote-refs", project_id),
+                        payload: serde_json::json!({ "refnames": refnames }),
+                        project_id,
+                    },
+                    Change::GitTargetBranchMoved {
+                        project_id,
+                        refname,
+                        sha,
+                    } => ChangeForFrontend {
+                        name: format!("project://{}/git/target-moved", project_id),
+                        payload: serde_json::json!({ "refname": refname, "sha": sha }),
+                        project_id,
+                    },
+                    Change::GitLocalBranchesCreated {
+                        project_id,
+                        refnames,
+                    } => ChangeForFrontend {
+                        name: format!("project://{}/git/local-branches-created", project_id),
+                        payload: serde_json::json!({ "refnames": refnames }),
+                        project_id,
+                    },
+                    Change::GitLocalBranchesDeleted {
+                        project_id,
+                        refnames,
+                    } => ChangeForFrontend {
+                        name: format!("project://{}/git/local-branches-deleted", project_id),
+                        payload: serde_json::json!({ "refnames": refnames }),
+                        project_id,
+                    },
+                    Change::GitLocalBranchesUpdated {
+                        project_id,
+                        refnames,
+                    } => ChangeForFrontend {
+                        name: format!("project://{}/git/local-branches-updated", project_id),
+                        payload: serde_json::json!({ "refnames": refnames }),
+                        project_id,
+                    },
+                    Change::GitTagsUpdated {
+                        project_id,
+                        refnames,
+                    } => ChangeForFrontend {
+                        name: format!("project://{}/git/tags", project_id),
+                        payload: serde_json::json!({ "refnames": refnames }),
+                        project_id,
+                    },
                     Change::VirtualBranches {
                         project_id,
                         virtual_branches,
@@ -57,6 +106,15 @@ pub(super) mod state {
                         payload: serde_json::json!(files),
                         project_id,
                     },
+                    Change::UncommitedFilesDelta {
+                        project_id,
+                        changed,
+                        removed,
+                    } => ChangeForFrontend {
+                        name: format!("project://{}/uncommited-files-delta", project_id),
+                        payload: serde_json::json!({ "changed": changed, "removed": removed }),
+                        project_id,
+                    },
                 }
             }
         }
@@ -107,10 +165,17 @@ pub(super) mod state {
     fn handler_from_app(app: &AppHandle) -> Result<gitbutler_watcher::Handler> {
         let projects = app.state::<projects::Controller>().inner().clone();
         let users = app.state::<users::Controller>().inner().clone();
+        let stream = app
+            .state::<Arc<gitbutler_watcher::stream::EventStream>>()
+            .inner()
+            .clone();
 
         Ok(gitbutler_watcher::Handler::new(projects, users, {
             let app = app.clone();
-            move |change| ChangeForFrontend::from(change).send(&app)
+            move |change| {
+                stream.send(&change);
+                ChangeForFrontend::from(change).send(&app)
+            }
         }))
     }
 
@@ -142,8 +207,12 @@ pub(super) mod state {
             let handler = handler_from_app(&self.app_handle)?;
             let worktree_dir = project.path.clone();
             let project_id = project.id;
-            let watcher =
-                gitbutler_watcher::watch_in_background(handler, worktree_dir, project_id)?;
+            let watcher = gitbutler_watcher::watch_in_background(
+                handler,
+                worktree_dir,
+                project_id,
+                project.file_watcher,
+            )?;
             state_by_label.insert(
                 window.to_owned(),
                 State {
@@ -188,6 +257,18 @@ pub(super) mod state {
             state_by_label.remove(window);
         }
 
+        /// Return the counters and timings of the watcher of the project with `project_id`, if it's open.
+        pub fn watcher_metrics(
+            &self,
+            project_id: ProjectId,
+        ) -> Option<gitbutler_watcher::WatcherMetrics> {
+            let state_by_label = self.state.lock();
+            state_by_label
+                .values()
+                .find(|state| state.project_id == project_id)
+                .map(|state| state.watcher.metrics())
+        }
+
         /// Return the list of project ids that are currently open.
         pub fn open_projects(&self) -> Vec<ProjectId> {
             let state_by_label = self.state.lock();
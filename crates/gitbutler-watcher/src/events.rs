@@ -43,6 +43,39 @@ impl From<Action> for InternalEvent {
     }
 }
 
+impl InternalEvent {
+    /// Merge `other` into this event if both are of the same kind and for the same project, so handling
+    /// this event takes care of `other` as well. Otherwise, return `other` so it can be handled separately.
+    pub(super) fn coalesce(&mut self, other: InternalEvent) -> Result<(), InternalEvent> {
+        match (self, other) {
+            (
+                InternalEvent::CalculateVirtualBranches(project_id),
+                InternalEvent::CalculateVirtualBranches(other_project_id),
+            )
+            | (
+                InternalEvent::GitButlerOplogChange(project_id),
+                InternalEvent::GitButlerOplogChange(other_project_id),
+            ) if *project_id == other_project_id => Ok(()),
+            (
+                InternalEvent::GitFilesChange(project_id, paths),
+                InternalEvent::GitFilesChange(other_project_id, other_paths),
+            )
+            | (
+                InternalEvent::ProjectFilesChange(project_id, paths),
+                InternalEvent::ProjectFilesChange(other_project_id, other_paths),
+            ) if *project_id == other_project_id => {
+                for path in other_paths {
+                    if !paths.contains(&path) {
+                        paths.push(path);
+                    }
+                }
+                Ok(())
+            }
+            (_, other) => Err(other),
+        }
+    }
+}
+
 impl Display for InternalEvent {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -97,6 +130,35 @@ pub enum Change {
         operating_mode: OperatingMode,
     },
     GitActivity(ProjectId),
+    /// Remote-tracking branches were created, moved or deleted, e.g. by a fetch or push.
+    GitRemoteRefsUpdated {
+        project_id: ProjectId,
+        refnames: Vec<String>,
+    },
+    /// The remote-tracking branch the workspace is based on moved to the commit `sha`.
+    GitTargetBranchMoved {
+        project_id: ProjectId,
+        refname: String,
+        sha: String,
+    },
+    GitLocalBranchesCreated {
+        project_id: ProjectId,
+        refnames: Vec<String>,
+    },
+    GitLocalBranchesDeleted {
+        project_id: ProjectId,
+        refnames: Vec<String>,
+    },
+    /// Local branches that existed before point to other commits now.
+    GitLocalBranchesUpdated {
+        project_id: ProjectId,
+        refnames: Vec<String>,
+    },
+    /// Tags were created, moved or deleted.
+    GitTagsUpdated {
+        project_id: ProjectId,
+        refnames: Vec<String>,
+    },
     VirtualBranches {
         project_id: ProjectId,
         virtual_branches: VirtualBranches,
@@ -105,4 +167,102 @@ pub enum Change {
         project_id: ProjectId,
         files: Vec<RemoteBranchFile>,
     },
+    /// The uncommitted files that changed since the last [`Change::UncommitedFiles`] or
+    /// [`Change::UncommitedFilesDelta`] event.
+    UncommitedFilesDelta {
+        project_id: ProjectId,
+        /// Files that are new or whose hunks changed.
+        changed: Vec<RemoteBranchFile>,
+        /// Files that don't have uncommitted changes anymore.
+        removed: Vec<PathBuf>,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(paths: &[&str]) -> Vec<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn coalesce_merges_events_of_the_same_kind_and_project() {
+        let project_id = ProjectId::generate();
+        let mut event = InternalEvent::CalculateVirtualBranches(project_id);
+        assert!(event
+            .coalesce(InternalEvent::CalculateVirtualBranches(project_id))
+            .is_ok());
+
+        let mut event = InternalEvent::GitButlerOplogChange(project_id);
+        assert!(event
+            .coalesce(InternalEvent::GitButlerOplogChange(project_id))
+            .is_ok());
+    }
+
+    #[test]
+    fn coalesce_merges_paths_without_duplicates() {
+        let project_id = ProjectId::generate();
+        let mut event = InternalEvent::ProjectFilesChange(project_id, paths(&["a", "b"]));
+        assert!(event
+            .coalesce(InternalEvent::ProjectFilesChange(
+                project_id,
+                paths(&["b", "c"])
+            ))
+            .is_ok());
+        let InternalEvent::ProjectFilesChange(_, merged) = &event else {
+            panic!("the kind of the event doesn't change");
+        };
+        assert_eq!(merged, &paths(&["a", "b", "c"]));
+
+        let mut event = InternalEvent::GitFilesChange(project_id, paths(&["HEAD"]));
+        assert!(event
+            .coalesce(InternalEvent::GitFilesChange(
+                project_id,
+                paths(&["index", "HEAD"])
+            ))
+            .is_ok());
+        let InternalEvent::GitFilesChange(_, merged) = &event else {
+            panic!("the kind of the event doesn't change");
+        };
+        assert_eq!(merged, &paths(&["HEAD", "index"]));
+    }
+
+    #[test]
+    fn coalesce_keeps_events_of_other_projects_apart() {
+        let (project_id, other_project_id) = (ProjectId::generate(), ProjectId::generate());
+        let mut event = InternalEvent::ProjectFilesChange(project_id, paths(&["a"]));
+        let not_coalesced = event
+            .coalesce(InternalEvent::ProjectFilesChange(
+                other_project_id,
+                paths(&["b"]),
+            ))
+            .unwrap_err();
+        assert!(
+            matches!(not_coalesced, InternalEvent::ProjectFilesChange(id, ref p) if id == other_project_id && p == &paths(&["b"]))
+        );
+        let InternalEvent::ProjectFilesChange(_, unchanged) = &event else {
+            panic!("the kind of the event doesn't change");
+        };
+        assert_eq!(unchanged, &paths(&["a"]));
+
+        let mut event = InternalEvent::CalculateVirtualBranches(project_id);
+        assert!(event
+            .coalesce(InternalEvent::CalculateVirtualBranches(other_project_id))
+            .is_err());
+    }
+
+    #[test]
+    fn coalesce_keeps_events_of_other_kinds_apart() {
+        let project_id = ProjectId::generate();
+        let mut event = InternalEvent::ProjectFilesChange(project_id, paths(&["a"]));
+        assert!(matches!(
+            event.coalesce(InternalEvent::GitFilesChange(project_id, paths(&["a"]))),
+            Err(InternalEvent::GitFilesChange(..))
+        ));
+        assert!(matches!(
+            event.coalesce(InternalEvent::CalculateVirtualBranches(project_id)),
+            Err(InternalEvent::CalculateVirtualBranches(_))
+        ));
+    }
 }
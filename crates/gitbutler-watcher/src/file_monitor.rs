@@ -1,14 +1,18 @@
-use std::{collections::HashSet, path::Path, time::Duration};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use anyhow::{anyhow, Context, Result};
-use gitbutler_notify_debouncer::{new_debouncer, Debouncer, NoCache};
+use gitbutler_notify_debouncer::{new_debouncer, DebounceEventResult, Debouncer, NoCache};
 use gitbutler_oplog::OPLOG_FILE_NAME;
-use gitbutler_project::ProjectId;
-use notify::{RecommendedWatcher, Watcher};
+use gitbutler_project::{FileWatcherBackend, FileWatcherSettings, ProjectId};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
 use tokio::task;
 use tracing::Level;
 
-use crate::events::InternalEvent;
+use crate::{events::InternalEvent, poll_monitor::PollMonitor};
 
 /// We will collect notifications for up to this amount of time at a very
 /// maximum before releasing them. This duration will be hit if e.g. a build
@@ -34,6 +38,24 @@ struct RunError {
     source: anyhow::Error,
 }
 
+/// The source of filesystem events, which stops producing them when dropped.
+pub enum Monitor {
+    /// Notifications by the operating system, debounced.
+    Native(Debouncer<RecommendedWatcher, NoCache>),
+    /// Periodic scans of the filesystem.
+    Polling(PollMonitor),
+}
+
+impl Monitor {
+    /// Deliver pending events as soon as possible.
+    pub fn flush_nonblocking(&self) {
+        match self {
+            Monitor::Native(debouncer) => debouncer.flush_nonblocking(),
+            Monitor::Polling(poller) => poller.flush_nonblocking(),
+        }
+    }
+}
+
 /// Listen to interesting filesystem events of files in `path` that are not `.gitignore`d,
 /// turn them into [`Events`](Event) which classifies it, and associates it with `project_id`.
 /// These are sent through the passed `out` channel, to indicate either **Git** repository changes
@@ -49,67 +71,62 @@ struct RunError {
 /// is chosen to allow all this state to live on the stack.
 ///
 /// Additionally, a channel plays better with how events are handled downstream.
+///
+/// `settings` determine whether events come from the operating system or from polling.
 pub fn spawn(
     project_id: ProjectId,
     worktree_path: &std::path::Path,
+    settings: FileWatcherSettings,
     out: tokio::sync::mpsc::UnboundedSender<InternalEvent>,
-) -> Result<Debouncer<RecommendedWatcher, NoCache>> {
+) -> Result<Monitor> {
     let (notify_tx, notify_rx) = std::sync::mpsc::channel();
-    let mut debouncer = new_debouncer(
-        DEBOUNCE_TIMEOUT,
-        Some(TICK_RATE),
-        Some(FLUSH_AFTER_EMPTY),
-        notify_tx,
-    )
-    .context("failed to create debouncer")?;
 
-    let policy = backoff::ExponentialBackoffBuilder::new()
-        .with_max_elapsed_time(Some(std::time::Duration::from_secs(30)))
-        .build();
+    let repo = gix::open_opts(worktree_path, gix::open::Options::isolated()).context(format!(
+        "failed to open project repository to obtain git-dir: {}",
+        worktree_path.display()
+    ))?;
+    let git_dir = gix::path::realpath(repo.git_dir())?;
+    // Differs from `git_dir` in linked worktrees, whose branches and tags are shared with the main worktree.
+    let common_dir = gix::path::realpath(repo.common_dir())?;
+    let mut extra_dirs_to_watch = Vec::new();
+    if git_dir.parent() != Some(worktree_path) {
+        extra_dirs_to_watch.push((git_dir.clone(), RecursiveMode::Recursive));
+    }
+    if common_dir != git_dir {
+        extra_dirs_to_watch.push((common_dir.join("refs"), RecursiveMode::Recursive));
+        extra_dirs_to_watch.push((common_dir.clone(), RecursiveMode::NonRecursive));
+    }
 
-    let git_dir = gix::open_opts(worktree_path, gix::open::Options::isolated())
-        .context(format!(
-            "failed to open project repository to obtain git-dir: {}",
-            worktree_path.display()
-        ))?
-        .path()
-        .to_owned();
-    let extra_git_dir_to_watch = {
-        let mut enclosing_worktree_dir = git_dir.clone();
-        enclosing_worktree_dir.pop();
-        if enclosing_worktree_dir != worktree_path {
-            Some(git_dir.as_path())
-        } else {
-            None
+    let monitor = match settings.backend {
+        FileWatcherBackend::Native => Monitor::Native(watch_natively(
+            worktree_path,
+            &extra_dirs_to_watch,
+            notify_tx,
+        )?),
+        FileWatcherBackend::Polling => Monitor::Polling(PollMonitor::spawn(
+            worktree_path,
+            &git_dir,
+            &common_dir,
+            settings.poll_interval(),
+            notify_tx,
+        )?),
+        FileWatcherBackend::Auto => {
+            match watch_natively(worktree_path, &extra_dirs_to_watch, notify_tx.clone()) {
+                Ok(debouncer) => Monitor::Native(debouncer),
+                Err(err) => {
+                    tracing::warn!(%project_id, ?err, "falling back to polling for file changes");
+                    Monitor::Polling(PollMonitor::spawn(
+                        worktree_path,
+                        &git_dir,
+                        &common_dir,
+                        settings.poll_interval(),
+                        notify_tx,
+                    )?)
+                }
+            }
         }
     };
 
-    // Start the watcher, but retry if there are transient errors.
-    backoff::retry(policy, || {
-        debouncer
-            .watcher()
-            .watch(worktree_path, notify::RecursiveMode::Recursive)
-            .and_then(|()| {
-                if let Some(git_dir) = extra_git_dir_to_watch {
-                    debouncer
-                        .watcher()
-                        .watch(git_dir, notify::RecursiveMode::Recursive)
-                } else {
-                    Ok(())
-                }
-            })
-            .map_err(|err| match err.kind {
-                notify::ErrorKind::PathNotFound => backoff::Error::permanent(RunError::from(
-                    anyhow!("{} not found", worktree_path.display()),
-                )),
-                notify::ErrorKind::Io(_) | notify::ErrorKind::InvalidConfig(_) => {
-                    backoff::Error::permanent(RunError::from(anyhow::Error::from(err)))
-                }
-                _ => backoff::Error::transient(RunError::from(anyhow::Error::from(err))),
-            })
-    })
-    .context("failed to start watcher")?;
-
     let worktree_path = worktree_path.to_owned();
     task::spawn_blocking(move || {
         let _runtime = tracing::span!(Level::INFO, "file monitor", %project_id ).entered();
@@ -140,7 +157,7 @@ pub fn spawn(
                         .filter(|event| is_interesting_kind(event.kind))
                         .flat_map(|event| event.event.paths)
                         .map(|file| {
-                            let kind = classify_file(&git_dir, &file);
+                            let kind = classify_file(&git_dir, &common_dir, &file);
                             (file, kind)
                         })
                         .collect();
@@ -172,19 +189,19 @@ pub fn spawn(
                             FileKind::GitButlerOplog => {
                                 oplog_changed = true;
                             }
-                            FileKind::Project | FileKind::Git => match file_path
-                                .strip_prefix(&worktree_path)
-                            {
+                            FileKind::Git => {
+                                if let Some(stripped) =
+                                    strip_git_dir(&git_dir, &common_dir, &file_path)
+                                {
+                                    stripped_git_paths.insert(stripped.to_owned());
+                                }
+                            }
+                            FileKind::Project => match file_path.strip_prefix(&worktree_path) {
                                 Ok(relative_file_path) => {
                                     if relative_file_path.as_os_str().is_empty() {
                                         continue;
                                     }
-                                    if let Ok(stripped) = relative_file_path.strip_prefix(".git") {
-                                        stripped_git_paths.insert(stripped.to_owned());
-                                    } else {
-                                        worktree_relative_paths
-                                            .insert(relative_file_path.to_owned());
-                                    };
+                                    worktree_relative_paths.insert(relative_file_path.to_owned());
                                 }
                                 Err(err) => {
                                     tracing::error!(%project_id, ?err, "failed to strip prefix");
@@ -228,6 +245,52 @@ pub fn spawn(
             }
         }
     });
+    Ok(monitor)
+}
+
+/// Watch `worktree_path` and `extra_dirs_to_watch` with the native file watcher of the operating system,
+/// sending debounced events to `notify_tx`.
+fn watch_natively(
+    worktree_path: &Path,
+    extra_dirs_to_watch: &[(PathBuf, RecursiveMode)],
+    notify_tx: std::sync::mpsc::Sender<DebounceEventResult>,
+) -> Result<Debouncer<RecommendedWatcher, NoCache>> {
+    let mut debouncer = new_debouncer(
+        DEBOUNCE_TIMEOUT,
+        Some(TICK_RATE),
+        Some(FLUSH_AFTER_EMPTY),
+        notify_tx,
+    )
+    .context("failed to create debouncer")?;
+
+    let policy = backoff::ExponentialBackoffBuilder::new()
+        .with_max_elapsed_time(Some(std::time::Duration::from_secs(30)))
+        .build();
+
+    // Start the watcher, but retry if there are transient errors.
+    backoff::retry(policy, || {
+        debouncer
+            .watcher()
+            .watch(worktree_path, RecursiveMode::Recursive)
+            .and_then(|()| {
+                extra_dirs_to_watch
+                    .iter()
+                    .try_for_each(|(dir, mode)| debouncer.watcher().watch(dir, *mode))
+            })
+            .map_err(|err| match err.kind {
+                notify::ErrorKind::PathNotFound => backoff::Error::permanent(RunError::from(
+                    anyhow!("{} not found", worktree_path.display()),
+                )),
+                // Running out of watches won't resolve itself by retrying.
+                notify::ErrorKind::Io(_)
+                | notify::ErrorKind::InvalidConfig(_)
+                | notify::ErrorKind::MaxFilesWatch => {
+                    backoff::Error::permanent(RunError::from(anyhow::Error::from(err)))
+                }
+                _ => backoff::Error::transient(RunError::from(anyhow::Error::from(err))),
+            })
+    })
+    .context("failed to start watcher")?;
     Ok(debouncer)
 }
 
@@ -265,13 +328,15 @@ enum FileKind {
     GitButlerOplog,
 }
 
-fn classify_file(git_dir: &Path, file_path: &Path) -> FileKind {
+fn classify_file(git_dir: &Path, common_dir: &Path, file_path: &Path) -> FileKind {
     if let Ok(check_file_path) = file_path.strip_prefix(git_dir) {
         if check_file_path == Path::new("FETCH_HEAD")
             || check_file_path == Path::new("logs/HEAD")
             || check_file_path == Path::new("HEAD")
             || check_file_path == Path::new("GB_FLUSH")
             || check_file_path == Path::new("index")
+            || check_file_path == Path::new("packed-refs")
+            || is_interesting_ref(check_file_path)
         {
             FileKind::Git
         } else if check_file_path == Path::new("gitbutler").join(OPLOG_FILE_NAME) {
@@ -279,7 +344,33 @@ fn classify_file(git_dir: &Path, file_path: &Path) -> FileKind {
         } else {
             FileKind::GitUninteresting
         }
+    } else if let Ok(check_file_path) = file_path.strip_prefix(common_dir) {
+        // Only references are shared by all worktrees, anything else belongs to the main worktree.
+        if check_file_path == Path::new("FETCH_HEAD")
+            || check_file_path == Path::new("packed-refs")
+            || is_interesting_ref(check_file_path)
+        {
+            FileKind::Git
+        } else {
+            FileKind::GitUninteresting
+        }
     } else {
         FileKind::Project
     }
 }
+
+/// Return `file_path` relative to `git_dir`, or to `common_dir` which is shared by all worktrees.
+fn strip_git_dir<'a>(git_dir: &Path, common_dir: &Path, file_path: &'a Path) -> Option<&'a Path> {
+    file_path
+        .strip_prefix(git_dir)
+        .or_else(|_| file_path.strip_prefix(common_dir))
+        .ok()
+}
+
+/// Return `true` if `path`, relative to the git directory, is a loose reference that isn't managed by GitButler.
+fn is_interesting_ref(path: &Path) -> bool {
+    path.starts_with("refs")
+        && !path.starts_with("refs/gitbutler")
+        && !path.starts_with("refs/heads/gitbutler")
+        && path.extension().map_or(true, |ext| ext != "lock")
+}
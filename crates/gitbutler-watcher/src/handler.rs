@@ -1,4 +1,11 @@
-use std::{path::PathBuf, sync::Arc};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
 
 use super::{events, Change};
 use anyhow::{Context, Result};
@@ -16,6 +23,7 @@ use gitbutler_oplog::{
 use gitbutler_project::ProjectId;
 use gitbutler_project::{self as projects, Project};
 use gitbutler_reference::{LocalRefname, Refname};
+use gitbutler_stack::VirtualBranchesHandle;
 use gitbutler_sync::cloud::{push_oplog, push_repo};
 use gitbutler_user as users;
 use tracing::instrument;
@@ -32,6 +40,17 @@ pub struct Handler {
     // need extra protection.
     projects: projects::Controller,
     users: users::Controller,
+    /// The uncommitted changes of each project as of the last file event, along with the commit they were
+    /// computed against, so the next file event only needs to diff the files it is about.
+    #[allow(clippy::type_complexity)]
+    worktree_changes: Arc<Mutex<HashMap<ProjectId, (git2::Oid, DiffByPathMap)>>>,
+    /// Incremented whenever uncommitted changes are forgotten, so ones computed meanwhile aren't kept.
+    worktree_changes_forgotten: Arc<AtomicUsize>,
+    /// The branches and tags of each project as of the last change to them, to tell what changed next time.
+    refs: Arc<Mutex<HashMap<ProjectId, HashMap<String, git2::Oid>>>>,
+    /// The branch `HEAD` of each project pointed to when it last changed, or `None` if it was detached,
+    /// to tell branch switches apart from updates of the branch.
+    heads: Arc<Mutex<HashMap<ProjectId, Option<String>>>>,
 
     /// A function to send events - decoupled from app-handle for testing purposes.
     #[allow(clippy::type_complexity)]
@@ -49,6 +68,10 @@ impl Handler {
         Handler {
             projects,
             users,
+            worktree_changes: Default::default(),
+            worktree_changes_forgotten: Default::default(),
+            refs: Default::default(),
+            heads: Default::default(),
             send_event: Arc::new(send_event),
         }
     }
@@ -70,9 +93,12 @@ impl Handler {
                 .context("failed to handle gitbutler oplog change event"),
 
             // This is only produced at the end of mutating Tauri commands to trigger a fresh state being served to the UI.
-            events::InternalEvent::CalculateVirtualBranches(project_id) => self
-                .calculate_virtual_branches(project_id, None)
-                .context("failed to handle virtual branch event"),
+            events::InternalEvent::CalculateVirtualBranches(project_id) => {
+                // The operation that triggered this may have changed the worktree in ways we can't see.
+                self.forget_worktree_changes(project_id);
+                self.calculate_virtual_branches(project_id, None)
+                    .context("failed to handle virtual branch event")
+            }
         }
     }
 }
@@ -90,11 +116,11 @@ impl Handler {
         CommandContext::open(&project).context("Failed to create a command context")
     }
 
-    #[instrument(skip(self, project_id, worktree_changes))]
+    #[instrument(skip(self, project_id, uncommited_files))]
     fn calculate_virtual_branches(
         &self,
         project_id: ProjectId,
-        worktree_changes: Option<DiffByPathMap>,
+        uncommited_files: Option<UncommitedFiles>,
     ) -> Result<()> {
         let ctx = self.open_command_context(project_id)?;
         // Skip if we're not on the open workspace mode
@@ -106,7 +132,18 @@ impl Handler {
             .projects
             .get(project_id)
             .context("failed to get project")?;
-        match gitbutler_branch_actions::list_virtual_branches_cached(&project, worktree_changes) {
+        let (worktree_changes, changed_paths) = match uncommited_files {
+            Some(UncommitedFiles {
+                files,
+                changed_paths,
+            }) => (Some(files), changed_paths),
+            None => (None, None),
+        };
+        match gitbutler_branch_actions::list_virtual_branches_cached(
+            &project,
+            worktree_changes,
+            changed_paths.as_deref(),
+        ) {
             Ok((branches, skipped_files)) => self.emit_app_event(Change::VirtualBranches {
                 project_id: project.id,
                 virtual_branches: VirtualBranches {
@@ -130,47 +167,136 @@ impl Handler {
     fn recalculate_everything(&self, paths: Vec<PathBuf>, project_id: ProjectId) -> Result<()> {
         let ctx = self.open_command_context(project_id)?;
 
-        let worktree_changes = self.emit_uncommited_files(ctx.project()).ok();
+        let uncommited_files = match self.emit_uncommited_files(ctx.project(), &paths) {
+            // None of the files changed in the eyes of Git, so nothing that depends on them can have changed either.
+            Ok(None) => return Ok(()),
+            Ok(Some(uncommited_files)) => Some(uncommited_files),
+            Err(_) => None,
+        };
 
         if in_open_workspace_mode(&ctx) {
-            self.maybe_create_snapshot(project_id).ok();
-            self.calculate_virtual_branches(project_id, worktree_changes)?;
+            self.maybe_create_snapshot(project_id, &paths).ok();
+            if let Err(err) = self.calculate_virtual_branches(project_id, uncommited_files) {
+                // Branches are only updated incrementally on top of their last successful update.
+                self.forget_worktree_changes(project_id);
+                return Err(err);
+            }
         }
 
         Ok(())
     }
 
-    /// Try to emit uncommited files. Swollow errors if they arrise.
-    fn emit_uncommited_files(&self, project: &Project) -> Result<DiffByPathMap> {
-        let files = gitbutler_branch_actions::get_uncommited_files_reusable(project)?;
-
-        let _ = self.emit_app_event(Change::UncommitedFiles {
-            project_id: project.id,
-            files: files
-                .clone()
-                .into_iter()
-                .map(|(path, file)| {
-                    let binary = file.hunks.iter().any(|h| h.binary);
-                    RemoteBranchFile {
-                        path,
-                        hunks: file.hunks,
-                        binary,
-                    }
-                })
-                .collect(),
-        });
-        Ok(files)
+    /// Update the uncommited files with the changes at `paths`, and emit the files that changed since the last event,
+    /// or all of them if they had to be recomputed. Return `None` if nothing changed. Swallow errors when emitting.
+    fn emit_uncommited_files(
+        &self,
+        project: &Project,
+        paths: &[PathBuf],
+    ) -> Result<Option<UncommitedFiles>> {
+        // The changes are taken out while diffing, so other events of the project recompute them instead of waiting.
+        let forgotten = self.worktree_changes_forgotten.load(Ordering::SeqCst);
+        // Ignore rules can change the status of any file, so changes to them can't be applied incrementally.
+        let previous = self
+            .worktree_changes
+            .lock()
+            .expect("not poisoned")
+            .remove(&project.id)
+            .filter(|_| !paths.iter().any(|path| path.ends_with(".gitignore")));
+        let (base_id, mut files) = match previous {
+            Some((base_id, files)) => (Some(base_id), files),
+            None => (None, DiffByPathMap::default()),
+        };
+        let previous_files = base_id.map(|_| files.clone());
+        let head_id = gitbutler_branch_actions::update_uncommited_files_reusable(
+            project, base_id, paths, &mut files,
+        )?;
+        {
+            let mut worktree_changes = self.worktree_changes.lock().expect("not poisoned");
+            let raced = worktree_changes.remove(&project.id).is_some()
+                || forgotten != self.worktree_changes_forgotten.load(Ordering::SeqCst);
+            // Changes computed concurrently may have missed each other, let the next event start over.
+            if !raced {
+                worktree_changes.insert(project.id, (head_id, files.clone()));
+            }
+        }
+
+        let changed_paths = match previous_files.filter(|_| base_id == Some(head_id)) {
+            Some(previous_files) => {
+                let changed: Vec<_> = files
+                    .iter()
+                    .filter(|(path, file)| previous_files.get(*path) != Some(*file))
+                    .map(|(path, file)| to_remote_branch_file(path.clone(), file.clone()))
+                    .collect();
+                let removed: Vec<_> = previous_files
+                    .into_keys()
+                    .filter(|path| !files.contains_key(path))
+                    .collect();
+                if changed.is_empty() && removed.is_empty() {
+                    return Ok(None);
+                }
+                let changed_paths = changed
+                    .iter()
+                    .map(|file| file.path.clone())
+                    .chain(removed.iter().cloned())
+                    .collect();
+                let _ = self.emit_app_event(Change::UncommitedFilesDelta {
+                    project_id: project.id,
+                    changed,
+                    removed,
+                });
+                Some(changed_paths)
+            }
+            None => {
+                let _ = self.emit_app_event(Change::UncommitedFiles {
+                    project_id: project.id,
+                    files: files
+                        .clone()
+                        .into_iter()
+                        .map(|(path, file)| to_remote_branch_file(path, file))
+                        .collect(),
+                });
+                None
+            }
+        };
+        Ok(Some(UncommitedFiles {
+            files,
+            changed_paths,
+        }))
+    }
+
+    /// Make sure the next file event recomputes all uncommited files of `project_id`.
+    fn forget_worktree_changes(&self, project_id: ProjectId) {
+        self.worktree_changes_forgotten
+            .fetch_add(1, Ordering::SeqCst);
+        self.worktree_changes
+            .lock()
+            .expect("not poisoned")
+            .remove(&project_id);
     }
 
-    fn maybe_create_snapshot(&self, project_id: ProjectId) -> anyhow::Result<()> {
+    /// Create a snapshot if the changes to `paths` are worth one according to the project's snapshot policy.
+    fn maybe_create_snapshot(
+        &self,
+        project_id: ProjectId,
+        paths: &[PathBuf],
+    ) -> anyhow::Result<()> {
         let project = self
             .projects
             .get(project_id)
             .context("failed to get project")?;
-        if project
-            .should_auto_snapshot(std::time::Duration::from_secs(300))
-            .unwrap_or_default()
-        {
+        let policy = &project.snapshot_policy;
+        let num_paths = paths
+            .iter()
+            .filter(|path| !policy.is_excluded(path))
+            .count();
+        if !policy.enabled || num_paths == 0 {
+            return Ok(());
+        }
+        let interval = match policy.save_burst_files {
+            Some(burst_files) if num_paths >= burst_files => std::time::Duration::ZERO,
+            _ => policy.interval(),
+        };
+        if project.should_auto_snapshot(interval).unwrap_or_default() {
             let mut guard = project.exclusive_worktree_access();
             project.create_snapshot(
                 SnapshotDetails::new(OperationKind::FileChanges),
@@ -180,12 +306,43 @@ impl Handler {
         Ok(())
     }
 
+    /// Remember the branches and tags of `project_id` and the branch `HEAD` points to, so that subsequent
+    /// changes to them can be told apart.
+    pub(super) fn remember_refs(&self, project_id: ProjectId) -> Result<()> {
+        let project = self
+            .projects
+            .get(project_id)
+            .context("failed to get project")?;
+        let ctx = CommandContext::open(&project).context("Failed to create a command context")?;
+        let refs = list_refs(ctx.repository())?;
+        self.refs
+            .lock()
+            .expect("not poisoned")
+            .insert(project_id, refs);
+        let head = head_target(ctx.repository())?;
+        self.heads
+            .lock()
+            .expect("not poisoned")
+            .insert(project_id, head);
+        Ok(())
+    }
+
     pub fn git_files_change(&self, paths: Vec<PathBuf>, project_id: ProjectId) -> Result<()> {
         let project = self
             .projects
             .get(project_id)
             .context("failed to get project")?;
 
+        let refs_changed = paths
+            .iter()
+            .any(|path| path == Path::new("packed-refs") || path.starts_with("refs"));
+        // Failing to tell what changed about references must not keep other files from being handled.
+        if refs_changed {
+            if let Err(err) = self.git_refs_change(&project) {
+                tracing::warn!(%project_id, ?err, "failed to handle reference change");
+            }
+        }
+
         for path in paths {
             let Some(file_name) = path.to_str() else {
                 continue;
@@ -197,10 +354,41 @@ impl Handler {
                 "logs/HEAD" => {
                     self.emit_app_event(Change::GitActivity(project.id))?;
                 }
+                "index" => {
+                    // Changes to the index alter the uncommited files without touching the worktree.
+                    self.forget_worktree_changes(project_id);
+                }
+                "info/exclude" => {
+                    // Like `.gitignore` files, ignore rules can change the status of any file.
+                    self.forget_worktree_changes(project_id);
+                }
                 "HEAD" => {
                     let ctx = CommandContext::open(&project)
                         .context("Failed to create a command context")?;
 
+                    // `HEAD` is also rewritten when the branch it points to is updated, as with every
+                    // change to the workspace commit, which isn't a switch.
+                    let head = head_target(ctx.repository())?;
+                    let previous_head = self
+                        .heads
+                        .lock()
+                        .expect("not poisoned")
+                        .insert(project_id, head.clone());
+                    let switched = previous_head.map_or(false, |previous| previous != head);
+                    if switched
+                        && project.snapshot_policy.enabled
+                        && project.snapshot_policy.on_branch_switch
+                    {
+                        // The switch already happened, so this captures the branch that was switched to.
+                        let mut guard = project.exclusive_worktree_access();
+                        if let Err(err) = project.create_snapshot(
+                            SnapshotDetails::new(OperationKind::SwitchBranch),
+                            guard.write_permission(),
+                        ) {
+                            tracing::warn!(%project_id, ?err, "failed to snapshot branch switch");
+                        }
+                    }
+
                     // If the user has left gitbutler/workspace, we want to delete the reference.
                     // TODO: why do we want to do this?
                     if in_outside_workspace_mode(&ctx) {
@@ -226,6 +414,72 @@ impl Handler {
         Ok(())
     }
 
+    /// Compare the branches and tags of `project` to what they were when they last changed, and emit what changed.
+    fn git_refs_change(&self, project: &Project) -> Result<()> {
+        let ctx = CommandContext::open(project).context("Failed to create a command context")?;
+        let refs = list_refs(ctx.repository())?;
+        let Some(previous_refs) = self
+            .refs
+            .lock()
+            .expect("not poisoned")
+            .insert(project.id, refs.clone())
+        else {
+            // Without knowing what was there before, we can't tell what changed.
+            return Ok(());
+        };
+
+        let RefChanges {
+            remote,
+            tags,
+            created,
+            updated,
+            deleted,
+        } = RefChanges::between(&previous_refs, &refs);
+
+        let project_id = project.id;
+        if let Ok(target) = VirtualBranchesHandle::new(project.gb_dir()).get_default_target() {
+            let refname = target.branch.to_string();
+            if let Some(id) = refs.get(&refname).filter(|_| remote.contains(&refname)) {
+                self.emit_app_event(Change::GitTargetBranchMoved {
+                    project_id,
+                    refname,
+                    sha: id.to_string(),
+                })?;
+            }
+        }
+        if !remote.is_empty() {
+            self.emit_app_event(Change::GitRemoteRefsUpdated {
+                project_id,
+                refnames: remote,
+            })?;
+        }
+        if !created.is_empty() {
+            self.emit_app_event(Change::GitLocalBranchesCreated {
+                project_id,
+                refnames: created,
+            })?;
+        }
+        if !deleted.is_empty() {
+            self.emit_app_event(Change::GitLocalBranchesDeleted {
+                project_id,
+                refnames: deleted,
+            })?;
+        }
+        if !updated.is_empty() {
+            self.emit_app_event(Change::GitLocalBranchesUpdated {
+                project_id,
+                refnames: updated,
+            })?;
+        }
+        if !tags.is_empty() {
+            self.emit_app_event(Change::GitTagsUpdated {
+                project_id,
+                refnames: tags,
+            })?;
+        }
+        Ok(())
+    }
+
     /// Invoked whenever there's a new oplog entry.
     /// If synchronizing with GitButler's servers is enabled it will push Oplog refs
     fn gitbutler_oplog_change(&self, project_id: ProjectId) -> Result<()> {
@@ -246,3 +500,244 @@ impl Handler {
         Ok(())
     }
 }
+
+/// Return the ids of all local and remote-tracking branches and tags in `repo`, by their full name,
+/// except for the ones managed by GitButler.
+/// Return the name of the reference `HEAD` points to, or `None` if it's detached.
+fn head_target(repo: &git2::Repository) -> Result<Option<String>> {
+    let head = repo.find_reference("HEAD")?;
+    Ok(head.symbolic_target().map(ToOwned::to_owned))
+}
+
+fn list_refs(repo: &git2::Repository) -> Result<HashMap<String, git2::Oid>> {
+    let mut refs = HashMap::new();
+    for reference in repo.references()? {
+        let reference = reference?;
+        // Symbolic references like `refs/remotes/origin/HEAD` follow the branches they point to.
+        let (Some(refname), Some(id)) = (reference.name(), reference.target()) else {
+            continue;
+        };
+        let is_branch_or_tag = ["refs/heads/", "refs/remotes/", "refs/tags/"]
+            .iter()
+            .any(|prefix| refname.starts_with(prefix));
+        if is_branch_or_tag && !refname.starts_with("refs/heads/gitbutler/") {
+            refs.insert(refname.to_owned(), id);
+        }
+    }
+    Ok(refs)
+}
+
+/// The uncommited files of a project after a file event.
+struct UncommitedFiles {
+    files: DiffByPathMap,
+    /// The paths whose changes differ from the last event, or `None` if any of them may differ.
+    changed_paths: Option<Vec<PathBuf>>,
+}
+
+/// The branches and tags that changed between two [listings](list_refs()), by their full name and sorted.
+#[derive(Debug, Default, PartialEq)]
+struct RefChanges {
+    /// Remote-tracking branches that were created, updated or deleted.
+    remote: Vec<String>,
+    /// Tags that were created, updated or deleted.
+    tags: Vec<String>,
+    created: Vec<String>,
+    updated: Vec<String>,
+    deleted: Vec<String>,
+}
+
+impl RefChanges {
+    fn between(
+        previous: &HashMap<String, git2::Oid>,
+        current: &HashMap<String, git2::Oid>,
+    ) -> Self {
+        let mut changes = RefChanges::default();
+        for (refname, id) in current {
+            let previous_id = previous.get(refname);
+            if previous_id == Some(id) {
+                continue;
+            }
+            if refname.starts_with("refs/remotes/") {
+                changes.remote.push(refname.clone());
+            } else if refname.starts_with("refs/tags/") {
+                changes.tags.push(refname.clone());
+            } else if previous_id.is_none() {
+                changes.created.push(refname.clone());
+            } else {
+                changes.updated.push(refname.clone());
+            }
+        }
+        for refname in previous.keys() {
+            if current.contains_key(refname) {
+                continue;
+            }
+            if refname.starts_with("refs/remotes/") {
+                changes.remote.push(refname.clone());
+            } else if refname.starts_with("refs/tags/") {
+                changes.tags.push(refname.clone());
+            } else {
+                changes.deleted.push(refname.clone());
+            }
+        }
+        for refnames in [
+            &mut changes.remote,
+            &mut changes.tags,
+            &mut changes.created,
+            &mut changes.updated,
+            &mut changes.deleted,
+        ] {
+            refnames.sort();
+        }
+        changes
+    }
+}
+
+fn to_remote_branch_file(path: PathBuf, file: gitbutler_diff::FileDiff) -> RemoteBranchFile {
+    let binary = file.hunks.iter().any(|h| h.binary);
+    RemoteBranchFile {
+        path,
+        hunks: file.hunks,
+        binary,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gitbutler_testsupport::testing_repository::TestingRepository;
+
+    fn oid(hex: &str) -> git2::Oid {
+        git2::Oid::from_str(hex).unwrap()
+    }
+
+    fn refs(refs: &[(&str, git2::Oid)]) -> HashMap<String, git2::Oid> {
+        refs.iter()
+            .map(|(refname, id)| (refname.to_string(), *id))
+            .collect()
+    }
+
+    #[test]
+    fn head_target_is_the_branch_head_points_to() {
+        let test_repository = TestingRepository::open();
+        let repo = &test_repository.repository;
+        let commit = test_repository.commit_tree(None, &[("file", "content")]);
+        repo.reference("refs/heads/feature", commit.id(), true, "")
+            .unwrap();
+
+        repo.set_head("refs/heads/feature").unwrap();
+        assert_eq!(
+            head_target(repo).unwrap().as_deref(),
+            Some("refs/heads/feature")
+        );
+        repo.set_head_detached(commit.id()).unwrap();
+        assert_eq!(head_target(repo).unwrap(), None);
+    }
+
+    #[test]
+    fn list_refs_skips_gitbutler_refs_and_symbolic_refs() {
+        let test_repository = TestingRepository::open();
+        let repo = &test_repository.repository;
+        let commit = test_repository.commit_tree(None, &[("file", "content")]);
+        repo.reference("refs/heads/feature", commit.id(), true, "")
+            .unwrap();
+        repo.reference("refs/remotes/origin/feature", commit.id(), true, "")
+            .unwrap();
+        repo.reference("refs/tags/v1", commit.id(), true, "")
+            .unwrap();
+        repo.reference("refs/heads/gitbutler/workspace", commit.id(), true, "")
+            .unwrap();
+        repo.reference("refs/gitbutler/feature", commit.id(), true, "")
+            .unwrap();
+        repo.reference_symbolic(
+            "refs/remotes/origin/HEAD",
+            "refs/remotes/origin/feature",
+            true,
+            "",
+        )
+        .unwrap();
+
+        let listed = list_refs(repo).unwrap();
+        assert_eq!(
+            listed,
+            refs(&[
+                ("refs/heads/feature", commit.id()),
+                ("refs/remotes/origin/feature", commit.id()),
+                ("refs/tags/v1", commit.id()),
+            ])
+        );
+    }
+
+    #[test]
+    fn ref_changes_classify_local_branches() {
+        let (a, b) = (
+            oid("1111111111111111111111111111111111111111"),
+            oid("2222222222222222222222222222222222222222"),
+        );
+        let previous = refs(&[
+            ("refs/heads/unchanged", a),
+            ("refs/heads/moved", a),
+            ("refs/heads/removed", a),
+        ]);
+        let current = refs(&[
+            ("refs/heads/unchanged", a),
+            ("refs/heads/moved", b),
+            ("refs/heads/new-b", b),
+            ("refs/heads/new-a", a),
+        ]);
+
+        assert_eq!(
+            RefChanges::between(&previous, &current),
+            RefChanges {
+                created: vec!["refs/heads/new-a".into(), "refs/heads/new-b".into()],
+                updated: vec!["refs/heads/moved".into()],
+                deleted: vec!["refs/heads/removed".into()],
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn ref_changes_report_remote_branches_and_tags_separately() {
+        let (a, b) = (
+            oid("1111111111111111111111111111111111111111"),
+            oid("2222222222222222222222222222222222222222"),
+        );
+        let previous = refs(&[
+            ("refs/remotes/origin/moved", a),
+            ("refs/remotes/origin/removed", a),
+            ("refs/tags/removed", a),
+            ("refs/tags/unchanged", a),
+        ]);
+        let current = refs(&[
+            ("refs/remotes/origin/moved", b),
+            ("refs/remotes/origin/added", a),
+            ("refs/tags/unchanged", a),
+            ("refs/tags/added", b),
+        ]);
+
+        assert_eq!(
+            RefChanges::between(&previous, &current),
+            RefChanges {
+                remote: vec![
+                    "refs/remotes/origin/added".into(),
+                    "refs/remotes/origin/moved".into(),
+                    "refs/remotes/origin/removed".into(),
+                ],
+                tags: vec!["refs/tags/added".into(), "refs/tags/removed".into()],
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn ref_changes_are_empty_without_changes() {
+        let current = refs(&[(
+            "refs/heads/main",
+            oid("1111111111111111111111111111111111111111"),
+        )]);
+        assert_eq!(
+            RefChanges::between(&current, &current),
+            RefChanges::default()
+        );
+    }
+}
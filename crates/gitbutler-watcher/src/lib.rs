@@ -3,13 +3,15 @@
 #![allow(clippy::doc_markdown, clippy::missing_errors_doc)]
 
 mod events;
-use std::path::Path;
+use std::{collections::VecDeque, path::Path, sync::Arc, time::Instant};
 
 use anyhow::{Context, Result};
 use events::InternalEvent;
 pub use events::{Action, Change};
-use gitbutler_project::ProjectId;
+use gitbutler_project::{FileWatcherSettings, ProjectId};
 pub use handler::Handler;
+use metrics::Metrics;
+pub use metrics::WatcherMetrics;
 use tokio::{
     sync::mpsc::{unbounded_channel, UnboundedSender},
     task,
@@ -18,6 +20,9 @@ use tokio_util::sync::CancellationToken;
 
 mod file_monitor;
 mod handler;
+mod metrics;
+mod poll_monitor;
+pub mod stream;
 
 /// An abstraction over a link to the spawned watcher, which runs in the background.
 pub struct WatcherHandle {
@@ -28,6 +33,8 @@ pub struct WatcherHandle {
     signal_flush: UnboundedSender<()>,
     /// A way to tell the background process to stop handling events.
     cancellation_token: CancellationToken,
+    /// Counters and timings of the events handled so far.
+    metrics: Arc<Metrics>,
 }
 
 impl Drop for WatcherHandle {
@@ -54,6 +61,11 @@ impl WatcherHandle {
         self.signal_flush.send(())?;
         Ok(())
     }
+
+    /// Return the counters and timings of the events handled since the watcher was started.
+    pub fn metrics(&self) -> WatcherMetrics {
+        self.metrics.snapshot()
+    }
 }
 
 /// Run our file watcher processing loop in the background and let `handler` deal with them.
@@ -63,47 +75,79 @@ impl WatcherHandle {
 /// ### How it works
 ///
 /// The watcher is a processing loop that relies on filesystem events. These are aggregated so
-/// every ~100ms, the changed paths sorted by 'worktree' and 'git-repository' will be processed,
-/// each of these events is handled in its own thread, while being able to spawn additional processing
-/// tasks as well.
+/// every ~100ms, the changed paths sorted by 'worktree' and 'git-repository' will be processed.
+/// Events are handled one at a time on a blocking thread, so handlers of the same project never compete
+/// for the worktree.
 ///
-/// This also means that when there are continuous changes to the filesystem, these events might pile
-/// up if they take longer to process than the 100ms window between them, causing high-CPU and possibly
-/// high-memory. However, the likelihood for this is much lower than it was before the architecture
-/// was changed to what it is now, which should be much less wasteful.
+/// When there are continuous changes to the filesystem, events arrive faster than they can be handled.
+/// Then each incoming event is merged into a waiting event of the same kind if there is one, so the
+/// backlog never grows beyond one event per kind and superseded recalculations are dropped.
+/// How many events were handled or merged, and how long that took, is available through
+/// [`WatcherHandle::metrics()`].
+///
+/// Filesystem events are obtained as configured by `settings`, see [`FileWatcherSettings`].
 pub fn watch_in_background(
     handler: handler::Handler,
     worktree_path: impl AsRef<Path>,
     project_id: ProjectId,
+    settings: FileWatcherSettings,
 ) -> Result<WatcherHandle, anyhow::Error> {
     let (events_out, mut events_in) = unbounded_channel();
     let (flush_tx, mut flush_rx) = unbounded_channel();
 
-    let debounce = file_monitor::spawn(project_id, worktree_path.as_ref(), events_out.clone())?;
+    if let Err(err) = handler.remember_refs(project_id) {
+        tracing::warn!(%project_id, ?err, "failed to list references, their changes won't be reported");
+    }
+    let debounce = file_monitor::spawn(
+        project_id,
+        worktree_path.as_ref(),
+        settings,
+        events_out.clone(),
+    )?;
 
     let cancellation_token = CancellationToken::new();
+    let metrics = Arc::new(Metrics::default());
     let handle = WatcherHandle {
         tx: events_out,
         project_id,
         signal_flush: flush_tx,
         cancellation_token: cancellation_token.clone(),
+        metrics: Arc::clone(&metrics),
     };
-    let handle_event = move |event: InternalEvent| -> Result<()> {
+    let queue_metrics = Arc::clone(&metrics);
+    let (done_tx, mut done_rx) = unbounded_channel();
+    let handle_event = move |event: InternalEvent| {
         let handler = handler.clone();
-        // NOTE: Traditional parallelization (blocking) is required as `tokio::spawn()` on
-        //       the `handler.handle()` future isn't `Send` as it keeps non-Send things
-        //       across await points. Further, there is a fair share of `sync` IO happening
-        //       as well, so nothing can really be done here.
-        task::spawn_blocking(move || {
-            handler.handle(event).ok();
+        let metrics = Arc::clone(&metrics);
+        let done_tx = done_tx.clone();
+        tokio::spawn(async move {
+            // NOTE: Traditional parallelization (blocking) is required as `tokio::spawn()` on
+            //       the `handler.handle()` future isn't `Send` as it keeps non-Send things
+            //       across await points. Further, there is a fair share of `sync` IO happening
+            //       as well, so nothing can really be done here.
+            let handled = task::spawn_blocking(move || {
+                let description = event.to_string();
+                let start = Instant::now();
+                let result = handler.handle(event);
+                metrics.record_processed(&description, start.elapsed(), result.is_err());
+            })
+            .await;
+            if let Err(err) = handled {
+                tracing::error!(%project_id, ?err, "watcher event handler panicked");
+            }
+            done_tx.send(()).ok();
         });
-        Ok(())
     };
 
     tokio::spawn(async move {
+        let mut pending = VecDeque::<InternalEvent>::new();
+        let mut is_handling_event = false;
         loop {
             tokio::select! {
-                Some(event) = events_in.recv() => handle_event(event)?,
+                Some(event) = events_in.recv() => {
+                    queue_event(&mut pending, event, &queue_metrics);
+                }
+                Some(()) = done_rx.recv() => is_handling_event = false,
                 Some(_signal_flush) = flush_rx.recv() => {
                     debounce.flush_nonblocking();
                 }
@@ -112,9 +156,101 @@ pub fn watch_in_background(
                     break;
                 }
             }
+            if !is_handling_event {
+                if let Some(event) = pending.pop_front() {
+                    is_handling_event = true;
+                    handle_event(event);
+                }
+            }
         }
-        Ok::<_, anyhow::Error>(())
     });
 
     Ok(handle)
 }
+
+/// Add `event` to the `pending` events, or merge it into one of them if possible.
+fn queue_event(pending: &mut VecDeque<InternalEvent>, mut event: InternalEvent, metrics: &Metrics) {
+    for pending_event in pending.iter_mut() {
+        match pending_event.coalesce(event) {
+            Ok(()) => {
+                metrics.record_coalesced();
+                return;
+            }
+            Err(not_coalesced) => event = not_coalesced,
+        }
+    }
+    pending.push_back(event);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn queue_event_merges_into_a_pending_event_of_the_same_kind() {
+        let project_id = ProjectId::generate();
+        let metrics = Metrics::default();
+        let mut pending = VecDeque::new();
+
+        queue_event(
+            &mut pending,
+            InternalEvent::ProjectFilesChange(project_id, vec![PathBuf::from("a")]),
+            &metrics,
+        );
+        queue_event(
+            &mut pending,
+            InternalEvent::CalculateVirtualBranches(project_id),
+            &metrics,
+        );
+        queue_event(
+            &mut pending,
+            InternalEvent::ProjectFilesChange(project_id, vec![PathBuf::from("b")]),
+            &metrics,
+        );
+        queue_event(
+            &mut pending,
+            InternalEvent::CalculateVirtualBranches(project_id),
+            &metrics,
+        );
+
+        assert_eq!(pending.len(), 2, "one event per kind is waiting");
+        assert!(
+            matches!(&pending[0], InternalEvent::ProjectFilesChange(_, paths) if paths == &[PathBuf::from("a"), PathBuf::from("b")]),
+            "later events are merged into the waiting one, keeping its position"
+        );
+        assert!(matches!(
+            pending[1],
+            InternalEvent::CalculateVirtualBranches(_)
+        ));
+        assert_eq!(metrics.snapshot().coalesced, 2);
+    }
+
+    #[test]
+    fn queue_event_keeps_events_of_other_projects_apart() {
+        let (project_id, other_project_id) = (ProjectId::generate(), ProjectId::generate());
+        let metrics = Metrics::default();
+        let mut pending = VecDeque::new();
+
+        queue_event(
+            &mut pending,
+            InternalEvent::CalculateVirtualBranches(project_id),
+            &metrics,
+        );
+        queue_event(
+            &mut pending,
+            InternalEvent::CalculateVirtualBranches(other_project_id),
+            &metrics,
+        );
+
+        assert_eq!(pending.len(), 2);
+        assert!(
+            matches!(pending[0], InternalEvent::CalculateVirtualBranches(id) if id == project_id)
+        );
+        assert!(
+            matches!(pending[1], InternalEvent::CalculateVirtualBranches(id) if id == other_project_id)
+        );
+        assert_eq!(metrics.snapshot().coalesced, 0);
+    }
+}
@@ -0,0 +1,70 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use serde::Serialize;
+
+/// Events taking longer than this to process are logged as warnings.
+const SLOW_EVENT_THRESHOLD: Duration = Duration::from_secs(2);
+
+/// Counters and timings of the events processed by a watcher, shared between its processing loop and
+/// its [handle](crate::WatcherHandle).
+#[derive(Debug, Default)]
+pub(crate) struct Metrics {
+    processed: AtomicU64,
+    coalesced: AtomicU64,
+    failed: AtomicU64,
+    total_processing_micros: AtomicU64,
+    max_processing_micros: AtomicU64,
+}
+
+impl Metrics {
+    /// Record that an event was merged into one that was still waiting to be processed.
+    pub(crate) fn record_coalesced(&self) {
+        self.coalesced.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record that the event described by `event` was processed in `duration`, and whether it `failed`.
+    pub(crate) fn record_processed(&self, event: &str, duration: Duration, failed: bool) {
+        self.processed.fetch_add(1, Ordering::Relaxed);
+        if failed {
+            self.failed.fetch_add(1, Ordering::Relaxed);
+        }
+        let micros = u64::try_from(duration.as_micros()).unwrap_or(u64::MAX);
+        self.total_processing_micros
+            .fetch_add(micros, Ordering::Relaxed);
+        self.max_processing_micros
+            .fetch_max(micros, Ordering::Relaxed);
+        if duration >= SLOW_EVENT_THRESHOLD {
+            tracing::warn!(event, ?duration, "slow watcher event");
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> WatcherMetrics {
+        WatcherMetrics {
+            processed: self.processed.load(Ordering::Relaxed),
+            coalesced: self.coalesced.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            total_processing_ms: self.total_processing_micros.load(Ordering::Relaxed) / 1000,
+            max_processing_ms: self.max_processing_micros.load(Ordering::Relaxed) / 1000,
+        }
+    }
+}
+
+/// A snapshot of the counters and timings of a watcher since it was started.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatcherMetrics {
+    /// The amount of events that were handled, successfully or not.
+    pub processed: u64,
+    /// The amount of events that were merged into an equivalent event that was still waiting
+    /// to be handled, and thus didn't cause additional work.
+    pub coalesced: u64,
+    /// The amount of events whose handling failed.
+    pub failed: u64,
+    /// The time spent handling all events, in milliseconds.
+    pub total_processing_ms: u64,
+    /// The time spent handling the slowest event, in milliseconds.
+    pub max_processing_ms: u64,
+}
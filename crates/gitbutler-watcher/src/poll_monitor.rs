@@ -0,0 +1,293 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::{Context, Result};
+use gitbutler_notify_debouncer::{DebounceEventResult, DebouncedEvent};
+use gitbutler_oplog::OPLOG_FILE_NAME;
+use notify::event::{CreateKind, DataChange, EventKind, ModifyKind, RemoveKind};
+use tracing::Level;
+
+/// Files in the git directories that are checked for changes, in addition to everything below `refs/`.
+const GIT_FILES: &[&str] = &[
+    "HEAD",
+    "FETCH_HEAD",
+    "logs/HEAD",
+    "index",
+    "packed-refs",
+    "GB_FLUSH",
+];
+
+/// The modification time and size of a file, which are assumed to change along with its content.
+type FileStamp = (SystemTime, u64);
+
+/// A file monitor for filesystems that don't deliver change notifications, like NFS or SSHFS.
+///
+/// It scans the worktree, skipping `.gitignore`d files, and the interesting parts of the git directory
+/// at a fixed interval, and sends events for files that were created, modified or removed in between,
+/// just like the debouncer of the native file monitor would.
+///
+/// Scanning stops when this instance is dropped.
+pub struct PollMonitor {
+    scan_now: mpsc::Sender<()>,
+}
+
+impl PollMonitor {
+    pub fn spawn(
+        worktree_path: &Path,
+        git_dir: &Path,
+        common_dir: &Path,
+        interval: Duration,
+        out: mpsc::Sender<DebounceEventResult>,
+    ) -> Result<Self> {
+        let repo = gix::open(worktree_path)
+            .context(format!(
+                "failed to open project repository to read ignore rules: {}",
+                worktree_path.display()
+            ))?
+            .into_sync();
+        let (scan_now, scan_requests) = mpsc::channel();
+        let worktree_path = worktree_path.to_owned();
+        let git_dir = git_dir.to_owned();
+        let common_dir = common_dir.to_owned();
+        std::thread::Builder::new()
+            .name("file poller".into())
+            .spawn(move || {
+                let repo = repo.to_thread_local();
+                let mut stamps = scan(&repo, &worktree_path, &git_dir, &common_dir);
+                loop {
+                    match scan_requests.recv_timeout(interval) {
+                        Ok(()) | Err(mpsc::RecvTimeoutError::Timeout) => {}
+                        Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                    }
+                    let _span = tracing::span!(Level::DEBUG, "poll for file changes").entered();
+                    let new_stamps = scan(&repo, &worktree_path, &git_dir, &common_dir);
+                    let events = changes(&stamps, &new_stamps);
+                    stamps = new_stamps;
+                    if !events.is_empty() && out.send(Ok(events)).is_err() {
+                        tracing::info!("channel closed - stopping file poller");
+                        break;
+                    }
+                }
+            })
+            .context("failed to spawn file poller")?;
+        Ok(PollMonitor { scan_now })
+    }
+
+    /// Scan for changes right away instead of waiting for the interval to pass.
+    pub fn flush_nonblocking(&self) {
+        self.scan_now.send(()).ok();
+    }
+}
+
+/// Return the stamps of all files in the worktree that aren't ignored, and of the files in `git_dir`
+/// and `common_dir` that the file monitor cares about, by their absolute path.
+fn scan(
+    repo: &gix::Repository,
+    worktree_path: &Path,
+    git_dir: &Path,
+    common_dir: &Path,
+) -> HashMap<PathBuf, FileStamp> {
+    let mut stamps = HashMap::new();
+
+    // Ignore rules are read each time as they may have changed since the last scan.
+    let index = repo.index_or_empty().ok();
+    let mut excludes = index.as_ref().and_then(|index| {
+        repo.excludes(
+            index,
+            None,
+            gix::worktree::stack::state::ignore::Source::WorktreeThenIdMappingIfNotSkipped,
+        )
+        .ok()
+    });
+    scan_dir(worktree_path, &mut stamps, |path, is_dir| {
+        if path.file_name().map_or(false, |name| name == ".git") {
+            return true;
+        }
+        let (Some(excludes), Ok(relative_path)) =
+            (excludes.as_mut(), path.strip_prefix(worktree_path))
+        else {
+            return false;
+        };
+        let mode = if is_dir {
+            gix::index::entry::Mode::DIR
+        } else {
+            gix::index::entry::Mode::FILE
+        };
+        excludes
+            .at_path(relative_path, Some(mode))
+            .map(|platform| platform.is_excluded())
+            .unwrap_or(false)
+    });
+
+    for file in GIT_FILES {
+        insert_stamp(&mut stamps, git_dir.join(file));
+        if common_dir != git_dir {
+            insert_stamp(&mut stamps, common_dir.join(file));
+        }
+    }
+    insert_stamp(&mut stamps, git_dir.join("gitbutler").join(OPLOG_FILE_NAME));
+    scan_dir(&common_dir.join("refs"), &mut stamps, |_, _| false);
+    stamps
+}
+
+/// Record the stamps of all files below `dir` in `stamps`, leaving out files and directories for which
+/// `skip(path, is_dir)` returns `true`. Symbolic links are recorded as files and not followed.
+fn scan_dir(
+    dir: &Path,
+    stamps: &mut HashMap<PathBuf, FileStamp>,
+    mut skip: impl FnMut(&Path, bool) -> bool,
+) {
+    let mut dirs = vec![dir.to_owned()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.filter_map(Result::ok) {
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let path = entry.path();
+            if skip(&path, file_type.is_dir()) {
+                continue;
+            }
+            if file_type.is_dir() {
+                dirs.push(path);
+            } else {
+                insert_stamp(stamps, path);
+            }
+        }
+    }
+}
+
+fn insert_stamp(stamps: &mut HashMap<PathBuf, FileStamp>, path: PathBuf) {
+    if let Ok(metadata) = std::fs::symlink_metadata(&path) {
+        let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+        stamps.insert(path, (modified, metadata.len()));
+    }
+}
+
+/// Turn the differences between the `previous` and `current` stamps into events.
+fn changes(
+    previous: &HashMap<PathBuf, FileStamp>,
+    current: &HashMap<PathBuf, FileStamp>,
+) -> Vec<DebouncedEvent> {
+    let now = Instant::now();
+    let event = |kind: EventKind, path: &PathBuf| {
+        DebouncedEvent::new(notify::Event::new(kind).add_path(path.clone()), now)
+    };
+    let mut events: Vec<_> = current
+        .iter()
+        .filter_map(|(path, stamp)| match previous.get(path) {
+            None => Some(event(EventKind::Create(CreateKind::File), path)),
+            Some(previous_stamp) if previous_stamp != stamp => Some(event(
+                EventKind::Modify(ModifyKind::Data(DataChange::Content)),
+                path,
+            )),
+            Some(_) => None,
+        })
+        .collect();
+    events.extend(
+        previous
+            .keys()
+            .filter(|path| !current.contains_key(*path))
+            .map(|path| event(EventKind::Remove(RemoveKind::File), path)),
+    );
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gitbutler_testsupport::testing_repository::TestingRepository;
+
+    fn stamp(seconds: u64, len: u64) -> FileStamp {
+        (SystemTime::UNIX_EPOCH + Duration::from_secs(seconds), len)
+    }
+
+    fn stamps(stamps: &[(&str, FileStamp)]) -> HashMap<PathBuf, FileStamp> {
+        stamps
+            .iter()
+            .map(|(path, stamp)| (PathBuf::from(path), *stamp))
+            .collect()
+    }
+
+    fn sorted_kinds_and_paths(events: Vec<DebouncedEvent>) -> Vec<(EventKind, PathBuf)> {
+        let mut kinds_and_paths: Vec<_> = events
+            .into_iter()
+            .map(|event| {
+                assert_eq!(event.paths.len(), 1, "every event is for a single path");
+                (event.kind, event.paths[0].clone())
+            })
+            .collect();
+        kinds_and_paths.sort_by(|a, b| a.1.cmp(&b.1));
+        kinds_and_paths
+    }
+
+    #[test]
+    fn changes_classify_created_modified_and_removed_files() {
+        let previous = stamps(&[
+            ("unchanged", stamp(1, 1)),
+            ("touched", stamp(1, 1)),
+            ("resized", stamp(1, 1)),
+            ("removed", stamp(1, 1)),
+        ]);
+        let current = stamps(&[
+            ("unchanged", stamp(1, 1)),
+            ("touched", stamp(2, 1)),
+            ("resized", stamp(1, 2)),
+            ("created", stamp(2, 1)),
+        ]);
+
+        assert_eq!(
+            sorted_kinds_and_paths(changes(&previous, &current)),
+            [
+                (EventKind::Create(CreateKind::File), "created".into()),
+                (EventKind::Remove(RemoveKind::File), "removed".into()),
+                (
+                    EventKind::Modify(ModifyKind::Data(DataChange::Content)),
+                    "resized".into()
+                ),
+                (
+                    EventKind::Modify(ModifyKind::Data(DataChange::Content)),
+                    "touched".into()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn changes_are_empty_if_no_stamp_changed() {
+        let current = stamps(&[("file", stamp(1, 1))]);
+        assert!(changes(&current, &current).is_empty());
+        assert!(changes(&HashMap::new(), &HashMap::new()).is_empty());
+    }
+
+    #[test]
+    fn scan_skips_ignored_files_and_the_git_directory() {
+        let test_repository = TestingRepository::open();
+        let worktree_path = test_repository.tempdir.path();
+        std::fs::write(worktree_path.join(".gitignore"), "ignored\n").unwrap();
+        std::fs::write(worktree_path.join("ignored"), "").unwrap();
+        std::fs::create_dir(worktree_path.join("dir")).unwrap();
+        std::fs::write(worktree_path.join("dir").join("file"), "").unwrap();
+        let git_dir = test_repository.repository.path().to_owned();
+        std::fs::write(git_dir.join("FETCH_HEAD"), "").unwrap();
+
+        let repo = gix::open(worktree_path).unwrap();
+        let scanned = scan(&repo, worktree_path, &git_dir, &git_dir);
+
+        assert!(scanned.contains_key(&worktree_path.join(".gitignore")));
+        assert!(scanned.contains_key(&worktree_path.join("dir").join("file")));
+        assert!(scanned.contains_key(&git_dir.join("HEAD")));
+        assert!(scanned.contains_key(&git_dir.join("FETCH_HEAD")));
+        assert!(!scanned.contains_key(&worktree_path.join("ignored")));
+        assert!(
+            !scanned.contains_key(&git_dir.join("config")),
+            "only the interesting parts of the git directory are scanned"
+        );
+    }
+}
@@ -0,0 +1,669 @@
+//! A stable, serializable form of [`Change`] for consumers outside of the application, like
+//! status-line integrations, along with sinks to deliver it to them.
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::Write,
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use anyhow::{Context, Result};
+use gitbutler_branch_actions::{RemoteBranchFile, VirtualBranch};
+use gitbutler_operating_modes::OperatingMode;
+use gitbutler_project::ProjectId;
+use serde::{Deserialize, Serialize};
+
+use crate::Change;
+
+/// The version of the schema of [`StreamEvent`]. It is incremented whenever a field is removed or changes
+/// its meaning, but not when fields or event types are added, so consumers should ignore what they don't know.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A change to a project, as delivered to [sinks](StreamSink).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamEvent {
+    /// The [`SCHEMA_VERSION`] this event was produced with.
+    pub version: u32,
+    /// The project that changed.
+    pub project_id: ProjectId,
+    /// What changed.
+    #[serde(flatten)]
+    pub kind: StreamEventKind,
+}
+
+/// The kinds of [`StreamEvent`], serialized with their name in the `type` field.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum StreamEventKind {
+    /// A fetch finished.
+    GitFetch,
+    /// Something happened in the repository, like a commit made outside of GitButler.
+    GitActivity,
+    /// `HEAD` changed.
+    GitHead {
+        /// The short name of the branch `HEAD` points to.
+        head: String,
+        /// Whether `HEAD` is the workspace branch, another branch, or the branch of edit mode.
+        mode: WorkspaceMode,
+    },
+    /// Branches, remote-tracking branches or tags were created, moved or deleted.
+    GitRefs {
+        /// The full names of the references that changed.
+        refnames: Vec<String>,
+    },
+    /// The stacks of the workspace changed.
+    VirtualBranches {
+        /// All applied stacks, in the order they are displayed.
+        stacks: Vec<StackStatus>,
+    },
+    /// The uncommitted changes of the worktree changed.
+    UncommittedFiles {
+        /// All files with uncommitted changes, sorted by path.
+        files: Vec<FileStatus>,
+    },
+}
+
+/// The mode of operation of GitButler, as implied by `HEAD`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum WorkspaceMode {
+    OpenWorkspace,
+    OutsideWorkspace,
+    Edit,
+}
+
+/// A summary of an applied stack.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StackStatus {
+    pub id: String,
+    pub name: String,
+    /// If `true`, new changes in the worktree are assigned to this stack.
+    pub selected_for_changes: bool,
+    /// The amount of commits that aren't integrated yet.
+    pub commits: usize,
+    /// The amount of files with uncommitted changes assigned to this stack.
+    pub files: usize,
+    /// The amount of uncommitted hunks assigned to this stack.
+    pub hunks: usize,
+    pub conflicted: bool,
+    /// If `true`, the next push has to be forced.
+    pub requires_force: bool,
+}
+
+/// A summary of the uncommitted changes of a file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileStatus {
+    pub path: PathBuf,
+    pub hunks: usize,
+    pub lines_added: usize,
+    pub lines_removed: usize,
+    pub binary: bool,
+}
+
+/// A destination for [`StreamEvent`]s.
+pub trait StreamSink: Send + Sync {
+    /// Deliver `event`, or fail if the sink can't take events anymore.
+    fn send(&self, event: &StreamEvent) -> Result<()>;
+}
+
+impl<F> StreamSink for F
+where
+    F: Fn(&StreamEvent) -> Result<()> + Send + Sync,
+{
+    fn send(&self, event: &StreamEvent) -> Result<()> {
+        self(event)
+    }
+}
+
+/// Turn [`Change`]s into [`StreamEvent`]s and deliver them to a set of sinks.
+///
+/// Incremental changes are accumulated, so each event describes the complete state of what it is about.
+#[derive(Default)]
+pub struct EventStream {
+    sinks: Vec<Box<dyn StreamSink>>,
+    uncommitted_files: Mutex<HashMap<ProjectId, BTreeMap<PathBuf, FileStatus>>>,
+}
+
+impl EventStream {
+    /// Create a new instance which delivers events to `sinks`.
+    pub fn new(sinks: Vec<Box<dyn StreamSink>>) -> Self {
+        EventStream {
+            sinks,
+            uncommitted_files: Default::default(),
+        }
+    }
+
+    /// Deliver `change` to all sinks if it translates into a [`StreamEvent`]. Errors of sinks are logged.
+    pub fn send(&self, change: &Change) {
+        if self.sinks.is_empty() {
+            return;
+        }
+        let Some(event) = self.to_stream_event(change) else {
+            return;
+        };
+        for sink in &self.sinks {
+            if let Err(err) = sink.send(&event) {
+                tracing::warn!(?err, "failed to send event to stream sink");
+            }
+        }
+    }
+
+    fn to_stream_event(&self, change: &Change) -> Option<StreamEvent> {
+        let (project_id, kind) = match change {
+            Change::GitFetch(project_id) => (*project_id, StreamEventKind::GitFetch),
+            Change::GitActivity(project_id) => (*project_id, StreamEventKind::GitActivity),
+            Change::GitHead {
+                project_id,
+                head,
+                operating_mode,
+            } => (
+                *project_id,
+                StreamEventKind::GitHead {
+                    head: head.clone(),
+                    mode: match operating_mode {
+                        OperatingMode::OpenWorkspace => WorkspaceMode::OpenWorkspace,
+                        OperatingMode::OutsideWorkspace => WorkspaceMode::OutsideWorkspace,
+                        OperatingMode::Edit(_) => WorkspaceMode::Edit,
+                    },
+                },
+            ),
+            Change::GitRemoteRefsUpdated {
+                project_id,
+                refnames,
+            }
+            | Change::GitLocalBranchesCreated {
+                project_id,
+                refnames,
+            }
+            | Change::GitLocalBranchesDeleted {
+                project_id,
+                refnames,
+            }
+            | Change::GitLocalBranchesUpdated {
+                project_id,
+                refnames,
+            }
+            | Change::GitTagsUpdated {
+                project_id,
+                refnames,
+            } => (
+                *project_id,
+                StreamEventKind::GitRefs {
+                    refnames: refnames.clone(),
+                },
+            ),
+            // The remote-tracking branch of the target is also reported as part of the remote references.
+            Change::GitTargetBranchMoved { .. } => return None,
+            Change::VirtualBranches {
+                project_id,
+                virtual_branches,
+            } => (
+                *project_id,
+                StreamEventKind::VirtualBranches {
+                    stacks: virtual_branches.branches.iter().map(stack_status).collect(),
+                },
+            ),
+            Change::UncommitedFiles { project_id, files } => {
+                self.update_uncommitted_files(*project_id, files, &[], true)
+            }
+            Change::UncommitedFilesDelta {
+                project_id,
+                changed,
+                removed,
+            } => self.update_uncommitted_files(*project_id, changed, removed, false),
+        };
+        Some(StreamEvent {
+            version: SCHEMA_VERSION,
+            project_id,
+            kind,
+        })
+    }
+
+    /// Apply `changed` and `removed` files to the uncommitted files known for `project_id`, after forgetting
+    /// all of them if `replace` is `true`, and return an event with the result.
+    fn update_uncommitted_files(
+        &self,
+        project_id: ProjectId,
+        changed: &[RemoteBranchFile],
+        removed: &[PathBuf],
+        replace: bool,
+    ) -> (ProjectId, StreamEventKind) {
+        let mut uncommitted_files = self.uncommitted_files.lock().expect("not poisoned");
+        let files = uncommitted_files.entry(project_id).or_default();
+        if replace {
+            files.clear();
+        }
+        for path in removed {
+            files.remove(path);
+        }
+        files.extend(
+            changed
+                .iter()
+                .map(|file| (file.path.clone(), file_status(file))),
+        );
+        (
+            project_id,
+            StreamEventKind::UncommittedFiles {
+                files: files.values().cloned().collect(),
+            },
+        )
+    }
+}
+
+/// A sink which writes each event as a line of JSON to a writer, like standard output.
+pub struct JsonLines<W>(Mutex<W>);
+
+impl<W: Write + Send> JsonLines<W> {
+    pub fn new(out: W) -> Self {
+        JsonLines(Mutex::new(out))
+    }
+}
+
+impl<W: Write + Send> StreamSink for JsonLines<W> {
+    fn send(&self, event: &StreamEvent) -> Result<()> {
+        let mut out = self.0.lock().expect("not poisoned");
+        serde_json::to_writer(&mut *out, event).context("failed to serialize event")?;
+        out.write_all(b"\n")?;
+        out.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+pub use socket::SocketSink;
+
+#[cfg(unix)]
+mod socket {
+    use std::{
+        collections::HashMap,
+        io::Write,
+        mem::Discriminant,
+        os::unix::net::{UnixListener, UnixStream},
+        path::{Path, PathBuf},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Mutex,
+        },
+        thread::JoinHandle,
+        time::Duration,
+    };
+
+    use anyhow::{Context, Result};
+    use gitbutler_project::ProjectId;
+
+    use super::{StreamEvent, StreamEventKind, StreamSink};
+
+    const WRITE_TIMEOUT: Duration = Duration::from_millis(100);
+
+    #[derive(Default)]
+    struct Shared {
+        clients: Vec<UnixStream>,
+        /// The last event describing `HEAD`, the stacks and the uncommitted files of each project, serialized,
+        /// to bring new clients up to date.
+        last_events: HashMap<(ProjectId, Discriminant<StreamEventKind>), Vec<u8>>,
+        /// The amount of events sent so far, to know if `last_events` changed while catching up a client.
+        events_sent: u64,
+    }
+
+    /// A sink which listens on a Unix domain socket and writes each event as a line of JSON to all connected
+    /// clients. When connecting, clients first receive the last known `HEAD`, stacks and uncommitted files
+    /// of each project. They may disconnect at any time.
+    ///
+    /// The socket file is removed and the thread accepting clients is stopped when this instance is dropped.
+    pub struct SocketSink {
+        path: PathBuf,
+        shared: Arc<Mutex<Shared>>,
+        closed: Arc<AtomicBool>,
+        listener: Option<JoinHandle<()>>,
+    }
+
+    impl SocketSink {
+        /// Listen on a socket at `path`, replacing a socket that may have been left behind by a previous run.
+        pub fn bind(path: impl Into<PathBuf>) -> Result<Self> {
+            let path = path.into();
+            remove_stale_socket(&path)?;
+            let listener = UnixListener::bind(&path)
+                .with_context(|| format!("failed to listen on {}", path.display()))?;
+            let shared = Arc::new(Mutex::new(Shared::default()));
+            let closed = Arc::new(AtomicBool::new(false));
+            let listener = std::thread::Builder::new()
+                .name("event stream socket".into())
+                .spawn({
+                    let shared = Arc::clone(&shared);
+                    let closed = Arc::clone(&closed);
+                    move || {
+                        for client in listener.incoming() {
+                            // Dropping the sink connects once to wake us up.
+                            if closed.load(Ordering::SeqCst) {
+                                break;
+                            }
+                            let Ok(client) = client else {
+                                continue;
+                            };
+                            // Don't let clients that don't read hold up the watcher for long.
+                            client.set_write_timeout(Some(WRITE_TIMEOUT)).ok();
+                            catch_up(&shared, client);
+                        }
+                    }
+                })
+                .context("failed to spawn event stream listener")?;
+            Ok(SocketSink {
+                path,
+                shared,
+                closed,
+                listener: Some(listener),
+            })
+        }
+    }
+
+    /// Write the last known events to `client` without holding the lock, so slow clients don't block events
+    /// from being sent, and add it to the clients that receive all further events.
+    fn catch_up(shared: &Mutex<Shared>, mut client: UnixStream) {
+        loop {
+            let (lines, events_sent) = {
+                let state = shared.lock().expect("not poisoned");
+                let lines: Vec<_> = state.last_events.values().cloned().collect();
+                (lines, state.events_sent)
+            };
+            if !lines.iter().all(|line| client.write_all(line).is_ok()) {
+                return;
+            }
+            let mut state = shared.lock().expect("not poisoned");
+            // Otherwise the client may have missed an event, so it's brought up to date once more.
+            if state.events_sent == events_sent {
+                state.clients.push(client);
+                return;
+            }
+        }
+    }
+
+    impl StreamSink for SocketSink {
+        fn send(&self, event: &StreamEvent) -> Result<()> {
+            let mut line = serde_json::to_vec(event).context("failed to serialize event")?;
+            line.push(b'\n');
+            let mut shared = self.shared.lock().expect("not poisoned");
+            shared.events_sent += 1;
+            // Clients that can't be written to have disconnected.
+            shared
+                .clients
+                .retain_mut(|client| client.write_all(&line).is_ok());
+            if matches!(
+                event.kind,
+                StreamEventKind::GitHead { .. }
+                    | StreamEventKind::VirtualBranches { .. }
+                    | StreamEventKind::UncommittedFiles { .. }
+            ) {
+                shared.last_events.insert(
+                    (event.project_id, std::mem::discriminant(&event.kind)),
+                    line,
+                );
+            }
+            Ok(())
+        }
+    }
+
+    impl Drop for SocketSink {
+        fn drop(&mut self) {
+            self.closed.store(true, Ordering::SeqCst);
+            // The listener only notices when the next client connects, and can't be joined if that fails.
+            if UnixStream::connect(&self.path).is_ok() {
+                if let Some(listener) = self.listener.take() {
+                    listener.join().ok();
+                }
+            }
+            std::fs::remove_file(&self.path).ok();
+        }
+    }
+
+    fn remove_stale_socket(path: &Path) -> Result<()> {
+        if UnixStream::connect(path).is_ok() {
+            anyhow::bail!(
+                "{} is already used by another process to stream events",
+                path.display()
+            );
+        }
+        match std::fs::remove_file(path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                Err(err).with_context(|| format!("failed to remove {}", path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn stack_status(branch: &VirtualBranch) -> StackStatus {
+    StackStatus {
+        id: branch.id.to_string(),
+        name: branch.name.clone(),
+        selected_for_changes: branch.selected_for_changes,
+        commits: branch
+            .commits
+            .iter()
+            .filter(|commit| !commit.is_integrated)
+            .count(),
+        files: branch.files.len(),
+        hunks: branch.files.iter().map(|file| file.hunks.len()).sum(),
+        conflicted: branch.conflicted,
+        requires_force: branch.requires_force,
+    }
+}
+
+fn file_status(file: &RemoteBranchFile) -> FileStatus {
+    let (mut lines_added, mut lines_removed) = (0, 0);
+    for hunk in file.hunks.iter().filter(|hunk| !hunk.binary) {
+        for line in hunk.diff_lines.split(|byte| *byte == b'\n') {
+            match line.first() {
+                Some(b'+') => lines_added += 1,
+                Some(b'-') => lines_removed += 1,
+                _ => {}
+            }
+        }
+    }
+    FileStatus {
+        path: file.path.clone(),
+        hunks: file.hunks.len(),
+        lines_added,
+        lines_removed,
+        binary: file.binary,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn project_id() -> ProjectId {
+        serde_json::from_value(json!("0b3a6e4f-4d7c-4e58-9c2e-2a3a9c8c1f10")).unwrap()
+    }
+
+    fn assert_json(kind: StreamEventKind, expected: serde_json::Value) {
+        let event = StreamEvent {
+            version: SCHEMA_VERSION,
+            project_id: project_id(),
+            kind,
+        };
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            expected,
+            "the serialized form is part of the schema"
+        );
+    }
+
+    #[test]
+    fn events_without_fields() {
+        assert_json(
+            StreamEventKind::GitFetch,
+            json!({
+                "version": 1,
+                "projectId": "0b3a6e4f-4d7c-4e58-9c2e-2a3a9c8c1f10",
+                "type": "gitFetch",
+            }),
+        );
+        assert_json(
+            StreamEventKind::GitActivity,
+            json!({
+                "version": 1,
+                "projectId": "0b3a6e4f-4d7c-4e58-9c2e-2a3a9c8c1f10",
+                "type": "gitActivity",
+            }),
+        );
+    }
+
+    #[test]
+    fn git_head() {
+        assert_json(
+            StreamEventKind::GitHead {
+                head: "gitbutler/workspace".into(),
+                mode: WorkspaceMode::OpenWorkspace,
+            },
+            json!({
+                "version": 1,
+                "projectId": "0b3a6e4f-4d7c-4e58-9c2e-2a3a9c8c1f10",
+                "type": "gitHead",
+                "head": "gitbutler/workspace",
+                "mode": "openWorkspace",
+            }),
+        );
+        assert_json(
+            StreamEventKind::GitHead {
+                head: "main".into(),
+                mode: WorkspaceMode::OutsideWorkspace,
+            },
+            json!({
+                "version": 1,
+                "projectId": "0b3a6e4f-4d7c-4e58-9c2e-2a3a9c8c1f10",
+                "type": "gitHead",
+                "head": "main",
+                "mode": "outsideWorkspace",
+            }),
+        );
+    }
+
+    #[test]
+    fn git_refs() {
+        assert_json(
+            StreamEventKind::GitRefs {
+                refnames: vec!["refs/heads/feature".into(), "refs/tags/v1".into()],
+            },
+            json!({
+                "version": 1,
+                "projectId": "0b3a6e4f-4d7c-4e58-9c2e-2a3a9c8c1f10",
+                "type": "gitRefs",
+                "refnames": ["refs/heads/feature", "refs/tags/v1"],
+            }),
+        );
+    }
+
+    #[test]
+    fn virtual_branches() {
+        assert_json(
+            StreamEventKind::VirtualBranches {
+                stacks: vec![StackStatus {
+                    id: "stack-id".into(),
+                    name: "feature".into(),
+                    selected_for_changes: true,
+                    commits: 2,
+                    files: 3,
+                    hunks: 4,
+                    conflicted: false,
+                    requires_force: true,
+                }],
+            },
+            json!({
+                "version": 1,
+                "projectId": "0b3a6e4f-4d7c-4e58-9c2e-2a3a9c8c1f10",
+                "type": "virtualBranches",
+                "stacks": [{
+                    "id": "stack-id",
+                    "name": "feature",
+                    "selectedForChanges": true,
+                    "commits": 2,
+                    "files": 3,
+                    "hunks": 4,
+                    "conflicted": false,
+                    "requiresForce": true,
+                }],
+            }),
+        );
+    }
+
+    #[test]
+    fn uncommitted_files() {
+        assert_json(
+            StreamEventKind::UncommittedFiles {
+                files: vec![FileStatus {
+                    path: "src/lib.rs".into(),
+                    hunks: 1,
+                    lines_added: 2,
+                    lines_removed: 3,
+                    binary: false,
+                }],
+            },
+            json!({
+                "version": 1,
+                "projectId": "0b3a6e4f-4d7c-4e58-9c2e-2a3a9c8c1f10",
+                "type": "uncommittedFiles",
+                "files": [{
+                    "path": "src/lib.rs",
+                    "hunks": 1,
+                    "linesAdded": 2,
+                    "linesRemoved": 3,
+                    "binary": false,
+                }],
+            }),
+        );
+    }
+
+    #[test]
+    fn unknown_fields_are_ignored_when_reading() {
+        let event: StreamEvent = serde_json::from_value(json!({
+            "version": 1,
+            "projectId": "0b3a6e4f-4d7c-4e58-9c2e-2a3a9c8c1f10",
+            "type": "gitFetch",
+            "addedLater": true,
+        }))
+        .unwrap();
+        assert_eq!(event.kind, StreamEventKind::GitFetch);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn socket_sink_catches_up_new_clients_and_stops_when_dropped() {
+        use std::io::{BufRead, BufReader};
+
+        let path =
+            std::env::temp_dir().join(format!("gitbutler-stream-test-{}.sock", std::process::id()));
+        let sink = SocketSink::bind(&path).unwrap();
+        let event = StreamEvent {
+            version: SCHEMA_VERSION,
+            project_id: project_id(),
+            kind: StreamEventKind::GitHead {
+                head: "main".into(),
+                mode: WorkspaceMode::OutsideWorkspace,
+            },
+        };
+        sink.send(&event).unwrap();
+
+        let client = std::os::unix::net::UnixStream::connect(&path).unwrap();
+        let mut line = String::new();
+        BufReader::new(client).read_line(&mut line).unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&line).unwrap(),
+            serde_json::to_value(&event).unwrap(),
+            "new clients receive the last known HEAD"
+        );
+
+        drop(sink);
+        assert!(!path.exists(), "the socket is removed");
+        SocketSink::bind(&path).expect("nothing listens on the socket anymore");
+    }
+}